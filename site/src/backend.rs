@@ -1,14 +1,22 @@
 use std::{
     any::Any,
-    collections::HashMap,
+    cell::RefCell,
+    collections::{HashMap, VecDeque},
     io::Cursor,
+    rc::Rc,
     sync::{
         atomic::{AtomicU64, Ordering},
         Mutex,
     },
 };
 
-use uiua::{value::Value, Handle, SysBackend, Uiua, UiuaError, UiuaResult};
+use js_sys::{ArrayBuffer, Uint8Array};
+use uiua::{
+    value::Value, Capability, Handle, HttpRequest, HttpResponse, SysBackend, Uiua, UiuaError,
+    UiuaResult, WsMessage,
+};
+use wasm_bindgen::{closure::Closure, JsCast, JsValue};
+use web_sys::{BinaryType, MessageEvent, WebSocket, XmlHttpRequest};
 
 pub struct WebBackend {
     pub stdout: Mutex<Vec<OutputItem>>,
@@ -32,6 +40,49 @@ impl Default for WebBackend {
     }
 }
 
+/// A websocket and the messages it has received
+///
+/// Websockets cannot be sent between threads, so they are kept outside of the backend.
+/// They stay open between runs until they are closed.
+struct WebSocketEntry {
+    socket: WebSocket,
+    received: Rc<RefCell<VecDeque<WsMessage>>>,
+}
+
+thread_local! {
+    static WEB_SOCKETS: RefCell<HashMap<Handle, WebSocketEntry>> = RefCell::new(HashMap::new());
+}
+
+static NEXT_WEB_SOCKET: AtomicU64 = AtomicU64::new(Handle::FIRST_UNRESERVED.0);
+
+thread_local! {
+    /// The last known clipboard text
+    ///
+    /// The browser's clipboard can only be read asynchronously, so reads return
+    /// this and then start updating it.
+    static CLIPBOARD: RefCell<String> = RefCell::new(String::new());
+}
+
+/// Remember text that was pasted or copied
+pub fn set_cached_clipboard(text: &str) {
+    CLIPBOARD.with(|clipboard| *clipboard.borrow_mut() = text.into());
+}
+
+/// Start reading the clipboard into the cache
+fn refresh_clipboard() {
+    let Some(clipboard) = web_sys::window().and_then(|window| window.navigator().clipboard())
+    else {
+        return;
+    };
+    let on_read = Closure::<dyn FnMut(JsValue)>::new(move |text: JsValue| {
+        if let Some(text) = text.as_string() {
+            set_cached_clipboard(&text);
+        }
+    });
+    _ = clipboard.read_text().then(&on_read);
+    on_read.forget();
+}
+
 pub enum OutputItem {
     String(String),
     Image(Vec<u8>),
@@ -108,6 +159,109 @@ impl SysBackend for WebBackend {
         while (instant::now() - start) / 1000.0 < seconds {}
         Ok(())
     }
+    fn http_request(&self, request: HttpRequest) -> Result<HttpResponse, String> {
+        // The interpreter cannot wait for a promise, so the request is synchronous
+        let js_err = |e: JsValue| format!("HTTP request failed: {e:?}");
+        let xhr = XmlHttpRequest::new().map_err(js_err)?;
+        xhr.open_with_async(&request.method, &request.url, false)
+            .map_err(js_err)?;
+        // Synchronous requests cannot have a binary response type,
+        // so each byte is read as a character instead
+        xhr.override_mime_type("text/plain; charset=x-user-defined")
+            .map_err(js_err)?;
+        for (name, value) in &request.headers {
+            xhr.set_request_header(name, value).map_err(js_err)?;
+        }
+        if request.body.is_empty() {
+            xhr.send()
+        } else {
+            let body = Uint8Array::from(request.body.as_slice());
+            xhr.send_with_opt_buffer_source(Some(&body))
+        }
+        .map_err(js_err)?;
+        let status = xhr.status().map_err(js_err)?;
+        let headers = (xhr.get_all_response_headers().map_err(js_err)?.lines())
+            .filter_map(|line| {
+                let (name, value) = line.split_once(':')?;
+                Some((name.trim().into(), value.trim().into()))
+            })
+            .collect();
+        let text = xhr.response_text().map_err(js_err)?.unwrap_or_default();
+        let body = text.chars().map(|c| c as u32 as u8).collect();
+        Ok(HttpResponse {
+            status,
+            headers,
+            body,
+        })
+    }
+    fn clipboard(&self) -> Result<String, String> {
+        refresh_clipboard();
+        Ok(CLIPBOARD.with(|clipboard| clipboard.borrow().clone()))
+    }
+    fn set_clipboard(&self, contents: &str) -> Result<(), String> {
+        let clipboard = (web_sys::window().and_then(|window| window.navigator().clipboard()))
+            .ok_or("The clipboard is not available")?;
+        _ = clipboard.write_text(contents);
+        set_cached_clipboard(contents);
+        Ok(())
+    }
+    fn ws_connect(&self, url: &str) -> Result<Handle, String> {
+        let socket = WebSocket::new(url).map_err(|e| format!("{e:?}"))?;
+        socket.set_binary_type(BinaryType::Arraybuffer);
+        let received = Rc::new(RefCell::new(VecDeque::new()));
+        let queue = received.clone();
+        let on_message = Closure::<dyn FnMut(MessageEvent)>::new(move |event: MessageEvent| {
+            let data = event.data();
+            let message = if let Some(text) = data.as_string() {
+                WsMessage::Text(text)
+            } else if let Ok(buffer) = data.dyn_into::<ArrayBuffer>() {
+                WsMessage::Binary(Uint8Array::new(&buffer).to_vec())
+            } else {
+                return;
+            };
+            queue.borrow_mut().push_back(message);
+        });
+        socket.set_onmessage(Some(on_message.as_ref().unchecked_ref()));
+        on_message.forget();
+        let handle = Handle(NEXT_WEB_SOCKET.fetch_add(1, Ordering::SeqCst));
+        WEB_SOCKETS.with(|sockets| {
+            (sockets.borrow_mut()).insert(handle, WebSocketEntry { socket, received })
+        });
+        Ok(handle)
+    }
+    fn ws_send(&self, handle: Handle, message: WsMessage) -> Result<(), String> {
+        WEB_SOCKETS.with(|sockets| {
+            let sockets = sockets.borrow();
+            let entry = sockets.get(&handle).ok_or("Invalid websocket handle")?;
+            if entry.socket.ready_state() != WebSocket::OPEN {
+                return Err("The websocket is not open yet".into());
+            }
+            match message {
+                WsMessage::Text(text) => entry.socket.send_with_str(&text),
+                WsMessage::Binary(bytes) => entry.socket.send_with_u8_array(&bytes),
+            }
+            .map_err(|e| format!("{e:?}"))
+        })
+    }
+    fn ws_receive(&self, handle: Handle) -> Result<WsMessage, String> {
+        WEB_SOCKETS.with(|sockets| {
+            let sockets = sockets.borrow();
+            let entry = sockets.get(&handle).ok_or("Invalid websocket handle")?;
+            let message = entry.received.borrow_mut().pop_front();
+            message.ok_or_else(|| "No message has been received yet".into())
+        })
+    }
+    fn stream_capability(&self, handle: Handle) -> Option<Capability> {
+        WEB_SOCKETS
+            .with(|sockets| sockets.borrow().contains_key(&handle))
+            .then_some(Capability::Network)
+    }
+    fn close(&self, handle: Handle) -> Result<(), String> {
+        if let Some(entry) = WEB_SOCKETS.with(|sockets| sockets.borrow_mut().remove(&handle)) {
+            entry.socket.close().map_err(|e| format!("{e:?}"))?;
+        }
+        Ok(())
+    }
     fn spawn(
         &self,
         env: Uiua,
@@ -24,7 +24,7 @@ use wasm_bindgen::{JsCast, JsValue};
 use web_sys::{Event, HtmlBrElement, HtmlDivElement, KeyboardEvent, MouseEvent, Node};
 
 use crate::{
-    backend::{OutputItem, WebBackend},
+    backend::{set_cached_clipboard, OutputItem, WebBackend},
     element, prim_class, Prim,
 };
 
@@ -647,6 +647,7 @@ pub fn Editor<'a>(
         event.prevent_default();
         event.stop_propagation();
         let text = event.clipboard_data().unwrap().get_data("text").unwrap();
+        set_cached_clipboard(&text);
         replace_code(&text);
     };
 
@@ -1331,7 +1332,8 @@ fn run_code(code: &str) -> Vec<OutputItem> {
     // Run
     let mut env = Uiua::with_backend(io)
         .with_mode(RunMode::All)
-        .with_execution_limit(Duration::from_secs(10));
+        .with_execution_limit(Duration::from_secs(10))
+        .with_memory_limit(1 << 30);
     let mut error = None;
     let values = match env.load_str(code) {
         Ok(env) => env.take_stack(),
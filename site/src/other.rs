@@ -115,6 +115,8 @@ pub fn Install() -> impl IntoView {
         <p>"This requires a "<a href="https://www.rust-lang.org/tools/install">"Rust"</a>" installation."</p>
         <p>"Once you have that, run the following command:"</p>
         <code class="code-block">"cargo install uiua"</code>
+        <p>"If you want the system functions for HTTP, TLS, WebSockets, raw terminal input, watching files, and the clipboard, enable the "<code>"full"</code>" feature:"</p>
+        <code class="code-block">"cargo install uiua --features full"</code>
         <p>"If you want to enable audio output, enable the "<code>"audio"</code>" feature:"</p>
         <code class="code-block">"cargo install uiua --features audio"</code>
         <p>"If you want audio on Linux, you may need to install some dependencies first:"</p>
@@ -124,10 +126,31 @@ pub fn Install() -> impl IntoView {
         <p>"Running just "<code>"uiua"</code>" will display the help message if there are no "<code>".ua"</code>" files in the directory."</p>
         <p>"You can initialize a "<code>"main.ua"</code>" with "<code>"uiua init"</code>"."</p>
         <p>"Once a "<code>".ua"</code>" file exists, running "<code>"uiua"</code>" will begin watching the directory for changes. If you edit and save a "<code>".ua"</code>" file, the interpreter will automatically format and run it."</p>
+        <p>"Use "<code>"uiua watch <path>"</code>" to watch a specific file. It is run again when it or any file it imports changes, and any changes to its output are shown."</p>
         <p>"You should configure you editor so that it automatically reloads files if they change on disk. This will allow you to see the formatted file as soon as it is saved."</p>
         <p>"Use "<code>"uiua run"</code>" to format and run a file without watching it."</p>
+        <p>"Use "<code>"uiua run -e <code>"</code>" to run a string of code and print the resulting stack, like in a shell pipeline."</p>
+        <p>"Use "<code>"uiua run -"</code>" to read the program from standard input. Add "<code>"--input <path>"</code>" to give the program input from a file."</p>
+        <p>"Use "<code>"uiua run --debug"</code>" to stop at each "<code>"breakpoint"</code>" in the program. At the prompt, you can show and change the stack, step through instructions one at a time, and continue or abort. Type "<code>"help"</code>" to see the commands."</p>
+        <p>"Use "<code>"uiua run --profile"</code>" to see how much time was spent in each primitive and binding, and how many times each was called. The slowest are listed first."</p>
+        <p>"Use "<code>"uiua run --timeline <path>"</code>" to write a timeline of every call to a file. If the path ends in "<code>".json"</code>", it is written in the Chrome tracing format, which can be opened in "<code>"chrome://tracing"</code>", Perfetto, or Speedscope. Otherwise, it is written as folded stacks, which flamegraph tools like "<code>"inferno"</code>" can turn into a flamegraph."</p>
+        <p>"Use "<code>"uiua run --time-limit <seconds>"</code>" or "<code>"uiua run --max-instructions <count>"</code>" to stop a program that runs for too long. The instruction limit stops a program at the same point every time."</p>
+        <p>"Use "<code>"uiua run --seed <seed>"</code>" or "<code>"uiua test --seed <seed>"</code>" to make a program produce the same output every run. Random numbers come from the seed, "<code>"now"</code>" reads a virtual clock that only advances when "<code>"sleep"</code>" is called, and spawned threads run as soon as they are spawned."</p>
+        <p>"Use "<code>"uiua run --sandbox"</code>" to run code you do not trust. System functions that access files, the network, or environment variables fail with an error instead, which can be caught with "<code>"try"</code>"."</p>
+        <p>"Use "<code>"--allow"</code>" to grant a sandboxed program only the access it needs, like "<code>"uiua run --allow read-files,network"</code>". The capabilities are "<code>"read-files"</code>", "<code>"write-files"</code>", "<code>"network"</code>", "<code>"subprocess"</code>", "<code>"audio"</code>", "<code>"environment"</code>", "<code>"clipboard"</code>", and "<code>"display"</code>"."</p>
         <p>"Use "<code>"uiua fmt"</code>" to format a file without running it."</p>
+        <p>"Use "<code>"uiua fmt --check"</code>" to see the changes formatting would make without making them. It fails if any file is not formatted."</p>
+        <p>"Use "<code>"uiua fmt --stdin"</code>" to format code from standard input and write it to standard output. Errors are written to standard error. This is useful for editor plugins."</p>
+        <p>"The formatter can be configured with a "<code>".fmt.ua"</code>" file in your project's directory. It is Uiua code that binds the options "<code>"TrailingNewline"</code>", "<code>"CommentSpaceAfterHash"</code>", "<code>"MultilineIndent"</code>", "<code>"CompactMultiline"</code>", "<code>"MultilineCompactThreshold"</code>", "<code>"MigrateDeprecated"</code>", "<code>"Ascii"</code>", and "<code>"AlignComments"</code>". For example, "<code>"MultilineIndent ← 4"</code>". The config is run in a sandbox, so it cannot access files or the network."</p>
+        <p>"Use "<code>"uiua fmt --ascii"</code>" to write glyphs with their ASCII spellings instead. This is useful if your font does not support the glyphs."</p>
         <p>"Use "<code>"uiua test"</code>" to run tests."</p>
+        <p>"Use "<code>"uiua doc <path>"</code>" to generate Markdown documentation for the bindings in a file, using their signatures and the comments above them. Without a path, it documents the primitives. Add "<code>"--html"</code>" to generate an HTML page instead."</p>
+        <p>"Use "<code>"uiua build"</code>" to make an executable that runs a file. It can be run on machines that do not have Uiua installed. The program is compiled ahead of time unless it must be run to be compiled, like when it imports files with "<code>"&i"</code>". Then its source and the files it imports are included in the executable instead."</p>
+        <p>"Use "<code>"uiua repl"</code>" to run code interactively. Press tab to turn a glyph's name into the glyph. Lines with unclosed brackets continue on the next line. Use "<code>":help"</code>" to see commands for inspecting the stack and bindings."</p>
+        <p>"Use "<code>":save <path>"</code>" to save the stack, bindings, and random number state of a REPL session to a file, and "<code>":load <path>"</code>" to restore it later."</p>
+        <p>"Use "<code>"uiua primitives --json"</code>" to get the glyph, ASCII spelling, class, argument and output counts, and description of every primitive. This is useful for editor plugins and other tools."</p>
+        <p>"Errors are shown with a code, like "<code>"E0012"</code>". Use "<code>"uiua explain E0012"</code>" to get a longer description of the error with examples."</p>
+        <p>"Use "<code>"uiua highlight"</code>" to print a file with syntax highlighting. Add "<code>"--html"</code>" to get HTML spans that can be styled with this site's stylesheet."</p>
 
         <h2>"Font"</h2>
         <p>"The Uiua glyphs were chosen to be compatible specifically with "<a href="https://dejavu-fonts.github.io/Download.html">"DejaVu Sans Mono"</a>". It is recommended that you install this font to get the best experience with Uiua."</p>
@@ -206,6 +229,7 @@ s ← ○×τ⊞×f
         <p>"Instead, you must use the "<Prim prim=Sys(SysOp::AudioPlay)/>" system function to play it."</p>
         <p><Prim prim=Sys(SysOp::AudioPlay)/>" should fine on the website as well, but it is not necessary."</p>
         <Editor example="&ap÷2×¬◿1×4∶±○×τ×55.÷∶⇡×2. &asr"/>
+        <p>"To play audio at a different sample rate, use "<Prim prim=Sys(SysOp::AudioPlayRate)/>". To generate audio as it plays, use "<Prim prim=Sys(SysOp::AudioStream)/>"."</p>
     }
 }
 
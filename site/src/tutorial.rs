@@ -167,6 +167,8 @@ fn TutorialBasic() -> impl IntoView {
         </table>
         <p>"As noted in the table, negative number literals are typed with the "<code>"`"</code>" character. This is because "<code>"-"</code>" is used for subtraction."</p>
         <Editor example="+ `1 `2"/>
+        <p>"Integers can also be written in hexadecimal with "<code>"0x"</code>" or in binary with "<code>"0b"</code>". The formatter leaves them as they are written."</p>
+        <Editor example="[0xff 0b101 ¯0x10]"/>
 
         <h2 id="stack-functions">"Stack Functions"</h2>
         <p>"There are a few functions that work on the stack itself. Some of these are critical and can be found scattered across all Uiua code."</p>
@@ -437,6 +439,10 @@ fn TutorialTypes() -> impl IntoView {
         <Editor example="&p $ Hello, \n   $ World!"/>
         <p>"This style of string is also useful when your string contains a lot of quotes that you don't want to escape."</p>
         <Editor example="$ An then she was like, \"No way!\"\n$ And I was like, \"Way...\""/>
+        <p>"Lines that start with "<code>"$$ "</code>" are raw strings. They have no escapes or placeholders, which is useful for things like regexes and JSON. Consecutive lines are joined with newlines."</p>
+        <Editor example="$$ {\"name\": \"Uiua\", \"path\": \"C:\\uiua\"}"/>
+        <p>"Lines that start with "<code>"$| "</code>" are grid strings. Each line becomes a row of a rank-2 character array, padded with spaces to the longest line."</p>
+        <Editor example="△. $| /\\\n   $| \\/ \n   $| ||"/>
         <br/>
 
         <h2 id="character-arithmetic">"Character Arithmetic"</h2>
@@ -535,6 +541,15 @@ X ← (
   ↥⇌.
 )
 X 5"/>
+        <p>"A line in an inline function can bind a value to a name with "<code>"←"</code>". The binding pops the value its code leaves on top of the stack, and the name can be used in the rest of the function. If there is no code after the "<code>"←"</code>", the name is bound to the function's argument."</p>
+        <Editor example="\
+Hyp ← (
+  a ←
+  b ←
+  √+×a a×b b
+)
+Hyp 3 4"/>
+        <p>"Local names cannot be used in functions nested inside the one that binds them."</p>
 
         <h2 id="format-strings">"Format Strings"</h2>
         <p>"Prefixing a string with "<code>"$"</code>", creates a format string. A format string is a function that is called immediately. It takes an argument for each "<code>"_"</code>" in the string and replaces it with the stringified version."</p>
@@ -572,6 +587,12 @@ f(↥)"/>
         <Editor example="∺(|1 ⊞^∶,)+_-⇡3"/>
         <p>"In addition, an error is thrown if a function's signature can be inferred and the inferred signature does not match the declared signature. This can help validate that a function is correct."</p>
         <Editor example="≡(|2 ↻.) 1_2_3 ↯3_3⇡9"/> // Should fail
+
+        <h2 id="macros">"Macros"</h2>
+        <p>"A binding whose name ends in "<code>"!"</code>" is a "<em>"macro"</em>". Macros take operands like modifiers, and their code is copied wherever they are used."</p>
+        <p>"In a macro, "<code>"^0"</code>", "<code>"^1"</code>", and so on are replaced with the macro's operands. A macro takes as many operands as its highest placeholder needs."</p>
+        <Editor example="Twice! ← ^0^0\nTwice!(×2) 5\nTwice!⇌ [1 2 3]"/>
+        <p>"Names in a macro refer to the bindings that existed where the macro was defined, so using a macro can never accidentally refer to your own bindings."</p>
     }
 }
 
@@ -695,6 +716,9 @@ double ← use "Double".
 increment ← use "Increment"
 
 increment square double 5"#/>
+        <p>"If you bind the imported module to a name, you can refer to its functions directly with "<code>"Module~Name"</code>". Names like this are looked up when the code is compiled, so a misspelled name is an error even if the code never runs."</p>
+        <Editor example=r#"Ex ← &i "example.ua"
+    Ex~Increment Ex~Square Ex~Double 5"#/>
         <p><Prim prim=Sys(SysOp::Import)/>" only imports a given file once and caches the results. Subsequent imports of the same file (from anywhere) will not run the file's code again, but they "<em>"will"</em>" push its stack values again."</p>
         <p>"In this example, we make some code that prints a message and then generates a random number. We then write the code to a file and import it 3 times. Notice that the message is only printed once, and the same number is returned every time."</p>
         <Editor example="\
@@ -731,5 +755,7 @@ fn TutorialTesting() -> impl IntoView {
         <p><code>"uiua watch"</code>" will run all code, including tests."</p>
         <p><code>"uiua run"</code>" will only run non-test code."</p>
         <p><code>"uiua test"</code>" will only run test code, but also any non-test bindings and any non-test code which makes imports."</p>
+        <p>"Each line in a test scope is a test. When a line fails, "<code>"uiua test"</code>" reports the error and continues with the next line. At the end, it prints how many tests passed and failed, and exits with an error code if any failed."</p>
+        <p>"Given a directory, or nothing, "<code>"uiua test"</code>" tests every "<code>".ua"</code>" file in the directory and its subdirectories, except hidden directories and directories listed in a "<code>".gitignore"</code>"."</p>
     }
 }
@@ -1,6 +1,12 @@
 //! Algorithms for dyadic array operations
 
-use std::{cmp::Ordering, iter::repeat, mem::take, sync::Arc};
+use std::{
+    cmp::Ordering,
+    collections::HashMap,
+    iter::{repeat, repeat_n},
+    mem::take,
+    sync::Arc,
+};
 
 use tinyvec::tiny_vec;
 
@@ -90,7 +96,7 @@ impl<T: ArrayValue> Array<T> {
         }
         let target_size = shape.iter().product();
         let mut new_data = vec![fill_value; target_size];
-        let mut curr = vec![0; shape.len()];
+        let mut curr = zero_index(shape.len());
         for new_data_index in 0..target_size {
             data_index_to_shape_index(new_data_index, shape, &mut curr);
             if let Some(data_index) = shape_index_to_data_index(&curr, &self.shape) {
@@ -104,6 +110,9 @@ impl<T: ArrayValue> Array<T> {
 
 impl Value {
     pub fn join(self, other: Self, env: &Uiua) -> UiuaResult<Self> {
+        let elements = self.flat_len().saturating_add(other.flat_len());
+        let element_size = self.element_size().max(other.element_size());
+        env.check_memory(elements.saturating_mul(element_size))?;
         self.join_impl(other, env)
     }
     pub fn join_infallible(self, other: Self) -> Self {
@@ -431,6 +440,8 @@ impl<T: ArrayValue> Array<T> {
 impl Value {
     pub fn reshape(&mut self, shape: &Self, env: &Uiua) -> UiuaResult {
         if let Ok(n) = shape.as_nat(env, "") {
+            let elements = n.saturating_mul(self.flat_len());
+            env.check_memory(elements.saturating_mul(self.element_size()))?;
             match self {
                 Value::Num(a) => a.reshape_scalar(n),
                 Value::Byte(a) => a.reshape_scalar(n),
@@ -444,6 +455,12 @@ impl Value {
                 or a list of natural numbers",
             )?;
             let target_shape = Shape::from(&*target_shape);
+            if self.flat_len() > 0 {
+                let elements = target_shape
+                    .iter()
+                    .fold(1usize, |acc, &n| acc.saturating_mul(n));
+                env.check_memory(elements.saturating_mul(self.element_size()))?;
+            }
             match self {
                 Value::Num(a) => a.reshape(target_shape),
                 Value::Byte(a) => a.reshape(target_shape),
@@ -457,17 +474,18 @@ impl Value {
 
 impl<T: ArrayValue> Array<T> {
     pub fn reshape_scalar(&mut self, count: usize) {
-        self.data.modify(|data| {
-            if count == 0 {
-                data.clear();
-                return;
-            }
-            data.reserve(count * data.len());
-            let row = data.clone();
-            for _ in 1..count {
-                data.extend_from_slice(&row);
-            }
-        });
+        // Zero or one copies can use the existing buffer, even if it is shared
+        match count {
+            0 => self.data.truncate(0),
+            1 => {}
+            _ => self.data.modify(|data| {
+                data.reserve(count * data.len());
+                let row = data.clone();
+                for _ in 1..count {
+                    data.extend_from_slice(&row);
+                }
+            }),
+        }
         self.shape.insert(0, count);
     }
     pub fn reshape(&mut self, shape: Shape) {
@@ -490,8 +508,40 @@ impl<T: ArrayValue> Array<T> {
     }
 }
 
+#[test]
+fn reshape_shares_buffer_test() {
+    let arr = Array::new(tiny_vec![3, 2], vec![0.0, 1.0, 2.0, 3.0, 4.0, 5.0]);
+    let ptr = arr.data.as_ptr();
+
+    let mut reshaped = arr.clone();
+    reshaped.reshape(tiny_vec![2, 3]);
+    assert_eq!(reshaped.shape(), [2, 3]);
+    assert_eq!(reshaped.data.as_ptr(), ptr);
+
+    let mut shrunk = arr.clone();
+    shrunk.reshape(tiny_vec![4]);
+    assert_eq!(*shrunk.data, [0.0, 1.0, 2.0, 3.0]);
+    assert_eq!(shrunk.data.as_ptr(), ptr);
+
+    let mut single = arr;
+    single.reshape_scalar(1);
+    assert_eq!(single.shape(), [1, 3, 2]);
+    assert_eq!(single.data.as_ptr(), ptr);
+}
+
 impl Value {
     pub fn keep(&self, kept: Self, env: &Uiua) -> UiuaResult<Self> {
+        // Boolean masks get a faster kernel
+        if let Value::Byte(mask) = self {
+            if mask.rank() == 1 && kept.rank() > 0 && mask.data.iter().all(|&b| b <= 1) {
+                return Ok(match kept {
+                    Value::Num(a) => a.mask_keep(&mask.data, env)?.into(),
+                    Value::Byte(a) => a.mask_keep(&mask.data, env)?.into(),
+                    Value::Char(a) => a.mask_keep(&mask.data, env)?.into(),
+                    Value::Func(a) => a.mask_keep(&mask.data, env)?.into(),
+                });
+            }
+        }
         let amount = self.as_naturals(
             env,
             "Keep amount must be a natural number \
@@ -552,6 +602,29 @@ impl<T: ArrayValue> Array<T> {
         self.validate_shape();
         self
     }
+    pub fn mask_keep(mut self, mask: &[u8], env: &Uiua) -> UiuaResult<Self> {
+        if self.row_count() != mask.len() {
+            return Err(env.error(format!(
+                "Cannot keep array with shape {} with array of length {}",
+                self.format_shape(),
+                mask.len()
+            )));
+        }
+        let kept_count = mask.iter().filter(|&&b| b == 1).count();
+        if kept_count == mask.len() {
+            return Ok(self);
+        }
+        let mut new_data = Vec::with_capacity(kept_count * self.row_len());
+        for (row, &b) in self.row_slices().zip(mask) {
+            if b == 1 {
+                new_data.extend_from_slice(row);
+            }
+        }
+        self.data = new_data.into();
+        self.shape[0] = kept_count;
+        self.validate_shape();
+        Ok(self)
+    }
     pub fn list_keep(mut self, amount: &[usize], env: &Uiua) -> UiuaResult<Self> {
         if self.row_count() != amount.len() {
             return Err(env.error(format!(
@@ -817,6 +890,16 @@ impl<T: ArrayValue> Array<T> {
     pub fn take(mut self, index: &[isize], env: &Uiua) -> UiuaResult<Self> {
         Ok(match index {
             [] => self,
+            // Taking within bounds shares the buffer
+            &[taking] if self.rank() > 0 && taking.unsigned_abs() <= self.row_count() => {
+                let row_count = self.row_count();
+                let abs_taking = taking.unsigned_abs();
+                if taking >= 0 {
+                    self.slice_rows(0, abs_taking)
+                } else {
+                    self.slice_rows(row_count - abs_taking, row_count)
+                }
+            }
             &[taking] => {
                 let row_len = self.row_len();
                 let row_count = self.row_count();
@@ -963,6 +1046,16 @@ impl<T: ArrayValue> Array<T> {
     pub fn drop(mut self, index: &[isize], env: &Uiua) -> UiuaResult<Self> {
         Ok(match index {
             [] => self,
+            // Dropping from a non-scalar shares the buffer
+            &[dropping] if self.rank() > 0 => {
+                let row_count = self.row_count();
+                let abs_dropping = dropping.unsigned_abs().min(row_count);
+                if dropping >= 0 {
+                    self.slice_rows(abs_dropping, row_count)
+                } else {
+                    self.slice_rows(0, row_count - abs_dropping)
+                }
+            }
             &[dropping] => {
                 let row_len = self.row_len();
                 let row_count = self.row_count();
@@ -1268,6 +1361,22 @@ impl<T: ArrayValue> Array<T> {
             Ok(res)
         }
     }
+    /// Get the range of rows that some indices select, if they are in bounds and consecutive
+    fn contiguous_rows(&self, indices: &[isize]) -> Option<(usize, usize)> {
+        if self.rank() == 0 || indices.is_empty() {
+            return None;
+        }
+        let row_count = self.row_count() as isize;
+        let normalize = |i: isize| if i < 0 { row_count + i } else { i };
+        let start = normalize(indices[0]);
+        for (offset, &i) in indices.iter().enumerate() {
+            let i = normalize(i);
+            if i < 0 || i >= row_count || i != start + offset as isize {
+                return None;
+            }
+        }
+        Some((start as usize, start as usize + indices.len()))
+    }
     fn unselect_impl(
         &self,
         indices_shape: &[usize],
@@ -1282,6 +1391,9 @@ impl<T: ArrayValue> Array<T> {
         }
     }
     fn select(&self, indices: &[isize], env: &Uiua) -> UiuaResult<Self> {
+        if let Some((start, end)) = self.contiguous_rows(indices) {
+            return Ok(self.slice_rows(start, end));
+        }
         let mut selected = Vec::with_capacity(self.row_len() * indices.len());
         let row_len = self.row_len();
         let row_count = self.row_count();
@@ -1374,6 +1486,29 @@ impl<T: ArrayValue> Array<T> {
     }
 }
 
+#[test]
+fn slicing_shares_buffer_test() {
+    let env = Uiua::with_native_sys();
+    let arr = Array::new(tiny_vec![3, 2], vec![0.0, 1.0, 2.0, 3.0, 4.0, 5.0]);
+    let ptr = arr.data.as_ptr();
+
+    let taken = arr.clone().take(&[-2], &env).unwrap();
+    assert_eq!(taken.shape(), [2, 2]);
+    assert_eq!(taken.data.as_ptr(), ptr.wrapping_add(2));
+
+    let dropped = arr.clone().drop(&[1], &env).unwrap();
+    assert_eq!(*dropped.data, [2.0, 3.0, 4.0, 5.0]);
+    assert_eq!(dropped.data.as_ptr(), ptr.wrapping_add(2));
+
+    let selected = arr.select(&[-2, -1], &env).unwrap();
+    assert_eq!(*selected.data, [2.0, 3.0, 4.0, 5.0]);
+    assert_eq!(selected.data.as_ptr(), ptr.wrapping_add(2));
+
+    let selected = arr.select(&[2, 1], &env).unwrap();
+    assert_eq!(*selected.data, [4.0, 5.0, 2.0, 3.0]);
+    assert_ne!(selected.data.as_ptr(), ptr.wrapping_add(4));
+}
+
 impl Value {
     pub fn windows(&self, from: &Self, env: &Uiua) -> UiuaResult<Self> {
         let size_spec = self.as_naturals(env, "Window size must be a list of natural numbers")?;
@@ -1408,15 +1543,15 @@ impl<T: ArrayValue> Array<T> {
         new_shape.extend_from_slice(size_spec);
         new_shape.extend_from_slice(&self.shape[size_spec.len()..]);
         // Make a new window shape with the same rank as the windowed array
-        let mut true_size: Vec<usize> = Vec::with_capacity(self.shape.len());
-        true_size.extend(size_spec);
+        let mut true_size = Shape::with_capacity(self.shape.len());
+        true_size.extend_from_slice(size_spec);
         if true_size.len() < self.shape.len() {
-            true_size.extend(&self.shape[true_size.len()..]);
+            true_size.extend_from_slice(&self.shape[true_size.len()..]);
         }
 
         let mut dst = Vec::new();
-        let mut corner = vec![0; self.shape.len()];
-        let mut curr = vec![0; self.shape.len()];
+        let mut corner = zero_index(self.shape.len());
+        let mut curr = zero_index(self.shape.len());
         'windows: loop {
             // Reset curr
             for i in curr.iter_mut() {
@@ -1502,8 +1637,8 @@ impl<T: ArrayValue> Array<T> {
             .collect();
 
         let mut data = Vec::new();
-        let mut corner = vec![0; searched.shape.len()];
-        let mut curr = vec![0; searched.shape.len()];
+        let mut corner = zero_index(searched.shape.len());
+        let mut curr = zero_index(searched.shape.len());
 
         'windows: loop {
             // Reset curr
@@ -1567,10 +1702,10 @@ impl<T: ArrayValue> Array<T> {
 impl Value {
     pub fn member(&self, of: &Self, env: &Uiua) -> UiuaResult<Self> {
         Ok(match (self, of) {
-            (Value::Num(a), Value::Num(b)) => a.member(b, env)?.into(),
-            (Value::Byte(a), Value::Byte(b)) => a.member(b, env)?.into(),
-            (Value::Char(a), Value::Char(b)) => a.member(b, env)?.into(),
-            (Value::Func(a), Value::Func(b)) => a.member(b, env)?.into(),
+            (Value::Num(a), Value::Num(b)) => a.member_same_type(b, env)?.into(),
+            (Value::Byte(a), Value::Byte(b)) => a.member_same_type(b, env)?.into(),
+            (Value::Char(a), Value::Char(b)) => a.member_same_type(b, env)?.into(),
+            (Value::Func(a), Value::Func(b)) => a.member_same_type(b, env)?.into(),
             (Value::Num(a), Value::Byte(b)) => a.member(b, env)?.into(),
             (Value::Byte(a), Value::Num(b)) => a.member(b, env)?.into(),
             (a, b) => {
@@ -1584,7 +1719,35 @@ impl Value {
     }
 }
 
+/// The number of row comparisons above which searching uses a hashed row index
+const HASH_INDEX_MIN_WORK: usize = 1 << 12;
+
 impl<T: ArrayValue> Array<T> {
+    /// Check whether searching for rows of `needles` in this array is worth building a row index
+    fn should_hash_rows(&self, needles: &Self) -> bool {
+        self.rank() > 0
+            && self.rank() == needles.rank()
+            && self.row_count().saturating_mul(needles.row_count()) > HASH_INDEX_MIN_WORK
+    }
+    /// Map each distinct row to the index of its first occurrence
+    fn row_index(&self) -> HashMap<Self, usize> {
+        let mut index = HashMap::with_capacity(self.row_count());
+        for (i, row) in self.rows().enumerate() {
+            index.entry(row).or_insert(i);
+        }
+        index
+    }
+    fn member_same_type(&self, of: &Self, env: &Uiua) -> UiuaResult<Array<u8>> {
+        if of.should_hash_rows(self) {
+            let index = of.row_index();
+            let data: Vec<u8> = self
+                .rows()
+                .map(|row| index.contains_key(&row) as u8)
+                .collect();
+            return Ok(data.into());
+        }
+        self.member(of, env)
+    }
     pub fn member<U>(&self, of: &Array<U>, env: &Uiua) -> UiuaResult<Array<u8>>
     where
         T: ArrayCmp<U>,
@@ -1664,6 +1827,15 @@ impl Value {
 impl<T: ArrayValue> Array<T> {
     fn index_of(&self, searched_in: &Array<T>, env: &Uiua) -> UiuaResult<Array<f64>> {
         let searched_for = self;
+        if searched_in.should_hash_rows(searched_for) {
+            let index = searched_in.row_index();
+            let not_found = searched_in.row_count();
+            let data: Vec<f64> = searched_for
+                .rows()
+                .map(|row| index.get(&row).copied().unwrap_or(not_found) as f64)
+                .collect();
+            return Ok(data.into());
+        }
         Ok(match searched_for.rank().cmp(&searched_in.rank()) {
             Ordering::Equal => {
                 let mut result_data = Vec::with_capacity(searched_for.row_count());
@@ -1731,3 +1903,237 @@ impl<T: ArrayValue> Array<T> {
         })
     }
 }
+
+impl Value {
+    pub fn format_num(&self, nums: &Self, env: &Uiua) -> UiuaResult<Self> {
+        let spec = self.as_string(env, "Number format must be a string")?;
+        let spec = NumFormat::parse(&spec).map_err(|e| env.error(e))?;
+        Ok(match nums {
+            Value::Num(arr) => arr.format_num(&spec, env)?.into(),
+            Value::Byte(arr) => arr.convert_ref().format_num(&spec, env)?.into(),
+            v => return Err(env.error(format!("Cannot format {} array as numbers", v.type_name()))),
+        })
+    }
+}
+
+impl Array<f64> {
+    pub fn format_num(&self, spec: &NumFormat, env: &Uiua) -> UiuaResult<Array<char>> {
+        let mut strings = Vec::with_capacity(self.data.len());
+        for &n in &self.data {
+            strings.push(spec.format(n).map_err(|e| env.error(e))?);
+        }
+        let len = strings
+            .iter()
+            .map(|s| s.chars().count())
+            .max()
+            .unwrap_or(0)
+            .max(spec.width);
+        let mut data = Vec::with_capacity(strings.len() * len);
+        for s in &strings {
+            spec.pad(s, len, &mut data);
+        }
+        let mut shape = self.shape.clone();
+        shape.push(len);
+        let arr = Array::new(shape, data);
+        arr.validate_shape();
+        Ok(arr)
+    }
+}
+
+/// A parsed specifier for the `format` primitive
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NumFormat {
+    fill: char,
+    align: NumAlign,
+    width: usize,
+    precision: Option<usize>,
+    base: u32,
+    upper: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum NumAlign {
+    Left,
+    Right,
+    Center,
+}
+
+impl NumAlign {
+    fn from_char(c: char) -> Option<Self> {
+        Some(match c {
+            '<' => NumAlign::Left,
+            '>' => NumAlign::Right,
+            '^' => NumAlign::Center,
+            _ => return None,
+        })
+    }
+}
+
+impl NumFormat {
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let invalid = || format!("Invalid number format {spec:?}");
+        let chars: Vec<char> = spec.chars().collect();
+        let mut format = NumFormat {
+            fill: ' ',
+            align: NumAlign::Right,
+            width: 0,
+            precision: None,
+            base: 10,
+            upper: false,
+        };
+        // Fill and alignment
+        let mut i = 0;
+        if let Some(align) = chars.get(1).copied().and_then(NumAlign::from_char) {
+            format.fill = chars[0];
+            format.align = align;
+            i = 2;
+        } else if let Some(align) = chars.first().copied().and_then(NumAlign::from_char) {
+            format.align = align;
+            i = 1;
+        } else if chars.first() == Some(&'0') && chars.get(1).is_some_and(char::is_ascii_digit) {
+            format.fill = '0';
+            i = 1;
+        }
+        // Width
+        let start = i;
+        while chars.get(i).is_some_and(char::is_ascii_digit) {
+            i += 1;
+        }
+        if i > start {
+            let width: String = chars[start..i].iter().collect();
+            format.width = width.parse().map_err(|_| invalid())?;
+        }
+        // Precision
+        if chars.get(i) == Some(&'.') {
+            i += 1;
+            let start = i;
+            while chars.get(i).is_some_and(char::is_ascii_digit) {
+                i += 1;
+            }
+            if i == start {
+                return Err(invalid());
+            }
+            let precision: String = chars[start..i].iter().collect();
+            format.precision = Some(precision.parse().map_err(|_| invalid())?);
+        }
+        // Base
+        if let Some(&c) = chars.get(i) {
+            format.base = match c {
+                'b' => 2,
+                'o' => 8,
+                'd' => 10,
+                'x' | 'X' => 16,
+                _ => return Err(invalid()),
+            };
+            format.upper = c == 'X';
+            i += 1;
+        }
+        if i != chars.len() {
+            return Err(invalid());
+        }
+        Ok(format)
+    }
+    fn format(&self, n: f64) -> Result<String, String> {
+        if n.is_nan() {
+            return Ok("NaN".into());
+        }
+        let sign = if n < 0.0 { "-" } else { "" };
+        let abs = n.abs();
+        if abs.is_infinite() {
+            return Ok(format!("{sign}∞"));
+        }
+        let body = if self.base == 10 {
+            if let Some(precision) = self.precision {
+                format!("{abs:.precision$}")
+            } else {
+                abs.to_string()
+            }
+        } else {
+            if self.precision.is_none() && abs.fract() != 0.0 {
+                return Err(format!(
+                    "Cannot format {n} in base {} without a precision",
+                    self.base
+                ));
+            }
+            let base = self.base as f64;
+            let mut int = abs.trunc();
+            let mut digits = Vec::new();
+            loop {
+                digits.push(self.digit(int % base));
+                int = (int / base).trunc();
+                if int == 0.0 {
+                    break;
+                }
+            }
+            let mut s: String = digits.into_iter().rev().collect();
+            if let Some(precision) = self.precision.filter(|&p| p > 0) {
+                s.push('.');
+                let mut frac = abs.fract();
+                for _ in 0..precision {
+                    frac *= base;
+                    let d = frac.trunc();
+                    s.push(self.digit(d));
+                    frac -= d;
+                }
+            }
+            s
+        };
+        Ok(format!("{sign}{body}"))
+    }
+    fn digit(&self, d: f64) -> char {
+        let c = char::from_digit(d as u32, self.base).unwrap_or('?');
+        if self.upper {
+            c.to_ascii_uppercase()
+        } else {
+            c
+        }
+    }
+    fn pad(&self, s: &str, len: usize, out: &mut Vec<char>) {
+        let diff = len - s.chars().count();
+        match self.align {
+            NumAlign::Right if self.fill == '0' && s.starts_with('-') => {
+                out.push('-');
+                out.extend(repeat_n('0', diff));
+                out.extend(s.chars().skip(1));
+            }
+            NumAlign::Right => {
+                out.extend(repeat_n(self.fill, diff));
+                out.extend(s.chars());
+            }
+            NumAlign::Left => {
+                out.extend(s.chars());
+                out.extend(repeat_n(self.fill, diff));
+            }
+            NumAlign::Center => {
+                let before = diff / 2;
+                out.extend(repeat_n(self.fill, before));
+                out.extend(s.chars());
+                out.extend(repeat_n(self.fill, diff - before));
+            }
+        }
+    }
+}
+
+#[test]
+fn num_format_test() {
+    for (spec, n, expected) in [
+        ("", 5.0, "5"),
+        ("8.3", 1.23456, "   1.235"),
+        ("*^7.1", 2.0, "**2.0**"),
+        ("<4", 1.0, "1   "),
+        ("05", -7.0, "-0007"),
+        ("x", 255.0, "ff"),
+        ("X", 255.0, "FF"),
+        ("08b", 5.0, "00000101"),
+        (".2b", 0.75, "0.11"),
+    ] {
+        let format = NumFormat::parse(spec).unwrap();
+        let s = format.format(n).unwrap();
+        let mut padded = Vec::new();
+        format.pad(&s, s.chars().count().max(format.width), &mut padded);
+        assert_eq!(padded.into_iter().collect::<String>(), expected);
+    }
+    assert!(NumFormat::parse("8.").is_err());
+    assert!(NumFormat::parse("z").is_err());
+    assert!(NumFormat::parse("b").unwrap().format(0.5).is_err());
+}
@@ -0,0 +1,96 @@
+//! Kernels for instructions fused from common sequences at compile time
+
+use std::ops::Add;
+
+use crate::{
+    algorithm::loops::{fast_reduce, sum_list},
+    run::ArrayArg,
+    value::Value,
+    Uiua, UiuaResult,
+};
+
+/// Reduce add multiply
+///
+/// Lists of equal length are summed without allocating their product.
+pub fn dot(env: &mut Uiua) -> UiuaResult {
+    crate::profile_function!();
+    let a = env.pop(ArrayArg(1))?;
+    let b = env.pop(ArrayArg(2))?;
+    let list_sum = match (&a, &b) {
+        (Value::Num(a), Value::Num(b)) if a.rank() == 1 && a.shape == b.shape => {
+            let (a, b) = (&a.data, &b.data);
+            Some(sum_list(a.len(), |i| b[i] * a[i], env))
+        }
+        (Value::Num(a), Value::Byte(b)) if a.rank() == 1 && a.shape == b.shape => {
+            let (a, b) = (&a.data, &b.data);
+            Some(sum_list(a.len(), |i| f64::from(b[i]) * a[i], env))
+        }
+        (Value::Byte(a), Value::Num(b)) if a.rank() == 1 && a.shape == b.shape => {
+            let (a, b) = (&a.data, &b.data);
+            Some(sum_list(a.len(), |i| b[i] * f64::from(a[i]), env))
+        }
+        (Value::Byte(a), Value::Byte(b)) if a.rank() == 1 && a.shape == b.shape => {
+            let (a, b) = (&a.data, &b.data);
+            Some(sum_list(
+                a.len(),
+                |i| f64::from(b[i]) * f64::from(a[i]),
+                env,
+            ))
+        }
+        _ => None,
+    };
+    if let Some(sum) = list_sum {
+        env.push(sum);
+        return Ok(());
+    }
+    match a.mul_in_place(b, env)? {
+        Value::Num(nums) => env.push(fast_reduce(nums, 0.0, Add::add)),
+        Value::Byte(bytes) => env.push(fast_reduce(bytes, 0.0, |a, b| a + f64::from(b))),
+        product => unreachable!("Multiplication produced a {} array", product.type_name()),
+    }
+    Ok(())
+}
+
+/// Keep with a mask produced by a comparison
+///
+/// When a list is compared with a scalar and the list being kept is a list
+/// of the same length, rows are copied without building the mask.
+pub fn keep_cmp(
+    env: &mut Uiua,
+    cmp: fn(&Value, &Value, &Uiua) -> UiuaResult<Value>,
+    num_num: fn(f64, f64) -> u8,
+) -> UiuaResult {
+    crate::profile_function!();
+    let a = env.pop(ArrayArg(1))?;
+    let b = env.pop(ArrayArg(2))?;
+    let kept = env.pop(ArrayArg(3))?;
+    if let (Value::Num(a), Value::Num(b), Value::Num(kept)) = (&a, &b, &kept) {
+        let filtered: Option<Vec<f64>> = match (a.rank(), b.rank()) {
+            (0, 1) if b.shape == kept.shape => {
+                let a = a.data[0];
+                Some(filter(&kept.data, b.data.iter().map(|&b| num_num(a, b))))
+            }
+            (1, 0) if a.shape == kept.shape => {
+                let b = b.data[0];
+                Some(filter(&kept.data, a.data.iter().map(|&a| num_num(a, b))))
+            }
+            _ => None,
+        };
+        if let Some(filtered) = filtered {
+            env.push(filtered);
+            return Ok(());
+        }
+    }
+    let mask = cmp(&a, &b, env)?;
+    env.push(mask.keep(kept, env)?);
+    Ok(())
+}
+
+fn filter(items: &[f64], mask: impl Iterator<Item = u8>) -> Vec<f64> {
+    items
+        .iter()
+        .zip(mask)
+        .filter(|(_, keep)| *keep == 1)
+        .map(|(item, _)| *item)
+        .collect()
+}
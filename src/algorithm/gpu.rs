@@ -0,0 +1,344 @@
+//! Offloading of large array operations to the GPU
+//!
+//! This is experimental. Values are computed as 64-bit floats if the GPU
+//! supports them, so results match the interpreter's. GPUs without 64-bit
+//! floats are only used if single precision is allowed with
+//! [`Uiua::with_gpu_single_precision`], because they give different results.
+//! Operations that are not supported, arrays below the size threshold,
+//! and systems without a usable GPU all fall back to the interpreter.
+//!
+//! Pervasive arithmetic and sums are offloaded. There is no matrix multiply
+//! primitive, so matrix products written with [`Primitive::Table`] are not.
+
+use std::{
+    collections::HashMap,
+    sync::{mpsc, Arc},
+};
+
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use wgpu::util::DeviceExt;
+
+use crate::{array::Array, primitive::Primitive, run::ArrayArg, value::Value, Uiua, UiuaResult};
+
+/// The minimum number of elements for which the transfer to the GPU is worth it
+const MIN_LEN: usize = 1 << 18;
+const WORKGROUP_SIZE: u32 = 256;
+/// The maximum number of workgroups along one dispatch dimension
+const MAX_GROUPS: u32 = 65535;
+
+struct Gpu {
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    /// Whether the GPU supports 64-bit floats
+    f64: bool,
+    pipelines: Mutex<HashMap<(&'static str, Float), Arc<wgpu::ComputePipeline>>>,
+}
+
+/// The type of floats that are computed with
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Float {
+    F32,
+    F64,
+}
+
+impl Float {
+    fn name(self) -> &'static str {
+        match self {
+            Float::F32 => "f32",
+            Float::F64 => "f64",
+        }
+    }
+    fn size(self) -> usize {
+        match self {
+            Float::F32 => 4,
+            Float::F64 => 8,
+        }
+    }
+    fn encode(self, data: &[f64]) -> Vec<u8> {
+        match self {
+            Float::F32 => data
+                .iter()
+                .flat_map(|&x| (x as f32).to_le_bytes())
+                .collect(),
+            Float::F64 => data.iter().flat_map(|&x| x.to_le_bytes()).collect(),
+        }
+    }
+    fn decode(self, bytes: &[u8]) -> Vec<f64> {
+        match self {
+            Float::F32 => (bytes.chunks_exact(4))
+                .map(|bytes| f32::from_le_bytes(bytes.try_into().unwrap()).into())
+                .collect(),
+            Float::F64 => (bytes.chunks_exact(8))
+                .map(|bytes| f64::from_le_bytes(bytes.try_into().unwrap()))
+                .collect(),
+        }
+    }
+}
+
+static GPU: Lazy<Option<Gpu>> = Lazy::new(|| {
+    let instance = wgpu::Instance::new(wgpu::InstanceDescriptor::default());
+    let adapter = pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
+        power_preference: wgpu::PowerPreference::HighPerformance,
+        force_fallback_adapter: false,
+        compatible_surface: None,
+    }))?;
+    let features = adapter.features() & wgpu::Features::SHADER_F64;
+    let (device, queue) = pollster::block_on(adapter.request_device(
+        &wgpu::DeviceDescriptor {
+            label: Some("uiua"),
+            features,
+            limits: wgpu::Limits::downlevel_defaults(),
+        },
+        None,
+    ))
+    .ok()?;
+    Some(Gpu {
+        device,
+        queue,
+        f64: features.contains(wgpu::Features::SHADER_F64),
+        pipelines: Mutex::new(HashMap::new()),
+    })
+});
+
+/// Get the GPU and the type of floats to compute with, if it may be used
+fn gpu(env: &Uiua) -> Option<(&'static Gpu, Float)> {
+    let gpu = GPU.as_ref()?;
+    if gpu.f64 {
+        Some((gpu, Float::F64))
+    } else if env.gpu_single_precision() {
+        Some((gpu, Float::F32))
+    } else {
+        None
+    }
+}
+
+/// Try to run a primitive on the GPU
+///
+/// Returns whether the primitive was run. If it was not, the stack is unchanged.
+pub fn run(prim: Primitive, env: &mut Uiua) -> UiuaResult<bool> {
+    if !env.gpu() {
+        return Ok(false);
+    }
+    let op = match prim {
+        // The first argument is on top of the stack, so it is `a` here
+        Primitive::Add => "b[i] + a[i]",
+        Primitive::Sub => "b[i] - a[i]",
+        Primitive::Mul => "b[i] * a[i]",
+        Primitive::Div => "b[i] / a[i]",
+        Primitive::Reduce => return reduce(env),
+        _ => return Ok(false),
+    };
+    let a = env.pop(ArrayArg(1))?;
+    let b = env.pop(ArrayArg(2))?;
+    if let (Value::Num(a_arr), Value::Num(b_arr)) = (&a, &b) {
+        if a_arr.shape == b_arr.shape && a_arr.flat_len() >= MIN_LEN {
+            if let Some((gpu, float)) = gpu(env) {
+                if let Some(data) = gpu.pervade(op, float, &a_arr.data, &b_arr.data) {
+                    env.push(Array::new(a_arr.shape.clone(), data));
+                    return Ok(true);
+                }
+            }
+        }
+    }
+    env.push(b);
+    env.push(a);
+    Ok(false)
+}
+
+fn reduce(env: &mut Uiua) -> UiuaResult<bool> {
+    let f = env.pop(1)?;
+    let xs = env.pop(ArrayArg(1))?;
+    if f.as_flipped_primitive() == Some((Primitive::Add, false)) {
+        if let Value::Num(nums) = &xs {
+            if nums.rank() == 1 && nums.flat_len() >= MIN_LEN {
+                if let Some((gpu, float)) = gpu(env) {
+                    if let Some(sums) = gpu.partial_sums(float, &nums.data) {
+                        env.push(sums.into_iter().sum::<f64>());
+                        return Ok(true);
+                    }
+                }
+            }
+        }
+    }
+    env.push(xs);
+    env.push(f);
+    Ok(false)
+}
+
+impl Gpu {
+    fn pipeline(
+        &self,
+        key: &'static str,
+        float: Float,
+        source: impl FnOnce() -> String,
+    ) -> Arc<wgpu::ComputePipeline> {
+        let mut pipelines = self.pipelines.lock();
+        if let Some(pipeline) = pipelines.get(&(key, float)) {
+            return pipeline.clone();
+        }
+        let module = self
+            .device
+            .create_shader_module(wgpu::ShaderModuleDescriptor {
+                label: Some(key),
+                source: wgpu::ShaderSource::Wgsl(source().into()),
+            });
+        let pipeline = self
+            .device
+            .create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some(key),
+                layout: None,
+                module: &module,
+                entry_point: "main",
+            });
+        let pipeline = Arc::new(pipeline);
+        pipelines.insert((key, float), pipeline.clone());
+        pipeline
+    }
+    /// Split a number of workgroups across dispatch dimensions
+    fn dispatch_size(groups: u32) -> (u32, u32) {
+        (groups.min(MAX_GROUPS), groups.div_ceil(MAX_GROUPS))
+    }
+    fn fits(&self, len: usize, float: Float) -> bool {
+        let limits = self.device.limits();
+        let bytes = (len * float.size()) as u64;
+        bytes <= limits.max_storage_buffer_binding_size as u64
+            && (len as u64 / WORKGROUP_SIZE as u64) < MAX_GROUPS as u64 * MAX_GROUPS as u64
+    }
+    /// Run a shader over `inputs`, returning the contents of its output buffer
+    fn dispatch(
+        &self,
+        pipeline: &wgpu::ComputePipeline,
+        float: Float,
+        inputs: &[&[f64]],
+        groups: u32,
+        out_len: usize,
+    ) -> Option<Vec<f64>> {
+        let input_buffers: Vec<wgpu::Buffer> = inputs
+            .iter()
+            .map(|data| {
+                (self.device).create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label: None,
+                    contents: &float.encode(data),
+                    usage: wgpu::BufferUsages::STORAGE,
+                })
+            })
+            .collect();
+        let out_size = (out_len * float.size()) as u64;
+        let output = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size: out_size,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let staging = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size: out_size,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let entries: Vec<wgpu::BindGroupEntry> = (input_buffers.iter())
+            .chain([&output])
+            .enumerate()
+            .map(|(i, buffer)| wgpu::BindGroupEntry {
+                binding: i as u32,
+                resource: buffer.as_entire_binding(),
+            })
+            .collect();
+        let bind_group = self.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: &pipeline.get_bind_group_layout(0),
+            entries: &entries,
+        });
+        let mut encoder = (self.device).create_command_encoder(&Default::default());
+        {
+            let mut pass = encoder.begin_compute_pass(&Default::default());
+            pass.set_pipeline(pipeline);
+            pass.set_bind_group(0, &bind_group, &[]);
+            let (x, y) = Self::dispatch_size(groups);
+            pass.dispatch_workgroups(x, y, 1);
+        }
+        encoder.copy_buffer_to_buffer(&output, 0, &staging, 0, out_size);
+        self.queue.submit(Some(encoder.finish()));
+        let slice = staging.slice(..);
+        let (send, recv) = mpsc::channel();
+        slice.map_async(wgpu::MapMode::Read, move |res| _ = send.send(res));
+        self.device.poll(wgpu::Maintain::Wait);
+        recv.recv().ok()?.ok()?;
+        let data = float.decode(&slice.get_mapped_range());
+        staging.unmap();
+        Some(data)
+    }
+    fn pervade(&self, op: &'static str, float: Float, a: &[f64], b: &[f64]) -> Option<Vec<f64>> {
+        if !self.fits(a.len(), float) {
+            return None;
+        }
+        let pipeline = self.pipeline(op, float, || {
+            format!(
+                "\
+@group(0) @binding(0) var<storage, read> a: array<{t}>;
+@group(0) @binding(1) var<storage, read> b: array<{t}>;
+@group(0) @binding(2) var<storage, read_write> out: array<{t}>;
+
+@compute @workgroup_size({WORKGROUP_SIZE})
+fn main(@builtin(global_invocation_id) id: vec3<u32>) {{
+    let i = id.x + id.y * {stride}u;
+    if i < arrayLength(&out) {{
+        out[i] = {op};
+    }}
+}}",
+                t = float.name(),
+                stride = MAX_GROUPS * WORKGROUP_SIZE
+            )
+        });
+        let groups = (a.len() as u32).div_ceil(WORKGROUP_SIZE);
+        self.dispatch(&pipeline, float, &[a, b], groups, a.len())
+    }
+    /// Sum each workgroup-sized chunk of the data
+    fn partial_sums(&self, float: Float, data: &[f64]) -> Option<Vec<f64>> {
+        if !self.fits(data.len(), float) {
+            return None;
+        }
+        let pipeline = self.pipeline("sum", float, || {
+            format!(
+                "\
+@group(0) @binding(0) var<storage, read> xs: array<{t}>;
+@group(0) @binding(1) var<storage, read_write> out: array<{t}>;
+
+var<workgroup> partial: array<{t}, {WORKGROUP_SIZE}>;
+
+@compute @workgroup_size({WORKGROUP_SIZE})
+fn main(
+    @builtin(global_invocation_id) id: vec3<u32>,
+    @builtin(local_invocation_index) local: u32,
+    @builtin(workgroup_id) group: vec3<u32>,
+) {{
+    let i = id.x + id.y * {stride}u;
+    if i < arrayLength(&xs) {{
+        partial[local] = xs[i];
+    }} else {{
+        partial[local] = {t}(0.0);
+    }}
+    workgroupBarrier();
+    for (var step = {half}u; step > 0u; step = step / 2u) {{
+        if local < step {{
+            partial[local] = partial[local] + partial[local + step];
+        }}
+        workgroupBarrier();
+    }}
+    // Groups that pad out the last row of the dispatch have no output
+    let index = group.x + group.y * {max_groups}u;
+    if local == 0u && index < arrayLength(&out) {{
+        out[index] = partial[0];
+    }}
+}}",
+                t = float.name(),
+                stride = MAX_GROUPS * WORKGROUP_SIZE,
+                half = WORKGROUP_SIZE / 2,
+                max_groups = MAX_GROUPS,
+            )
+        });
+        let groups = (data.len() as u32).div_ceil(WORKGROUP_SIZE);
+        self.dispatch(&pipeline, float, &[data], groups, groups as usize)
+    }
+}
@@ -0,0 +1,300 @@
+//! Native code generation for chains of arithmetic on large arrays
+//!
+//! A chain is a sequence of instructions that each apply a pervasive operation
+//! to the array on top of the stack, like `×2+1`, optionally followed by a [`Primitive::Reduce`]
+//! with [`Primitive::Add`]. Chains are compiled with Cranelift into a single loop
+//! over the array's data. Kernels are cached by the operations in the chain, and the
+//! constants are passed in at runtime, so chains that differ only in their constants
+//! share a kernel. A trailing sum is done by the interpreter so that it adds the
+//! elements in the same order as [`Primitive::Reduce`] does.
+
+use std::{cell::RefCell, collections::HashMap, mem::transmute, rc::Rc};
+
+use cranelift_codegen::{
+    ir::{condcodes::IntCC, types, AbiParam, InstBuilder, MemFlags},
+    settings::{self, Configurable},
+};
+use cranelift_frontend::{FunctionBuilder, FunctionBuilderContext};
+use cranelift_jit::{JITBuilder, JITModule};
+use cranelift_module::Module;
+
+use crate::{
+    algorithm::loops::sum_list, array::Array, function::Instr, primitive::Primitive, value::Value,
+    Uiua,
+};
+
+/// The minimum number of elements for which compiling a chain is worth it
+pub const MIN_LEN: usize = 1 << 14;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Op {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Neg,
+    Abs,
+    Sqrt,
+    Floor,
+    Ceil,
+}
+
+/// A chain of operations recognized in a function's instructions
+pub struct Chain {
+    ops: Vec<Op>,
+    consts: Vec<f64>,
+    reduce: bool,
+    /// The number of instructions the chain covers
+    pub len: usize,
+}
+
+impl Chain {
+    /// Recognize a chain at the start of `instrs`
+    ///
+    /// Chains must have at least two operations, because single operations
+    /// are already run by tight loops in the interpreter.
+    /// A trailing reduction is only included if `reducible` is true.
+    pub fn parse(instrs: &[Instr], reducible: bool) -> Option<Self> {
+        let mut chain = Chain {
+            ops: Vec::new(),
+            consts: Vec::new(),
+            reduce: false,
+            len: 0,
+        };
+        loop {
+            match &instrs[chain.len..] {
+                [Instr::Push(val), Instr::Prim(prim, _), ..] => {
+                    let op = match prim {
+                        Primitive::Add => Op::Add,
+                        Primitive::Sub => Op::Sub,
+                        Primitive::Mul => Op::Mul,
+                        Primitive::Div => Op::Div,
+                        Primitive::Reduce if reducible => {
+                            if val.as_flipped_primitive() != Some((Primitive::Add, false)) {
+                                break;
+                            }
+                            chain.reduce = true;
+                            chain.len += 2;
+                            break;
+                        }
+                        _ => break,
+                    };
+                    let Value::Num(n) = &**val else {
+                        break;
+                    };
+                    if n.rank() != 0 {
+                        break;
+                    }
+                    chain.ops.push(op);
+                    chain.consts.push(n.data[0]);
+                    chain.len += 2;
+                }
+                [Instr::Prim(prim, _), ..] => {
+                    chain.ops.push(match prim {
+                        Primitive::Neg => Op::Neg,
+                        Primitive::Abs => Op::Abs,
+                        Primitive::Sqrt => Op::Sqrt,
+                        Primitive::Floor => Op::Floor,
+                        Primitive::Ceil => Op::Ceil,
+                        _ => break,
+                    });
+                    chain.len += 1;
+                }
+                _ => break,
+            }
+        }
+        if chain.ops.len() + chain.reduce as usize >= 2 {
+            Some(chain)
+        } else {
+            None
+        }
+    }
+    /// Run the chain on an array
+    ///
+    /// Returns `None` if the chain could not be compiled.
+    pub fn run(&self, xs: &Array<f64>, env: &Uiua) -> Option<Value> {
+        let kernel = kernel(&self.ops)?;
+        let mut out = vec![0.0; xs.data.len()];
+        // Safety: the kernel reads and writes `len` elements and reads one constant per dyadic op
+        let f: extern "C" fn(*const f64, *mut f64, usize, *const f64) =
+            unsafe { transmute(kernel.code) };
+        f(
+            xs.data.as_ptr(),
+            out.as_mut_ptr(),
+            out.len(),
+            self.consts.as_ptr(),
+        );
+        Some(if self.reduce {
+            sum_list(out.len(), |i| out[i], env).into()
+        } else {
+            Array::new(xs.shape.clone(), out).into()
+        })
+    }
+}
+
+struct Kernel {
+    /// The module owns the memory the code lives in
+    _module: JITModule,
+    code: *const u8,
+}
+
+type KernelCache = HashMap<Vec<Op>, Option<Rc<Kernel>>>;
+
+thread_local! {
+    static KERNELS: RefCell<KernelCache> = RefCell::new(HashMap::new());
+}
+
+fn kernel(ops: &[Op]) -> Option<Rc<Kernel>> {
+    KERNELS.with(|kernels| {
+        kernels
+            .borrow_mut()
+            .entry(ops.to_vec())
+            .or_insert_with(|| compile(ops).ok().map(Rc::new))
+            .clone()
+    })
+}
+
+fn compile(ops: &[Op]) -> Result<Kernel, String> {
+    let mut flags = settings::builder();
+    for (name, value) in [
+        ("opt_level", "speed"),
+        // Operations without native instructions are lowered to calls that must be linked
+        ("use_colocated_libcalls", "false"),
+        ("is_pic", "false"),
+    ] {
+        flags.set(name, value).map_err(|e| e.to_string())?;
+    }
+    let isa = cranelift_native::builder()?
+        .finish(settings::Flags::new(flags))
+        .map_err(|e| e.to_string())?;
+    let mut module = JITModule::new(JITBuilder::with_isa(
+        isa,
+        cranelift_module::default_libcall_names(),
+    ));
+    let ptr = module.target_config().pointer_type();
+    let mut ctx = module.make_context();
+    let sig = &mut ctx.func.signature;
+    for _ in 0..4 {
+        sig.params.push(AbiParam::new(ptr));
+    }
+
+    let mut builder_ctx = FunctionBuilderContext::new();
+    let mut b = FunctionBuilder::new(&mut ctx.func, &mut builder_ctx);
+    let flags = MemFlags::trusted();
+    let entry = b.create_block();
+    b.append_block_params_for_function_params(entry);
+    b.switch_to_block(entry);
+    b.seal_block(entry);
+    let params = b.block_params(entry).to_vec();
+    let [input, output, len, consts_ptr] = params[..] else {
+        unreachable!()
+    };
+    // Load constants once, outside the loop
+    let mut consts = Vec::new();
+    for op in ops {
+        if let Op::Add | Op::Sub | Op::Mul | Op::Div = op {
+            let offset = (consts.len() * 8) as i32;
+            consts.push(b.ins().load(types::F64, flags, consts_ptr, offset));
+        }
+    }
+
+    let header = b.create_block();
+    let body = b.create_block();
+    let exit = b.create_block();
+    b.append_block_param(header, ptr);
+    let zero = b.ins().iconst(ptr, 0);
+    b.ins().jump(header, &[zero]);
+
+    b.switch_to_block(header);
+    let i = b.block_params(header)[0];
+    let done = b.ins().icmp(IntCC::UnsignedGreaterThanOrEqual, i, len);
+    b.ins().brif(done, exit, &[], body, &[]);
+
+    b.switch_to_block(body);
+    b.seal_block(body);
+    let offset = b.ins().ishl_imm(i, 3);
+    let addr = b.ins().iadd(input, offset);
+    let mut x = b.ins().load(types::F64, flags, addr, 0);
+    let mut consts = consts.into_iter();
+    for op in ops {
+        // Dyadic operations have the array as their second argument, so `x - c` rather than `c - x`
+        x = match op {
+            Op::Add => b.ins().fadd(x, consts.next().unwrap()),
+            Op::Sub => b.ins().fsub(x, consts.next().unwrap()),
+            Op::Mul => b.ins().fmul(x, consts.next().unwrap()),
+            Op::Div => b.ins().fdiv(x, consts.next().unwrap()),
+            Op::Neg => b.ins().fneg(x),
+            Op::Abs => b.ins().fabs(x),
+            Op::Sqrt => b.ins().sqrt(x),
+            Op::Floor => b.ins().floor(x),
+            Op::Ceil => b.ins().ceil(x),
+        };
+    }
+    let addr = b.ins().iadd(output, offset);
+    b.ins().store(flags, x, addr, 0);
+    let next = b.ins().iadd_imm(i, 1);
+    b.ins().jump(header, &[next]);
+    b.seal_block(header);
+
+    b.switch_to_block(exit);
+    b.seal_block(exit);
+    b.ins().return_(&[]);
+    b.finalize();
+
+    let id = module
+        .declare_anonymous_function(&ctx.func.signature)
+        .map_err(|e| e.to_string())?;
+    module
+        .define_function(id, &mut ctx)
+        .map_err(|e| e.to_string())?;
+    module.clear_context(&mut ctx);
+    module.finalize_definitions().map_err(|e| e.to_string())?;
+    let code = module.get_finalized_function(id);
+    Ok(Kernel {
+        _module: module,
+        code,
+    })
+}
+
+#[test]
+fn chains_match_interpreter() {
+    let stack = crate::Uiua::with_native_sys()
+        .load_str("¯×2+1 ⇡20000\n/+⌵-3÷2 ⇡20000")
+        .unwrap()
+        .take_stack();
+    let xs = (0..20000).map(f64::from);
+    let mapped: Value = xs.clone().map(|x| -((x + 1.0) * 2.0)).collect();
+    // A lone reduction is not compiled, so this sums in the interpreter's order
+    let mut env = crate::Uiua::with_native_sys();
+    env.push(xs.map(|x| (x / 2.0 - 3.0).abs()).collect::<Value>());
+    let sum = env.load_str("/+").unwrap().pop("sum").unwrap();
+    assert_eq!(stack, [mapped, sum]);
+}
+
+#[test]
+fn chains_use_fuel_and_are_profiled() {
+    use crate::run::ProfileKey;
+    // Only materializing the range uses more fuel for a longer array
+    let used = |len: usize| {
+        let mut env = (crate::Uiua::with_native_sys())
+            .with_folding(false)
+            .with_fuel(1 << 20);
+        env.load_str(&format!("¯×2+1 ⇡{len}")).unwrap();
+        (1 << 20) - env.fuel().unwrap()
+    };
+    assert_eq!(used(20000) - used(200), 19800);
+    let mut env = crate::Uiua::with_native_sys().with_profiling(true);
+    env.load_str("/+⌵-3÷2 ⇡20000").unwrap();
+    let profile = env.profile().unwrap();
+    for prim in [
+        Primitive::Div,
+        Primitive::Sub,
+        Primitive::Abs,
+        Primitive::Reduce,
+    ] {
+        let calls = (profile.entries().into_iter())
+            .find(|(k, _)| **k == ProfileKey::Primitive(prim))
+            .map_or(0, |(_, entry)| entry.calls);
+        assert_eq!(calls, 1, "{prim}");
+    }
+}
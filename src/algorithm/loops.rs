@@ -17,6 +17,9 @@ use crate::{
     Uiua, UiuaResult,
 };
 
+#[cfg(feature = "parallel")]
+use crate::algorithm::parallel;
+
 fn flip<A, B, C>(f: impl Fn(A, B) -> C) -> impl Fn(B, A) -> C {
     move |b, a| f(a, b)
 }
@@ -27,26 +30,58 @@ pub fn reduce(env: &mut Uiua) -> UiuaResult {
     let xs = env.pop(ArrayArg(1))?;
 
     match (f.as_flipped_primitive(), xs) {
+        // Lists are reduced by dedicated kernels that the compiler can vectorize
+        (Some((Primitive::Max, _)), Value::Num(nums))
+            if nums.rank() == 1 && nums.flat_len() > 0 =>
+        {
+            env.push(lane_reduce(nums.data.iter().copied(), &f64::max, &f64::max))
+        }
+        (Some((Primitive::Min, _)), Value::Num(nums))
+            if nums.rank() == 1 && nums.flat_len() > 0 =>
+        {
+            env.push(lane_reduce(nums.data.iter().copied(), &f64::min, &f64::min))
+        }
+        (Some((Primitive::Add, _)), Value::Byte(bytes)) if bytes.rank() == 1 => {
+            env.push(sum_bytes(&bytes.data))
+        }
+        (Some((Primitive::Max, _)), Value::Byte(bytes)) if bytes.rank() == 1 => {
+            env.push((bytes.data.iter().max()).map_or(f64::NEG_INFINITY, |&b| f64::from(b)))
+        }
+        (Some((Primitive::Min, _)), Value::Byte(bytes)) if bytes.rank() == 1 => {
+            env.push((bytes.data.iter().min()).map_or(f64::INFINITY, |&b| f64::from(b)))
+        }
         (Some((prim, flipped)), Value::Num(nums)) => env.push(match prim {
-            Primitive::Add => fast_reduce(nums, 0.0, Add::add),
+            Primitive::Add => split_reduce(nums, 0.0, Add::add, Add::add, env),
             Primitive::Sub if flipped => fast_reduce(nums, 0.0, Sub::sub),
             Primitive::Sub => fast_reduce(nums, 0.0, flip(Sub::sub)),
-            Primitive::Mul => fast_reduce(nums, 1.0, Mul::mul),
+            Primitive::Mul => split_reduce(nums, 1.0, Mul::mul, Mul::mul, env),
             Primitive::Div if flipped => fast_reduce(nums, 1.0, Div::div),
             Primitive::Div => fast_reduce(nums, 1.0, flip(Div::div)),
-            Primitive::Max => fast_reduce(nums, f64::NEG_INFINITY, f64::max),
-            Primitive::Min => fast_reduce(nums, f64::INFINITY, f64::min),
+            Primitive::Max => split_reduce(nums, f64::NEG_INFINITY, f64::max, f64::max, env),
+            Primitive::Min => split_reduce(nums, f64::INFINITY, f64::min, f64::min, env),
             _ => return generic_fold(f, Value::Num(nums), None, env),
         }),
         (Some((prim, flipped)), Value::Byte(bytes)) => env.push(match prim {
-            Primitive::Add => fast_reduce(bytes, 0.0, |a, b| a + f64::from(b)),
+            Primitive::Add => split_reduce(bytes, 0.0, |a, b| a + f64::from(b), Add::add, env),
             Primitive::Sub if flipped => fast_reduce(bytes, 0.0, |a, b| a - f64::from(b)),
             Primitive::Sub => fast_reduce(bytes, 0.0, |a, b| f64::from(b) - a),
-            Primitive::Mul => fast_reduce(bytes, 1.0, |a, b| a * f64::from(b)),
+            Primitive::Mul => split_reduce(bytes, 1.0, |a, b| a * f64::from(b), Mul::mul, env),
             Primitive::Div if flipped => fast_reduce(bytes, 1.0, |a, b| a / f64::from(b)),
             Primitive::Div => fast_reduce(bytes, 1.0, |a, b| f64::from(b) / a),
-            Primitive::Max => fast_reduce(bytes, f64::NEG_INFINITY, |a, b| a.max(f64::from(b))),
-            Primitive::Min => fast_reduce(bytes, f64::INFINITY, |a, b| a.min(f64::from(b))),
+            Primitive::Max => split_reduce(
+                bytes,
+                f64::NEG_INFINITY,
+                |a, b| a.max(f64::from(b)),
+                f64::max,
+                env,
+            ),
+            Primitive::Min => split_reduce(
+                bytes,
+                f64::INFINITY,
+                |a, b| a.min(f64::from(b)),
+                f64::min,
+                env,
+            ),
             _ => return generic_fold(f, Value::Byte(bytes), None, env),
         }),
         (_, xs) => generic_fold(f, xs, None, env)?,
@@ -83,23 +118,147 @@ pub fn fast_reduce<T: ArrayValue + Into<R>, R: ArrayValue>(
                 let data = cowslice![identity; row_len];
                 return Array::new(arr.shape, data);
             }
-            let mut new_data: Vec<R> = arr.data[..row_len]
-                .iter()
-                .cloned()
-                .map(Into::into)
-                .collect();
-            for i in 1..row_count {
-                let start = i * row_len;
-                for j in 0..row_len {
-                    new_data[j] = f(new_data[j].clone(), arr.data[start + j].clone());
-                }
-            }
+            let new_data = reduce_rows(&arr.data, row_len, &f);
             arr.shape.remove(0);
             Array::new(arr.shape, new_data)
         }
     }
 }
 
+/// Sum bytes exactly with integer arithmetic
+fn sum_bytes(bytes: &[u8]) -> f64 {
+    // The sum of a chunk this size always fits in a u32
+    (bytes.chunks(1 << 16))
+        .map(|chunk| chunk.iter().map(|&b| u32::from(b)).sum::<u32>() as u64)
+        .sum::<u64>() as f64
+}
+
+/// Fold rows of `row_len` elements together element-wise
+fn reduce_rows<T: ArrayValue + Into<R>, R: ArrayValue>(
+    data: &[T],
+    row_len: usize,
+    f: &impl Fn(R, T) -> R,
+) -> Vec<R> {
+    let mut new_data: Vec<R> = data[..row_len].iter().cloned().map(Into::into).collect();
+    for row in data[row_len..].chunks_exact(row_len) {
+        for (acc, x) in new_data.iter_mut().zip(row) {
+            *acc = f(acc.clone(), x.clone());
+        }
+    }
+    new_data
+}
+
+/// The minimum number of elements for which a reduction is split into parts
+const MIN_SPLIT_LEN: usize = 1 << 12;
+/// The number of independent accumulators used to reduce a list
+const LANES: usize = 8;
+
+/// Reduce with an associative and commutative operation
+///
+/// Large lists are reduced with several independent accumulators, which lets
+/// the processor overlap operations that would otherwise wait on each other.
+/// With the `parallel` feature, large arrays are also split into chunks that
+/// are reduced on multiple threads. Partial results are merged with `combine`.
+///
+/// Because the elements are combined in a different order, floating point
+/// sums and products may differ in the last few bits from a left-to-right fold.
+fn split_reduce<T, R>(
+    arr: Array<T>,
+    identity: R,
+    f: impl Fn(R, T) -> R + Send + Sync,
+    combine: impl Fn(R, R) -> R + Send + Sync,
+    env: &Uiua,
+) -> Array<R>
+where
+    T: ArrayValue + Into<R> + Sync,
+    R: ArrayValue + Send,
+{
+    if arr.rank() == 0 || arr.flat_len() < MIN_SPLIT_LEN {
+        return fast_reduce(arr, identity, f);
+    }
+    if arr.rank() == 1 {
+        #[cfg(feature = "parallel")]
+        if parallel::should_split(arr.flat_len(), env) {
+            let value = parallel::reduce_chunks(
+                &arr.data,
+                1,
+                |chunk| lane_reduce(chunk.iter().cloned(), &f, &combine),
+                &combine,
+            );
+            return Array::new(tiny_vec![], vec![value]);
+        }
+        let value = lane_reduce(arr.data.iter().cloned(), &f, &combine);
+        return Array::new(tiny_vec![], vec![value]);
+    }
+    #[cfg(feature = "parallel")]
+    if parallel::should_split(arr.flat_len(), env) {
+        let row_len = arr.row_len();
+        let new_data = parallel::reduce_chunks(
+            &arr.data,
+            row_len,
+            |chunk| reduce_rows(chunk, row_len, &f),
+            |a: Vec<R>, b: Vec<R>| (a.into_iter().zip(b)).map(|(a, b)| combine(a, b)).collect(),
+        );
+        let mut shape = arr.shape;
+        shape.remove(0);
+        return Array::new(shape, new_data);
+    }
+    #[cfg(not(feature = "parallel"))]
+    let _ = env;
+    fast_reduce(arr, identity, f)
+}
+
+/// Reduce a non-empty list with several independent accumulators
+///
+/// Each group of [`LANES`] elements is spread across the accumulators,
+/// and the elements left over at the end go to the first one.
+fn lane_reduce<T: Into<R>, R: Clone>(
+    mut xs: impl ExactSizeIterator<Item = T>,
+    f: &impl Fn(R, T) -> R,
+    combine: &impl Fn(R, R) -> R,
+) -> R {
+    let len = xs.len();
+    if len < LANES {
+        let first = xs.next().unwrap().into();
+        return xs.fold(first, f);
+    }
+    let mut lanes: [R; LANES] = std::array::from_fn(|_| xs.next().unwrap().into());
+    for _ in 1..len / LANES {
+        for lane in &mut lanes {
+            *lane = f(lane.clone(), xs.next().unwrap());
+        }
+    }
+    for x in xs {
+        lanes[0] = f(lanes[0].clone(), x);
+    }
+    lanes.into_iter().reduce(combine).unwrap()
+}
+
+/// Sum a list in the same order as reducing it with add
+///
+/// The list is given by its length and a function that gets the element at an index,
+/// so that fused and compiled kernels can sum values without collecting them first.
+pub fn sum_list(len: usize, item: impl Fn(usize) -> f64 + Send + Sync, env: &Uiua) -> f64 {
+    if len == 0 {
+        return 0.0;
+    }
+    if len < MIN_SPLIT_LEN {
+        return (1..len).map(&item).fold(item(0), Add::add);
+    }
+    #[cfg(feature = "parallel")]
+    if parallel::should_split(len, env) {
+        return parallel::reduce_ranges(
+            len,
+            1,
+            |range| lane_reduce(range.map(&item), &Add::add, &Add::add),
+            Add::add,
+        );
+    }
+    #[cfg(not(feature = "parallel"))]
+    let _ = env;
+    lane_reduce((0..len).map(item), &Add::add, &Add::add)
+}
+
 fn generic_fold(f: Value, xs: Value, init: Option<Value>, env: &mut Uiua) -> UiuaResult {
     match f.signature().args {
         0 | 1 => {
@@ -301,6 +460,17 @@ pub fn each(env: &mut Uiua) -> UiuaResult {
 }
 
 fn each1_1(f: Value, xs: Value, env: &mut Uiua) -> UiuaResult {
+    #[cfg(feature = "parallel")]
+    if parallel::should_parallelize(&f, &[&xs], xs.flat_len(), env) {
+        let mut new_shape = Shape::from(xs.shape());
+        let arg_sets = xs.into_flat_values().map(|val| vec![val]).collect();
+        let new_values = parallel::map(&f, arg_sets, "each's function result", env)?;
+        let mut eached = Value::from_row_values(new_values, env)?;
+        new_shape.extend_from_slice(&eached.shape()[1..]);
+        *eached.shape_mut() = new_shape;
+        env.push(eached);
+        return Ok(());
+    }
     let mut new_values = Vec::with_capacity(xs.flat_len());
     let mut new_shape = Shape::from(xs.shape());
     let mut old_values = xs.into_flat_values();
@@ -334,8 +504,24 @@ fn each1_0(f: Value, xs: Value, env: &mut Uiua) -> UiuaResult {
 }
 
 fn each2_1(f: Value, xs: Value, ys: Value, env: &mut Uiua) -> UiuaResult {
-    let xs_shape = xs.shape().to_vec();
-    let ys_shape = ys.shape().to_vec();
+    #[cfg(feature = "parallel")]
+    if xs.shape() == ys.shape() && parallel::should_parallelize(&f, &[&xs, &ys], xs.flat_len(), env)
+    {
+        let mut new_shape = Shape::from(xs.shape());
+        let arg_sets = xs
+            .into_flat_values()
+            .zip(ys.into_flat_values())
+            .map(|(x, y)| vec![y, x])
+            .collect();
+        let new_values = parallel::map(&f, arg_sets, "each's function result", env)?;
+        let mut eached = Value::from_row_values(new_values, env)?;
+        new_shape.extend_from_slice(&eached.shape()[1..]);
+        *eached.shape_mut() = new_shape;
+        env.push(eached);
+        return Ok(());
+    }
+    let xs_shape = Shape::from(xs.shape());
+    let ys_shape = Shape::from(ys.shape());
     let xs_values: Vec<_> = xs.into_flat_values().collect();
     let ys_values: Vec<_> = ys.into_flat_values().collect();
     let (mut shape, values) = bin_pervade_generic(
@@ -359,8 +545,8 @@ fn each2_1(f: Value, xs: Value, ys: Value, env: &mut Uiua) -> UiuaResult {
 }
 
 fn each2_0(f: Value, xs: Value, ys: Value, env: &mut Uiua) -> UiuaResult {
-    let xs_shape = xs.shape().to_vec();
-    let ys_shape = ys.shape().to_vec();
+    let xs_shape = Shape::from(xs.shape());
+    let ys_shape = Shape::from(ys.shape());
     let xs_values: Vec<_> = xs.into_flat_values().collect();
     let ys_values: Vec<_> = ys.into_flat_values().collect();
     bin_pervade_generic(
@@ -475,6 +661,13 @@ pub fn rows(env: &mut Uiua) -> UiuaResult {
 }
 
 fn rows1_1(f: Value, xs: Value, env: &mut Uiua) -> UiuaResult {
+    #[cfg(feature = "parallel")]
+    if parallel::should_parallelize(&f, &[&xs], xs.row_count(), env) {
+        let arg_sets = xs.into_rows().map(|row| vec![row]).collect();
+        let new_rows = parallel::map(&f, arg_sets, "rows' function result", env)?;
+        env.push(Value::from_row_values(new_rows, env)?);
+        return Ok(());
+    }
     let mut new_rows = Vec::with_capacity(xs.row_count());
     let mut old_rows = xs.into_rows();
     for row in old_rows.by_ref() {
@@ -512,6 +705,17 @@ fn rows2_1(f: Value, xs: Value, ys: Value, env: &mut Uiua) -> UiuaResult {
             ys.row_count()
         )));
     }
+    #[cfg(feature = "parallel")]
+    if parallel::should_parallelize(&f, &[&xs, &ys], xs.row_count(), env) {
+        let arg_sets = xs
+            .into_rows()
+            .zip(ys.into_rows())
+            .map(|(x, y)| vec![y, x])
+            .collect();
+        let new_rows = parallel::map(&f, arg_sets, "rows's function result", env)?;
+        env.push(Value::from_row_values(new_rows, env)?);
+        return Ok(());
+    }
     let mut new_rows = Vec::with_capacity(xs.row_count());
     let x_rows = xs.into_rows();
     let y_rows = ys.into_rows();
@@ -596,6 +800,8 @@ pub fn table(env: &mut Uiua) -> UiuaResult {
     let f = env.pop(FunctionArg(1))?;
     let xs = env.pop(ArrayArg(1))?;
     let ys = env.pop(ArrayArg(2))?;
+    let cells = xs.flat_len().saturating_mul(ys.flat_len());
+    env.check_memory(cells.saturating_mul(xs.element_size().max(ys.element_size())))?;
     match (f.as_flipped_primitive(), xs, ys) {
         (Some((prim, flipped)), Value::Num(xs), Value::Num(ys)) => {
             if let Err((xs, ys)) = table_nums(prim, flipped, xs, ys, env) {
@@ -711,18 +917,36 @@ fn fast_table_join_or_couple<T: ArrayValue>(a: Array<T>, b: Array<T>) -> Array<T
 fn generic_table(f: Value, xs: Value, ys: Value, env: &mut Uiua) -> UiuaResult {
     let mut new_shape = Shape::from(xs.shape());
     new_shape.extend_from_slice(ys.shape());
-    let mut items = Vec::with_capacity(xs.flat_len() * ys.flat_len());
+    let iterations = xs.flat_len() * ys.flat_len();
+    #[cfg(feature = "parallel")]
+    if parallel::should_parallelize(&f, &[&xs, &ys], iterations, env) {
+        let y_values = ys.into_flat_values().collect::<Vec<_>>();
+        let mut arg_sets = Vec::with_capacity(iterations);
+        for x in xs.into_flat_values() {
+            for y in y_values.iter().cloned() {
+                arg_sets.push(vec![y, x.clone()]);
+            }
+        }
+        let items = parallel::map(&f, arg_sets, "tabled function result", env)?;
+        return push_tabled(items, new_shape, env);
+    }
+    let mut items = Vec::with_capacity(iterations);
     let y_values = ys.into_flat_values().collect::<Vec<_>>();
     for x in xs.into_flat_values() {
         for y in y_values.iter().cloned() {
             env.push(y);
             env.push(x.clone());
             env.call_error_on_break(f.clone(), "break is not allowed in table")?;
-            let item = env.pop("tabled function result")?;
-            item.validate_shape();
-            items.push(item);
+            items.push(env.pop("tabled function result")?);
         }
     }
+    push_tabled(items, new_shape, env)
+}
+
+fn push_tabled(items: Vec<Value>, mut new_shape: Shape, env: &mut Uiua) -> UiuaResult {
+    for item in &items {
+        item.validate_shape();
+    }
     let mut tabled = Value::from_row_values(items, env)?;
     new_shape.extend_from_slice(&tabled.shape()[1..]);
     *tabled.shape_mut() = new_shape;
@@ -766,21 +990,13 @@ pub fn repeat(env: &mut Uiua) -> UiuaResult {
 
     if n.is_infinite() {
         let f = if n < 0.0 { f.invert(env)? } else { f };
-        loop {
-            if env.call_catch_break(f.clone())? {
-                break;
-            }
-        }
+        env.call_repeated(f, None)?;
     } else {
         if n.fract().abs() > f64::EPSILON {
             return Err(env.error("Repetitions must be a single integer or infinity"));
         };
         let f = if n < 0.0 { f.invert(env)? } else { f };
-        for _ in 0..n.abs() as usize {
-            if env.call_catch_break(f.clone())? {
-                return Ok(());
-            }
-        }
+        env.call_repeated(f, Some(n.abs() as usize))?;
     }
     Ok(())
 }
@@ -9,11 +9,20 @@ use crate::{
 
 mod dyadic;
 pub mod fork;
+pub(crate) mod fuse;
+#[cfg(feature = "gpu")]
+pub(crate) mod gpu;
 pub(crate) mod invert;
+#[cfg(feature = "jit")]
+pub(crate) mod jit;
 pub mod loops;
 mod monadic;
+#[cfg(feature = "parallel")]
+mod parallel;
 pub mod pervade;
 
+pub(crate) use monadic::LazyRange;
+
 fn max_shape(a: &[usize], b: &[usize]) -> Shape {
     let shape_len = a.len().max(b.len());
     let mut new_shape = Shape::with_capacity(shape_len);
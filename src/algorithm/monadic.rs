@@ -3,13 +3,14 @@
 use std::{
     cmp::Ordering,
     collections::{BTreeMap, BTreeSet},
+    mem::size_of,
     ptr,
     sync::Arc,
 };
 
 use tinyvec::tiny_vec;
 
-use crate::{array::*, value::Value, Uiua, UiuaResult};
+use crate::{array::*, primitive::Primitive, value::Value, Uiua, UiuaResult};
 
 impl Value {
     pub fn deshape(&mut self) {
@@ -43,6 +44,10 @@ impl Value {
             or a list of natural numbers",
         )?;
         let mut shape = Shape::from(shape.as_slice());
+        let elements = shape
+            .iter()
+            .fold(shape.len().max(1), |acc, &n| acc.saturating_mul(n));
+        env.check_memory(elements.saturating_mul(size_of::<f64>()))?;
         let data = range(&shape);
         if shape.len() > 1 {
             shape.push(shape.len());
@@ -51,6 +56,94 @@ impl Value {
     }
 }
 
+/// A range of natural numbers that has not been materialized yet
+///
+/// This lets operations that only need to know the bounds of a range
+/// skip allocating its buffer entirely.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LazyRange {
+    start: usize,
+    end: usize,
+}
+
+impl LazyRange {
+    /// Get the range that `range` would produce for a value, if it is a single natural number
+    pub fn from_max(max: &Value) -> Option<Self> {
+        let end = match max {
+            Value::Num(nums) if nums.rank() == 0 => {
+                let n = nums.data[0];
+                if n < 0.0 || n.fract() != 0.0 || n > usize::MAX as f64 {
+                    return None;
+                }
+                n as usize
+            }
+            Value::Byte(bytes) if bytes.rank() == 0 => bytes.data[0] as usize,
+            _ => return None,
+        };
+        Some(LazyRange { start: 0, end })
+    }
+    pub fn len(&self) -> usize {
+        self.end - self.start
+    }
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+    /// Take from the range
+    ///
+    /// Returns `None` if taking more than the length of the range,
+    /// which may need to fill or error.
+    pub fn take(self, n: isize) -> Option<Self> {
+        let abs = n.unsigned_abs();
+        if abs > self.len() {
+            return None;
+        }
+        Some(if n >= 0 {
+            LazyRange {
+                start: self.start,
+                end: self.start + abs,
+            }
+        } else {
+            LazyRange {
+                start: self.end - abs,
+                end: self.end,
+            }
+        })
+    }
+    /// Drop from the range
+    pub fn drop(self, n: isize) -> Self {
+        let abs = n.unsigned_abs().min(self.len());
+        if n >= 0 {
+            LazyRange {
+                start: self.start + abs,
+                end: self.end,
+            }
+        } else {
+            LazyRange {
+                start: self.start,
+                end: self.end - abs,
+            }
+        }
+    }
+    /// Reduce the range with a primitive, if the result can be calculated without iterating
+    pub fn reduce(self, prim: Primitive) -> Option<f64> {
+        let (start, end) = (self.start as f64, self.end as f64);
+        Some(match prim {
+            Primitive::Add => (start + end - 1.0) * (end - start) / 2.0,
+            Primitive::Max if !self.is_empty() => end - 1.0,
+            Primitive::Min if !self.is_empty() => start,
+            Primitive::Max => f64::NEG_INFINITY,
+            Primitive::Min => f64::INFINITY,
+            _ => return None,
+        })
+    }
+    /// Allocate the range's buffer
+    pub fn materialize(self, env: &Uiua) -> UiuaResult<Value> {
+        env.check_memory(self.len().saturating_mul(size_of::<f64>()))?;
+        let data: Vec<f64> = (self.start..self.end).map(|i| i as f64).collect();
+        Ok(Array::new(tiny_vec![self.len()], data).into())
+    }
+}
+
 fn range(shape: &[usize]) -> Vec<f64> {
     if shape.is_empty() {
         return vec![0.0];
@@ -60,7 +153,7 @@ fn range(shape: &[usize]) -> Vec<f64> {
     }
     let len = shape.len() * shape.iter().product::<usize>();
     let mut data: Vec<f64> = Vec::with_capacity(len);
-    let mut curr = vec![0; shape.len()];
+    let mut curr = zero_index(shape.len());
     loop {
         for d in &curr {
             data.push(*d as f64);
@@ -185,15 +278,7 @@ impl<T: ArrayValue> Array<T> {
             self.shape.rotate_left(1);
             return;
         }
-        let mut temp = Vec::with_capacity(self.data.len());
-        let row_len = self.row_len();
-        let row_count = self.row_count();
-        for j in 0..row_len {
-            for i in 0..row_count {
-                temp.push(self.data[i * row_len + j].clone());
-            }
-        }
-        self.data = temp.into();
+        self.data = transpose_matrix(&self.data, self.row_count(), self.row_len()).into();
         self.shape.rotate_left(1);
     }
     pub fn inv_transpose(&mut self) {
@@ -205,23 +290,63 @@ impl<T: ArrayValue> Array<T> {
             self.shape.rotate_right(1);
             return;
         }
-        let mut temp = Vec::with_capacity(self.data.len());
         let col_len = *self.shape.last().unwrap();
         let col_count: usize = self.shape.iter().rev().skip(1).product();
-        for j in 0..col_len {
-            for i in 0..col_count {
-                temp.push(self.data[i * col_len + j].clone());
+        self.data = transpose_matrix(&self.data, col_count, col_len).into();
+        self.shape.rotate_right(1);
+    }
+}
+
+/// The side length of the square tiles large matrices are transposed in
+const TRANSPOSE_TILE: usize = 16;
+
+/// Transpose a row-major matrix
+///
+/// Transposing any array is a transposition of the matrix of its rows,
+/// or of its columns for [`Array::inv_transpose`].
+/// Large matrices are copied in tiles, so the rows being read and the
+/// rows being written both stay in cache.
+fn transpose_matrix<T: Clone>(data: &[T], rows: usize, cols: usize) -> Vec<T> {
+    if rows < TRANSPOSE_TILE || cols < TRANSPOSE_TILE {
+        let mut transposed = Vec::with_capacity(data.len());
+        for j in 0..cols {
+            for i in 0..rows {
+                transposed.push(data[i * cols + j].clone());
+            }
+        }
+        return transposed;
+    }
+    let mut transposed = vec![data[0].clone(); data.len()];
+    for i_start in (0..rows).step_by(TRANSPOSE_TILE) {
+        let i_end = (i_start + TRANSPOSE_TILE).min(rows);
+        for j_start in (0..cols).step_by(TRANSPOSE_TILE) {
+            let j_end = (j_start + TRANSPOSE_TILE).min(cols);
+            for i in i_start..i_end {
+                let row = &data[i * cols..][j_start..j_end];
+                for (j, x) in (j_start..j_end).zip(row) {
+                    transposed[j * rows + i] = x.clone();
+                }
             }
         }
-        self.data = temp.into();
-        self.shape.rotate_right(1);
     }
+    transposed
 }
 
 impl Value {
     pub fn grade(&self, env: &Uiua) -> UiuaResult<Self> {
-        self.generic_ref_env(Array::grade, Array::grade, Array::grade, Array::grade, env)
-            .map(Self::from_iter)
+        match self {
+            Value::Num(nums) if nums.rank() == 1 => {
+                Ok(grade_nums(&nums.data).into_iter().collect())
+            }
+            Value::Byte(bytes) if bytes.rank() == 1 => {
+                Ok(counting_grade(&bytes.data, 256, |&b| b as usize)
+                    .into_iter()
+                    .collect())
+            }
+            value => value
+                .generic_ref_env(Array::grade, Array::grade, Array::grade, Array::grade, env)
+                .map(Self::from_iter),
+        }
     }
     pub fn classify(&self, env: &Uiua) -> UiuaResult<Self> {
         self.generic_ref_env(
@@ -243,12 +368,54 @@ impl Value {
     }
 }
 
+/// Grade a list of numbers
+///
+/// Integers that span a small enough range are graded with a counting sort.
+/// Everything else, including NaNs, uses an unstable sort with ties broken by index.
+fn grade_nums(nums: &[f64]) -> Vec<usize> {
+    let mut min = f64::INFINITY;
+    let mut max = f64::NEG_INFINITY;
+    let mut all_ints = true;
+    for &n in nums {
+        all_ints &= n.fract() == 0.0;
+        min = min.min(n);
+        max = max.max(n);
+    }
+    if all_ints && !nums.is_empty() && max - min < (nums.len() * 4).max(256) as f64 {
+        let range = (max - min) as usize + 1;
+        return counting_grade(nums, range, |&n| (n - min) as usize);
+    }
+    let mut indices: Vec<usize> = (0..nums.len()).collect();
+    indices.sort_unstable_by(|&a, &b| nums[a].array_cmp(&nums[b]).then(a.cmp(&b)));
+    indices
+}
+
+/// Grade items that map to small keys
+///
+/// The grade is stable, so equal items stay in order of their indices.
+fn counting_grade<T>(items: &[T], key_count: usize, key: impl Fn(&T) -> usize) -> Vec<usize> {
+    let mut starts = vec![0; key_count + 1];
+    for item in items {
+        starts[key(item) + 1] += 1;
+    }
+    for k in 1..starts.len() {
+        starts[k] += starts[k - 1];
+    }
+    let mut indices = vec![0; items.len()];
+    for (i, item) in items.iter().enumerate() {
+        let start = &mut starts[key(item)];
+        indices[*start] = i;
+        *start += 1;
+    }
+    indices
+}
+
 impl<T: ArrayValue> Array<T> {
     pub fn grade(&self, env: &Uiua) -> UiuaResult<Vec<usize>> {
         if self.rank() == 0 {
             return Err(env.error("Cannot grade a rank-0 array"));
         }
-        let mut indices = (0..self.flat_len()).collect::<Vec<_>>();
+        let mut indices = (0..self.row_count()).collect::<Vec<_>>();
         indices.sort_by(|&a, &b| {
             self.row_slice(a)
                 .iter()
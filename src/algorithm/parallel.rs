@@ -0,0 +1,107 @@
+//! Parallel execution of looping modifiers
+
+use std::ops::Range;
+
+use rayon::prelude::*;
+
+use crate::{value::Value, Uiua, UiuaResult};
+
+/// The minimum number of iterations that is worth running in parallel
+const MIN_ITERATIONS: usize = 64;
+/// The minimum estimated amount of work that is worth running in parallel
+const MIN_WORK: usize = 1 << 14;
+
+/// Check whether calling a function for every iteration of a loop is worth doing in parallel
+pub fn should_parallelize(f: &Value, args: &[&Value], iterations: usize, env: &Uiua) -> bool {
+    if !env.parallel() || iterations < MIN_ITERATIONS || rayon::current_num_threads() < 2 {
+        return false;
+    }
+    let Some(f) = f.as_function() else {
+        return false;
+    };
+    if f.signature().outputs != 1 || !f.is_pure() {
+        return false;
+    }
+    // Function arrays may contain impure functions that get called
+    if args.iter().any(|arg| matches!(arg, Value::Func(_))) {
+        return false;
+    }
+    let arg_size: usize = args.iter().map(|arg| arg.flat_len()).sum();
+    let work = f.instrs.len().max(1) * arg_size.max(iterations);
+    work >= MIN_WORK
+}
+
+/// Check whether a reduction over this many elements is worth splitting across threads
+pub fn should_split(len: usize, env: &Uiua) -> bool {
+    env.parallel() && len >= MIN_WORK && rayon::current_num_threads() >= 2
+}
+
+/// Reduce chunks of a slice on multiple threads and combine the results
+///
+/// Chunks are made of whole rows of `row_len` elements. The slice must not be empty.
+pub fn reduce_chunks<T: Sync, R: Send>(
+    data: &[T],
+    row_len: usize,
+    reduce_chunk: impl Fn(&[T]) -> R + Send + Sync,
+    combine: impl Fn(R, R) -> R + Send + Sync,
+) -> R {
+    reduce_ranges(
+        data.len(),
+        row_len,
+        |range| reduce_chunk(&data[range]),
+        combine,
+    )
+}
+
+/// Reduce chunks of a range of indices on multiple threads and combine the results
+///
+/// The chunks are the same as those [`reduce_chunks`] makes for a slice of length `len`.
+pub fn reduce_ranges<R: Send>(
+    len: usize,
+    row_len: usize,
+    reduce_range: impl Fn(Range<usize>) -> R + Send + Sync,
+    combine: impl Fn(R, R) -> R + Send + Sync,
+) -> R {
+    let row_count = len / row_len;
+    let chunk_len = (row_count / rayon::current_num_threads()).max(1) * row_len;
+    (0..len.div_ceil(chunk_len))
+        .into_par_iter()
+        .map(|i| reduce_range(i * chunk_len..((i + 1) * chunk_len).min(len)))
+        .reduce_with(combine)
+        .unwrap()
+}
+
+/// Call a function once for each set of arguments, spreading the calls across threads
+///
+/// The arguments in each set are pushed in order, so the last one ends up on top of the stack.
+/// The results are returned in the same order as the argument sets.
+pub fn map(
+    f: &Value,
+    arg_sets: Vec<Vec<Value>>,
+    result_name: &'static str,
+    env: &Uiua,
+) -> UiuaResult<Vec<Value>> {
+    let count = arg_sets.len();
+    let chunk_size = (count / rayon::current_num_threads()).max(1);
+    let mut chunks = Vec::with_capacity(count / chunk_size + 1);
+    let mut arg_sets = arg_sets.into_iter().peekable();
+    while arg_sets.peek().is_some() {
+        let chunk: Vec<Vec<Value>> = arg_sets.by_ref().take(chunk_size).collect();
+        chunks.push((env.thread_env(Vec::new()), f.clone(), chunk));
+    }
+    let results: Vec<Vec<Value>> = chunks
+        .into_par_iter()
+        .map(|(mut env, f, chunk)| {
+            let mut results = Vec::with_capacity(chunk.len());
+            for args in chunk {
+                for arg in args {
+                    env.push(arg);
+                }
+                env.call(f.clone())?;
+                results.push(env.pop(result_name)?);
+            }
+            Ok(results)
+        })
+        .collect::<UiuaResult<_>>()?;
+    Ok(results.into_iter().flatten().collect())
+}
@@ -140,7 +140,7 @@ where
         match a.row_count().cmp(&b.row_count()) {
             Ordering::Less => {
                 if let Some(fill) = A::get_fill(env) {
-                    let mut target_shape = a.shape().to_vec();
+                    let mut target_shape = Shape::from(a.shape());
                     target_shape[0] = b.row_count();
                     reshaped_a = a.clone();
                     reshaped_a.fill_to_shape(&target_shape, fill);
@@ -149,7 +149,7 @@ where
             }
             Ordering::Greater => {
                 if let Some(fill) = B::get_fill(env) {
-                    let mut target_shape = b.shape().to_vec();
+                    let mut target_shape = Shape::from(b.shape());
                     target_shape[0] = a.row_count();
                     reshaped_b = b.clone();
                     reshaped_b.fill_to_shape(&target_shape, fill);
@@ -214,6 +214,83 @@ where
     Ok(Array::new(shape, data))
 }
 
+/// Like [`bin_pervade`], but reuses the buffer of an operand when it is uniquely owned
+///
+/// Falls back to [`bin_pervade`] when neither buffer can be reused.
+pub fn bin_pervade_mut<T>(
+    mut a: Array<T>,
+    mut b: Array<T>,
+    env: &Uiua,
+    f: impl Fn(T, T) -> T + Copy,
+) -> UiuaResult<Array<T>>
+where
+    T: ArrayValue + Copy,
+{
+    if (a.shape == b.shape || b.rank() == 0) && a.data.is_unique() {
+        if b.rank() == 0 && a.rank() > 0 {
+            let b = b.data[0];
+            for a in a.data.iter_mut() {
+                *a = f(*a, b);
+            }
+        } else {
+            for (a, &b) in a.data.iter_mut().zip(b.data.iter()) {
+                *a = f(*a, b);
+            }
+        }
+        return Ok(a);
+    }
+    if (a.shape == b.shape || a.rank() == 0) && b.data.is_unique() {
+        if a.rank() == 0 && b.rank() > 0 {
+            let a = a.data[0];
+            for b in b.data.iter_mut() {
+                *b = f(a, *b);
+            }
+        } else {
+            for (&a, b) in a.data.iter().zip(b.data.iter_mut()) {
+                *b = f(a, *b);
+            }
+        }
+        return Ok(b);
+    }
+    bin_pervade(&a, &b, env, InfalliblePervasiveFn::new(f))
+}
+
+#[test]
+fn bin_pervade_mut_test() {
+    let env = Uiua::with_native_sys();
+    // Unique array and scalar
+    let a = Array::from(vec![1.0, 2.0, 3.0]);
+    let ptr = a.data.as_ptr();
+    let res = bin_pervade_mut(a, Array::from(10.0), &env, add::num_num).unwrap();
+    assert_eq!(res.data.as_ptr(), ptr);
+    assert_eq!(*res.data, [11.0, 12.0, 13.0]);
+    // Scalar and unique array
+    let b = Array::from(vec![1.0, 2.0, 3.0]);
+    let ptr = b.data.as_ptr();
+    let res = bin_pervade_mut(Array::from(1.0), b, &env, sub::num_num).unwrap();
+    assert_eq!(res.data.as_ptr(), ptr);
+    assert_eq!(*res.data, [0.0, 1.0, 2.0]);
+    // Shared arrays are not mutated
+    let a = Array::from(vec![1.0, 2.0]);
+    let res = bin_pervade_mut(a.clone(), a.clone(), &env, mul::num_num).unwrap();
+    assert_eq!(*a.data, [1.0, 2.0]);
+    assert_eq!(*res.data, [1.0, 4.0]);
+}
+
+#[test]
+fn scalar_pervade_test() {
+    let env = Uiua::with_native_sys();
+    let scalar = Array::from(1.0);
+    let table = Array::new(tinyvec::tiny_vec![2, 2], vec![1.0, 2.0, 3.0, 4.0]);
+    let f = InfalliblePervasiveFn::new(sub::num_num);
+    let res = bin_pervade(&scalar, &table, &env, f.clone()).unwrap();
+    assert_eq!(res.shape(), [2, 2]);
+    assert_eq!(*res.data, [0.0, 1.0, 2.0, 3.0]);
+    let res = bin_pervade(&table, &scalar, &env, f).unwrap();
+    assert_eq!(res.shape(), [2, 2]);
+    assert_eq!(*res.data, [0.0, -1.0, -2.0, -3.0]);
+}
+
 fn bin_pervade_recursive<A, B, C, F>(
     a: &A,
     b: &B,
@@ -234,14 +311,17 @@ where
                 c.push(f.call(a.clone(), b.clone(), env)?);
             }
         }
-        ([], bsh) => {
-            for brow in b.rows() {
-                bin_pervade_recursive(a, &(&bsh[1..], brow), c, env, f.clone())?;
+        // A scalar pairs with every element of the other array, whatever its shape
+        ([], _) => {
+            let a = &a.data()[0];
+            for b in b.data() {
+                c.push(f.call(a.clone(), b.clone(), env)?);
             }
         }
-        (ash, []) => {
-            for arow in a.rows() {
-                bin_pervade_recursive(&(&ash[1..], arow), b, c, env, f.clone())?;
+        (_, []) => {
+            let b = &b.data()[0];
+            for a in a.data() {
+                c.push(f.call(a.clone(), b.clone(), env)?);
             }
         }
         (ash, bsh) => {
@@ -261,6 +341,9 @@ pub mod not {
     pub fn byte(a: u8) -> f64 {
         num(a.into())
     }
+    pub fn bool(a: u8) -> u8 {
+        1 - a
+    }
     pub fn error<T: Display>(a: T, env: &Uiua) -> UiuaError {
         env.error(format!("Cannot negate {a}"))
     }
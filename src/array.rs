@@ -23,7 +23,17 @@ pub struct Array<T> {
     pub(crate) data: CowSlice<T>,
 }
 
-pub type Shape = TinyVec<[usize; 3]>;
+/// The shape of an array
+///
+/// Shapes of rank 4 or less are stored inline, so they do not allocate.
+pub type Shape = TinyVec<[usize; 4]>;
+
+/// Create an index into an array of the given rank with all components set to 0
+pub(crate) fn zero_index(rank: usize) -> Shape {
+    let mut index = Shape::new();
+    index.resize(rank, 0);
+    index
+}
 
 impl<T: ArrayValue> Default for Array<T> {
     fn default() -> Self {
@@ -163,6 +173,14 @@ impl<T: ArrayValue> Array<T> {
         let end = start + row_len;
         Self::new(&self.shape[1..], self.data.slice(start..end))
     }
+    /// Get a contiguous range of rows that shares this array's buffer
+    #[track_caller]
+    pub(crate) fn slice_rows(&self, start: usize, end: usize) -> Self {
+        let row_len = self.row_len();
+        let mut shape = self.shape.clone();
+        shape[0] = end - start;
+        Self::new(shape, self.data.slice(start * row_len..end * row_len))
+    }
     pub fn convert<U>(self) -> Array<U>
     where
         T: Into<U>,
@@ -264,6 +282,15 @@ impl Array<Arc<Function>> {
     }
 }
 
+impl<T> Array<T> {
+    /// Check whether two arrays have the same shape and share the same data
+    ///
+    /// This is a cheap check that implies equality.
+    pub(crate) fn ptr_eq(&self, other: &Self) -> bool {
+        self.shape == other.shape && self.data.ptr_eq(&other.data)
+    }
+}
+
 impl<T: ArrayValue + ArrayCmp<U>, U: ArrayValue> PartialEq<Array<U>> for Array<T> {
     fn eq(&self, other: &Array<U>) -> bool {
         if self.shape() != other.shape() {
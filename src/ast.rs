@@ -1,40 +1,98 @@
+//! The syntax tree of parsed Uiua code
+//!
+//! Use [`parse`] to get the [`Item`]s of some code. Every word is wrapped in an [`Sp`],
+//! which holds the [`CodeSpan`] of the code it was parsed from.
+//!
+//! Parsing does not resolve names or check signatures, so any code that parses
+//! can be analyzed, even if it would fail to compile.
+//!
+//! New kinds of items and words may be added as the language grows, so matches
+//! on [`Item`] and [`Word`] must have a wildcard arm.
+
 use std::fmt;
 
-use crate::{
+pub use crate::{
     function::{FunctionId, Signature},
-    lex::{CodeSpan, Sp},
-    primitive::Primitive,
-    Ident,
+    lex::{CodeSpan, Loc, Sp},
+    parse::{parse, ParseError},
 };
+use crate::{primitive::Primitive, Ident};
 
+/// A top-level item
 #[derive(Debug, Clone)]
+#[non_exhaustive]
 pub enum Item {
-    Scoped { items: Vec<Item>, test: bool },
+    /// A block of items delimited by `---`
+    ///
+    /// Bindings in the block are not visible outside it.
+    /// `test` is whether the block is only run in test mode.
+    Scoped {
+        items: Vec<Item>,
+        test: bool,
+    },
+    /// A line of words
     Words(Vec<Sp<Word>>),
     Binding(Binding),
+    /// Blank lines, which the formatter preserves
     ExtraNewlines(CodeSpan),
 }
 
+/// A binding of a name, like `Name ← words`
+///
+/// Bindings whose names end in `!` define macros.
 #[derive(Debug, Clone)]
 pub struct Binding {
     pub name: Sp<Ident>,
+    /// The declared signature, if any
     pub signature: Option<Sp<Signature>>,
     pub words: Vec<Sp<Word>>,
 }
 
+/// A word in a line of code
+///
+/// Words in a line are in the order they appear in the code,
+/// which is the reverse of the order they are run in.
 #[derive(Clone)]
+#[non_exhaustive]
 pub enum Word {
+    /// A number literal, with its source text and value
     Number(String, f64),
     Char(char),
     String(String),
+    /// A format string, split at its `_` placeholders
     FormatString(Vec<String>),
+    /// The lines of a multiline string, each split at its `_` placeholders
     MultilineString(Vec<Sp<Vec<String>>>),
+    /// The lines of a raw string, which has no escapes or placeholders
+    ///
+    /// The lines are joined with newlines.
+    RawString(Vec<Sp<String>>),
+    /// The lines of a grid string, which has no escapes or placeholders
+    ///
+    /// Each line is a row of a character array, padded with spaces to the longest line.
+    GridString(Vec<Sp<String>>),
+    /// A name, which may refer to a binding
+    ///
+    /// Names of primitives written out in full are also parsed as identifiers.
     Ident(Ident),
+    /// Words joined with `_`
     Strand(Vec<Sp<Word>>),
+    /// An array in `[]` or `{}`
     Array(Arr),
+    /// An inline function in `()`
     Func(Func),
     Primitive(Primitive),
+    /// A modifier and its operands
     Modified(Box<Modified>),
+    /// A placeholder for a macro's operand, like `^0`
+    Placeholder(usize),
+    /// A macro and its operands
+    MacroCall(Box<MacroCall>),
+    /// A binding of a name in a function, like `name ← words`
+    ///
+    /// The name is only visible in the rest of the function that binds it.
+    /// Local bindings never have signatures.
+    LocalBinding(Box<Binding>),
     Comment(String),
     Spaces,
 }
@@ -54,6 +112,18 @@ impl fmt::Debug for Word {
                 }
                 write!(f, "\"")
             }
+            Word::RawString(lines) => {
+                for line in lines {
+                    write!(f, "$$ {}", line.value)?;
+                }
+                Ok(())
+            }
+            Word::GridString(lines) => {
+                for line in lines {
+                    write!(f, "$| {}", line.value)?;
+                }
+                Ok(())
+            }
             Word::MultilineString(lines) => {
                 for line in lines {
                     write!(f, "$ ")?;
@@ -71,15 +141,22 @@ impl fmt::Debug for Word {
             Word::Func(func) => func.fmt(f),
             Word::Primitive(prim) => prim.fmt(f),
             Word::Modified(modified) => modified.fmt(f),
+            Word::Placeholder(n) => write!(f, "^{n}"),
+            Word::MacroCall(call) => call.fmt(f),
+            Word::LocalBinding(binding) => {
+                write!(f, "local({} ← {:?})", binding.name.value, binding.words)
+            }
             Word::Spaces => write!(f, "' '"),
             Word::Comment(comment) => write!(f, "# {comment}"),
         }
     }
 }
 
+/// An array literal
 #[derive(Clone)]
 pub struct Arr {
     pub lines: Vec<Vec<Sp<Word>>>,
+    /// Whether the array is in `{}`, which boxes its items as constant functions
     pub constant: bool,
 }
 
@@ -95,6 +172,7 @@ impl fmt::Debug for Arr {
     }
 }
 
+/// An inline function
 #[derive(Clone)]
 pub struct Func {
     pub id: FunctionId,
@@ -115,10 +193,12 @@ impl fmt::Debug for Func {
     }
 }
 
+/// A modifier applied to its operands
 #[derive(Clone)]
 pub struct Modified {
     pub modifier: Sp<Primitive>,
     pub operands: Vec<Sp<Word>>,
+    /// Whether the operands are terminated with `^`
     pub terminated: bool,
 }
 
@@ -134,3 +214,76 @@ impl fmt::Debug for Modified {
         Ok(())
     }
 }
+
+/// A macro applied to its operands
+#[derive(Clone)]
+pub struct MacroCall {
+    /// The name of the macro, including the `!`
+    pub name: Sp<Ident>,
+    pub operands: Vec<Sp<Word>>,
+    /// Whether the operands are terminated with `^`
+    pub terminated: bool,
+}
+
+impl fmt::Debug for MacroCall {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name.value)?;
+        for word in &self.operands {
+            write!(f, "({:?})", word.value)?;
+        }
+        if self.terminated {
+            write!(f, "|")?;
+        }
+        Ok(())
+    }
+}
+
+/// Get the number of operands a macro with some words takes
+///
+/// This is one more than the highest placeholder index.
+pub fn macro_operand_count(words: &[Sp<Word>]) -> usize {
+    words
+        .iter()
+        .map(|word| match &word.value {
+            Word::Placeholder(n) => n.saturating_add(1),
+            Word::Strand(items) => macro_operand_count(items),
+            Word::Array(Arr { lines, .. }) | Word::Func(Func { lines, .. }) => lines
+                .iter()
+                .map(|line| macro_operand_count(line))
+                .max()
+                .unwrap_or(0),
+            Word::Modified(m) => macro_operand_count(&m.operands),
+            Word::MacroCall(call) => macro_operand_count(&call.operands),
+            Word::LocalBinding(binding) => macro_operand_count(&binding.words),
+            _ => 0,
+        })
+        .max()
+        .unwrap_or(0)
+}
+
+/// Get the comment of a line that is only a comment
+///
+/// Consecutive comment lines directly above a binding are its documentation.
+pub fn line_comment(words: &[Sp<Word>]) -> Option<&str> {
+    match words {
+        [Sp {
+            value: Word::Comment(comment),
+            ..
+        }] => Some(comment),
+        _ => None,
+    }
+}
+
+/// Add a comment line to the documentation of a binding
+///
+/// Lines are joined with spaces, and empty lines separate paragraphs.
+pub fn push_doc_line(doc: &mut String, comment: &str) {
+    if !doc.is_empty() {
+        if comment.trim().is_empty() {
+            doc.push_str("\n\n");
+        } else if !doc.ends_with('\n') {
+            doc.push(' ');
+        }
+    }
+    doc.push_str(comment.trim());
+}
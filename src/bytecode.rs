@@ -0,0 +1,494 @@
+//! A binary encoding of compiled values and functions
+//!
+//! The encoding starts with a magic number and a format version.
+//! It is followed by a table of the source inputs referenced by spans,
+//! a table of the spans themselves, and then the encoded body.
+//! Instructions refer to spans by their index in the span table, and these
+//! indices are remapped to the loading runtime's spans when decoding.
+
+use std::{collections::HashMap, path::Path, sync::Arc};
+
+use once_cell::sync::Lazy;
+
+use crate::{
+    array::{Array, Shape},
+    function::{Function, FunctionId, FunctionKind, Instr, Signature},
+    lex::{CodeSpan, Loc, Span},
+    primitive::Primitive,
+    value::Value,
+    Ident,
+};
+
+const MAGIC: &[u8; 4] = b"UIUA";
+/// Incremented whenever the encoding changes in a way that old data cannot be read
+const FORMAT_VERSION: u8 = 1;
+
+/// Primitives are encoded by name so that adding primitives does not invalidate old data
+static PRIMITIVES_BY_NAME: Lazy<HashMap<String, Primitive>> = Lazy::new(|| {
+    Primitive::all()
+        .map(|prim| (format!("{prim:?}"), prim))
+        .collect()
+});
+
+pub(crate) struct Encoder<'a> {
+    runtime_spans: &'a [Span],
+    inputs: Vec<Arc<str>>,
+    spans: Vec<Span>,
+    span_indices: HashMap<usize, usize>,
+    body: Vec<u8>,
+}
+
+impl<'a> Encoder<'a> {
+    pub fn new(runtime_spans: &'a [Span]) -> Self {
+        Self {
+            runtime_spans,
+            inputs: Vec::new(),
+            spans: Vec::new(),
+            span_indices: HashMap::new(),
+            body: Vec::new(),
+        }
+    }
+    /// Finish encoding, prepending the header and tables to the body
+    pub fn finish(mut self) -> Vec<u8> {
+        let mut tables = Encoder::new(&[]);
+        tables.body.extend(MAGIC);
+        tables.body.push(FORMAT_VERSION);
+        for span in &self.spans {
+            if let Span::Code(span) = span {
+                if !self
+                    .inputs
+                    .iter()
+                    .any(|input| Arc::ptr_eq(input, &span.input))
+                {
+                    self.inputs.push(span.input.clone());
+                }
+            }
+        }
+        tables.usize(self.inputs.len());
+        for input in &self.inputs {
+            tables.str(input);
+        }
+        tables.usize(self.spans.len());
+        for span in &self.spans {
+            match span {
+                Span::Builtin => tables.u8(0),
+                Span::Code(span) => {
+                    tables.u8(1);
+                    let input = self
+                        .inputs
+                        .iter()
+                        .position(|input| Arc::ptr_eq(input, &span.input))
+                        .unwrap();
+                    tables.usize(input);
+                    match &span.path {
+                        Some(path) => {
+                            tables.u8(1);
+                            tables.str(&path.to_string_lossy());
+                        }
+                        None => tables.u8(0),
+                    }
+                    for loc in [&span.start, &span.end] {
+                        tables.usize(loc.char_pos);
+                        tables.usize(loc.byte_pos);
+                        tables.usize(loc.line);
+                        tables.usize(loc.col);
+                    }
+                }
+            }
+        }
+        tables.body.extend(self.body);
+        tables.body
+    }
+    fn u8(&mut self, n: u8) {
+        self.body.push(n);
+    }
+    fn usize(&mut self, mut n: usize) {
+        loop {
+            let byte = (n & 0x7f) as u8;
+            n >>= 7;
+            if n == 0 {
+                self.body.push(byte);
+                break;
+            }
+            self.body.push(byte | 0x80);
+        }
+    }
+    fn str(&mut self, s: &str) {
+        self.usize(s.len());
+        self.body.extend(s.as_bytes());
+    }
+    fn span(&mut self, runtime_index: usize) {
+        let index = match self.span_indices.get(&runtime_index) {
+            Some(&index) => index,
+            None => {
+                let index = self.spans.len();
+                let span = self
+                    .runtime_spans
+                    .get(runtime_index)
+                    .cloned()
+                    .unwrap_or(Span::Builtin);
+                self.spans.push(span);
+                self.span_indices.insert(runtime_index, index);
+                index
+            }
+        };
+        self.usize(index);
+    }
+    pub fn value(&mut self, value: &Value) -> Result<(), String> {
+        fn shape(enc: &mut Encoder, shape: &[usize]) {
+            enc.usize(shape.len());
+            for &dim in shape {
+                enc.usize(dim);
+            }
+        }
+        match value {
+            Value::Num(arr) => {
+                self.u8(0);
+                shape(self, &arr.shape);
+                for n in arr.data.iter() {
+                    self.body.extend(n.to_le_bytes());
+                }
+            }
+            Value::Byte(arr) => {
+                self.u8(1);
+                shape(self, &arr.shape);
+                self.body.extend(arr.data.iter());
+            }
+            Value::Char(arr) => {
+                self.u8(2);
+                shape(self, &arr.shape);
+                for &c in arr.data.iter() {
+                    self.usize(c as usize);
+                }
+            }
+            Value::Func(arr) => {
+                self.u8(3);
+                shape(self, &arr.shape);
+                for f in arr.data.iter() {
+                    self.function(f)?;
+                }
+            }
+        }
+        Ok(())
+    }
+    pub fn function(&mut self, f: &Function) -> Result<(), String> {
+        if let FunctionKind::Dynamic(_) = f.kind {
+            return Err(format!("Cannot encode dynamic function {f}"));
+        }
+        self.function_id(&f.id);
+        self.usize(f.signature().args);
+        self.usize(f.signature().outputs);
+        self.usize(f.instrs.len());
+        for instr in &f.instrs {
+            match instr {
+                Instr::Push(val) => {
+                    self.u8(0);
+                    self.value(val)?;
+                }
+                Instr::BeginArray => self.u8(1),
+                Instr::EndArray { constant, span } => {
+                    self.u8(2);
+                    self.u8(*constant as u8);
+                    self.span(*span);
+                }
+                Instr::Prim(prim, span) => {
+                    self.u8(3);
+                    self.str(&format!("{prim:?}"));
+                    self.span(*span);
+                }
+                Instr::Call(span) => {
+                    self.u8(4);
+                    self.span(*span);
+                }
+                Instr::BindLocal => self.u8(5),
+                Instr::Local(n) => {
+                    self.u8(6);
+                    self.usize(*n);
+                }
+                Instr::DropLocals(n) => {
+                    self.u8(7);
+                    self.usize(*n);
+                }
+            }
+        }
+        Ok(())
+    }
+    fn function_id(&mut self, id: &FunctionId) {
+        match id {
+            FunctionId::Named(name) => {
+                self.u8(0);
+                self.str(name);
+            }
+            FunctionId::Anonymous(span) => {
+                self.u8(1);
+                self.usize(self.spans.len());
+                self.spans.push(Span::Code(span.clone()));
+            }
+            FunctionId::Primitive(prim) => {
+                self.u8(2);
+                self.str(&format!("{prim:?}"));
+            }
+            FunctionId::Constant => self.u8(3),
+            FunctionId::Main => self.u8(4),
+            FunctionId::Composed(ids) => {
+                self.u8(5);
+                self.usize(ids.len());
+                for id in ids {
+                    self.function_id(id);
+                }
+            }
+        }
+    }
+    pub fn name(&mut self, name: &str) {
+        self.str(name);
+    }
+    pub fn count(&mut self, n: usize) {
+        self.usize(n);
+    }
+    pub fn u64(&mut self, n: u64) {
+        self.body.extend(n.to_le_bytes());
+    }
+}
+
+pub(crate) struct Decoder<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+    /// The decoded span table
+    spans: Vec<Span>,
+    /// The loading runtime's index for each span in the table
+    span_indices: Vec<usize>,
+}
+
+impl<'a> Decoder<'a> {
+    /// Read the header and tables, registering spans with `add_span`
+    pub fn new(bytes: &'a [u8], mut add_span: impl FnMut(Span) -> usize) -> Result<Self, String> {
+        let mut dec = Decoder {
+            bytes,
+            pos: 0,
+            spans: Vec::new(),
+            span_indices: Vec::new(),
+        };
+        if dec.take(MAGIC.len())? != MAGIC {
+            return Err("Data is not compiled Uiua".into());
+        }
+        let version = dec.u8()?;
+        if version != FORMAT_VERSION {
+            return Err(format!(
+                "Compiled Uiua has format version {version}, \
+                but only version {FORMAT_VERSION} is supported"
+            ));
+        }
+        let input_count = dec.len()?;
+        let mut inputs: Vec<Arc<str>> = Vec::with_capacity(input_count);
+        for _ in 0..input_count {
+            inputs.push(dec.str()?.into());
+        }
+        let span_count = dec.len()?;
+        for _ in 0..span_count {
+            let span = match dec.u8()? {
+                0 => Span::Builtin,
+                1 => {
+                    let input = inputs
+                        .get(dec.usize()?)
+                        .cloned()
+                        .ok_or("Span refers to an invalid input")?;
+                    let path = match dec.u8()? {
+                        0 => None,
+                        _ => Some(Arc::from(Path::new(&dec.str()?))),
+                    };
+                    let start = dec.loc()?;
+                    let end = dec.loc()?;
+                    Span::Code(CodeSpan {
+                        start,
+                        end,
+                        path,
+                        input,
+                    })
+                }
+                tag => return Err(format!("Invalid span tag {tag}")),
+            };
+            dec.span_indices.push(add_span(span.clone()));
+            dec.spans.push(span);
+        }
+        Ok(dec)
+    }
+    pub fn is_done(&self) -> bool {
+        self.pos == self.bytes.len()
+    }
+    fn take(&mut self, n: usize) -> Result<&'a [u8], String> {
+        let end = self
+            .pos
+            .checked_add(n)
+            .filter(|&end| end <= self.bytes.len());
+        let end = end.ok_or("Compiled Uiua ended unexpectedly")?;
+        let bytes = &self.bytes[self.pos..end];
+        self.pos = end;
+        Ok(bytes)
+    }
+    fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.take(1)?[0])
+    }
+    fn usize(&mut self) -> Result<usize, String> {
+        let mut n: usize = 0;
+        let mut shift = 0;
+        loop {
+            let byte = self.u8()?;
+            if shift >= usize::BITS {
+                return Err("Encoded number is too large".into());
+            }
+            n |= ((byte & 0x7f) as usize) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(n);
+            }
+            shift += 7;
+        }
+    }
+    /// Read a length, checking that there are enough bytes left for it to be valid
+    ///
+    /// Every encoded item takes at least one byte, so this prevents
+    /// huge allocations when reading corrupted data.
+    fn len(&mut self) -> Result<usize, String> {
+        let len = self.usize()?;
+        if len > self.bytes.len() - self.pos {
+            return Err("Compiled Uiua ended unexpectedly".into());
+        }
+        Ok(len)
+    }
+    fn loc(&mut self) -> Result<Loc, String> {
+        Ok(Loc {
+            char_pos: self.usize()?,
+            byte_pos: self.usize()?,
+            line: self.usize()?,
+            col: self.usize()?,
+        })
+    }
+    fn str(&mut self) -> Result<String, String> {
+        let len = self.len()?;
+        let bytes = self.take(len)?;
+        String::from_utf8(bytes.to_vec()).map_err(|e| e.to_string())
+    }
+    fn span(&mut self) -> Result<usize, String> {
+        let index = self.usize()?;
+        self.span_indices
+            .get(index)
+            .copied()
+            .ok_or_else(|| format!("Invalid span index {index}"))
+    }
+    fn primitive(&mut self) -> Result<Primitive, String> {
+        let name = self.str()?;
+        PRIMITIVES_BY_NAME
+            .get(&name)
+            .copied()
+            .ok_or_else(|| format!("Unknown primitive {name}"))
+    }
+    pub fn value(&mut self) -> Result<Value, String> {
+        let tag = self.u8()?;
+        let rank = self.len()?;
+        let mut shape = Shape::with_capacity(rank);
+        for _ in 0..rank {
+            shape.push(self.usize()?);
+        }
+        let len = shape
+            .iter()
+            .try_fold(1usize, |acc, &dim| acc.checked_mul(dim))
+            .filter(|&len| len <= self.bytes.len() - self.pos)
+            .ok_or("Compiled Uiua ended unexpectedly")?;
+        Ok(match tag {
+            0 => {
+                let mut data = Vec::with_capacity(len);
+                for _ in 0..len {
+                    data.push(f64::from_le_bytes(self.take(8)?.try_into().unwrap()));
+                }
+                Array::new(shape, data).into()
+            }
+            1 => Array::new(shape, self.take(len)?.to_vec()).into(),
+            2 => {
+                let mut data = Vec::with_capacity(len);
+                for _ in 0..len {
+                    let c = u32::try_from(self.usize()?).ok().and_then(char::from_u32);
+                    data.push(c.ok_or("Invalid character")?);
+                }
+                Array::new(shape, data).into()
+            }
+            3 => {
+                let mut data = Vec::with_capacity(len);
+                for _ in 0..len {
+                    data.push(Arc::new(self.function()?));
+                }
+                Array::new(shape, data).into()
+            }
+            tag => return Err(format!("Invalid value tag {tag}")),
+        })
+    }
+    pub fn function(&mut self) -> Result<Function, String> {
+        let id = self.function_id()?;
+        let signature = Signature::new(self.usize()?, self.usize()?);
+        let instr_count = self.len()?;
+        let mut instrs = Vec::with_capacity(instr_count);
+        for _ in 0..instr_count {
+            instrs.push(match self.u8()? {
+                0 => Instr::push(self.value()?),
+                1 => Instr::BeginArray,
+                2 => Instr::EndArray {
+                    constant: self.u8()? != 0,
+                    span: self.span()?,
+                },
+                3 => Instr::Prim(self.primitive()?, self.span()?),
+                4 => Instr::Call(self.span()?),
+                5 => Instr::BindLocal,
+                6 => Instr::Local(self.usize()?),
+                7 => Instr::DropLocals(self.usize()?),
+                tag => return Err(format!("Invalid instruction tag {tag}")),
+            });
+        }
+        Ok(Function::new(id, instrs, FunctionKind::Normal, signature))
+    }
+    fn function_id(&mut self) -> Result<FunctionId, String> {
+        Ok(match self.u8()? {
+            0 => FunctionId::Named(Ident::from(self.str()?)),
+            1 => {
+                let index = self.usize()?;
+                match self.spans.get(index) {
+                    Some(Span::Code(span)) => FunctionId::Anonymous(span.clone()),
+                    _ => return Err(format!("Invalid function span index {index}")),
+                }
+            }
+            2 => FunctionId::Primitive(self.primitive()?),
+            3 => FunctionId::Constant,
+            4 => FunctionId::Main,
+            5 => {
+                let count = self.len()?;
+                let mut ids = Vec::with_capacity(count);
+                for _ in 0..count {
+                    ids.push(self.function_id()?);
+                }
+                FunctionId::Composed(ids)
+            }
+            tag => return Err(format!("Invalid function id tag {tag}")),
+        })
+    }
+    pub fn name(&mut self) -> Result<Ident, String> {
+        self.str().map(Ident::from)
+    }
+    pub fn count(&mut self) -> Result<usize, String> {
+        self.len()
+    }
+    pub fn u64(&mut self) -> Result<u64, String> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+}
+
+#[test]
+fn varint_round_trip() {
+    let mut enc = Encoder::new(&[]);
+    let nums = [0, 1, 127, 128, 300, usize::MAX];
+    for n in nums {
+        enc.usize(n);
+    }
+    let bytes = enc.finish();
+    let mut dec = Decoder::new(&bytes, |_| 0).unwrap();
+    for n in nums {
+        assert_eq!(dec.usize().unwrap(), n);
+    }
+    assert!(dec.is_done());
+}
@@ -105,6 +105,11 @@ impl<'a> VirtualEnv<'a> {
                 self.stack.push(BasicValue::Arr(items));
             }
             Instr::Call(_) => self.handle_call(false)?,
+            Instr::BindLocal => {
+                self.pop()?;
+            }
+            Instr::Local(_) => self.stack.push(BasicValue::Unknown),
+            Instr::DropLocals(_) => {}
             Instr::Prim(prim, _) => match prim {
                 Reduce | Scan => self.handle_mod(prim, Some(2), Some(1), 1, None)?,
                 Fold => self.handle_mod(prim, Some(2), Some(1), 2, None)?,
@@ -271,12 +276,15 @@ impl<'a> VirtualEnv<'a> {
                     let handler = self.pop()?;
                     let f_sig = f.signature();
                     let handler_sig = handler.signature();
-                    if !f_sig.is_compatible_with(handler_sig) {
+                    // The handler is called with f's arguments and the error
+                    let expected = Signature::new(f_sig.args + 1, f_sig.outputs);
+                    if !handler_sig.is_compatible_with(expected) {
                         return Err(format!(
                             "try's functions have incompatible signatures {f_sig} and {handler_sig}"
                         ));
                     }
-                    let sig = f_sig.max_with(handler_sig);
+                    let args = f_sig.args.max(handler_sig.args.saturating_sub(1));
+                    let sig = Signature::new(args, args - f_sig.args + f_sig.outputs);
                     for _ in 0..sig.args {
                         self.pop()?;
                     }
@@ -556,7 +564,7 @@ impl<'a> VirtualEnv<'a> {
 
 #[cfg(test)]
 mod test {
-    use crate::value::Value;
+    use crate::{value::Value, Uiua};
 
     use super::*;
     use Instr::*;
@@ -614,4 +622,39 @@ mod test {
             ])
         );
     }
+
+    #[test]
+    fn try_handlers_take_the_error() {
+        let mut env = Uiua::with_native_sys();
+        env.load_str("F ← ⍣(+1)(;)\nG ← ⍣+(+;)\nH ← ⍣(⍤\"no\" 0;)(;;)")
+            .unwrap();
+        let sigs: Vec<Signature> = env.bindings().into_iter().map(|(_, sig)| sig).collect();
+        assert_eq!(sigs, [(1, 1), (2, 1), (1, 0)]);
+        assert!(Uiua::with_native_sys().load_str("F ← ⍣(+1)(5)").is_err());
+    }
+
+    #[test]
+    fn popped_copies_keep_their_effect() {
+        let mut env = Uiua::with_native_sys();
+        env.load_str("F ← (;.)\nG ← (;,)\nH ← (;△.)\nI ← (;.5)")
+            .unwrap();
+        let sigs: Vec<Signature> = env.bindings().into_iter().map(|(_, sig)| sig).collect();
+        assert_eq!(sigs, [(1, 1), (2, 2), (1, 1), (0, 1)]);
+        for code in [";.", ";,", ";, 1", ";△."] {
+            let err = Uiua::with_native_sys().load_str(code).err().unwrap();
+            assert!(err.to_string().contains("Stack was empty"), "{code}: {err}");
+        }
+    }
+
+    #[test]
+    fn declared_signatures_are_verified() {
+        for code in ["F ← |2 +1", "∵(|2 +1) 1_2"] {
+            let err = Uiua::with_native_sys().load_str(code).err().unwrap();
+            let message = err.to_string();
+            assert!(message.contains("declared |2"), "{message}");
+            assert!(message.contains("inferred |1"), "{message}");
+        }
+        // Declaring more arguments than are used is allowed if the stack effect matches
+        Uiua::with_native_sys().load_str("F ← |2.2 +1").unwrap();
+    }
 }
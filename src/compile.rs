@@ -0,0 +1,187 @@
+//! Compile-time evaluation of constant instructions
+
+use crate::{
+    check::instrs_signature,
+    function::{Instr, Signature},
+    lex::{CodeSpan, Sp, Span},
+    primitive::Primitive,
+    value::Value,
+    ErrorCode, Uiua, UiuaError, UiuaResult,
+};
+
+/// Check a function's instructions against its declared signature
+///
+/// Returns the function's signature and whether it was inferred.
+/// A declared signature is used if it is compatible with the inferred one
+/// or if no signature can be inferred.
+pub(crate) fn verify_signature(
+    instrs: &[Instr],
+    declared: Option<&Sp<Signature>>,
+    span: &CodeSpan,
+) -> UiuaResult<(Signature, bool)> {
+    match (instrs_signature(instrs), declared) {
+        (Ok(sig), None) => Ok((sig, true)),
+        (Ok(sig), Some(declared)) => {
+            if declared.value.is_superset_of(sig) {
+                Ok((declared.value, true))
+            } else {
+                Err(UiuaError::Compile(
+                    ErrorCode::SignatureMismatch,
+                    Span::Code(declared.span.clone()).sp(format!(
+                        "Function signature mismatch: declared {} but inferred {}",
+                        declared.value, sig
+                    )),
+                )
+                .with_label(span.clone(), format!("{sig} inferred from here")))
+            }
+        }
+        (Err(_), Some(declared)) => Ok((declared.value, false)),
+        (Err(e), None) => Err(UiuaError::Compile(
+            ErrorCode::SignatureMismatch,
+            Span::Code(span.clone()).sp(format!("Cannot infer function signature: {e}")),
+        )),
+    }
+}
+
+/// The maximum size in bytes of a value that may be produced by folding
+///
+/// This keeps both compilation time and the size of compiled functions small.
+const MAX_FOLDED_BYTES: usize = 1 << 16;
+
+/// The total size in bytes of the arguments and results that may be folded
+/// while compiling a single input
+///
+/// Folding stops once this runs out, so an input made of many constant
+/// expressions cannot make compilation arbitrarily slow.
+pub(crate) const FOLD_FUEL: usize = 1 << 24;
+
+/// Evaluate a primitive at compile time if all of its arguments are
+/// pushed by the last instructions in `instrs`
+///
+/// On success, the argument pushes are replaced with pushes of the results,
+/// and the size of the arguments and results is taken from `fuel`.
+/// Primitives that fail, hit the memory limit, or would use more than
+/// the remaining fuel are left to run at runtime, where a failure has a proper trace.
+///
+/// Fill values only affect operations that would otherwise fail,
+/// so an operation that succeeds here gives the same result under any fill.
+pub(crate) fn fold_constant(
+    instrs: &mut Vec<Instr>,
+    prim: Primitive,
+    env: &Uiua,
+    fuel: &mut usize,
+) -> bool {
+    if !prim.is_pure()
+        || prim.modifier_args().is_some()
+        || prim.as_constant().is_some()
+        || may_grow_unchecked(prim)
+    {
+        return false;
+    }
+    let (Some(args), Some(outputs)) = (prim.args(), prim.outputs()) else {
+        return false;
+    };
+    let args = args as usize;
+    if args == 0 || args > instrs.len() {
+        return false;
+    }
+    let start = instrs.len() - args;
+    let mut stack = Vec::with_capacity(args);
+    for instr in &instrs[start..] {
+        match instr {
+            Instr::Push(val) if !matches!(**val, Value::Func(_)) => stack.push(Value::clone(val)),
+            _ => return false,
+        }
+    }
+    let size = |val: &Value| val.flat_len().saturating_mul(val.element_size());
+    let arg_bytes = stack.iter().map(size).fold(0, usize::saturating_add);
+    if arg_bytes > *fuel {
+        return false;
+    }
+    let mut env = env.fold_env(stack, MAX_FOLDED_BYTES.min(*fuel - arg_bytes));
+    if prim.run(&mut env).is_err() {
+        return false;
+    }
+    let results = env.take_stack();
+    let result_bytes = results.iter().map(size).fold(0, usize::saturating_add);
+    if results.len() != outputs as usize
+        || results.iter().any(|val| size(val) > MAX_FOLDED_BYTES)
+        || arg_bytes.saturating_add(result_bytes) > *fuel
+    {
+        return false;
+    }
+    *fuel -= arg_bytes + result_bytes;
+    instrs.truncate(start);
+    instrs.extend(results.into_iter().map(Instr::push));
+    true
+}
+
+/// Whether a primitive's result can be much larger than its arguments
+/// without the primitive checking the memory limit first
+///
+/// Running these at compile time could take a long time or exhaust memory
+/// before their result is found to be too large to fold.
+fn may_grow_unchecked(prim: Primitive) -> bool {
+    use Primitive::*;
+    matches!(
+        prim,
+        Dot | Pick
+            | Unpick
+            | Select
+            | Unselect
+            | Untake
+            | Undrop
+            | Windows
+            | Keep
+            | KeepEq
+            | KeepNe
+            | KeepLt
+            | KeepLe
+            | KeepGt
+            | KeepGe
+    )
+}
+
+/// Remove the instruction that produced a value which is immediately popped
+///
+/// Only instructions that cannot fail are removed. Instructions that consume
+/// arguments are replaced with pops of those arguments, which may in turn be removed.
+/// Instructions that copy values are only removed if the values they copy are
+/// pushed by the instructions before them, since copying a value that is not
+/// there fails and counts toward the function's signature.
+///
+/// Returns whether the pop was absorbed.
+pub(crate) fn eliminate_pop(instrs: &mut Vec<Instr>, span: usize) -> bool {
+    use Primitive::*;
+    let pushed_before = |n: usize| {
+        let len = instrs.len();
+        len > n
+            && (instrs[len - 1 - n..len - 1].iter()).all(|instr| matches!(instr, Instr::Push(_)))
+    };
+    match instrs.last() {
+        Some(Instr::Push(_)) => {
+            instrs.pop();
+            true
+        }
+        Some(&Instr::Prim(prim, _)) if prim.as_constant().is_some() => {
+            instrs.pop();
+            true
+        }
+        Some(Instr::Prim(Dup, _)) if pushed_before(1) => {
+            instrs.pop();
+            true
+        }
+        Some(Instr::Prim(Over, _)) if pushed_before(2) => {
+            instrs.pop();
+            true
+        }
+        Some(Instr::Prim(Len | Rank | Shape | Type, _)) => {
+            instrs.pop();
+            if !eliminate_pop(instrs, span) {
+                instrs.push(Instr::Prim(Pop, span));
+            }
+            true
+        }
+        _ => false,
+    }
+}
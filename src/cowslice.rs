@@ -32,6 +32,18 @@ impl<T> CowSlice<T> {
     pub fn truncate(&mut self, len: usize) {
         self.end = (self.start + len as u32).min(self.end);
     }
+    /// Check whether this slice is the only reference to its buffer
+    ///
+    /// If it is, mutating it will not copy.
+    pub fn is_unique(&mut self) -> bool {
+        self.data.is_unique()
+    }
+    /// Check whether two slices view the same part of the same buffer
+    pub fn ptr_eq(&self, other: &Self) -> bool {
+        self.data.as_ptr() == other.data.as_ptr()
+            && self.start == other.start
+            && self.end == other.end
+    }
 }
 
 impl<T: Clone> CowSlice<T> {
@@ -119,7 +131,7 @@ impl<T: Clone> DerefMut for CowSlice<T> {
         if !self.data.is_unique() {
             *self = self.to_vec().into();
         }
-        self.data.make_mut()
+        &mut self.data.make_mut()[self.start as usize..self.end as usize]
     }
 }
 
@@ -133,6 +145,11 @@ fn cow_slice_deref_mut() {
     sub[1] = 5;
     assert_eq!(slice, [1, 7, 3, 4]);
     assert_eq!(sub, [7, 5]);
+
+    let mut sub = CowSlice::from([1, 2, 3, 4]).slice(1..=2);
+    assert!(sub.is_unique());
+    sub[0] = 8;
+    assert_eq!(sub, [8, 3]);
 }
 
 impl<T: Clone> From<Vec<T>> for CowSlice<T> {
@@ -0,0 +1,219 @@
+//! Documentation generation for primitives and bindings
+
+use crate::{
+    highlight::{escape_html, highlight_html},
+    primitive::{PrimDoc, PrimDocFragment, PrimDocLine, Primitive},
+    Uiua,
+};
+
+/// The format to render documentation in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DocFormat {
+    /// Markdown
+    Markdown,
+    /// A standalone HTML page
+    Html,
+}
+
+/// Render documentation for all primitives
+///
+/// Rendering runs the examples to show their outputs.
+pub fn primitive_docs(format: DocFormat) -> String {
+    let mut body = String::new();
+    for prim in Primitive::all() {
+        let (Some(name), Some(doc)) = (prim.name(), prim.doc()) else {
+            continue;
+        };
+        let title = match prim.unicode() {
+            Some(c) => format!("{c} {name}"),
+            None => name.into(),
+        };
+        let mut details = Vec::new();
+        match prim.modifier_args() {
+            Some(1) => details.push("Modifier with 1 function argument".into()),
+            Some(args) => details.push(format!("Modifier with {args} function arguments")),
+            None => {}
+        }
+        if let Some(sig) = prim.signature() {
+            details.push(format!("Signature {sig}"));
+        }
+        if let Some(ascii) = prim.ascii() {
+            details.push(format!("ASCII {ascii}"));
+        }
+        match format {
+            DocFormat::Markdown => {
+                body.push_str(&format!("## {title}\n\n"));
+                if !details.is_empty() {
+                    body.push_str(&format!("*{}*\n\n", details.join(" · ")));
+                }
+                prim_doc_markdown(doc, &mut body);
+            }
+            DocFormat::Html => {
+                body.push_str(&format!("<h2>{}</h2>\n", escape_html(&title)));
+                if !details.is_empty() {
+                    body.push_str(&format!(
+                        "<p><em>{}</em></p>\n",
+                        escape_html(&details.join(" · "))
+                    ));
+                }
+                prim_doc_html(doc, &mut body);
+            }
+        }
+    }
+    page("Primitives", &body, format)
+}
+
+/// Render documentation for the bindings in scope
+///
+/// Each binding is documented with its signature and the comment lines
+/// directly above it.
+pub fn binding_docs(env: &Uiua, title: &str, format: DocFormat) -> String {
+    let mut body = String::new();
+    for (name, sig) in env.bindings() {
+        let doc = env.binding_doc(&name);
+        match format {
+            DocFormat::Markdown => {
+                body.push_str(&format!("## {name}\n\n*Signature {sig}*\n\n"));
+                if let Some(doc) = doc {
+                    body.push_str(&format!("{doc}\n\n"));
+                }
+            }
+            DocFormat::Html => {
+                body.push_str(&format!("<h2>{}</h2>\n", escape_html(&name)));
+                body.push_str(&format!("<p><em>Signature {sig}</em></p>\n"));
+                if let Some(doc) = doc {
+                    for para in doc.split("\n\n") {
+                        let para = escape_html(para).replace('\n', "<br>");
+                        body.push_str(&format!("<p>{para}</p>\n"));
+                    }
+                }
+            }
+        }
+    }
+    page(title, &body, format)
+}
+
+fn page(title: &str, body: &str, format: DocFormat) -> String {
+    match format {
+        DocFormat::Markdown => format!("# {title}\n\n{}\n", body.trim_end()),
+        DocFormat::Html => {
+            let title = escape_html(title);
+            format!(
+                "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n\
+                <title>{title}</title>\n</head>\n<body>\n<h1>{title}</h1>\n{body}</body>\n</html>\n"
+            )
+        }
+    }
+}
+
+fn prim_doc_markdown(doc: &PrimDoc, s: &mut String) {
+    s.push_str(&format!("{}\n\n", fragments_markdown(&doc.short)));
+    for line in &doc.lines {
+        match line {
+            PrimDocLine::Text(frags) if frags.is_empty() => {}
+            PrimDocLine::Text(frags) => s.push_str(&format!("{}\n\n", fragments_markdown(frags))),
+            PrimDocLine::Example(ex) => {
+                s.push_str(&format!("```uiua\n{}\n```\n", ex.input()));
+                match ex.output() {
+                    Ok(values) if values.is_empty() => {}
+                    Ok(values) => s.push_str(&format!("```\n{}\n```\n", values.join("\n"))),
+                    Err(e) => s.push_str(&format!("```\nError: {e}\n```\n")),
+                }
+                s.push('\n');
+            }
+        }
+    }
+}
+
+fn prim_doc_html(doc: &PrimDoc, s: &mut String) {
+    s.push_str(&format!("<p>{}</p>\n", fragments_html(&doc.short)));
+    for line in &doc.lines {
+        match line {
+            PrimDocLine::Text(frags) if frags.is_empty() => {}
+            PrimDocLine::Text(frags) => s.push_str(&format!("<p>{}</p>\n", fragments_html(frags))),
+            PrimDocLine::Example(ex) => {
+                s.push_str(&format!(
+                    "<pre><code>{}</code></pre>\n",
+                    highlight_html(ex.input())
+                ));
+                match ex.output() {
+                    Ok(values) if values.is_empty() => {}
+                    Ok(values) => {
+                        let output = escape_html(&values.join("\n"));
+                        s.push_str(&format!("<pre class=\"output\">{output}</pre>\n"));
+                    }
+                    Err(e) => {
+                        let e = escape_html(e);
+                        s.push_str(&format!("<pre class=\"output-error\">Error: {e}</pre>\n"));
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn prim_text(prim: Primitive, named: bool) -> String {
+    match (named, prim.unicode(), prim.name()) {
+        (false, Some(c), _) => c.into(),
+        (_, _, Some(name)) => name.into(),
+        _ => format!("{prim:?}"),
+    }
+}
+
+fn fragments_markdown(frags: &[PrimDocFragment]) -> String {
+    let mut s = String::new();
+    for frag in frags {
+        match frag {
+            PrimDocFragment::Text(t) => s.push_str(t),
+            PrimDocFragment::Code(c) => s.push_str(&format!("`{c}`")),
+            PrimDocFragment::Emphasis(e) => s.push_str(&format!("*{e}*")),
+            PrimDocFragment::Primitive { prim, named: true } => match prim.unicode() {
+                Some(c) => s.push_str(&format!("`{c}` {}", prim_text(*prim, true))),
+                None => s.push_str(&format!("`{}`", prim_text(*prim, true))),
+            },
+            PrimDocFragment::Primitive { prim, named: false } => {
+                s.push_str(&format!("`{}`", prim_text(*prim, false)))
+            }
+        }
+    }
+    s
+}
+
+fn fragments_html(frags: &[PrimDocFragment]) -> String {
+    let mut s = String::new();
+    for frag in frags {
+        match frag {
+            PrimDocFragment::Text(t) => s.push_str(&escape_html(t)),
+            PrimDocFragment::Code(c) => s.push_str(&format!("<code>{}</code>", escape_html(c))),
+            PrimDocFragment::Emphasis(e) => s.push_str(&format!("<em>{}</em>", escape_html(e))),
+            PrimDocFragment::Primitive { prim, named } => {
+                let text = escape_html(&prim_text(*prim, *named));
+                s.push_str(&format!("<code>{text}</code>"))
+            }
+        }
+    }
+    s
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn binding_documentation() {
+        use {binding_docs, DocFormat};
+        let mut env = Uiua::with_native_sys();
+        env.load_str("# Add one to a number\nIncr ← +1\nX ← 5")
+            .unwrap();
+        let md = binding_docs(&env, "Module", DocFormat::Markdown);
+        assert!(md.starts_with("# Module\n"), "{md}");
+        assert!(
+            md.contains("## Incr\n\n*Signature |1.1*\n\nAdd one to a number"),
+            "{md}"
+        );
+        assert!(md.contains("## X\n"), "{md}");
+        let html = binding_docs(&env, "<Module>", DocFormat::Html);
+        assert!(html.contains("<title>&lt;Module&gt;</title>"), "{html}");
+        assert!(html.contains("<p>Add one to a number</p>"), "{html}");
+    }
+}
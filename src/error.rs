@@ -1,8 +1,7 @@
 use std::{
-    collections::HashMap,
     convert::Infallible,
     error::Error,
-    fmt, fs, io,
+    fmt, io,
     path::{Path, PathBuf},
     sync::Arc,
 };
@@ -10,7 +9,7 @@ use std::{
 use ariadne::{Color, Config, Label, Report, ReportKind, Source};
 
 use crate::{
-    example_ua,
+    error_code::ErrorCode,
     function::FunctionId,
     lex::{CodeSpan, Sp, Span},
     parse::ParseError,
@@ -23,6 +22,8 @@ pub enum UiuaError {
     Format(PathBuf, Arc<io::Error>),
     Parse(Vec<Sp<ParseError>>),
     Run(Sp<String, Span>),
+    /// An error found while compiling that has its own [`ErrorCode`]
+    Compile(ErrorCode, Sp<String, Span>),
     Traced {
         error: Box<Self>,
         trace: Vec<TraceFrame>,
@@ -30,7 +31,16 @@ pub enum UiuaError {
     Throw(Box<Value>, Span),
     Break(usize, Span),
     Timeout(Span),
+    /// The runtime ran out of fuel for executing instructions
+    OutOfFuel(Span),
+    /// The runtime was interrupted with an [`InterruptHandle`](crate::run::InterruptHandle)
+    Interrupted(Span),
     Fill(Box<Self>),
+    /// An error with secondary labels that point at related code
+    Labeled {
+        error: Box<Self>,
+        labels: Vec<Sp<String>>,
+    },
 }
 
 pub type UiuaResult<T = ()> = Result<T, UiuaError>;
@@ -68,7 +78,7 @@ impl fmt::Display for UiuaError {
                 }
                 Ok(())
             }
-            UiuaError::Run(error) => write!(f, "{error}"),
+            UiuaError::Run(error) | UiuaError::Compile(_, error) => write!(f, "{error}"),
             UiuaError::Traced { error, trace } => {
                 write!(f, "{error}")?;
                 format_trace(f, trace)
@@ -76,7 +86,16 @@ impl fmt::Display for UiuaError {
             UiuaError::Throw(value, span) => write!(f, "{span}: {value}"),
             UiuaError::Break(_, span) => write!(f, "{span}: break outside of loop"),
             UiuaError::Timeout(_) => write!(f, "Maximum execution time exceeded"),
+            UiuaError::OutOfFuel(_) => write!(f, "Instruction limit exceeded"),
+            UiuaError::Interrupted(_) => write!(f, "Program interrupted"),
             UiuaError::Fill(error) => error.fmt(f),
+            UiuaError::Labeled { error, labels } => {
+                write!(f, "{error}")?;
+                for label in labels {
+                    write!(f, "\n  {}: {}", label.span, label.value)?;
+                }
+                Ok(())
+            }
         }
     }
 }
@@ -84,14 +103,14 @@ impl fmt::Display for UiuaError {
 impl UiuaError {
     pub fn message(&self) -> String {
         match self {
-            UiuaError::Traced { error, .. } => error.message(),
+            UiuaError::Traced { error, .. } | UiuaError::Labeled { error, .. } => error.message(),
             error => error.to_string(),
         }
     }
     pub fn value(self) -> Value {
         match self {
             UiuaError::Throw(value, _) => *value,
-            UiuaError::Traced { error, .. } => error.value(),
+            UiuaError::Traced { error, .. } | UiuaError::Labeled { error, .. } => error.value(),
             error => error.message().into(),
         }
     }
@@ -103,6 +122,9 @@ impl UiuaError {
                     trace,
                 })
             }
+            UiuaError::Labeled { error, labels } => error
+                .break_data()
+                .map_err(|error| error.with_labels(labels)),
             UiuaError::Break(n, span) => Ok((n, span)),
             error => Err(error),
         }
@@ -110,7 +132,7 @@ impl UiuaError {
     /// Check if the error is fill-related
     pub(crate) fn is_fill(&self) -> bool {
         match self {
-            UiuaError::Traced { error, .. } => error.is_fill(),
+            UiuaError::Traced { error, .. } | UiuaError::Labeled { error, .. } => error.is_fill(),
             UiuaError::Fill(_) => true,
             _ => false,
         }
@@ -119,6 +141,54 @@ impl UiuaError {
     pub(crate) fn fill(self) -> Self {
         UiuaError::Fill(Box::new(self))
     }
+    /// Get the code of the error's category
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            UiuaError::Load(..) => ErrorCode::Load,
+            UiuaError::Format(..) => ErrorCode::Format,
+            UiuaError::Parse(errors) => errors
+                .first()
+                .map_or(ErrorCode::Expected, |error| error.value.code()),
+            UiuaError::Run(_) => ErrorCode::Runtime,
+            UiuaError::Compile(code, _) => *code,
+            UiuaError::Traced { error, .. }
+            | UiuaError::Labeled { error, .. }
+            | UiuaError::Fill(error) => error.code(),
+            UiuaError::Throw(..) => ErrorCode::Thrown,
+            UiuaError::Break(..) => ErrorCode::BreakOutsideLoop,
+            UiuaError::Timeout(_) => ErrorCode::Timeout,
+            UiuaError::OutOfFuel(_) => ErrorCode::OutOfFuel,
+            UiuaError::Interrupted(_) => ErrorCode::Interrupted,
+        }
+    }
+    /// Create an error for code that cannot be compiled
+    pub(crate) fn compile(span: CodeSpan, message: impl Into<String>) -> Self {
+        UiuaError::Compile(ErrorCode::Compile, Span::Code(span).sp(message.into()))
+    }
+    /// Add a secondary label that points at related code
+    pub fn with_label(self, span: CodeSpan, message: impl Into<String>) -> Self {
+        self.with_labels(vec![span.sp(message.into())])
+    }
+    fn with_labels(self, mut new_labels: Vec<Sp<String>>) -> Self {
+        match self {
+            UiuaError::Labeled { error, mut labels } => {
+                labels.append(&mut new_labels);
+                UiuaError::Labeled { error, labels }
+            }
+            error => UiuaError::Labeled {
+                error: Box::new(error),
+                labels: new_labels,
+            },
+        }
+    }
+    /// Get the secondary labels of the error
+    pub fn labels(&self) -> &[Sp<String>] {
+        match self {
+            UiuaError::Labeled { labels, .. } => labels,
+            UiuaError::Traced { error, .. } | UiuaError::Fill(error) => error.labels(),
+            _ => &[],
+        }
+    }
 }
 
 fn format_trace<F: fmt::Write>(f: &mut F, trace: &[TraceFrame]) -> fmt::Result {
@@ -189,63 +259,217 @@ impl From<Infallible> for UiuaError {
 
 impl UiuaError {
     pub fn show(&self, color: bool) -> String {
+        (self.reports().iter())
+            .map(|report| report.show(color))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+    /// Get structured descriptions of the error
+    ///
+    /// This is for hosts that render errors their own way. Most errors have
+    /// one report, but there is one for each error found while parsing.
+    pub fn reports(&self) -> Vec<ErrorReport> {
+        let single = |message: String, span: Span| {
+            vec![ErrorReport {
+                code: self.code(),
+                message,
+                span,
+                labels: Vec::new(),
+                trace: Vec::new(),
+            }]
+        };
         match self {
-            UiuaError::Parse(errors) => report(
-                errors
-                    .iter()
-                    .map(|error| (error.value.to_string(), error.span.clone().into())),
-                color,
-            ),
-            UiuaError::Run(error) => report([(&error.value, error.span.clone())], color),
+            UiuaError::Parse(errors) => (errors.iter())
+                .map(|error| ErrorReport {
+                    code: error.value.code(),
+                    message: error.value.to_string(),
+                    span: error.span.clone().into(),
+                    labels: Vec::new(),
+                    trace: Vec::new(),
+                })
+                .collect(),
+            UiuaError::Run(error) | UiuaError::Compile(_, error) => {
+                single(error.value.clone(), error.span.clone())
+            }
             UiuaError::Traced { error, trace } => {
-                let mut s = error.show(color);
-                format_trace(&mut s, trace).unwrap();
-                s
+                let mut reports = error.reports();
+                if let Some(report) = reports.first_mut() {
+                    report.trace = trace.clone();
+                }
+                reports
             }
-            UiuaError::Throw(message, span) => report([(&message, span.clone())], color),
-            UiuaError::Break(_, span) => report([("break outside of loop", span.clone())], color),
+            UiuaError::Throw(value, span) => single(value.to_string(), span.clone()),
+            UiuaError::Break(_, span) => single("break outside of loop".into(), span.clone()),
             UiuaError::Timeout(span) => {
-                report([("Maximum execution time exceeded", span.clone())], color)
+                single("Maximum execution time exceeded".into(), span.clone())
             }
-            UiuaError::Fill(error) => error.show(color),
-            UiuaError::Load(..) | UiuaError::Format(..) => self.to_string(),
+            UiuaError::OutOfFuel(span) => single("Instruction limit exceeded".into(), span.clone()),
+            UiuaError::Interrupted(span) => single("Program interrupted".into(), span.clone()),
+            UiuaError::Fill(error) => error.reports(),
+            UiuaError::Labeled { error, labels } => {
+                let mut reports = error.reports();
+                if let Some(report) = reports.first_mut() {
+                    report.labels.extend(labels.iter().cloned());
+                }
+                reports
+            }
+            UiuaError::Load(..) | UiuaError::Format(..) => single(self.to_string(), Span::Builtin),
         }
     }
 }
 
-fn report<I, T>(errors: I, complex_output: bool) -> String
+/// A structured description of an error
+///
+/// This is what [`UiuaError::show`] renders.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ErrorReport {
+    /// The code of the error's category
+    pub code: ErrorCode,
+    /// A description of the error
+    pub message: String,
+    /// Where the error is
+    pub span: Span,
+    /// Secondary labels that point at related code
+    pub labels: Vec<Sp<String>>,
+    /// The functions that were being called when the error occurred
+    pub trace: Vec<TraceFrame>,
+}
+
+impl ErrorReport {
+    pub fn show(&self, color: bool) -> String {
+        let mut s = report(
+            [((&self.message, self.span.clone()), Some(self.code))],
+            &self.labels,
+            ReportKind::Error,
+            color,
+        );
+        format_trace(&mut s, &self.trace).unwrap();
+        s
+    }
+}
+
+/// A non-fatal problem found while compiling
+///
+/// Diagnostics do not stop code from running. They are collected by the
+/// runtime and can be retrieved with [`crate::Uiua::take_diagnostics`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    /// What kind of problem this is
+    pub kind: DiagnosticKind,
+    /// A description of the problem
+    pub message: String,
+    /// Where the problem is
+    pub span: CodeSpan,
+    /// Secondary labels that point at related code
+    pub labels: Vec<Sp<String>>,
+}
+
+/// A kind of [`Diagnostic`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[non_exhaustive]
+pub enum DiagnosticKind {
+    /// A binding that is never used
+    UnusedBinding,
+    /// A value that is pushed and then immediately popped
+    AlwaysPopped,
+    /// A binding that hides an earlier binding with the same name
+    ShadowedName,
+    /// A name that is parsed as several primitives
+    AmbiguousGlyph,
+    /// A use of a deprecated primitive
+    Deprecated,
+}
+
+impl Diagnostic {
+    pub fn new(kind: DiagnosticKind, message: impl Into<String>, span: CodeSpan) -> Self {
+        Self {
+            kind,
+            message: message.into(),
+            span,
+            labels: Vec::new(),
+        }
+    }
+    /// Add a secondary label that points at related code
+    pub fn with_label(mut self, span: CodeSpan, message: impl Into<String>) -> Self {
+        self.labels.push(span.sp(message.into()));
+        self
+    }
+    pub fn show(&self, color: bool) -> String {
+        report(
+            [((&self.message, self.span.clone().into()), None)],
+            &self.labels,
+            ReportKind::Warning,
+            color,
+        )
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.span, self.message)
+    }
+}
+
+/// Render reports of some messages
+///
+/// Secondary labels are added to the first report. Labels in other code
+/// than the report's are shown as notes.
+fn report<I, T>(errors: I, labels: &[Sp<String>], kind: ReportKind, complex_output: bool) -> String
 where
-    I: IntoIterator<Item = (T, Span)>,
+    I: IntoIterator<Item = ((T, Span), Option<ErrorCode>)>,
     T: ToString,
 {
     let config = Config::default().with_color(complex_output);
-    let color = if complex_output {
-        Color::Red
+    let color = match kind {
+        _ if !complex_output => Color::Unset,
+        ReportKind::Warning => Color::Yellow,
+        _ => Color::Red,
+    };
+    let label_color = if complex_output {
+        Color::Blue
     } else {
         Color::Unset
     };
+    let mut labels = Some(labels);
     let mut buffer = Vec::new();
-    let mut chache = None;
-    for (message, span) in errors {
+    for ((message, span), code) in errors {
         if let Span::Code(span) = span {
-            let cache = chache.get_or_insert_with(|| Cache {
-                input: Source::from(&span.input),
-                files: HashMap::new(),
-            });
-            let report = Report::<CodeSpan>::build(
-                ReportKind::Error,
-                span.path.clone(),
-                span.start.char_pos,
-            )
-            .with_message(message)
-            .with_label(Label::new(span.clone()).with_color(color))
-            .with_config(config)
-            .finish();
-            let _ = report.write(cache, &mut buffer);
+            let mut cache = Cache {
+                id: span.path.clone(),
+                source: Source::from(&span.input),
+            };
+            let mut report =
+                Report::<CodeSpan>::build(kind, span.path.clone(), span.start.char_pos)
+                    .with_message(message.to_string())
+                    .with_label(Label::new(span.clone()).with_color(color))
+                    .with_config(config);
+            let mut notes = Vec::new();
+            for label in labels.take().into_iter().flatten() {
+                if label.span.path == span.path && label.span.input == span.input {
+                    report.add_label(
+                        Label::new(label.span.clone())
+                            .with_message(&label.value)
+                            .with_color(label_color),
+                    );
+                } else {
+                    notes.push(format!("{}: {}", label.span, label.value));
+                }
+            }
+            if !notes.is_empty() {
+                report.set_note(notes.join("\n"));
+            }
+            if let Some(code) = code {
+                report = report.with_code(code);
+            }
+            let report = report.finish();
+            let _ = report.write(&mut cache, &mut buffer);
         } else {
             if !buffer.ends_with(b"\n") {
                 buffer.push(b'\n');
             }
+            if let Some(code) = code {
+                buffer.extend(format!("[{code}] ").into_bytes());
+            }
             buffer.extend(message.to_string().into_bytes());
         }
     }
@@ -279,31 +503,21 @@ impl ariadne::Span for CodeSpan {
     }
 }
 
+/// The source of a span being reported
+///
+/// Spans carry the code they are in, so the snippet always comes from the
+/// code that was run, even if it was imported or is not a file on disk.
 struct Cache {
-    input: Source,
-    files: HashMap<SourceId, Source>,
+    id: SourceId,
+    source: Source,
 }
 
 impl ariadne::Cache<SourceId> for Cache {
     fn fetch(&mut self, id: &SourceId) -> Result<&Source, Box<dyn fmt::Debug + '_>> {
-        match id {
-            Some(path) => {
-                if !self.files.contains_key(id) {
-                    let text = fs::read_to_string(path)
-                        .or_else(|e| {
-                            if path.to_string_lossy() == "example.ua" {
-                                Ok(example_ua(|ex| ex.clone()))
-                            } else {
-                                Err(e)
-                            }
-                        })
-                        .map_err(|e| Box::new(e) as Box<dyn fmt::Debug>)?;
-                    let source = Source::from(text);
-                    self.files.insert(id.clone(), source);
-                }
-                Ok(&self.files[id])
-            }
-            None => Ok(&self.input),
+        if *id == self.id {
+            Ok(&self.source)
+        } else {
+            Err(Box::new(format!("Unknown source {id:?}")))
         }
     }
     fn display<'a>(&self, id: &'a SourceId) -> Option<Box<dyn fmt::Display + 'a>> {
@@ -313,3 +527,125 @@ impl ariadne::Cache<SourceId> for Cache {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Uiua;
+
+    /// A temporary directory for tests that is removed when dropped
+    struct TempDir(std::path::PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let dir = std::env::temp_dir().join(format!("uiua-{name}-{}", std::process::id()));
+            std::fs::create_dir_all(&dir).unwrap();
+            TempDir(dir)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn errors_in_imports_show_their_code() {
+        let dir = TempDir::new("import-error");
+        let path = dir.0.join("uiua_import_error.ua");
+        std::fs::write(&path, "F ← +1\n⍤\"bad import\" 0").unwrap();
+        let code = format!("&i \"{}\"", path.display());
+        let err = Uiua::with_native_sys().load_str(&code).err().unwrap();
+        // The snippet comes from the code that was run, not the file as it is now
+        std::fs::write(&path, "").unwrap();
+        let shown = err.show(false);
+        assert!(shown.contains("uiua_import_error.ua"), "{shown}");
+        assert!(shown.contains("⍤\"bad import\" 0"), "{shown}");
+    }
+
+    #[test]
+    fn errors_have_secondary_labels() {
+        let err = Uiua::with_native_sys()
+            .load_str("F ← |1.2 +")
+            .err()
+            .unwrap();
+        let labels = err.labels();
+        assert_eq!(labels.len(), 1);
+        assert_eq!(labels[0].span.as_str(), "F");
+        let shown = err.show(false);
+        assert!(shown.contains("|2.1 inferred from here"), "{shown}");
+
+        let mut env = Uiua::with_native_sys();
+        env.load_str("X ← 1\nX ← 2\nX").unwrap();
+        let diagnostic = (env.take_diagnostics().into_iter())
+            .find(|d| d.kind == DiagnosticKind::ShadowedName)
+            .unwrap();
+        assert_eq!(diagnostic.labels[0].span.start.line, 1);
+        assert!(diagnostic.show(false).contains("previously bound here"));
+
+        let err = Uiua::with_native_sys()
+            .load_str("+ [1_2_3 4_5_6] ↯3_4 0")
+            .err()
+            .unwrap();
+        assert!(err.labels().is_empty());
+        let err = Uiua::with_native_sys()
+            .load_str("+ [1_2 3_4] [1_2_3 4_5_6 7_8_9]")
+            .err()
+            .unwrap();
+        let labels: Vec<_> = (err.labels().iter())
+            .map(|label| (label.span.as_str(), label.value.as_str()))
+            .collect();
+        assert_eq!(
+            labels,
+            [
+                ("[1_2 3_4]", "shape [2 × 2] from here"),
+                ("[1_2_3 4_5_6 7_8_9]", "shape [3 × 3] from here")
+            ]
+        );
+    }
+
+    #[test]
+    fn error_codes() {
+        let code = |code: &str| Uiua::with_native_sys().load_str(code).err().unwrap().code();
+        assert_eq!(code("[1 2"), ErrorCode::Expected);
+        assert_eq!(code("F ← |1 +"), ErrorCode::SignatureMismatch);
+        assert_eq!(code("+ [1 2] [1 2 3]"), ErrorCode::Runtime);
+        assert_eq!(code("⍤\"Oh no!\" 0"), ErrorCode::Thrown);
+        assert_eq!(code("+ x 1"), ErrorCode::Compile);
+        assert_eq!(code("F! ← ^0 ^1\nF!+"), ErrorCode::Compile);
+        let err = Uiua::with_native_sys()
+            .load_str("⍤\"Oh no!\" 0")
+            .err()
+            .unwrap();
+        assert!(err.show(false).contains("[E0011]"));
+        for code in ErrorCode::all() {
+            assert_eq!(code.to_string().parse::<ErrorCode>(), Ok(code));
+        }
+        assert!("E9999".parse::<ErrorCode>().is_err());
+    }
+
+    #[test]
+    fn error_reports() {
+        let err = Uiua::with_native_sys()
+            .load_str("F ← |1.2 +")
+            .err()
+            .unwrap();
+        let reports = err.reports();
+        assert_eq!(reports.len(), 1);
+        let report = &reports[0];
+        assert_eq!(report.code, ErrorCode::SignatureMismatch);
+        assert!(report.message.starts_with("Function signature mismatch"));
+        assert!(matches!(&report.span, Span::Code(span) if span.as_str() == "|1.2"));
+        assert_eq!(report.labels.len(), 1);
+        assert_eq!(err.show(false), report.show(false));
+
+        let (_, errors) = crate::parse::parse("(1\n[2", None);
+        assert!(!errors.is_empty());
+        let reports = UiuaError::Parse(errors.clone()).reports();
+        assert_eq!(reports.len(), errors.len());
+        assert!(reports
+            .iter()
+            .all(|report| report.code == ErrorCode::Expected));
+    }
+}
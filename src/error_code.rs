@@ -0,0 +1,223 @@
+use std::{fmt, str::FromStr};
+
+/// A code that identifies a category of error
+///
+/// Codes are shown with errors as `E0001` and so on. `uiua explain` prints the
+/// [`ErrorCode::explanation`] of a code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[repr(u16)]
+pub enum ErrorCode {
+    /// A file could not be read
+    Load = 1,
+    /// A formatted file could not be written
+    Format = 2,
+    /// Code contains something that is not a token
+    Lex = 3,
+    /// Some token was expected but not found
+    Expected = 4,
+    /// A number literal is not valid
+    InvalidNumber = 5,
+    /// A token appears where it does not belong
+    Unexpected = 6,
+    /// The argument or output count of a signature is not valid
+    InvalidSignature = 7,
+    /// A binding name contains a `&`
+    AmpersandBindingName = 8,
+    /// A function's signature is wrong or cannot be inferred
+    SignatureMismatch = 9,
+    /// A function failed while running
+    Runtime = 10,
+    /// An error was thrown with assert
+    Thrown = 11,
+    /// A break was used outside of a loop
+    BreakOutsideLoop = 12,
+    /// Code ran for longer than it was allowed to
+    Timeout = 13,
+    /// Code executed more instructions than it was allowed to
+    OutOfFuel = 14,
+    /// The program was interrupted while running
+    Interrupted = 15,
+    /// Code refers to something that does not exist or is used where it is not allowed
+    Compile = 16,
+}
+
+impl ErrorCode {
+    /// Get all error codes
+    pub fn all() -> impl Iterator<Item = Self> {
+        use ErrorCode::*;
+        [
+            Load,
+            Format,
+            Lex,
+            Expected,
+            InvalidNumber,
+            Unexpected,
+            InvalidSignature,
+            AmpersandBindingName,
+            SignatureMismatch,
+            Runtime,
+            Thrown,
+            BreakOutsideLoop,
+            Timeout,
+            OutOfFuel,
+            Interrupted,
+            Compile,
+        ]
+        .into_iter()
+    }
+    /// Get the number of the code
+    pub fn number(&self) -> u16 {
+        *self as u16
+    }
+    /// Get a longer description of the error, with examples
+    pub fn explanation(&self) -> &'static str {
+        match self {
+            ErrorCode::Load => {
+                "A file could not be read.
+
+This happens when running or formatting a file that does not exist, or when \
+importing one with &i.
+
+    &i \"missing.ua\""
+            }
+            ErrorCode::Format => {
+                "A formatted file could not be written.
+
+The formatter writes files back to disk after formatting them. Check that the \
+file is not read-only."
+            }
+            ErrorCode::Lex => {
+                "The code contains something that is not a valid token.
+
+This is usually a character that is not part of Uiua or an invalid escape \
+sequence in a string or character literal.
+
+    \"a\\qb\"
+
+Valid escapes are \\n, \\t, \\r, \\0, \\\\, \\\", \\', \\_, \\xHH, and \\u{...}."
+            }
+            ErrorCode::Expected => {
+                "Some token was expected but not found.
+
+This is usually an unclosed bracket or parenthesis.
+
+    [1 2 3
+    (+1"
+            }
+            ErrorCode::InvalidNumber => {
+                "A number literal is not valid.
+
+    0x
+    1e"
+            }
+            ErrorCode::Unexpected => {
+                "A token appears where it does not belong.
+
+This is usually a closing bracket or parenthesis without an opening one.
+
+    1 2]"
+            }
+            ErrorCode::InvalidSignature => {
+                "The argument or output count of a signature is not valid.
+
+Signatures are written as |args or |args.outputs, where both are natural \
+numbers.
+
+    F ← |1.x +1"
+            }
+            ErrorCode::AmpersandBindingName => {
+                "A binding name contains a &.
+
+Names that contain & are reserved for system functions.
+
+    a&b ← 5"
+            }
+            ErrorCode::SignatureMismatch => {
+                "A function's signature is wrong or cannot be inferred.
+
+A declared signature must match the one that is inferred from the function's \
+code. Here, + takes 2 arguments, but 1 is declared.
+
+    F ← |1 +
+
+Some functions' signatures cannot be inferred, such as ones that call \
+functions from the stack a variable number of times. Declare their \
+signatures to use them."
+            }
+            ErrorCode::Runtime => {
+                "A function failed while running.
+
+This covers errors like taking from an empty stack, mismatched shapes, and \
+invalid arguments. The message describes what went wrong.
+
+    + [1 2] [1 2 3]"
+            }
+            ErrorCode::Thrown => {
+                "An error was thrown with assert.
+
+⍤ throws its first argument if its second argument is not 1.
+
+    ⍤\"Oh no!\" =2 3"
+            }
+            ErrorCode::BreakOutsideLoop => {
+                "A break was used outside of a loop.
+
+⎋ breaks out of loops like ⍥ and ∧. It cannot break out of more loops than \
+there are.
+
+    ⎋1"
+            }
+            ErrorCode::Timeout => {
+                "Code ran for longer than it was allowed to.
+
+This happens when a time limit is set, like on the website, and usually \
+means there is an infinite loop.
+
+    ⍥(+1)∞ 0"
+            }
+            ErrorCode::OutOfFuel => {
+                "Code executed more instructions than it was allowed to.
+
+This happens when an instruction limit is set, like with \
+`uiua run --max-instructions`. Unlike a time limit, an instruction limit \
+stops a program at the same point every time it is run.
+
+    ⍥(+1)∞ 0"
+            }
+            ErrorCode::Interrupted => {
+                "The program was interrupted while running.
+
+This happens when Ctrl-C is pressed while a program is running. The program \
+stops before its next instruction, but a single long-running primitive is \
+not interrupted. Press Ctrl-C again to exit immediately."
+            }
+            ErrorCode::Compile => {
+                "Code refers to something that does not exist or is used where it \
+is not allowed.
+
+This covers errors like unknown names, macros given too few operands, and \
+placeholders outside of macros. These are found before the code runs.
+
+    + x 1"
+            }
+        }
+    }
+}
+
+impl fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "E{:04}", self.number())
+    }
+}
+
+impl FromStr for ErrorCode {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let digits = s.strip_prefix(['E', 'e']).unwrap_or(s);
+        digits
+            .parse::<u16>()
+            .ok()
+            .and_then(|n| Self::all().find(|code| code.number() == n))
+            .ok_or_else(|| format!("Unknown error code {s}"))
+    }
+}
@@ -1,11 +1,28 @@
 //! Functions for formatting Uiua code.
 
-use std::{env, fs, path::Path};
+use std::{
+    env, fs,
+    iter::once,
+    path::{Path, PathBuf},
+};
+
+use instant::Duration;
 
 use crate::{
-    ast::*, function::Signature, grid_fmt::GridFmt, lex::Sp, parse::parse, UiuaError, UiuaResult,
+    ast::*,
+    function::Signature,
+    grid_fmt::GridFmt,
+    lex::{is_ident_char, lex, CodeSpan, Sp, Token},
+    parse::parse,
+    primitive::Primitive,
+    MemorySys, Uiua, UiuaError, UiuaResult,
 };
 
+/// The name of the file that configures the formatter for a project
+pub const FORMAT_CONFIG_FILE: &str = ".fmt.ua";
+const CONFIG_INSTRUCTION_LIMIT: u64 = 100_000;
+const CONFIG_MEMORY_LIMIT: usize = 1 << 20;
+
 #[derive(Debug, Clone)]
 pub struct FormatConfig {
     /// Whether to add a trailing newline to the output.
@@ -30,6 +47,18 @@ pub struct FormatConfig {
     ///
     /// Default: `10`
     pub multiline_compact_threshold: usize,
+    /// Whether to replace deprecated primitives with their replacements
+    ///
+    /// Default: `false`
+    pub migrate_deprecated: bool,
+    /// Whether to write primitives with their ASCII spellings instead of their glyphs
+    ///
+    /// Default: `false`
+    pub ascii: bool,
+    /// Whether to align the end-of-line comments of consecutive lines
+    ///
+    /// Default: `false`
+    pub align_comments: bool,
 }
 
 impl Default for FormatConfig {
@@ -40,16 +69,96 @@ impl Default for FormatConfig {
             multiline_indent: 2,
             compact_multiline: None,
             multiline_compact_threshold: 10,
+            migrate_deprecated: false,
+            ascii: false,
+            align_comments: false,
+        }
+    }
+}
+
+impl FormatConfig {
+    /// Load a config from Uiua code that binds the options
+    ///
+    /// Options are bound with names like `TrailingNewline ← 0`.
+    /// Options that are not bound keep their default values.
+    ///
+    /// A config may be found in any ancestor directory, so the code is run
+    /// in a sandbox without access to the system, and with limits on how
+    /// long it may run.
+    pub fn from_source(source: &str, path: Option<&Path>) -> UiuaResult<Self> {
+        let mut env = Uiua::with_backend(MemorySys::new())
+            .with_sandbox(true)
+            .with_instruction_limit(CONFIG_INSTRUCTION_LIMIT)
+            .with_execution_limit(Duration::from_secs(1))
+            .with_memory_limit(CONFIG_MEMORY_LIMIT);
+        match path {
+            Some(path) => env.load_str_path(source, path)?,
+            None => env.load_str(source)?,
+        };
+        let nat = |name: &str| {
+            (env.bound_value(name))
+                .map(|val| val.as_nat(&env, "Format options must be natural numbers"))
+                .transpose()
+        };
+        let mut config = Self::default();
+        if let Some(n) = nat("TrailingNewline")? {
+            config.trailing_newline = n != 0;
+        }
+        if let Some(n) = nat("CommentSpaceAfterHash")? {
+            config.comment_space_after_hash = n != 0;
+        }
+        if let Some(n) = nat("MultilineIndent")? {
+            config.multiline_indent = n;
+        }
+        if let Some(n) = nat("CompactMultiline")? {
+            config.compact_multiline = Some(n != 0);
+        }
+        if let Some(n) = nat("MultilineCompactThreshold")? {
+            config.multiline_compact_threshold = n;
+        }
+        if let Some(n) = nat("MigrateDeprecated")? {
+            config.migrate_deprecated = n != 0;
+        }
+        if let Some(n) = nat("Ascii")? {
+            config.ascii = n != 0;
+        }
+        if let Some(n) = nat("AlignComments")? {
+            config.align_comments = n != 0;
+        }
+        Ok(config)
+    }
+    /// Load the config for the project that a directory is in
+    ///
+    /// This looks for a [`FORMAT_CONFIG_FILE`] in the directory and its ancestors.
+    /// If there is none, the default config is used.
+    pub fn find(dir: &Path) -> UiuaResult<Self> {
+        match find_config_file(dir) {
+            Some(path) => {
+                let source = fs::read_to_string(&path)
+                    .map_err(|e| UiuaError::Load(path.clone(), e.into()))?;
+                Self::from_source(&source, Some(&path))
+            }
+            None => Ok(Self::default()),
         }
     }
 }
 
+fn find_config_file(dir: &Path) -> Option<PathBuf> {
+    let dir = dir.canonicalize().ok()?;
+    (dir.ancestors())
+        .map(|dir| dir.join(FORMAT_CONFIG_FILE))
+        .find(|path| path.is_file())
+}
+
 pub fn format_items(items: &[Item], config: &FormatConfig) -> String {
     let mut output = String::new();
     format_items_impl(&mut output, items, config);
     while output.ends_with('\n') {
         output.pop();
     }
+    if config.align_comments {
+        output = align_comments(&output);
+    }
     if config.trailing_newline && !output.trim().is_empty() {
         output.push('\n');
     }
@@ -66,7 +175,12 @@ pub fn format_str(input: &str, config: &FormatConfig) -> UiuaResult<String> {
 fn format_impl(input: &str, path: Option<&Path>, config: &FormatConfig) -> UiuaResult<String> {
     let (items, errors) = parse(input, path);
     if errors.is_empty() {
-        Ok(format_items(&items, config))
+        let formatted = format_items(&items, config);
+        // The lexer skips a `#!` line, so it is kept as it is
+        Ok(match shebang(input) {
+            Some(line) => format!("{line}\n{formatted}"),
+            None => formatted,
+        })
     } else {
         Err(errors.into())
     }
@@ -87,6 +201,49 @@ pub fn format_file<P: AsRef<Path>>(path: P, config: &FormatConfig) -> UiuaResult
     Ok(formatted)
 }
 
+/// Get the lines that differ between some code and its formatted version
+///
+/// Removed lines start with `-` and added lines start with `+`.
+/// Each group of changed lines starts with the line number it is at in the old code.
+pub fn diff(old: &str, new: &str) -> String {
+    let old: Vec<&str> = old.split('\n').collect();
+    let new: Vec<&str> = new.split('\n').collect();
+    // Lengths of the longest common subsequences of the lines' suffixes
+    let mut lcs = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lcs[i][j] = if old[i] == new[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+    let mut output = String::new();
+    let (mut i, mut j) = (0, 0);
+    let mut in_hunk = false;
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            i += 1;
+            j += 1;
+            in_hunk = false;
+            continue;
+        }
+        if !in_hunk {
+            output.push_str(&format!("@@ line {} @@\n", i + 1));
+            in_hunk = true;
+        }
+        if i < old.len() && (j == new.len() || lcs[i + 1][j] >= lcs[i][j + 1]) {
+            output.push_str(&format!("-{}\n", old[i]));
+            i += 1;
+        } else {
+            output.push_str(&format!("+{}\n", new[j]));
+            j += 1;
+        }
+    }
+    output
+}
+
 fn format_items_impl(output: &mut String, items: &[Item], config: &FormatConfig) {
     for item in items {
         format_item(output, item, config);
@@ -108,7 +265,7 @@ fn format_item(output: &mut String, item: &Item, config: &FormatConfig) {
         }
         Item::Binding(binding) => {
             output.push_str(&binding.name.value);
-            output.push_str(" ← ");
+            output.push_str(if config.ascii { " = " } else { " ← " });
             if let Some(sig) = &binding.signature {
                 format_signature(output, sig.value);
             }
@@ -144,12 +301,20 @@ fn format_word(output: &mut String, word: &Sp<Word>, config: &FormatConfig, dept
     match &word.value {
         Word::Number(s, n) => {
             let grid_str = n.grid_string();
-            if grid_str.len() < s.len() {
+            // Hexadecimal, binary, and exponent literals are kept as they are written
+            let verbatim = s.contains(['x', 'b', 'e', 'E']);
+            if grid_str.len() < s.len() && !verbatim {
                 output.push_str(&grid_str);
+            } else if config.ascii {
+                output.push_str(&s.replace('¯', "`"));
             } else {
                 output.push_str(&s.replace('`', "¯"));
             }
         }
+        // Literals with code escapes are kept as they are written
+        Word::Char(_) | Word::String(_) if has_code_escape(word.span.as_str()) => {
+            output.push_str(word.span.as_str())
+        }
         Word::Char(c) => {
             let formatted = format!("{c:?}");
             let formatted = &formatted[1..formatted.len() - 1];
@@ -158,27 +323,11 @@ fn format_word(output: &mut String, word: &Sp<Word>, config: &FormatConfig, dept
         }
         Word::String(s) => output.push_str(&format!("{:?}", s)),
         Word::FormatString(_) => output.push_str(word.span.as_str()),
-        Word::MultilineString(lines) => {
-            if lines.len() == 1 {
-                output.push_str(lines[0].span.as_str());
-                return;
-            }
-            let curr_line_pos = if output.ends_with('\n') {
-                0
-            } else {
-                output.lines().last().unwrap_or_default().chars().count()
-            };
-            for (i, line) in lines.iter().enumerate() {
-                if i > 0 {
-                    output.push('\n');
-                    for _ in 0..curr_line_pos {
-                        output.push(' ');
-                    }
-                }
-                output.push_str(line.span.as_str());
-            }
+        Word::MultilineString(lines) => format_lines(output, lines.iter().map(|line| &line.span)),
+        Word::RawString(lines) | Word::GridString(lines) => {
+            format_lines(output, lines.iter().map(|line| &line.span))
         }
-        Word::Ident(ident) => output.push_str(ident),
+        Word::Ident(ident) => push_ident_text(output, ident),
         Word::Strand(items) => {
             for (i, item) in items.iter().enumerate() {
                 if i > 0 {
@@ -212,20 +361,44 @@ fn format_word(output: &mut String, word: &Sp<Word>, config: &FormatConfig, dept
             output.push(')');
         }
         Word::Primitive(prim) => {
-            output.push_str(&prim.to_string());
+            push_ident_text(output, &format_prim(*prim, config));
             if prim.is_modifier() {
                 output.push(' ');
             }
         }
         Word::Modified(m) => {
-            output.push_str(&m.modifier.value.to_string());
+            push_ident_text(output, &format_prim(m.modifier.value, config));
+            format_words(output, &m.operands, config, true, depth);
+            if m.terminated {
+                output.push('^');
+            }
+        }
+        Word::Placeholder(n) => output.push_str(&format!("^{n}")),
+        Word::MacroCall(m) => {
+            output.push_str(&m.name.value);
             format_words(output, &m.operands, config, true, depth);
             if m.terminated {
                 output.push('^');
             }
         }
+        Word::LocalBinding(binding) => {
+            output.push_str(&binding.name.value);
+            output.push_str(" ←");
+            if !binding.words.is_empty() {
+                output.push(' ');
+                format_words(output, &binding.words, config, true, depth);
+            }
+        }
         Word::Spaces => output.push(' '),
         Word::Comment(comment) => {
+            // End-of-line comments stay in the column they were written in
+            let curr_line = output.lines().last().unwrap_or_default();
+            if !output.ends_with('\n') && !curr_line.trim().is_empty() {
+                let width = curr_line.chars().count();
+                for _ in width..word.span.start.col - 1 {
+                    output.push(' ');
+                }
+            }
             output.push('#');
             if config.comment_space_after_hash {
                 output.push(' ');
@@ -286,6 +459,111 @@ fn format_multiline_words(
     }
 }
 
+/// Align the end-of-line comments of consecutive lines to the same column
+fn align_comments(output: &str) -> String {
+    let mut lines: Vec<String> = output.lines().map(Into::into).collect();
+    let line_starts: Vec<usize> = (once(0))
+        .chain(output.match_indices('\n').map(|(i, _)| i + 1))
+        .collect();
+    // The line index and column in characters of each end-of-line comment
+    let comments: Vec<(usize, usize)> = (lex(output, None).0.into_iter())
+        .filter(|token| token.value == Token::Comment)
+        .map(|token| {
+            let line = token.span.start.line - 1;
+            let before = &output[line_starts[line]..token.span.start.byte_pos];
+            (line, before.chars().count())
+        })
+        .filter(|&(line, col)| {
+            let before: String = lines[line].chars().take(col).collect();
+            !before.trim().is_empty()
+        })
+        .collect();
+    let mut i = 0;
+    while i < comments.len() {
+        let mut j = i + 1;
+        while j < comments.len() && comments[j].0 == comments[j - 1].0 + 1 {
+            j += 1;
+        }
+        let target = comments[i..j].iter().map(|&(_, col)| col).max().unwrap();
+        for &(line, col) in &comments[i..j] {
+            let at = (lines[line].char_indices())
+                .nth(col)
+                .map_or(lines[line].len(), |(i, _)| i);
+            lines[line].insert_str(at, &" ".repeat(target - col));
+        }
+        i = j;
+    }
+    let mut aligned = lines.join("\n");
+    if output.ends_with('\n') {
+        aligned.push('\n');
+    }
+    aligned
+}
+
+/// Get the `#!` line at the start of some code
+fn shebang(input: &str) -> Option<&str> {
+    let line = input.lines().next()?;
+    line.starts_with("#!").then_some(line)
+}
+
+/// Check if the source of a literal contains a `\x` or `\u{}` escape
+fn has_code_escape(s: &str) -> bool {
+    s.contains("\\x") || s.contains("\\u{")
+}
+
+/// Format the lines of a multiline string, aligned with the first one
+fn format_lines<'a>(output: &mut String, spans: impl Iterator<Item = &'a CodeSpan>) {
+    let curr_line_pos = if output.ends_with('\n') {
+        0
+    } else {
+        output.lines().last().unwrap_or_default().chars().count()
+    };
+    for (i, span) in spans.enumerate() {
+        if i > 0 {
+            output.push('\n');
+            for _ in 0..curr_line_pos {
+                output.push(' ');
+            }
+        }
+        output.push_str(span.as_str());
+    }
+}
+
+fn format_prim(prim: Primitive, config: &FormatConfig) -> String {
+    match prim.deprecation() {
+        Some(replacement) if config.migrate_deprecated => replacement.into(),
+        _ if config.ascii => ascii_prim(prim),
+        _ => prim.to_string(),
+    }
+}
+
+/// Get the ASCII spelling of a primitive
+///
+/// Glyphs that have no ASCII token are spelled with the first word of their name.
+fn ascii_prim(prim: Primitive) -> String {
+    if let Some(ascii) = prim.ascii() {
+        return ascii.to_string();
+    }
+    if let Some(c) = prim.unicode().filter(char::is_ascii) {
+        return c.to_string();
+    }
+    if let Some(names) = prim.names().filter(|n| n.is_name_formattable()) {
+        let word = names.text.split(' ').next().unwrap_or(names.text);
+        if Primitive::from_format_name(word) == Some(prim) {
+            return word.into();
+        }
+    }
+    prim.to_string()
+}
+
+/// Push text to the output, separating it from a preceding identifier
+fn push_ident_text(output: &mut String, text: &str) {
+    if output.ends_with(is_ident_char) && text.starts_with(is_ident_char) {
+        output.push(' ');
+    }
+    output.push_str(text);
+}
+
 fn trim_spaces(words: &[Sp<Word>], trim_end: bool) -> &[Sp<Word>] {
     let mut start = 0;
     for word in words {
@@ -318,6 +596,7 @@ fn word_is_multiline(word: &Word) -> bool {
         Word::String(_) => false,
         Word::FormatString(_) => false,
         Word::MultilineString(lines) => lines.len() > 1,
+        Word::RawString(lines) | Word::GridString(lines) => lines.len() > 1,
         Word::Ident(_) => false,
         Word::Strand(_) => false,
         Word::Array(arr) => {
@@ -333,7 +612,129 @@ fn word_is_multiline(word: &Word) -> bool {
         }
         Word::Primitive(_) => false,
         Word::Modified(m) => m.operands.iter().any(|word| word_is_multiline(&word.value)),
+        Word::Placeholder(_) => false,
+        Word::LocalBinding(binding) => {
+            (binding.words.iter()).any(|word| word_is_multiline(&word.value))
+        }
+        Word::MacroCall(m) => m.operands.iter().any(|word| word_is_multiline(&word.value)),
         Word::Comment(_) => false,
         Word::Spaces => false,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn number_literals_are_formatted_as_written() {
+        let config = FormatConfig {
+            trailing_newline: false,
+            ..Default::default()
+        };
+        for (input, expected) in [
+            ("1e6", "1e6"),
+            ("1e1", "1e1"),
+            ("2.5e-3", "2.5e-3"),
+            ("2.5e`3", "2.5e¯3"),
+            ("0xff", "0xff"),
+            ("`0b101", "¯0b101"),
+            ("1.50", "1.5"),
+        ] {
+            assert_eq!(format_str(input, &config).unwrap(), expected);
+        }
+        // Only numbers that start with 0 have a radix
+        for (input, number) in [("10x5", "10"), ("10b1", "10"), ("`10b1", "`10")] {
+            let (tokens, _) = lex(input, None);
+            assert_eq!(tokens[0].span.as_str(), number);
+        }
+    }
+
+    #[test]
+    fn unicode_escapes() {
+        let config = FormatConfig {
+            trailing_newline: false,
+            ..Default::default()
+        };
+        for code in [r#""\u{41}\x42""#, r"@\u{1F600}", r"@\x7f"] {
+            assert_eq!(format_str(code, &config).unwrap(), code);
+        }
+        for code in [r#""\u{110000}""#, r#""\u41""#, r"@\xg"] {
+            assert!(
+                Uiua::with_native_sys().load_str(code).is_err(),
+                "{code} should not lex"
+            );
+        }
+    }
+
+    #[test]
+    fn format_config_from_source() {
+        let config =
+            FormatConfig::from_source("TrailingNewline ← 0\nCommentSpaceAfterHash ← 0", None)
+                .unwrap();
+        assert!(!config.trailing_newline);
+        assert_eq!(config.multiline_indent, 2);
+        assert_eq!(format_str("#hi\n1", &config).unwrap(), "#hi\n1");
+        assert!(FormatConfig::from_source("MultilineIndent ← ¯1", None).is_err());
+        // Configs cannot access the system or run forever
+        assert!(FormatConfig::from_source("&fras \"Cargo.toml\"", None).is_err());
+        assert!(FormatConfig::from_source("⍥(+1)∞ 0", None).is_err());
+    }
+
+    #[test]
+    fn format_diff() {
+        assert_eq!(diff("a\nb\nc", "a\nB\nc"), "@@ line 2 @@\n-b\n+B\n");
+        assert_eq!(diff("a\nb", "b\nc"), "@@ line 1 @@\n-a\n@@ line 3 @@\n+c\n");
+        assert_eq!(diff("a", "a\n"), "@@ line 2 @@\n+\n");
+        assert_eq!(diff("a", "a"), "");
+    }
+
+    #[test]
+    fn format_ascii() {
+        let config = FormatConfig {
+            ascii: true,
+            trailing_newline: false,
+            ..Default::default()
+        };
+        let code = "X ← ⇌⇡5\n×¯2 ⌵X";
+        let formatted = format_str(code, &config).unwrap();
+        assert_eq!(formatted, "X = reverse range5\n*`2 absolute X");
+        let run = |code: &str| {
+            let mut env = Uiua::with_native_sys();
+            env.load_str(code).unwrap();
+            env.take_stack()
+        };
+        assert_eq!(run(code), run(&formatted));
+    }
+
+    #[test]
+    fn format_comment_columns() {
+        let config = FormatConfig {
+            trailing_newline: false,
+            ..Default::default()
+        };
+        let code = "reverse 1_2   # a\n+ 1 2 # b";
+        assert_eq!(
+            format_str(code, &config).unwrap(),
+            "⇌ 1_2         # a\n+ 1 2 # b"
+        );
+        let config = FormatConfig {
+            align_comments: true,
+            ..config
+        };
+        assert_eq!(
+            format_str(code, &config).unwrap(),
+            "⇌ 1_2         # a\n+ 1 2         # b"
+        );
+        let code = "⇌⇌ 1 # a\n1 # b\n\"é\" # c";
+        assert_eq!(
+            format_str(code, &config).unwrap(),
+            "⇌⇌ 1 # a\n1    # b\n\"é\"  # c"
+        );
+        let code = "1 # a\n\n22 # b\n# c\n333 # d";
+        assert_eq!(
+            format_str(code, &config).unwrap(),
+            "1 # a\n\n22 # b\n# c\n333 # d"
+        );
+    }
+}
@@ -14,9 +14,18 @@ use crate::{
 pub enum Instr {
     Push(Box<Value>),
     BeginArray,
-    EndArray { constant: bool, span: usize },
+    EndArray {
+        constant: bool,
+        span: usize,
+    },
     Prim(Primitive, usize),
     Call(usize),
+    /// Pop a value and bind it locally
+    BindLocal,
+    /// Push a local binding's value, counting back from the most recent one
+    Local(usize),
+    /// Unbind the most recent local bindings
+    DropLocals(usize),
 }
 
 impl PartialEq for Instr {
@@ -27,6 +36,9 @@ impl PartialEq for Instr {
             (Self::EndArray { .. }, Self::EndArray { .. }) => true,
             (Self::Prim(a, s_span), Self::Prim(b, b_span)) => a == b && s_span == b_span,
             (Self::Call(a), Self::Call(b)) => a == b,
+            (Self::BindLocal, Self::BindLocal) => true,
+            (Self::Local(a), Self::Local(b)) => a == b,
+            (Self::DropLocals(a), Self::DropLocals(b)) => a == b,
             _ => false,
         }
     }
@@ -59,7 +71,18 @@ impl Ord for Instr {
                 Ordering::Greater
             }
             (Self::Prim(_, _), _) => Ordering::Less,
+            (Self::Call(_), Self::BindLocal | Self::Local(_) | Self::DropLocals(_)) => {
+                Ordering::Less
+            }
             (Self::Call(_), _) => Ordering::Greater,
+            (Self::BindLocal, Self::BindLocal) => Ordering::Equal,
+            (Self::BindLocal, Self::Local(_) | Self::DropLocals(_)) => Ordering::Less,
+            (Self::BindLocal, _) => Ordering::Greater,
+            (Self::Local(a), Self::Local(b)) => a.cmp(b),
+            (Self::Local(_), Self::DropLocals(_)) => Ordering::Less,
+            (Self::Local(_), _) => Ordering::Greater,
+            (Self::DropLocals(a), Self::DropLocals(b)) => a.cmp(b),
+            (Self::DropLocals(_), _) => Ordering::Greater,
         }
     }
 }
@@ -82,13 +105,54 @@ impl Hash for Instr {
                 4u8.hash(state);
                 span.hash(state);
             }
+            Instr::BindLocal => 5u8.hash(state),
+            Instr::Local(n) => {
+                6u8.hash(state);
+                n.hash(state);
+            }
+            Instr::DropLocals(n) => {
+                7u8.hash(state);
+                n.hash(state);
+            }
+        }
+    }
+}
+
+/// Replace small constants with shared instances
+///
+/// Interned values share a buffer, so comparing them is a pointer comparison.
+fn intern(val: Value) -> Value {
+    const INTERNED_MAX: usize = 127;
+    thread_local! {
+        static NUMS: Vec<Value> = (0..=INTERNED_MAX).map(|n| Value::from(n as f64)).collect();
+        static CHARS: Vec<Value> = (0..=INTERNED_MAX).map(|c| Value::from(c as u8 as char)).collect();
+        static EMPTY_STRING: Value = Value::from("");
+    }
+    match &val {
+        Value::Num(nums) if nums.rank() == 0 => {
+            let n = nums.data[0];
+            if (0.0..=INTERNED_MAX as f64).contains(&n) && n.fract() == 0.0 && n.is_sign_positive()
+            {
+                return NUMS.with(|nums| nums[n as usize].clone());
+            }
+        }
+        Value::Char(chars) if chars.rank() == 0 => {
+            let c = chars.data[0];
+            if c as usize <= INTERNED_MAX {
+                return CHARS.with(|chars| chars[c as usize].clone());
+            }
+        }
+        Value::Char(chars) if chars.shape() == [0] => {
+            return EMPTY_STRING.with(Value::clone);
         }
+        _ => {}
     }
+    val
 }
 
 impl Instr {
     pub fn push(val: impl Into<Value>) -> Self {
-        Self::Push(Box::new(val.into()))
+        Self::Push(Box::new(intern(val.into())))
     }
     pub fn as_push(&self) -> Option<&Value> {
         match self {
@@ -98,6 +162,19 @@ impl Instr {
     }
 }
 
+#[test]
+fn interned_constants_share_buffers() {
+    let pushed = |val: Value| match Instr::push(val) {
+        Instr::Push(val) => *val,
+        _ => unreachable!(),
+    };
+    let nums = [5.0, 5.0, 500.0].map(|n| pushed(n.into()).as_num_array().unwrap().clone());
+    assert!(nums[0].ptr_eq(&nums[1]));
+    assert!(!nums[0].ptr_eq(&nums[2]));
+    let chars = ['x', 'x'].map(|c| pushed(c.into()).as_char_array().unwrap().clone());
+    assert!(chars[0].ptr_eq(&chars[1]));
+}
+
 impl fmt::Display for Instr {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
@@ -106,6 +183,9 @@ impl fmt::Display for Instr {
             Instr::EndArray { .. } => write!(f, "["),
             Instr::Prim(prim, _) => write!(f, "{prim}"),
             Instr::Call(_) => write!(f, "!"),
+            Instr::BindLocal => write!(f, "bind"),
+            Instr::Local(n) => write!(f, "local({n})"),
+            Instr::DropLocals(n) => write!(f, "unbind({n})"),
         }
     }
 }
@@ -308,6 +388,20 @@ impl Function {
     pub fn signature(&self) -> Signature {
         self.signature
     }
+    /// Check whether the function can safely be called on multiple threads at once
+    ///
+    /// This is conservative. Dynamic functions are never considered pure.
+    pub fn is_pure(&self) -> bool {
+        matches!(self.kind, FunctionKind::Normal)
+            && self.instrs.iter().all(|instr| match instr {
+                Instr::Prim(prim, _) => prim.is_pure(),
+                Instr::Push(val) => match &**val {
+                    Value::Func(fs) => fs.data.iter().all(|f| f.is_pure()),
+                    _ => true,
+                },
+                _ => true,
+            })
+    }
     pub fn is_constant(&self) -> bool {
         matches!(&*self.instrs, [Instr::Push(_)])
     }
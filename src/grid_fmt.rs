@@ -21,19 +21,34 @@ use crate::{
 type Grid<T = char> = Vec<Vec<T>>;
 type Metagrid = Grid<Grid>;
 
+/// The default maximum number of rows shown along each axis when pretty-printing arrays
+pub(crate) const DEFAULT_DISPLAY_BUDGET: usize = 100;
+
+/// Get the number of rows to show from the start and end of an axis
+fn shown_rows(len: usize, budget: usize) -> (usize, usize) {
+    if len <= budget {
+        (len, 0)
+    } else {
+        (budget.div_ceil(2), budget / 2)
+    }
+}
+
 pub trait GridFmt {
     fn fmt_grid(&self, boxed: bool) -> Grid;
     fn grid_string(&self) -> String {
-        let mut s: String = self
-            .fmt_grid(false)
-            .into_iter()
-            .flat_map(|v| v.into_iter().chain(once('\n')))
-            .collect();
-        s.pop();
-        s
+        grid_to_string(self.fmt_grid(false))
     }
 }
 
+pub(crate) fn grid_to_string(grid: Grid) -> String {
+    let mut s: String = grid
+        .into_iter()
+        .flat_map(|v| v.into_iter().chain(once('\n')))
+        .collect();
+    s.pop();
+    s
+}
+
 fn boxed_scalar(boxed: bool) -> impl Iterator<Item = char> {
     boxed
         .then_some(Primitive::Constant.unicode().unwrap())
@@ -138,6 +153,14 @@ impl GridFmt for Value {
 
 impl<T: GridFmt + ArrayValue> GridFmt for Array<T> {
     fn fmt_grid(&self, boxed: bool) -> Grid {
+        self.fmt_grid_budget(boxed, DEFAULT_DISPLAY_BUDGET)
+    }
+}
+
+impl<T: GridFmt + ArrayValue> Array<T> {
+    /// Format the array, showing at most `budget` rows along each axis
+    pub(crate) fn fmt_grid_budget(&self, boxed: bool, budget: usize) -> Grid {
+        let budget = budget.max(2);
         if self.shape.is_empty() {
             return self.data[0].fmt_grid(boxed);
         }
@@ -173,7 +196,7 @@ impl<T: GridFmt + ArrayValue> GridFmt for Array<T> {
         let mut grid: Grid = Grid::new();
 
         if !just_dims {
-            fmt_array(&self.shape, &self.data, stringy, &mut metagrid);
+            fmt_array(&self.shape, &self.data, stringy, budget, &mut metagrid);
             // Determine max row heights and column widths
             let metagrid_width = metagrid.iter().map(|row| row.len()).max().unwrap();
             let metagrid_height = metagrid.len();
@@ -274,6 +297,7 @@ fn fmt_array<T: GridFmt + ArrayValue>(
     shape: &[usize],
     data: &[T],
     stringy: bool,
+    budget: usize,
     metagrid: &mut Metagrid,
 ) {
     if data.is_empty() {
@@ -286,14 +310,32 @@ fn fmt_array<T: GridFmt + ArrayValue>(
         return;
     }
     if rank == 1 {
-        let mut row = Vec::with_capacity(shape[0]);
+        let (head, tail) = shown_rows(shape[0], budget);
+        let mut row = Vec::with_capacity(head + tail + 1);
         if stringy {
-            let mut s = String::new();
-            s.extend(data.iter().map(|c| c.to_string()));
-            row.push(vec![format!("{s:?}").chars().collect()]);
+            let head_str: String = data[..head].iter().map(|c| c.to_string()).collect();
+            let mut s = format!("{head_str:?}");
+            if tail > 0 {
+                let tail_str: String = data[data.len() - tail..]
+                    .iter()
+                    .map(|c| c.to_string())
+                    .collect();
+                s.pop();
+                s.push('…');
+                s.push_str(&format!("{tail_str:?}")[1..]);
+            }
+            row.push(vec![s.chars().collect()]);
         } else {
-            for (i, val) in data.iter().enumerate() {
-                let mut grid = val.fmt_grid(false);
+            let mut shown: Vec<Option<&T>> = data[..head].iter().map(Some).collect();
+            if tail > 0 {
+                shown.push(None);
+                shown.extend(data[data.len() - tail..].iter().map(Some));
+            }
+            for (i, val) in shown.into_iter().enumerate() {
+                let mut grid = match val {
+                    Some(val) => val.fmt_grid(false),
+                    None => vec![vec!['…']],
+                };
                 if i > 0 {
                     pad_grid_min(grid[0].len() + 1, grid.len(), &mut grid)
                 }
@@ -310,13 +352,23 @@ fn fmt_array<T: GridFmt + ArrayValue>(
     }
     let shape = &shape[1..];
     let cell_size = data.len() / cell_count;
-    for (i, cell) in data.chunks(cell_size).enumerate() {
+    let (head, tail) = shown_rows(cell_count, budget);
+    let mut cells: Vec<Option<&[T]>> = data.chunks(cell_size).take(head).map(Some).collect();
+    if tail > 0 {
+        cells.push(None);
+        cells.extend(data.chunks(cell_size).skip(cell_count - tail).map(Some));
+    }
+    for (i, cell) in cells.into_iter().enumerate() {
         if i > 0 && rank > 2 {
             for _ in 0..rank - 2 {
                 metagrid.push(vec![vec![vec![' ']]; metagrid.last().unwrap().len()]);
             }
         }
-        fmt_array(shape, cell, stringy, metagrid);
+        match cell {
+            Some(cell) => fmt_array(shape, cell, stringy, budget, metagrid),
+            // Mark the skipped rows in every column
+            None => metagrid.push(vec![vec![vec!['⋮']]; metagrid.last().unwrap().len()]),
+        }
     }
 }
 
@@ -365,3 +417,23 @@ fn pad_grid_min(width: usize, height: usize, grid: &mut Grid) {
         }
     }
 }
+
+#[test]
+fn long_arrays_are_truncated() {
+    let list: Value = (0..1000).map(f64::from).collect();
+    let shown = list.show();
+    assert!(shown.starts_with("[0 1 2 "), "{shown}");
+    assert!(shown.ends_with(" 998 999]"), "{shown}");
+    assert!(shown.contains('…') && !shown.contains(" 500 "), "{shown}");
+
+    let shape: crate::array::Shape = [1000, 2].into_iter().collect();
+    let table = Value::from((shape, vec![0.0; 2000]));
+    assert_eq!(table.show(), "[1000 × 2 number]");
+
+    let shape: crate::array::Shape = [50, 2].into_iter().collect();
+    let table = Value::from((shape, vec![0.0; 100]));
+    let shown = table.show_with_budget(10);
+    assert!(shown.contains('⋮'), "{shown}");
+    assert!(shown.lines().count() < 20, "{shown}");
+    assert!(!table.show().contains('⋮'));
+}
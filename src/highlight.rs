@@ -0,0 +1,143 @@
+//! Syntax highlighting for terminals and the web
+
+use crate::{
+    lsp::{spans, SpanKind},
+    primitive::{PrimClass, Primitive},
+};
+
+/// Highlight some code with ANSI escape codes for a terminal
+pub fn highlight_ansi(input: &str) -> String {
+    highlight(input, |output, kind, text| {
+        match kind.and_then(ansi_color) {
+            Some(color) => output.push_str(&format!("\x1b[{color}m{text}\x1b[0m")),
+            None => output.push_str(text),
+        }
+    })
+}
+
+/// Highlight some code as HTML spans
+///
+/// The spans use the same classes as the Uiua website, so they can be styled
+/// with its stylesheet.
+pub fn highlight_html(input: &str) -> String {
+    highlight(input, |output, kind, text| {
+        let text = escape_html(text);
+        match kind.and_then(html_class) {
+            Some(class) => output.push_str(&format!(r#"<span class="{class}">{text}</span>"#)),
+            None => output.push_str(&text),
+        }
+    })
+}
+
+/// Walk the classified spans of some code, including the text between them
+fn highlight(input: &str, mut push: impl FnMut(&mut String, Option<SpanKind>, &str)) -> String {
+    let chars: Vec<char> = input.chars().collect();
+    let text = |start: usize, end: usize| -> String { chars[start..end].iter().collect() };
+    let mut output = String::new();
+    let mut curr = 0;
+    for span in spans(input) {
+        let (start, end) = (span.span.start.char_pos, span.span.end.char_pos);
+        if start < curr || end > chars.len() {
+            continue;
+        }
+        if curr < start {
+            push(&mut output, None, &text(curr, start));
+        }
+        push(&mut output, Some(span.value), &text(start, end));
+        curr = end;
+    }
+    if curr < chars.len() {
+        push(&mut output, None, &text(curr, chars.len()));
+    }
+    output
+}
+
+fn ansi_color(kind: SpanKind) -> Option<u8> {
+    Some(match kind {
+        SpanKind::Primitive(prim) => match prim_kind(prim)? {
+            "noadic" => 31,
+            "monadic" => 32,
+            "dyadic" => 34,
+            "modifier1" => 33,
+            "modifier2" => 35,
+            _ => 90,
+        },
+        SpanKind::Number => 91,
+        SpanKind::String => 36,
+        SpanKind::Comment | SpanKind::Strand => 90,
+        _ => return None,
+    })
+}
+
+fn html_class(kind: SpanKind) -> Option<&'static str> {
+    Some(match kind {
+        SpanKind::Primitive(prim) => match prim_kind(prim)? {
+            "noadic" => "noadic-function-button",
+            "monadic" => "monadic-function-button",
+            "dyadic" => "dyadic-function-button",
+            "triadic" => "triadic-function-button",
+            "modifier1" => "modifier1-button",
+            "modifier2" => "modifier2-button",
+            _ => "variadic-function-button",
+        },
+        SpanKind::Number => "number-literal-span",
+        SpanKind::String => "string-literal-span",
+        SpanKind::Comment => "comment-span",
+        SpanKind::Strand => "strand-span",
+        _ => return None,
+    })
+}
+
+/// Get the kind of coloring for a primitive
+///
+/// Stack primitives are not colored.
+fn prim_kind(prim: Primitive) -> Option<&'static str> {
+    if let PrimClass::Stack = prim.class() {
+        return None;
+    }
+    Some(match prim.modifier_args() {
+        Some(1) => "modifier1",
+        Some(_) => "modifier2",
+        None => match prim.args() {
+            Some(0) => "noadic",
+            Some(1) => "monadic",
+            Some(2) => "dyadic",
+            Some(3) => "triadic",
+            _ => "variadic",
+        },
+    })
+}
+
+pub(crate) fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn highlighting() {
+        use {highlight_ansi, highlight_html};
+        assert_eq!(
+            highlight_html("+1 \"<a>\""),
+            "<span class=\"dyadic-function-button\">+</span>\
+             <span class=\"number-literal-span\">1</span> \
+             <span class=\"string-literal-span\">&quot;&lt;a&gt;&quot;</span>"
+        );
+        assert_eq!(
+            highlight_ansi("X ← ⇌ # hi"),
+            "X ← \x1b[32m⇌\x1b[0m \x1b[90m# hi\x1b[0m"
+        );
+    }
+}
@@ -159,6 +159,21 @@ impl CodeSpan {
     pub fn as_str(&self) -> &str {
         &self.input[self.start.byte_pos..self.end.byte_pos]
     }
+    /// Split a single-line span after the given number of characters
+    pub(crate) fn split_at(self, chars: usize) -> (Self, Self) {
+        let prefix: String = self.as_str().chars().take(chars).collect();
+        let mid = Loc {
+            col: self.start.col + chars,
+            char_pos: self.start.char_pos + chars,
+            byte_pos: self.start.byte_pos + prefix.len(),
+            ..self.start
+        };
+        let first = CodeSpan {
+            end: mid,
+            ..self.clone()
+        };
+        (first, CodeSpan { start: mid, ..self })
+    }
     pub fn contains_line_col(&self, line: usize, col: usize) -> bool {
         if self.start.line == self.end.line {
             self.start.line == line && (self.start.col..=self.end.col).contains(&col)
@@ -276,8 +291,14 @@ pub enum Token {
     Str(String),
     FormatStr(Vec<String>),
     MultilineString(Vec<String>),
+    /// A line of a raw string, written after `$$ `
+    RawString(String),
+    /// A line of a grid string, written after `$| `
+    GridString(String),
     Simple(AsciiToken),
     Glyph(Primitive),
+    /// A placeholder for a macro operand, like `^0`
+    Placeholder(usize),
     LeftArrow,
     Newline,
     Spaces,
@@ -308,6 +329,24 @@ impl Token {
             _ => None,
         }
     }
+    pub fn as_raw_string(&self) -> Option<String> {
+        match self {
+            Token::RawString(line) => Some(line.clone()),
+            _ => None,
+        }
+    }
+    pub fn as_grid_string(&self) -> Option<String> {
+        match self {
+            Token::GridString(line) => Some(line.clone()),
+            _ => None,
+        }
+    }
+    pub fn as_placeholder(&self) -> Option<usize> {
+        match self {
+            Token::Placeholder(n) => Some(*n),
+            _ => None,
+        }
+    }
     pub fn as_glyph(&self) -> Option<Primitive> {
         match self {
             Token::Glyph(glyph) => Some(*glyph),
@@ -446,6 +485,11 @@ impl Lexer {
     }
     fn run(mut self) -> (Vec<Sp<Token>>, Vec<Sp<LexError>>) {
         use {self::AsciiToken::*, Token::*};
+        // A `#!` line for running the file as a script
+        if self.next_chars_exact("#!") {
+            while self.next_char_if(|c| c != '\n').is_some() {}
+            self.next_char_exact('\n');
+        }
         // Initial scope delimiters
         let start = self.loc;
         if self.next_chars_exact("---") {
@@ -482,6 +526,14 @@ impl Lexer {
                 }
                 '*' => self.end(Star, start),
                 '%' => self.end(Percent, start),
+                '^' if self.peek_char().filter(char::is_ascii_digit).is_some() => {
+                    let mut n = String::new();
+                    while let Some(c) = self.next_char_if(|c| c.is_ascii_digit()) {
+                        n.push(c);
+                    }
+                    let n = n.parse().unwrap_or(usize::MAX);
+                    self.end(Placeholder(n), start)
+                }
                 '^' => self.end(Caret, start),
                 '=' => self.end(Equal, start),
                 '<' if self.next_char_exact('=') => self.end(LessEqual, start),
@@ -520,6 +572,42 @@ impl Lexer {
                 // Strings
                 '"' | '$' => {
                     let format = c == '$';
+                    let grid = format && self.next_char_exact('|');
+                    if grid || format && self.next_char_exact('$') {
+                        // Raw and grid strings
+                        let prefix = if grid { "$|" } else { "$$" };
+                        let mut start = start;
+                        loop {
+                            self.next_char_exact(' ');
+                            let mut line = String::new();
+                            while let Some(c) = self.next_char_if(|c| c != '\n') {
+                                line.push(c);
+                            }
+                            if line.ends_with('\r') {
+                                line.pop();
+                            }
+                            let token = if grid {
+                                GridString(line)
+                            } else {
+                                RawString(line)
+                            };
+                            self.end(token, start);
+                            let checkpoint = self.loc;
+                            if self.next_char_exact('\n') {
+                                while self
+                                    .next_char_if(|c| c.is_whitespace() && c != '\n')
+                                    .is_some()
+                                {}
+                                start = self.loc;
+                                if self.next_chars_exact(prefix) {
+                                    continue;
+                                }
+                            }
+                            self.loc = checkpoint;
+                            break;
+                        }
+                        continue;
+                    }
                     if format && self.next_char_exact(' ') {
                         // Multiline strings
                         let mut start = start;
@@ -573,8 +661,42 @@ impl Lexer {
                     while let Some(c) = self.next_char_if(is_ident_char) {
                         ident.push(c);
                     }
+                    // Qualified names refer to a function in a module, like `Module~Name`
+                    // Whether the `~` is really a trace is decided when compiling
+                    let mut qualified = false;
+                    let before_tilde = self.loc;
+                    if Primitive::from_format_name_multi(&ident).is_none()
+                        && self.next_char_exact('~')
+                    {
+                        let mut name = String::new();
+                        while let Some(c) = self.next_char_if(is_ident_char) {
+                            name.push(c);
+                        }
+                        if !name.is_empty() && Primitive::from_format_name_multi(&name).is_none() {
+                            qualified = true;
+                        } else {
+                            self.loc = before_tilde;
+                        }
+                    }
+                    // Macro names end in `!`, like `Name!`
+                    let mut is_macro = false;
+                    if !qualified && ident.starts_with(char::is_uppercase) {
+                        let before_bang = self.loc;
+                        if self.next_char_exact('!') {
+                            if self.peek_char() == Some('=') {
+                                self.loc = before_bang;
+                            } else {
+                                is_macro = true;
+                            }
+                        }
+                    }
                     // Try to parse as primitives
-                    if let Some(prims) = Primitive::from_format_name_multi(&ident) {
+                    let prims = if qualified || is_macro {
+                        None
+                    } else {
+                        Primitive::from_format_name_multi(&ident)
+                    };
+                    if let Some(prims) = prims {
                         let mut start = start;
                         for (prim, frag) in prims {
                             let end = Loc {
@@ -633,6 +755,25 @@ impl Lexer {
         (self.tokens, self.errors)
     }
     fn number(&mut self, init: char) -> bool {
+        // Hexadecimal and binary
+        // `init` is `-` for negative numbers, whose digits have not been read yet
+        let before_prefix = self.loc;
+        if init == '0' || init == '-' && self.next_char_exact('0') {
+            for (prefix, radix) in [('x', 16), ('b', 2)] {
+                let before_radix = self.loc;
+                if self.next_char_exact(prefix) {
+                    let mut got_digit = false;
+                    while self.next_char_if(|c| c.is_digit(radix)).is_some() {
+                        got_digit = true;
+                    }
+                    if got_digit {
+                        return true;
+                    }
+                    self.loc = before_radix;
+                }
+            }
+        }
+        self.loc = before_prefix;
         // Whole part
         let mut got_digit = false;
         while self.next_char_if(|c| c.is_ascii_digit()).is_some() {
@@ -687,6 +828,30 @@ impl Lexer {
                 '"' => '"',
                 '\'' => '\'',
                 '_' => char::MAX,
+                'x' => {
+                    let mut code = String::new();
+                    for _ in 0..2 {
+                        code.extend(self.next_char_if(|c| c.is_ascii_hexdigit()));
+                    }
+                    (u32::from_str_radix(&code, 16).ok())
+                        .and_then(char::from_u32)
+                        .ok_or('x')?
+                }
+                'u' => {
+                    if !self.next_char_exact('{') {
+                        return Err('u');
+                    }
+                    let mut code = String::new();
+                    while let Some(c) = self.next_char_if(|c| c.is_ascii_hexdigit()) {
+                        code.push(c);
+                    }
+                    if !self.next_char_exact('}') {
+                        return Err('u');
+                    }
+                    (u32::from_str_radix(&code, 16).ok())
+                        .and_then(char::from_u32)
+                        .ok_or('u')?
+                }
                 c => return Err(c),
             }
         } else if c == '\\' {
@@ -10,14 +10,20 @@ The current API should be considered deeply unstable.
 mod algorithm;
 pub mod array;
 pub mod ast;
+mod bytecode;
 mod check;
+mod compile;
 mod cowslice;
+pub mod doc;
 mod error;
+mod error_code;
 pub mod format;
 pub mod function;
 mod grid_fmt;
+pub mod highlight;
 pub mod lex;
 pub mod lsp;
+mod memory_sys;
 pub mod parse;
 pub mod primitive;
 #[doc(hidden)]
@@ -28,7 +34,7 @@ pub mod value;
 
 use std::sync::Arc;
 
-pub use {error::*, run::Uiua, sys::*};
+pub use {error::*, error_code::ErrorCode, memory_sys::MemorySys, run::Uiua, sys::*};
 
 pub type Ident = Arc<str>;
 
@@ -41,6 +47,14 @@ fn suite() {
             if let Err(e) = Uiua::with_native_sys().load_file(&path) {
                 panic!("Test failed in {}:\n{}", path.display(), e.show(true));
             }
+            // Also run everything that would be evaluated at compile time
+            if let Err(e) = Uiua::with_native_sys().with_folding(false).load_file(&path) {
+                panic!(
+                    "Test failed without folding in {}:\n{}",
+                    path.display(),
+                    e.show(true)
+                );
+            }
         }
     }
 }
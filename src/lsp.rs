@@ -1,24 +1,45 @@
-use std::slice;
+use std::{ops, slice};
 
 use crate::{
     ast::{Item, Word},
+    check::instrs_signature,
+    function::Signature,
     lex::{CodeSpan, Loc, Sp},
     parse::parse,
     primitive::Primitive,
+    MemorySys, Uiua,
 };
 
+/// The kind of a span of code, for syntax highlighting
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum SpanKind {
+    /// A primitive, including modifiers
     Primitive(Primitive),
+    /// A string or character literal
     String,
     Number,
     Comment,
+    /// The `_` between the items of a strand
     Strand,
+    /// A use of a name
     Ident,
+    /// The name of a binding where it is bound
+    Binding,
     Signature,
     Whitespace,
 }
 
+impl SpanKind {
+    /// Check whether this is a modifier primitive
+    pub fn is_modifier(&self) -> bool {
+        matches!(self, SpanKind::Primitive(prim) if prim.modifier_args().is_some())
+    }
+}
+
+/// Classify the spans of some code for syntax highlighting
+///
+/// The spans are in the order they appear in the code. Code that does not parse
+/// still produces spans for the parts that do.
 pub fn spans(input: &str) -> Vec<Sp<SpanKind>> {
     let (items, _) = parse(input, None);
     items_spans(&items)
@@ -31,6 +52,7 @@ fn items_spans(items: &[Item]) -> Vec<Sp<SpanKind>> {
             Item::Scoped { items, .. } => spans.extend(items_spans(items)),
             Item::Words(words) => spans.extend(words_spans(words)),
             Item::Binding(binding) => {
+                spans.push(binding.name.span.clone().sp(SpanKind::Binding));
                 if let Some(sig) = &binding.signature {
                     spans.push(sig.span.clone().sp(SpanKind::Signature));
                 }
@@ -53,6 +75,9 @@ fn words_spans(words: &[Sp<Word>]) -> Vec<Sp<SpanKind>> {
             Word::MultilineString(lines) => {
                 spans.extend((lines.iter()).map(|line| line.span.clone().sp(SpanKind::String)))
             }
+            Word::RawString(lines) | Word::GridString(lines) => {
+                spans.extend((lines.iter()).map(|line| line.span.clone().sp(SpanKind::String)))
+            }
             Word::Ident(_) => spans.push(word.span.clone().sp(SpanKind::Ident)),
             Word::Strand(items) => {
                 for (i, word) in items.iter().enumerate() {
@@ -90,6 +115,15 @@ fn words_spans(words: &[Sp<Word>]) -> Vec<Sp<SpanKind>> {
                 spans.push(m.modifier.clone().map(SpanKind::Primitive));
                 spans.extend(words_spans(&m.operands));
             }
+            Word::Placeholder(_) => spans.push(word.span.clone().sp(SpanKind::Ident)),
+            Word::MacroCall(m) => {
+                spans.push(m.name.span.clone().sp(SpanKind::Ident));
+                spans.extend(words_spans(&m.operands));
+            }
+            Word::LocalBinding(binding) => {
+                spans.push(binding.name.span.clone().sp(SpanKind::Binding));
+                spans.extend(words_spans(&binding.words));
+            }
             Word::Spaces => spans.push(word.span.clone().sp(SpanKind::Whitespace)),
             Word::Comment(_) => spans.push(word.span.clone().sp(SpanKind::Comment)),
         }
@@ -97,6 +131,211 @@ fn words_spans(words: &[Sp<Word>]) -> Vec<Sp<SpanKind>> {
     spans
 }
 
+#[test]
+fn spans_are_classified_in_order() {
+    let kinds: Vec<SpanKind> = spans("F ← ∵+1 \"hi\" # comment")
+        .into_iter()
+        .map(|sp| sp.value)
+        .filter(|kind| *kind != SpanKind::Whitespace)
+        .collect();
+    assert_eq!(
+        kinds,
+        [
+            SpanKind::Binding,
+            SpanKind::Primitive(Primitive::Each),
+            SpanKind::Primitive(Primitive::Add),
+            SpanKind::Number,
+            SpanKind::String,
+            SpanKind::Comment,
+        ]
+    );
+    assert!(kinds[1].is_modifier() && !kinds[2].is_modifier());
+}
+
+/// A candidate for completing the name being typed at a cursor
+#[derive(Debug, Clone, PartialEq)]
+pub struct Completion {
+    /// The full name of the candidate
+    pub name: String,
+    /// The text that should replace the typed name
+    ///
+    /// For primitives with a glyph, this is the glyph.
+    pub text: String,
+    /// The byte range of the typed name
+    pub replace: ops::Range<usize>,
+    pub kind: CompletionKind,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompletionKind {
+    Primitive(Primitive),
+    /// A binding, with its signature if it is declared or can be inferred
+    Binding(Option<Signature>),
+}
+
+/// Get the completion candidates for the name ending at a byte offset in some code
+///
+/// Bindings are candidates if they are in scope at the cursor.
+/// Primitives are only candidates for lowercase names, because uppercase names
+/// are never formatted into primitives.
+pub fn completions(input: &str, cursor: usize) -> Vec<Completion> {
+    let cursor = cursor.min(input.len());
+    let start = input[..cursor]
+        .char_indices()
+        .rev()
+        .take_while(|(_, c)| c.is_ascii_alphabetic() || *c == '&')
+        .last()
+        .map_or(cursor, |(i, _)| i);
+    let prefix = &input[start..cursor];
+    if prefix.is_empty() {
+        return Vec::new();
+    }
+    let replace = start..cursor;
+    let mut completions = Vec::new();
+    if !prefix.chars().any(char::is_uppercase) {
+        for prim in Primitive::all() {
+            let Some(name) = prim.name() else {
+                continue;
+            };
+            if name.starts_with(prefix) {
+                completions.push(Completion {
+                    name: name.into(),
+                    text: prim.unicode().map_or_else(|| name.into(), String::from),
+                    replace: replace.clone(),
+                    kind: CompletionKind::Primitive(prim),
+                });
+            }
+        }
+    }
+    let (items, _) = parse(input, None);
+    for (name, sig) in bindings_in_scope(&items, start) {
+        if name.starts_with(prefix) {
+            completions.push(Completion {
+                text: name.clone(),
+                name,
+                replace: replace.clone(),
+                kind: CompletionKind::Binding(sig),
+            });
+        }
+    }
+    completions
+}
+
+/// Get the bindings defined before a byte offset that are in scope there
+fn bindings_in_scope(items: &[Item], pos: usize) -> Vec<(String, Option<Signature>)> {
+    let mut bindings: Vec<(String, Option<Signature>)> = Vec::new();
+    let mut bind = |name: String, sig: Option<Signature>| {
+        bindings.retain(|(other, _)| *other != name);
+        bindings.push((name, sig));
+    };
+    for (i, item) in items.iter().enumerate() {
+        if item_start(item).is_some_and(|start| start >= pos) {
+            break;
+        }
+        match item {
+            Item::Binding(binding) => {
+                let sig = binding.signature.as_ref().map(|sig| sig.value).or_else(|| {
+                    if words_have_idents(&binding.words) {
+                        return None;
+                    }
+                    let instrs = signature_env()
+                        .compile_words(binding.words.clone(), true)
+                        .ok()?;
+                    instrs_signature(&instrs).ok()
+                });
+                bind(binding.name.value.to_string(), sig);
+            }
+            Item::Scoped { items: scoped, .. } => {
+                // Bindings in a scope are only visible inside it
+                let next_start = items[i + 1..].iter().find_map(item_start);
+                if next_start.map_or(true, |start| start >= pos) {
+                    for (name, sig) in bindings_in_scope(scoped, pos) {
+                        bind(name, sig);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    bindings
+}
+
+/// Create a runtime for compiling bindings to infer their signatures
+///
+/// Completions are requested on every keystroke, so the code is compiled
+/// without access to the system. Folding constants cannot change a
+/// signature, so it is turned off rather than run on untrusted code.
+fn signature_env() -> Uiua {
+    Uiua::with_backend(MemorySys::new())
+        .with_sandbox(true)
+        .with_folding(false)
+}
+
+fn item_start(item: &Item) -> Option<usize> {
+    match item {
+        Item::Scoped { items, .. } => items.iter().find_map(item_start),
+        Item::Words(words) => words.first().map(|word| word.span.start.byte_pos),
+        Item::Binding(binding) => Some(binding.name.span.start.byte_pos),
+        Item::ExtraNewlines(span) => Some(span.start.byte_pos),
+    }
+}
+
+fn words_have_idents(words: &[Sp<Word>]) -> bool {
+    words.iter().any(|word| match &word.value {
+        Word::Ident(_) => true,
+        Word::Strand(items) => words_have_idents(items),
+        Word::Array(arr) => arr.lines.iter().any(|line| words_have_idents(line)),
+        Word::Func(func) => func.lines.iter().any(|line| words_have_idents(line)),
+        Word::Modified(m) => words_have_idents(&m.operands),
+        Word::MacroCall(_) => true,
+        Word::LocalBinding(binding) => words_have_idents(&binding.words),
+        _ => false,
+    })
+}
+
+#[test]
+fn completions_include_primitives_and_bindings() {
+    let code = "Foo ← +1\nF ← 5\nrev F";
+    let names = |cursor: usize| -> Vec<(String, String, CompletionKind)> {
+        (completions(code, cursor).into_iter())
+            .map(|c| (c.name, c.text, c.kind))
+            .collect()
+    };
+    assert_eq!(
+        names(code.len()),
+        [
+            (
+                "Foo".into(),
+                "Foo".into(),
+                CompletionKind::Binding(Some(Signature::new(1, 1)))
+            ),
+            (
+                "F".into(),
+                "F".into(),
+                CompletionKind::Binding(Some(Signature::new(0, 1)))
+            ),
+        ]
+    );
+    let rev = code.find("rev").unwrap() + 3;
+    assert_eq!(
+        names(rev),
+        [(
+            "reverse".into(),
+            "⇌".into(),
+            CompletionKind::Primitive(Primitive::Reverse)
+        )]
+    );
+    assert_eq!(completions(code, rev)[0].replace, rev - 3..rev);
+    // Bindings are not in scope before they are defined
+    assert!(completions("F\nF ← 5", 1).is_empty());
+    // Constants are not evaluated to find signatures
+    let code = "Big ← ⊞+.⇡1e5\nB";
+    assert_eq!(
+        completions(code, code.len())[0].kind,
+        CompletionKind::Binding(Some(Signature::new(0, 1)))
+    );
+}
+
 #[cfg(feature = "lsp")]
 pub use server::run_server;
 
@@ -110,31 +349,62 @@ mod server {
     use super::*;
 
     use crate::{
+        ast::{line_comment, push_doc_line},
         format::{format_str, FormatConfig},
-        lex::Loc,
-        primitive::PrimDocFragment,
-        Ident, Uiua,
+        lex::{Loc, Span},
+        parse::ParseError,
+        Ident, Uiua, UiuaError,
     };
 
     pub struct LspDoc {
         pub input: String,
         pub spans: Vec<Sp<SpanKind>>,
         pub bindings: BindingsInfo,
+        pub errors: Vec<Sp<ParseError>>,
     }
 
     type BindingsInfo = BTreeMap<Sp<Ident>, Arc<BindingInfo>>;
 
     impl LspDoc {
         fn new(input: String) -> Self {
-            let (items, _) = parse(&input, None);
+            let (items, errors) = parse(&input, None);
             let spans = items_spans(&items);
             let bindings = bindings_info(&items);
             Self {
                 input,
                 spans,
                 bindings,
+                errors,
             }
         }
+        fn diagnostics(&self, uri: &Url) -> Vec<Diagnostic> {
+            let reports = UiuaError::Parse(self.errors.clone()).reports();
+            (reports.into_iter())
+                .filter_map(|report| {
+                    let Span::Code(span) = report.span else {
+                        return None;
+                    };
+                    let related = (report.labels.iter())
+                        .map(|label| DiagnosticRelatedInformation {
+                            location: Location {
+                                uri: uri.clone(),
+                                range: uiua_span_to_lsp(&label.span),
+                            },
+                            message: label.value.clone(),
+                        })
+                        .collect::<Vec<_>>();
+                    Some(Diagnostic {
+                        range: uiua_span_to_lsp(&span),
+                        severity: Some(DiagnosticSeverity::ERROR),
+                        code: Some(NumberOrString::String(report.code.to_string())),
+                        source: Some("uiua".into()),
+                        message: report.message,
+                        related_information: (!related.is_empty()).then_some(related),
+                        ..Default::default()
+                    })
+                })
+                .collect()
+        }
     }
 
     pub struct BindingInfo {
@@ -150,21 +420,8 @@ mod server {
             match item {
                 Item::Scoped { items, .. } => scope_bindings.push(bindings_info(items)),
                 Item::Words(words) => {
-                    if let [Sp {
-                        value: Word::Comment(comment),
-                        ..
-                    }] = words.as_slice()
-                    {
-                        let full = last_comment.get_or_insert_with(String::new);
-                        if !full.is_empty() {
-                            if comment.trim().is_empty() {
-                                full.push('\n');
-                                full.push('\n');
-                            } else {
-                                full.push(' ');
-                            }
-                        }
-                        full.push_str(comment.trim());
+                    if let Some(comment) = line_comment(words) {
+                        push_doc_line(last_comment.get_or_insert_with(String::new), comment);
                     } else {
                         last_comment = None;
                         for word in words {
@@ -220,6 +477,18 @@ mod server {
         docs: DashMap<Url, LspDoc>,
     }
 
+    impl Backend {
+        /// Replace a document's contents and publish its syntax errors
+        async fn update_doc(&self, uri: Url, text: String) {
+            let doc = LspDoc::new(text);
+            let diagnostics = doc.diagnostics(&uri);
+            self.docs.insert(uri.clone(), doc);
+            self.client
+                .publish_diagnostics(uri, diagnostics, None)
+                .await;
+        }
+    }
+
     #[tower_lsp::async_trait]
     impl LanguageServer for Backend {
         async fn initialize(&self, _params: InitializeParams) -> Result<InitializeResult> {
@@ -229,6 +498,7 @@ mod server {
                         TextDocumentSyncKind::FULL,
                     )),
                     hover_provider: Some(HoverProviderCapability::Simple(true)),
+                    definition_provider: Some(OneOf::Left(true)),
                     document_formatting_provider: Some(OneOf::Left(true)),
                     semantic_tokens_provider: Some(
                         SemanticTokensServerCapabilities::SemanticTokensOptions(
@@ -239,6 +509,10 @@ mod server {
                                         SemanticTokenType::STRING,
                                         SemanticTokenType::NUMBER,
                                         SemanticTokenType::COMMENT,
+                                        SemanticTokenType::OPERATOR,
+                                        SemanticTokenType::MACRO,
+                                        SemanticTokenType::VARIABLE,
+                                        SemanticTokenType::FUNCTION,
                                     ],
                                     token_modifiers: vec![],
                                 },
@@ -260,17 +534,37 @@ mod server {
         }
 
         async fn did_open(&self, param: DidOpenTextDocumentParams) {
-            self.docs.insert(
-                param.text_document.uri,
-                LspDoc::new(param.text_document.text),
-            );
+            self.update_doc(param.text_document.uri, param.text_document.text)
+                .await;
         }
 
         async fn did_change(&self, params: DidChangeTextDocumentParams) {
-            self.docs.insert(
+            self.update_doc(
                 params.text_document.uri,
-                LspDoc::new(params.content_changes[0].text.clone()),
-            );
+                params.content_changes[0].text.clone(),
+            )
+            .await;
+        }
+
+        async fn goto_definition(
+            &self,
+            params: GotoDefinitionParams,
+        ) -> Result<Option<GotoDefinitionResponse>> {
+            let uri = params.text_document_position_params.text_document.uri;
+            let Some(doc) = self.docs.get(&uri) else {
+                return Ok(None);
+            };
+            let (line, col) = lsp_pos_to_uiua(params.text_document_position_params.position);
+            Ok(doc
+                .bindings
+                .iter()
+                .find(|(ident, _)| ident.span.contains_line_col(line, col))
+                .map(|(_, binding)| {
+                    GotoDefinitionResponse::Scalar(Location {
+                        uri: uri.clone(),
+                        range: uiua_span_to_lsp(&binding.span),
+                    })
+                }))
         }
 
         async fn hover(&self, params: HoverParams) -> Result<Option<Hover>> {
@@ -305,30 +599,7 @@ mod server {
             Ok(Some(if let Some((prim, range)) = prim_range {
                 let mut contents = vec![MarkedString::String(prim.name().unwrap().into())];
                 if let Some(doc) = prim.doc() {
-                    contents.push(MarkedString::String(
-                        doc.short
-                            .iter()
-                            .map(|frag| match frag {
-                                PrimDocFragment::Text(text) => text.clone(),
-                                PrimDocFragment::Code(code) => code.clone(),
-                                PrimDocFragment::Emphasis(text) => text.clone(),
-                                PrimDocFragment::Primitive { prim, named } => {
-                                    let name = prim.name().unwrap();
-                                    if *named {
-                                        if let Some(unicode) = prim.unicode() {
-                                            format!("{} {}", unicode, name)
-                                        } else {
-                                            name.into()
-                                        }
-                                    } else if let Some(unicode) = prim.unicode() {
-                                        unicode.into()
-                                    } else {
-                                        name.into()
-                                    }
-                                }
-                            })
-                            .collect(),
-                    ))
+                    contents.push(MarkedString::String(doc.text()));
                 }
                 Hover {
                     contents: HoverContents::Array(contents),
@@ -429,17 +700,23 @@ mod server {
                     SpanKind::String => 0,
                     SpanKind::Number => 1,
                     SpanKind::Comment => 2,
+                    kind @ SpanKind::Primitive(_) if kind.is_modifier() => 4,
+                    SpanKind::Primitive(_) => 3,
+                    SpanKind::Ident => 5,
+                    SpanKind::Binding => 6,
                     _ => continue,
                 };
                 let span = &sp.span;
                 let start = uiua_loc_to_lsp(span.start);
-                let delta_start = if start.character > prev_char {
+                // Tokens on the same line as the previous one are positioned relative to it
+                let delta_line = start.line - prev_line;
+                let delta_start = if delta_line == 0 {
                     start.character - prev_char
                 } else {
                     start.character
                 };
                 tokens.push(SemanticToken {
-                    delta_line: start.line - prev_line,
+                    delta_line,
                     delta_start,
                     length: (span.end.char_pos - span.start.char_pos) as u32,
                     token_type,
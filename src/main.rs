@@ -2,12 +2,13 @@
 compile_error!("To compile the uiua interpreter binary, you must enable the `binary` feature flag");
 
 use std::{
+    borrow::Cow,
     env, fs,
-    io::{self, stderr, Write},
+    io::{self, stderr, BufRead, BufReader, IsTerminal, Read, Seek, SeekFrom, Write},
     path::{Path, PathBuf},
-    process::{exit, Child, Command},
+    process::{exit, Child, Command, Stdio},
     sync::mpsc::channel,
-    thread::sleep,
+    thread::{self, sleep, JoinHandle},
     time::Duration,
 };
 
@@ -16,16 +17,50 @@ use instant::Instant;
 use notify::{EventKind, RecursiveMode, Watcher};
 use once_cell::sync::Lazy;
 use parking_lot::Mutex;
+use rustyline::{
+    completion::{Completer, Pair},
+    error::ReadlineError,
+    highlight::Highlighter,
+    hint::Hinter,
+    history::DefaultHistory,
+    validate::{ValidationContext, ValidationResult, Validator},
+    Context, Editor, Helper,
+};
 use uiua::{
-    format::{format_file, FormatConfig},
-    run::RunMode,
-    Uiua, UiuaError, UiuaResult,
+    ast::{Item, Word},
+    doc::{binding_docs, primitive_docs, DocFormat},
+    format::{diff, format, format_file, format_str, FormatConfig},
+    highlight::{highlight_ansi, highlight_html},
+    lex::{lex, AsciiToken, Sp, Token},
+    parse::parse,
+    primitive::Primitive,
+    restore_native_terminal,
+    run::{InterruptHandle, RunMode},
+    set_native_stdin, Capability, DiagnosticKind, ErrorCode, NativeStdin, SysOp, Uiua, UiuaError,
+    UiuaResult,
 };
 
 fn main() {
     color_backtrace::install();
 
+    if let Some(program) = embedded_program() {
+        let res = run_embedded(program);
+        restore_native_terminal();
+        if let Err(e) = res {
+            println!("{}", e.show(true));
+            exit(1);
+        }
+        return;
+    }
+
     let _ = ctrlc::set_handler(|| {
+        // Interrupt a running program the first time, and exit the second time
+        if let Some(handle) = &*INTERRUPT.lock() {
+            if !handle.is_interrupted() {
+                handle.interrupt();
+                return;
+            }
+        }
         let mut child = WATCH_CHILD.lock();
         if let Some(ch) = &mut *child {
             _ = ch.kill();
@@ -33,21 +68,47 @@ fn main() {
             println!("# Program interrupted");
             print_watching();
         } else {
-            if let Ok(App::Watch) | Err(_) = App::try_parse() {
+            if let Ok(App::Watch { .. }) | Err(_) = App::try_parse() {
                 clear_watching_with(" ", "");
             }
             exit(0)
         }
     });
 
-    if let Err(e) = run() {
+    let res = run();
+    restore_native_terminal();
+    if let Err(e) = res {
         println!("{}", e.show(true));
         exit(1);
     }
 }
 
+fn print_diagnostics(rt: &mut Uiua) {
+    for diagnostic in rt.take_diagnostics() {
+        eprintln!("{}", diagnostic.show(stderr_color()));
+    }
+}
+
+/// Whether to color what is printed to stderr
+fn stderr_color() -> bool {
+    stderr().is_terminal() && env::var_os("NO_COLOR").is_none()
+}
+
+/// Whether to color what is printed to stdout
+fn stdout_color() -> bool {
+    io::stdout().is_terminal() && env::var_os("NO_COLOR").is_none()
+}
+
 static WATCH_CHILD: Lazy<Mutex<Option<Child>>> = Lazy::new(Default::default);
 
+/// The handle of the runtime that Ctrl-C interrupts
+static INTERRUPT: Lazy<Mutex<Option<InterruptHandle>>> = Lazy::new(Default::default);
+
+/// Make Ctrl-C interrupt a runtime instead of exiting
+fn interrupt_on_ctrl_c(rt: &Uiua) {
+    *INTERRUPT.lock() = Some(rt.interrupt_handle());
+}
+
 fn run() -> UiuaResult {
     if cfg!(feature = "profile") {
         uiua::profile::run_profile();
@@ -55,7 +116,8 @@ fn run() -> UiuaResult {
     }
     match App::try_parse() {
         Ok(app) => {
-            let config = FormatConfig::default();
+            // The config is only loaded by the commands that format
+            let find_config = || FormatConfig::find(Path::new("."));
             match app {
                 App::Init => {
                     if let Some(path) = working_file_path() {
@@ -65,36 +127,151 @@ fn run() -> UiuaResult {
                         _ = open::that("main.ua");
                     }
                 }
-                App::Fmt { path } => {
-                    if let Some(path) = path {
-                        format_file(path, &config)?;
+                App::Fmt {
+                    path,
+                    migrate,
+                    check,
+                    ascii,
+                    stdin,
+                } => {
+                    let config = find_config()?;
+                    let config = FormatConfig {
+                        migrate_deprecated: migrate,
+                        ascii: ascii || config.ascii,
+                        ..config
+                    };
+                    if stdin {
+                        let input = io::read_to_string(io::stdin())
+                            .map_err(|e| UiuaError::Load("<stdin>".into(), e.into()))?;
+                        match format_str(&input, &config) {
+                            Ok(formatted) => print!("{formatted}"),
+                            Err(e) => {
+                                eprintln!("{}", e.show(false));
+                                exit(1);
+                            }
+                        }
+                        return Ok(());
+                    }
+                    let paths = path.map(|path| vec![path]).unwrap_or_else(uiua_files);
+                    if check {
+                        let mut unformatted = 0;
+                        for path in paths {
+                            let input = fs::read_to_string(&path)
+                                .map_err(|e| UiuaError::Load(path.clone(), e.into()))?;
+                            let formatted = format(&input, &path, &config)?;
+                            if formatted != input {
+                                unformatted += 1;
+                                println!("{} would be reformatted", path.display());
+                                print!("{}", diff(&input, &formatted));
+                            }
+                        }
+                        if unformatted > 0 {
+                            exit(1);
+                        }
                     } else {
-                        for path in uiua_files() {
+                        for path in paths {
                             format_file(path, &config)?;
                         }
                     }
                 }
                 App::Run {
                     path,
+                    eval,
+                    input,
                     no_format,
                     mode,
+                    time_limit,
+                    max_instructions,
+                    debug,
+                    profile,
+                    timeline,
+                    seed,
+                    sandbox,
+                    allow,
+                    #[cfg(feature = "gpu")]
+                    gpu,
+                    #[cfg(feature = "gpu")]
+                    gpu_f32,
                     #[cfg(feature = "audio")]
                     audio_options,
                 } => {
-                    if let Some(path) = path.or_else(working_file_path) {
-                        if !no_format {
-                            format_file(&path, &config)?;
+                    let path = path.or_else(working_file_path);
+                    if eval.is_none() && path.is_none() {
+                        eprintln!("{NO_UA_FILE}");
+                        return Ok(());
+                    }
+                    let mode = mode.unwrap_or(RunMode::Normal);
+                    #[cfg(feature = "audio")]
+                    setup_audio(audio_options);
+                    let mut rt = Uiua::with_native_sys().with_mode(mode);
+                    if sandbox || !allow.is_empty() {
+                        rt = rt.with_capabilities(allow);
+                    }
+                    #[cfg(feature = "gpu")]
+                    {
+                        rt = rt.with_gpu(gpu).with_gpu_single_precision(gpu_f32);
+                    }
+                    if let Some(seconds) = time_limit {
+                        rt = rt.with_execution_limit(Duration::from_secs_f64(seconds));
+                    }
+                    if let Some(limit) = max_instructions {
+                        rt = rt.with_instruction_limit(limit);
+                    }
+                    if debug {
+                        rt = rt.with_step_handler(debugger());
+                    }
+                    if timeline.is_some() {
+                        rt = rt.with_timeline(true);
+                    } else if profile {
+                        rt = rt.with_profiling(true);
+                    }
+                    if let Some(seed) = seed {
+                        rt = rt.with_deterministic(seed);
+                    }
+                    interrupt_on_ctrl_c(&rt);
+                    let from_stdin = eval.is_none() && path.as_deref() == Some(Path::new("-"));
+                    if let Some(input) = input {
+                        let file: Box<dyn io::Read + Send + Sync> = Box::new(
+                            fs::File::open(&input)
+                                .map_err(|e| UiuaError::Load(input.clone(), e.into()))?,
+                        );
+                        set_native_stdin(NativeStdin::Reader(BufReader::new(file)));
+                    } else if from_stdin {
+                        set_native_stdin(NativeStdin::Disabled);
+                    }
+                    let res = match (eval, path) {
+                        (Some(code), _) => rt.load_str(&code).map(drop),
+                        (None, Some(_)) if from_stdin => {
+                            let code = io::read_to_string(io::stdin())
+                                .map_err(|e| UiuaError::Load("<stdin>".into(), e.into()))?;
+                            rt.load_str(&code).map(drop)
                         }
-                        let mode = mode.unwrap_or(RunMode::Normal);
-                        #[cfg(feature = "audio")]
-                        setup_audio(audio_options);
-                        let mut rt = Uiua::with_native_sys().with_mode(mode);
-                        rt.load_file(path)?;
-                        for value in rt.take_stack() {
-                            println!("{}", value.show());
+                        (None, Some(path)) => {
+                            if !no_format {
+                                format_file(&path, &find_config()?)?;
+                            }
+                            rt.load_file(path).map(drop)
                         }
-                    } else {
-                        eprintln!("{NO_UA_FILE}");
+                        (None, None) => unreachable!(),
+                    };
+                    print_diagnostics(&mut rt);
+                    if let Some(recorded) = rt.profile() {
+                        if profile {
+                            eprint!("{}", recorded.report());
+                        }
+                        if let Some(path) = timeline {
+                            let contents = if path.extension().is_some_and(|ext| ext == "json") {
+                                recorded.chrome_trace()
+                            } else {
+                                recorded.folded_stacks()
+                            };
+                            fs::write(&path, contents)
+                                .map_err(|e| UiuaError::Load(path.clone(), e.into()))?;
+                        }
+                    }
+                    res?;
+                    for value in rt.take_stack() {
+                        println!("{}", value.show());
                     }
                 }
                 App::Eval {
@@ -105,25 +282,123 @@ fn run() -> UiuaResult {
                     #[cfg(feature = "audio")]
                     setup_audio(audio_options);
                     let mut rt = Uiua::with_native_sys().with_mode(RunMode::Normal);
-                    rt.load_str(&code)?;
+                    interrupt_on_ctrl_c(&rt);
+                    let res = rt.load_str(&code).map(drop);
+                    print_diagnostics(&mut rt);
+                    res?;
                     for value in rt.take_stack() {
                         println!("{}", value.show());
                     }
                 }
-                App::Test { path } => {
+                App::Test { path, format, seed } => {
+                    let paths = match path {
+                        Some(dir) if dir.is_dir() => uiua_files_in(&dir),
+                        Some(path) => vec![path],
+                        None => uiua_files_in(Path::new(".")),
+                    };
+                    if paths.is_empty() {
+                        eprintln!("{NO_UA_FILE}");
+                        return Ok(());
+                    }
+                    let (mut passed, mut failed) = (0, 0);
+                    for path in paths {
+                        if format && has_tests(&path)? {
+                            format_file(&path, &find_config()?)?;
+                        }
+                        let mut rt = Uiua::with_native_sys().with_mode(RunMode::Test);
+                        if let Some(seed) = seed {
+                            rt = rt.with_deterministic(seed);
+                        }
+                        let res = rt.load_file(&path).map(drop);
+                        print_diagnostics(&mut rt);
+                        for result in rt.take_test_results() {
+                            if let Some(error) = result.error {
+                                failed += 1;
+                                println!("{}", error.show(true));
+                            } else {
+                                passed += 1;
+                            }
+                        }
+                        if let Err(error) = res {
+                            failed += 1;
+                            println!("{}", error.show(true));
+                        }
+                    }
+                    println!("{passed} passed, {failed} failed");
+                    if failed > 0 {
+                        restore_native_terminal();
+                        exit(1);
+                    }
+                }
+                App::Highlight { path, html } => {
+                    let input = fs::read_to_string(&path)
+                        .map_err(|e| UiuaError::Load(path.clone(), e.into()))?;
+                    if html {
+                        print!("{}", highlight_html(&input));
+                    } else {
+                        print!("{}", highlight_ansi(&input));
+                    }
+                }
+                App::Doc { path, html, output } => {
+                    let format = if html {
+                        DocFormat::Html
+                    } else {
+                        DocFormat::Markdown
+                    };
+                    let docs = if let Some(path) = path {
+                        let mut rt = Uiua::with_native_sys();
+                        rt.load_file(&path)?;
+                        let title = path.file_stem().unwrap_or_default().to_string_lossy();
+                        binding_docs(&rt, &title, format)
+                    } else {
+                        primitive_docs(format)
+                    };
+                    if let Some(output) = output {
+                        fs::write(&output, docs)
+                            .map_err(|e| UiuaError::Load(output.clone(), e.into()))?;
+                    } else {
+                        print!("{docs}");
+                    }
+                }
+                App::Primitives { json } => {
+                    let prims: Vec<PrimitiveInfo> =
+                        Primitive::all().filter_map(PrimitiveInfo::new).collect();
+                    if json {
+                        println!("{}", serde_json::to_string_pretty(&prims).unwrap());
+                    } else {
+                        for prim in prims {
+                            let glyph = prim.glyph.map(String::from).unwrap_or_default();
+                            let ascii = prim.ascii.unwrap_or_default();
+                            println!("{glyph:2} {ascii:3} {}", prim.name);
+                        }
+                    }
+                }
+                App::Explain { code } => match code.parse::<ErrorCode>() {
+                    Ok(code) => println!("{code}\n\n{}", code.explanation()),
+                    Err(e) => {
+                        eprintln!("{e}");
+                        exit(1);
+                    }
+                },
+                App::Build { path, output } => {
                     if let Some(path) = path.or_else(working_file_path) {
-                        format_file(&path, &config)?;
-                        Uiua::with_native_sys()
-                            .with_mode(RunMode::Test)
-                            .load_file(path)?;
-                        println!("No failures!");
+                        let output = output.unwrap_or_else(|| {
+                            let stem = path.file_stem().unwrap_or_default();
+                            PathBuf::from(stem).with_extension(env::consts::EXE_EXTENSION)
+                        });
+                        build(&path, &output)?;
+                        println!("Built {}", output.display());
                     } else {
                         eprintln!("{NO_UA_FILE}");
-                        return Ok(());
                     }
                 }
-                App::Watch => {
-                    if let Some(path) = working_file_path() {
+                App::Repl => {
+                    if let Err(e) = repl() {
+                        eprintln!("Error in REPL: {e}");
+                    }
+                }
+                App::Watch { path } => {
+                    if let Some(path) = path.or_else(working_file_path) {
                         _ = open::that(&path);
                         if let Err(e) = watch(&path) {
                             eprintln!("Error watching file: {e}");
@@ -147,11 +422,206 @@ fn run() -> UiuaResult {
                 eprintln!("\n{NO_UA_FILE}");
             }
         }
+        // Running a file directly, like from a `#!` line
+        Err(e) if e.kind() == ErrorKind::InvalidSubcommand => {
+            match env::args_os().nth(1).map(PathBuf::from) {
+                Some(path) if path.is_file() => run_script(&path)?,
+                _ => _ = e.print(),
+            }
+        }
         Err(e) => _ = e.print(),
     }
     Ok(())
 }
 
+fn run_script(path: &Path) -> UiuaResult {
+    let mut rt = Uiua::with_native_sys().with_mode(RunMode::Normal);
+    interrupt_on_ctrl_c(&rt);
+    let res = rt.load_file(path).map(drop);
+    print_diagnostics(&mut rt);
+    res?;
+    for value in rt.take_stack() {
+        println!("{}", value.show());
+    }
+    Ok(())
+}
+
+/// Marks the end of an executable made by `uiua build`
+///
+/// The executable is this one, followed by the program, its length as
+/// 8 little-endian bytes, and this marker. The program starts with a byte
+/// that says how it is stored:
+/// - [`EMBED_COMPILED`]: the rest was made by [`Uiua::compile_program`]
+/// - [`EMBED_SOURCES`]: the rest is the program's files. Each file is its path
+///   and its source, each preceded by its length as 8 little-endian bytes. The
+///   first file is the program, and the rest are the files it imports.
+const EMBED_MARKER: &[u8; 8] = b"uiua\0prg";
+const EMBED_COMPILED: u8 = 0;
+const EMBED_SOURCES: u8 = 1;
+
+/// A program embedded in an executable made by `uiua build`
+enum EmbeddedProgram {
+    Compiled(Vec<u8>),
+    /// Programs that must be run to be compiled are embedded as their files
+    Sources(Vec<(String, String)>),
+}
+
+/// Get the program embedded in this executable, if there is one
+///
+/// This runs at the start of every invocation. It only opens this executable
+/// and reads its last 16 bytes unless a program is embedded, which is cheap
+/// next to starting the interpreter.
+fn embedded_program() -> Option<EmbeddedProgram> {
+    let mut file = fs::File::open(env::current_exe().ok()?).ok()?;
+    let (start, len) = embedded_range(&mut file)?;
+    file.seek(SeekFrom::Start(start)).ok()?;
+    let mut bytes = vec![0; len as usize];
+    file.read_exact(&mut bytes).ok()?;
+    let (&kind, mut rest) = bytes.split_first()?;
+    match kind {
+        EMBED_COMPILED => Some(EmbeddedProgram::Compiled(rest.to_vec())),
+        EMBED_SOURCES => {
+            let mut files = Vec::new();
+            while !rest.is_empty() {
+                files.push((
+                    take_embedded_string(&mut rest)?,
+                    take_embedded_string(&mut rest)?,
+                ));
+            }
+            (!files.is_empty()).then_some(EmbeddedProgram::Sources(files))
+        }
+        _ => None,
+    }
+}
+
+/// Take a length-prefixed string from the start of embedded bytes
+fn take_embedded_string(bytes: &mut &[u8]) -> Option<String> {
+    let len = u64::from_le_bytes(bytes.get(..8)?.try_into().unwrap());
+    let end = usize::try_from(len).ok()?.checked_add(8)?;
+    let string = String::from_utf8(bytes.get(8..end)?.to_vec()).ok()?;
+    *bytes = &bytes[end..];
+    Some(string)
+}
+
+/// Get the start and length of an embedded program
+fn embedded_range(file: &mut fs::File) -> Option<(u64, u64)> {
+    let trailer_start = file.seek(SeekFrom::End(-16)).ok()?;
+    let mut trailer = [0; 16];
+    file.read_exact(&mut trailer).ok()?;
+    if trailer[8..] != EMBED_MARKER[..] {
+        return None;
+    }
+    let len = u64::from_le_bytes(trailer[..8].try_into().unwrap());
+    Some((trailer_start.checked_sub(len)?, len))
+}
+
+fn run_embedded(program: EmbeddedProgram) -> UiuaResult {
+    let mut rt = Uiua::with_native_sys().with_mode(RunMode::Normal);
+    match program {
+        EmbeddedProgram::Compiled(bytes) => {
+            rt.run_compiled(&bytes)?;
+        }
+        EmbeddedProgram::Sources(files) => {
+            let mut files = files.into_iter();
+            let (_, source) = files.next().unwrap_or_default();
+            rt = files.fold(rt, |rt, (path, source)| rt.with_import_source(path, source));
+            rt.load_str(&source)?;
+        }
+    }
+    for value in rt.take_stack() {
+        println!("{}", value.show());
+    }
+    Ok(())
+}
+
+/// Write an executable that runs a program
+///
+/// The program is compiled ahead of time if it can be. Otherwise, it and the
+/// files it imports with string literal paths are embedded as source.
+fn build(path: &Path, output: &Path) -> UiuaResult {
+    let read = |path: &Path| {
+        fs::read_to_string(path).map_err(|e| UiuaError::Load(path.to_path_buf(), e.into()))
+    };
+    let source = read(path)?;
+    let mut rt = Uiua::with_native_sys().with_mode(RunMode::Normal);
+    let program = match rt.compile_program(&source, Some(path))? {
+        Some(compiled) => EmbeddedProgram::Compiled(compiled),
+        None => {
+            eprintln!(
+                "{} must be run to be compiled, so its source is embedded instead",
+                path.display()
+            );
+            EmbeddedProgram::Sources(embedded_sources(path, source)?)
+        }
+    };
+    let write_error = |e: io::Error| UiuaError::Load(output.to_path_buf(), e.into());
+    let exe_path = env::current_exe().map_err(write_error)?;
+    let mut exe = fs::File::open(&exe_path).map_err(write_error)?;
+    // Don't include the program of an executable that was itself built
+    let exe_len = match embedded_range(&mut exe) {
+        Some((start, _)) => start,
+        None => exe.seek(SeekFrom::End(0)).map_err(write_error)?,
+    };
+    exe.seek(SeekFrom::Start(0)).map_err(write_error)?;
+    let mut bytes = Vec::new();
+    exe.take(exe_len)
+        .read_to_end(&mut bytes)
+        .map_err(write_error)?;
+    let program_start = bytes.len();
+    match program {
+        EmbeddedProgram::Compiled(compiled) => {
+            bytes.push(EMBED_COMPILED);
+            bytes.extend(compiled);
+        }
+        EmbeddedProgram::Sources(files) => {
+            bytes.push(EMBED_SOURCES);
+            for (path, source) in &files {
+                for string in [path, source] {
+                    bytes.extend_from_slice(&(string.len() as u64).to_le_bytes());
+                    bytes.extend_from_slice(string.as_bytes());
+                }
+            }
+        }
+    }
+    let program_len = (bytes.len() - program_start) as u64;
+    bytes.extend_from_slice(&program_len.to_le_bytes());
+    bytes.extend_from_slice(EMBED_MARKER);
+    fs::write(output, bytes).map_err(write_error)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(output, fs::Permissions::from_mode(0o755)).map_err(write_error)?;
+    }
+    Ok(())
+}
+
+/// Get the paths and sources of a program and the files it imports with string literal paths
+fn embedded_sources(path: &Path, source: String) -> UiuaResult<Vec<(String, String)>> {
+    let read = |path: &Path| {
+        fs::read_to_string(path).map_err(|e| UiuaError::Load(path.to_path_buf(), e.into()))
+    };
+    let mut files = vec![(path.to_string_lossy().into_owned(), source)];
+    let mut i = 0;
+    while i < files.len() {
+        let (items, errors) = parse(&files[i].1, Some(Path::new(&files[i].0)));
+        if !errors.is_empty() {
+            return Err(errors.into());
+        }
+        let mut imports = Vec::new();
+        items_imports(&items, &mut imports);
+        for import in imports {
+            // The example file is built in
+            let builtin = import == "example.ua" && !Path::new(&import).exists();
+            if !builtin && !files.iter().any(|(path, _)| *path == import) {
+                let source = read(Path::new(&import))?;
+                files.push((import, source));
+            }
+        }
+        i += 1;
+    }
+    Ok(files)
+}
+
 const NO_UA_FILE: &str =
     "No .ua file found nearby. Initialize one in the current directory with `uiua init`";
 
@@ -186,7 +656,10 @@ fn watch(open_path: &Path) -> io::Result<()> {
 
     println!("Watching for changes... (end with ctrl+C, use `uiua help` to see options)");
 
-    let config = FormatConfig::default();
+    let config = FormatConfig::find(Path::new(".")).unwrap_or_else(|e| {
+        eprintln!("{}", e.show(true));
+        FormatConfig::default()
+    });
     #[cfg(feature = "audio")]
     let audio_time = std::sync::Arc::new(std::sync::atomic::AtomicU64::new(0f64.to_bits()));
     #[cfg(feature = "audio")]
@@ -198,83 +671,107 @@ fn watch(open_path: &Path) -> io::Result<()> {
         socket.set_nonblocking(true)?;
         (socket, port)
     };
-    let run = |path: &Path| -> io::Result<()> {
+    // The output of the running program is collected so it can be compared with the last run's
+    let run = |path: &Path| -> io::Result<Option<JoinHandle<String>>> {
         if let Some(mut child) = WATCH_CHILD.lock().take() {
             _ = child.kill();
-            print_watching();
         }
         const TRIES: u8 = 10;
         for i in 0..TRIES {
             match format_file(path, &config) {
                 Ok(formatted) => {
+                    clear_screen();
                     if formatted.is_empty() {
-                        clear_watching();
                         print_watching();
-                        return Ok(());
+                        return Ok(None);
                     }
-                    clear_watching();
                     #[cfg(feature = "audio")]
                     let audio_time =
                         f64::from_bits(audio_time_clone.load(std::sync::atomic::Ordering::Relaxed))
                             .to_string();
                     #[cfg(feature = "audio")]
                     let audio_port = audio_time_port.to_string();
-                    *WATCH_CHILD.lock() = Some(
-                        Command::new(env::current_exe().unwrap())
-                            .arg("run")
-                            .arg(path)
-                            .args([
-                                "--no-format",
-                                "--mode",
-                                "all",
-                                #[cfg(feature = "audio")]
-                                "--audio-time",
-                                #[cfg(feature = "audio")]
-                                &audio_time,
-                                #[cfg(feature = "audio")]
-                                "--audio-port",
-                                #[cfg(feature = "audio")]
-                                &audio_port,
-                            ])
-                            .spawn()
-                            .unwrap(),
-                    );
-                    return Ok(());
+                    let mut child = Command::new(env::current_exe().unwrap())
+                        .arg("run")
+                        .arg(path)
+                        .args([
+                            "--no-format",
+                            "--mode",
+                            "all",
+                            #[cfg(feature = "audio")]
+                            "--audio-time",
+                            #[cfg(feature = "audio")]
+                            &audio_time,
+                            #[cfg(feature = "audio")]
+                            "--audio-port",
+                            #[cfg(feature = "audio")]
+                            &audio_port,
+                        ])
+                        .stdout(Stdio::piped())
+                        .spawn()
+                        .unwrap();
+                    let stdout = child.stdout.take();
+                    *WATCH_CHILD.lock() = Some(child);
+                    let reader = thread::spawn(move || {
+                        let mut output = String::new();
+                        for line in stdout
+                            .into_iter()
+                            .flat_map(|out| BufReader::new(out).lines())
+                        {
+                            let Ok(line) = line else {
+                                break;
+                            };
+                            println!("{line}");
+                            output.push_str(&line);
+                            output.push('\n');
+                        }
+                        output
+                    });
+                    return Ok(Some(reader));
                 }
                 Err(UiuaError::Format(..)) => sleep(Duration::from_millis((i as u64 + 1) * 10)),
                 Err(e) => {
-                    clear_watching();
-                    println!("{}", e.show(true));
+                    clear_screen();
+                    println!("{}", e.show(stdout_color()));
                     print_watching();
-                    return Ok(());
+                    return Ok(None);
                 }
             }
         }
         println!("Failed to format file after {TRIES} tries");
-        Ok(())
+        Ok(None)
     };
-    run(open_path)?;
+    let mut watched = watched_files(open_path);
+    let mut reader = run(open_path)?;
+    let mut last_output: Option<String> = None;
     let mut last_time = Instant::now();
     loop {
         sleep(Duration::from_millis(10));
-        if let Some(path) = recv
+        let changed = recv
             .try_iter()
             .filter_map(Result::ok)
             .filter(|event| matches!(event.kind, EventKind::Modify(_)))
             .flat_map(|event| event.paths)
-            .filter(|path| path.extension().map_or(false, |ext| ext == "ua"))
-            .last()
-        {
-            if last_time.elapsed() > Duration::from_millis(100) {
-                run(&path)?;
-                last_time = Instant::now();
-            }
+            .filter_map(|path| path.canonicalize().ok())
+            .fold(false, |changed, path| changed || watched.contains(&path));
+        if changed && last_time.elapsed() > Duration::from_millis(100) {
+            watched = watched_files(open_path);
+            reader = run(open_path)?;
+            last_time = Instant::now();
         }
         let mut child = WATCH_CHILD.lock();
         if let Some(ch) = &mut *child {
             if ch.try_wait()?.is_some() {
-                print_watching();
                 *child = None;
+                let output = reader.take().and_then(|reader| reader.join().ok());
+                if let Some(output) = output {
+                    if let Some(last) = last_output.as_ref().filter(|last| **last != output) {
+                        println!("Output changed since the last run:");
+                        print!("{}", diff(last, &output));
+                    }
+                    last_output = Some(output);
+                }
+                print_watching();
             }
             #[cfg(feature = "audio")]
             {
@@ -288,6 +785,342 @@ fn watch(open_path: &Path) -> io::Result<()> {
     }
 }
 
+/// Get the canonical paths of a file and the files it imports, recursively
+///
+/// Imports are found by looking for `&i` followed by a string literal.
+fn watched_files(path: &Path) -> Vec<PathBuf> {
+    let mut files = vec![path.to_path_buf()];
+    let mut i = 0;
+    while i < files.len() {
+        if let Ok(input) = fs::read_to_string(&files[i]) {
+            let (items, _) = parse(&input, Some(&files[i]));
+            let mut imports = Vec::new();
+            items_imports(&items, &mut imports);
+            for import in imports.into_iter().map(PathBuf::from) {
+                if !files.contains(&import) {
+                    files.push(import);
+                }
+            }
+        }
+        i += 1;
+    }
+    files
+        .iter()
+        .filter_map(|path| path.canonicalize().ok())
+        .collect()
+}
+
+fn items_imports(items: &[Item], imports: &mut Vec<String>) {
+    for item in items {
+        match item {
+            Item::Scoped { items, .. } => items_imports(items, imports),
+            Item::Words(words) => words_imports(words, imports),
+            Item::Binding(binding) => words_imports(&binding.words, imports),
+            Item::ExtraNewlines(_) => {}
+            _ => {}
+        }
+    }
+}
+
+fn words_imports(words: &[Sp<Word>], imports: &mut Vec<String>) {
+    let words: Vec<&Sp<Word>> = (words.iter())
+        .filter(|word| !matches!(word.value, Word::Spaces))
+        .collect();
+    for (i, word) in words.iter().enumerate() {
+        match &word.value {
+            Word::Primitive(Primitive::Sys(SysOp::Import)) => {
+                if let Some(Word::String(path)) = words.get(i + 1).map(|word| &word.value) {
+                    imports.push(path.clone());
+                }
+            }
+            Word::Array(arr) => (arr.lines.iter()).for_each(|line| words_imports(line, imports)),
+            Word::Func(func) => (func.lines.iter()).for_each(|line| words_imports(line, imports)),
+            Word::Modified(m) => words_imports(&m.operands, imports),
+            _ => {}
+        }
+    }
+}
+
+fn repl() -> rustyline::Result<()> {
+    let mut editor = Editor::<ReplHelper, DefaultHistory>::new()?;
+    editor.set_helper(Some(ReplHelper));
+    let history = repl_history_path();
+    if let Some(path) = &history {
+        _ = editor.load_history(path);
+    }
+    println!("Uiua REPL (end with ctrl+D, use tab to complete glyph names, :help for commands)");
+    let mut rt = Uiua::with_native_sys().with_mode(RunMode::Normal);
+    interrupt_on_ctrl_c(&rt);
+    let mut timing = false;
+    let mut last_file: Option<PathBuf> = None;
+    loop {
+        let line = match editor.readline("» ") {
+            Ok(line) => line,
+            Err(ReadlineError::Interrupted) => continue,
+            Err(ReadlineError::Eof) => break,
+            Err(e) => return Err(e),
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+        editor.add_history_entry(line.as_str())?;
+        if let Some(command) = line.trim().strip_prefix(':') {
+            let (command, arg) = command.split_once(' ').unwrap_or((command, ""));
+            match command {
+                "stack" | "s" => {
+                    for value in rt.stack() {
+                        println!("{}", value.show());
+                    }
+                }
+                "bindings" | "b" => {
+                    for (name, sig) in rt.bindings() {
+                        println!("{name} {sig}");
+                    }
+                }
+                "clear" | "c" => rt.clear_stack(),
+                "reload" | "r" => {
+                    let path = match arg.trim() {
+                        "" => last_file.clone(),
+                        path => Some(PathBuf::from(path)),
+                    };
+                    let Some(path) = path else {
+                        eprintln!("No file to reload. Use :reload <path>");
+                        continue;
+                    };
+                    rt = Uiua::with_native_sys().with_mode(RunMode::Normal);
+                    interrupt_on_ctrl_c(&rt);
+                    if let Err(e) = rt.load_file(&path) {
+                        eprintln!("{}", e.show(true));
+                    }
+                    print_diagnostics(&mut rt);
+                    last_file = Some(path);
+                }
+                "save" => {
+                    let path = arg.trim();
+                    if path.is_empty() {
+                        eprintln!("No file to save to. Use :save <path>");
+                        continue;
+                    }
+                    let res = rt
+                        .store_state()
+                        .map_err(|e| e.show(true))
+                        .and_then(|bytes| fs::write(path, bytes).map_err(|e| e.to_string()));
+                    if let Err(e) = res {
+                        eprintln!("{e}");
+                    }
+                }
+                "load" => {
+                    let path = arg.trim();
+                    if path.is_empty() {
+                        eprintln!("No file to load. Use :load <path>");
+                        continue;
+                    }
+                    let res = fs::read(path)
+                        .map_err(|e| e.to_string())
+                        .and_then(|bytes| rt.load_state(&bytes).map_err(|e| e.show(true)));
+                    if let Err(e) = res {
+                        eprintln!("{e}");
+                    }
+                }
+                "time" | "t" => {
+                    timing = !timing;
+                    println!("Timing {}", if timing { "on" } else { "off" });
+                }
+                "help" | "h" => println!("{REPL_HELP}"),
+                _ => eprintln!("Unknown command :{command}. Use :help to see commands"),
+            }
+            continue;
+        }
+        let start = Instant::now();
+        let res = rt.load_str(&line).map(drop);
+        let elapsed = start.elapsed();
+        // Bindings are usually used in later entries
+        for diagnostic in rt.take_diagnostics() {
+            if diagnostic.kind != DiagnosticKind::UnusedBinding {
+                eprintln!("{}", diagnostic.show(stderr_color()));
+            }
+        }
+        match res {
+            Ok(()) => {
+                for value in rt.stack() {
+                    println!("{}", value.show());
+                }
+            }
+            Err(e) => eprintln!("{}", e.show(true)),
+        }
+        if timing {
+            println!("took {elapsed:?}");
+        }
+    }
+    if let Some(path) = &history {
+        editor.save_history(path)?;
+    }
+    Ok(())
+}
+
+const REPL_HELP: &str = "\
+:stack, :s          show the stack
+:bindings, :b       list bindings with their signatures
+:clear, :c          clear the stack
+:reload, :r [path]  run a file in a new runtime, or the last one again
+:save <path>        save the stack, bindings, and random state to a file
+:load <path>        restore a session saved with :save
+:time, :t           toggle timing of each entry
+:help, :h           show this message";
+
+const DEBUG_HELP: &str = "\
+continue, c    run until the next breakpoint
+step, s        run the next instruction
+stack          show the stack
+where, w       show the functions being called
+pop            remove the top value from the stack
+push <code>    run some code and push its results onto the stack
+abort, q       stop the program
+help, h        show this message";
+
+/// Make a step handler that opens a prompt at breakpoints
+fn debugger() -> impl FnMut(&mut Uiua) -> UiuaResult + Send {
+    let mut stepping = false;
+    move |env| {
+        let at_breakpoint = env.next_primitive() == Some(Primitive::Breakpoint);
+        if !stepping && !at_breakpoint {
+            return Ok(());
+        }
+        if let Some(span) = env.next_span() {
+            let kind = if at_breakpoint { "Breakpoint" } else { "Step" };
+            eprintln!("{kind} at {span}");
+        }
+        show_debug_stack(env);
+        loop {
+            eprint!("debug> ");
+            _ = io::stderr().flush();
+            let mut line = String::new();
+            if io::stdin().read_line(&mut line).unwrap_or(0) == 0 {
+                return Err(env.error("Aborted by the debugger"));
+            }
+            let (command, arg) = line.trim().split_once(' ').unwrap_or((line.trim(), ""));
+            match command {
+                "continue" | "c" => {
+                    stepping = false;
+                    return Ok(());
+                }
+                "step" | "s" => {
+                    stepping = true;
+                    return Ok(());
+                }
+                "stack" => show_debug_stack(env),
+                "where" | "w" => {
+                    for frame in env.call_frames().iter().rev() {
+                        eprintln!("  in {} at {}", frame.id, frame.span);
+                    }
+                }
+                "pop" => {
+                    if env.pop("debugger").is_err() {
+                        eprintln!("The stack is empty");
+                    }
+                }
+                "push" => match Uiua::with_native_sys().load_str(arg) {
+                    Ok(rt) => {
+                        for value in rt.take_stack() {
+                            env.push(value);
+                        }
+                    }
+                    Err(e) => eprintln!("{}", e.show(true)),
+                },
+                "abort" | "q" => return Err(env.error("Aborted by the debugger")),
+                "help" | "h" => eprintln!("{DEBUG_HELP}"),
+                "" => {}
+                _ => eprintln!("Unknown command {command:?}. Use help to see commands."),
+            }
+        }
+    }
+}
+
+fn show_debug_stack(env: &Uiua) {
+    if env.stack().is_empty() {
+        eprintln!("The stack is empty");
+    }
+    for value in env.stack().iter().rev() {
+        eprintln!("{}", value.show());
+    }
+}
+
+fn repl_history_path() -> Option<PathBuf> {
+    let home = env::var_os("HOME").or_else(|| env::var_os("USERPROFILE"))?;
+    Some(PathBuf::from(home).join(".uiua_history"))
+}
+
+/// Line editing for the REPL
+struct ReplHelper;
+
+impl Helper for ReplHelper {}
+
+impl Completer for ReplHelper {
+    type Candidate = Pair;
+    /// Complete the name of a primitive to its glyph
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<Pair>)> {
+        let start = line[..pos]
+            .char_indices()
+            .rev()
+            .take_while(|(_, c)| c.is_ascii_lowercase())
+            .last()
+            .map_or(pos, |(i, _)| i);
+        let prefix = &line[start..pos];
+        if prefix.is_empty() {
+            return Ok((pos, Vec::new()));
+        }
+        let candidates = Primitive::all()
+            .filter_map(|prim| {
+                let name = prim.name()?;
+                let glyph = prim.unicode()?;
+                name.starts_with(prefix).then(|| Pair {
+                    display: format!("{glyph} {name}"),
+                    replacement: glyph.to_string(),
+                })
+            })
+            .collect();
+        Ok((start, candidates))
+    }
+}
+
+impl Hinter for ReplHelper {
+    type Hint = String;
+}
+
+impl Highlighter for ReplHelper {
+    fn highlight<'l>(&self, line: &'l str, _: usize) -> Cow<'l, str> {
+        Cow::Owned(highlight_ansi(line))
+    }
+}
+
+impl Validator for ReplHelper {
+    /// Keep reading lines while there are unclosed brackets
+    fn validate(&self, ctx: &mut ValidationContext) -> rustyline::Result<ValidationResult> {
+        let mut depth = 0i32;
+        for token in lex(ctx.input(), None).0 {
+            match token.value {
+                Token::Simple(
+                    AsciiToken::OpenParen | AsciiToken::OpenBracket | AsciiToken::OpenCurly,
+                ) => depth += 1,
+                Token::Simple(
+                    AsciiToken::CloseParen | AsciiToken::CloseBracket | AsciiToken::CloseCurly,
+                ) => depth -= 1,
+                _ => {}
+            }
+        }
+        Ok(if depth > 0 {
+            ValidationResult::Incomplete
+        } else {
+            ValidationResult::Valid(None)
+        })
+    }
+}
+
 #[derive(Parser)]
 enum App {
     #[clap(about = "Initialize a new main.ua file")]
@@ -295,10 +1128,82 @@ enum App {
     #[clap(about = "Format and run a file")]
     Run {
         path: Option<PathBuf>,
+        #[clap(
+            short,
+            long,
+            conflicts_with = "path",
+            help = "Evaluate a string of code instead of a file"
+        )]
+        eval: Option<String>,
+        #[clap(
+            long,
+            help = "Read the program's standard input from a file. \
+                    If the program itself is read from standard input with `-`, \
+                    reading standard input is an error unless this is given."
+        )]
+        input: Option<PathBuf>,
         #[clap(long, help = "Don't format the file before running")]
         no_format: bool,
         #[clap(long, help = "Run the file in a specific mode")]
         mode: Option<RunMode>,
+        #[clap(
+            long,
+            value_name = "SECONDS",
+            value_parser = parse_time_limit,
+            help = "Stop the program if it runs for longer than this many seconds"
+        )]
+        time_limit: Option<f64>,
+        #[clap(
+            long,
+            value_name = "COUNT",
+            help = "Stop the program if it executes more than this many instructions"
+        )]
+        max_instructions: Option<u64>,
+        #[clap(long, help = "Open a debugger prompt when a breakpoint is reached")]
+        debug: bool,
+        #[clap(
+            long,
+            help = "Print the time spent in each primitive and binding when the program ends"
+        )]
+        profile: bool,
+        #[clap(
+            long,
+            value_name = "PATH",
+            help = "Write a timeline of every call to a file. \
+                    A .json file is written in the Chrome tracing format. \
+                    Any other file is written as folded stacks for flamegraph tools."
+        )]
+        timeline: Option<PathBuf>,
+        #[clap(
+            long,
+            help = "Make random numbers, the current time, and threads reproducible \
+                    from this seed"
+        )]
+        seed: Option<u64>,
+        #[clap(
+            long,
+            help = "Forbid access to the filesystem, the network, and the environment"
+        )]
+        sandbox: bool,
+        #[clap(
+            long,
+            value_name = "CAPABILITY",
+            value_delimiter = ',',
+            help = "Run in a sandbox that only grants these capabilities: \
+                    read-files, write-files, network, subprocess, audio, environment, clipboard, display"
+        )]
+        allow: Vec<Capability>,
+        #[cfg(feature = "gpu")]
+        #[clap(long, help = "Offload large array operations to the GPU")]
+        gpu: bool,
+        #[cfg(feature = "gpu")]
+        #[clap(
+            long,
+            requires = "gpu",
+            help = "Allow offloaded operations to be computed in single precision \
+                    on GPUs without double precision support"
+        )]
+        gpu_f32: bool,
         #[cfg(feature = "audio")]
         #[clap(flatten)]
         audio_options: AudioOptions,
@@ -310,17 +1215,113 @@ enum App {
         #[clap(flatten)]
         audio_options: AudioOptions,
     },
-    #[clap(about = "Format and test a file")]
-    Test { path: Option<PathBuf> },
-    #[clap(about = "Run a main.ua in watch mode")]
-    Watch,
+    #[clap(about = "Run the tests in a file or all files in a directory")]
+    Test {
+        path: Option<PathBuf>,
+        #[clap(long, help = "Format the files that have tests before running them")]
+        format: bool,
+        #[clap(
+            long,
+            help = "Make random numbers, the current time, and threads reproducible \
+                    from this seed"
+        )]
+        seed: Option<u64>,
+    },
+    #[clap(about = "Run a file, or a main.ua, again whenever it or its imports change")]
+    Watch { path: Option<PathBuf> },
+    #[clap(about = "Build an executable that runs a file without needing Uiua installed")]
+    Build {
+        path: Option<PathBuf>,
+        #[clap(short, long, help = "The path of the executable")]
+        output: Option<PathBuf>,
+    },
+    #[clap(about = "Start a read-eval-print loop")]
+    Repl,
     #[clap(about = "Format a uiua file or all files in the current directory")]
-    Fmt { path: Option<PathBuf> },
+    Fmt {
+        path: Option<PathBuf>,
+        #[clap(long, help = "Replace deprecated primitives with their replacements")]
+        migrate: bool,
+        #[clap(
+            long,
+            help = "Show the changes formatting would make without writing them, \
+                    and fail if there are any"
+        )]
+        check: bool,
+        #[clap(
+            long,
+            help = "Write primitives with their ASCII spellings instead of glyphs"
+        )]
+        ascii: bool,
+        #[clap(
+            long,
+            conflicts_with_all = ["path", "check"],
+            help = "Format code from standard input and write it to standard output"
+        )]
+        stdin: bool,
+    },
+    #[clap(about = "Generate documentation for the primitives or the bindings in a file")]
+    Doc {
+        #[clap(help = "The file to document. If not given, document the primitives.")]
+        path: Option<PathBuf>,
+        #[clap(long, help = "Output an HTML page instead of Markdown")]
+        html: bool,
+        #[clap(short, long, help = "Write the documentation to a file")]
+        output: Option<PathBuf>,
+    },
+    #[clap(about = "List the primitives")]
+    Primitives {
+        #[clap(long, help = "Output each primitive's metadata as JSON")]
+        json: bool,
+    },
+    #[clap(about = "Explain an error code, like E0012")]
+    Explain { code: String },
+    #[clap(about = "Print a file with syntax highlighting")]
+    Highlight {
+        path: PathBuf,
+        #[clap(long, help = "Output HTML spans instead of ANSI escape codes")]
+        html: bool,
+    },
     #[cfg(feature = "lsp")]
     #[clap(about = "Run the Language Server")]
     Lsp,
 }
 
+/// Metadata about a primitive for tools that need to stay in sync with it
+#[derive(serde::Serialize)]
+struct PrimitiveInfo {
+    name: &'static str,
+    glyph: Option<char>,
+    ascii: Option<String>,
+    class: String,
+    args: Option<u8>,
+    outputs: Option<u8>,
+    modifier_args: Option<u8>,
+    description: Option<String>,
+}
+
+impl PrimitiveInfo {
+    fn new(prim: Primitive) -> Option<Self> {
+        Some(PrimitiveInfo {
+            name: prim.name()?,
+            glyph: prim.unicode(),
+            ascii: prim.ascii().map(|ascii| ascii.to_string()),
+            class: format!("{:?}", prim.class()),
+            args: prim.args(),
+            outputs: prim.outputs(),
+            modifier_args: prim.modifier_args(),
+            description: prim.doc().map(|doc| doc.short_text().into_owned()),
+        })
+    }
+}
+
+fn parse_time_limit(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(seconds) if seconds.is_finite() && seconds >= 0.0 => Ok(seconds),
+        _ => Err(format!("{s} is not a valid number of seconds")),
+    }
+}
+
 #[cfg(feature = "audio")]
 #[derive(clap::Args)]
 struct AudioOptions {
@@ -352,16 +1353,63 @@ fn uiua_files() -> Vec<PathBuf> {
         .collect()
 }
 
+/// Find all Uiua files in a directory and its subdirectories
+///
+/// Hidden directories and directories named in a `.gitignore` are skipped.
+fn uiua_files_in(dir: &Path) -> Vec<PathBuf> {
+    let ignored: Vec<String> = fs::read_to_string(dir.join(".gitignore"))
+        .unwrap_or_default()
+        .lines()
+        .map(|line| line.trim().trim_matches('/').to_string())
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .collect();
+    let mut files = Vec::new();
+    for entry in fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .filter_map(Result::ok)
+    {
+        let path = entry.path();
+        if path.is_dir() {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if !name.starts_with('.') && !ignored.contains(&name) {
+                files.extend(uiua_files_in(&path));
+            }
+        } else if path.extension().is_some_and(|ext| ext == "ua") {
+            files.push(path);
+        }
+    }
+    files.sort();
+    files
+}
+
+/// Check whether a file has any test scopes
+fn has_tests(path: &Path) -> UiuaResult<bool> {
+    let input = fs::read_to_string(path).map_err(|e| UiuaError::Load(path.into(), e.into()))?;
+    let (items, _) = parse(&input, Some(path));
+    Ok(items
+        .iter()
+        .any(|item| matches!(item, Item::Scoped { test: true, .. })))
+}
+
 const WATCHING: &str = "watching for changes...";
 fn print_watching() {
     eprint!("{}", WATCHING);
     stderr().flush().unwrap();
 }
-fn clear_watching() {
-    clear_watching_with("―", "\n")
+/// Clear the terminal, unless stdout is not one
+fn clear_screen() {
+    if !io::stdout().is_terminal() {
+        return;
+    }
+    print!("\x1b[2J\x1b[H");
+    _ = io::stdout().flush();
 }
 
 fn clear_watching_with(s: &str, end: &str) {
+    if !io::stdout().is_terminal() {
+        return;
+    }
     print!(
         "\r{}{}",
         s.repeat(term_size::dimensions().map_or(10, |(w, _)| w)),
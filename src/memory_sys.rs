@@ -0,0 +1,532 @@
+//! A system backend that keeps files in memory
+
+use std::{
+    any::Any,
+    collections::{BTreeMap, BTreeSet, HashMap},
+    sync::atomic::{self, AtomicU64},
+};
+
+use parking_lot::Mutex;
+
+use crate::{
+    value::Value, Capability, FileKind, FileMetadata, Handle, SysBackend, Uiua, UiuaError,
+    UiuaResult,
+};
+
+/// A [`SysBackend`] with an in-memory filesystem
+///
+/// Files are stored in a map from paths to their contents, so programs that
+/// read and write files can run without touching the real filesystem.
+/// Directories exist implicitly for every path with files under it, and
+/// empty directories can be created explicitly.
+///
+/// Standard output and standard error are collected, and standard input
+/// comes from a preset string, which is also read one character at a time as
+/// key presses. Environment variables and the clipboard are kept in memory too.
+/// Spawned threads run to completion as soon as they are spawned, and
+/// sleeping does not block but advances a virtual monotonic clock.
+pub struct MemorySys {
+    files: Mutex<BTreeMap<String, Vec<u8>>>,
+    dirs: Mutex<BTreeSet<String>>,
+    vars: Mutex<BTreeMap<String, String>>,
+    clipboard: Mutex<String>,
+    streams: Mutex<HashMap<Handle, MemoryStream>>,
+    next_handle: AtomicU64,
+    stdin: Mutex<MemoryStream>,
+    stdout: Mutex<String>,
+    stderr: Mutex<String>,
+    thread_results: Mutex<HashMap<Handle, UiuaResult<Vec<Value>>>>,
+    clock: Mutex<f64>,
+}
+
+/// An open file
+enum MemoryStream {
+    /// A file opened for reading, with the read position
+    Reader(Vec<u8>, usize),
+    /// A file opened for writing, by path
+    Writer(String),
+}
+
+impl Default for MemorySys {
+    fn default() -> Self {
+        MemorySys {
+            files: Mutex::new(BTreeMap::new()),
+            dirs: Mutex::new(BTreeSet::new()),
+            vars: Mutex::new(BTreeMap::new()),
+            clipboard: Mutex::new(String::new()),
+            streams: Mutex::new(HashMap::new()),
+            next_handle: AtomicU64::new(Handle::FIRST_UNRESERVED.0),
+            stdin: Mutex::new(MemoryStream::Reader(Vec::new(), 0)),
+            stdout: Mutex::new(String::new()),
+            stderr: Mutex::new(String::new()),
+            thread_results: Mutex::new(HashMap::new()),
+            clock: Mutex::new(0.0),
+        }
+    }
+}
+
+impl MemorySys {
+    /// Create a backend with no files
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Add a file
+    pub fn with_file(self, path: &str, contents: impl Into<Vec<u8>>) -> Self {
+        self.files.lock().insert(normalize(path), contents.into());
+        self
+    }
+    /// Set an environment variable
+    pub fn with_var(self, name: &str, value: &str) -> Self {
+        self.vars.lock().insert(name.into(), value.into());
+        self
+    }
+    /// Set the text on the clipboard
+    pub fn with_clipboard(self, contents: impl Into<String>) -> Self {
+        *self.clipboard.lock() = contents.into();
+        self
+    }
+    /// Set the text that standard input reads from
+    pub fn with_stdin(self, input: impl Into<String>) -> Self {
+        *self.stdin.lock() = MemoryStream::Reader(input.into().into_bytes(), 0);
+        self
+    }
+    /// Get the contents of a file
+    pub fn file(&self, path: &str) -> Option<Vec<u8>> {
+        self.files.lock().get(&normalize(path)).cloned()
+    }
+    /// Get the paths of all files, in order
+    pub fn file_paths(&self) -> Vec<String> {
+        self.files.lock().keys().cloned().collect()
+    }
+    /// Get the text on the clipboard
+    pub fn clipboard(&self) -> String {
+        self.clipboard.lock().clone()
+    }
+    /// Get everything written to standard output so far
+    pub fn stdout(&self) -> String {
+        self.stdout.lock().clone()
+    }
+    /// Get everything written to standard error so far
+    pub fn stderr(&self) -> String {
+        self.stderr.lock().clone()
+    }
+    fn is_dir(&self, path: &str) -> bool {
+        let path = normalize(path);
+        if path.is_empty() {
+            return true;
+        }
+        let prefix = format!("{path}/");
+        let files = self.files.lock();
+        let dirs = self.dirs.lock();
+        dirs.contains(&path)
+            || (files.keys().chain(dirs.iter())).any(|entry| entry.starts_with(&prefix))
+    }
+}
+
+/// Normalize a path so that equivalent spellings refer to the same file
+fn normalize(path: &str) -> String {
+    let parts: Vec<&str> = path
+        .split(['/', '\\'])
+        .filter(|part| !part.is_empty() && *part != ".")
+        .collect();
+    parts.join("/")
+}
+
+fn read_stream(stream: &mut MemoryStream, count: usize) -> Result<Vec<u8>, String> {
+    match stream {
+        MemoryStream::Reader(bytes, pos) => {
+            let end = pos.saturating_add(count).min(bytes.len());
+            let read = bytes[*pos..end].to_vec();
+            *pos = end;
+            Ok(read)
+        }
+        MemoryStream::Writer(_) => Err("Cannot read from a file opened for writing".into()),
+    }
+}
+
+impl SysBackend for MemorySys {
+    fn any(&self) -> &dyn Any {
+        self
+    }
+    fn print_str_stdout(&self, s: &str) -> Result<(), String> {
+        self.stdout.lock().push_str(s);
+        Ok(())
+    }
+    fn print_str_stderr(&self, s: &str) -> Result<(), String> {
+        self.stderr.lock().push_str(s);
+        Ok(())
+    }
+    fn print_str_trace(&self, s: &str) {
+        self.stderr.lock().push_str(s);
+    }
+    fn scan_line_stdin(&self) -> Result<String, String> {
+        self.scan_line_stdin_or_eof().map(Option::unwrap_or_default)
+    }
+    fn scan_line_stdin_or_eof(&self) -> Result<Option<String>, String> {
+        let mut stdin = self.stdin.lock();
+        let MemoryStream::Reader(bytes, pos) = &mut *stdin else {
+            unreachable!("stdin is always a reader")
+        };
+        let rest = &bytes[*pos..];
+        if rest.is_empty() {
+            return Ok(None);
+        }
+        let len = rest.iter().position(|&b| b == b'\n');
+        let mut line = String::from_utf8_lossy(&rest[..len.unwrap_or(rest.len())]).into_owned();
+        *pos += len.map_or(rest.len(), |len| len + 1);
+        if line.ends_with('\r') {
+            line.pop();
+        }
+        Ok(Some(line))
+    }
+    fn scan_all_stdin(&self) -> Result<Vec<u8>, String> {
+        read_stream(&mut self.stdin.lock(), usize::MAX)
+    }
+    fn stdin_eof(&self) -> Result<bool, String> {
+        match &*self.stdin.lock() {
+            MemoryStream::Reader(bytes, pos) => Ok(*pos >= bytes.len()),
+            MemoryStream::Writer(_) => unreachable!("stdin is always a reader"),
+        }
+    }
+    fn set_raw_mode(&self, _: bool) -> Result<(), String> {
+        Ok(())
+    }
+    fn read_key(&self, _: Option<f64>) -> Result<Option<String>, String> {
+        let mut stdin = self.stdin.lock();
+        let MemoryStream::Reader(bytes, pos) = &mut *stdin else {
+            unreachable!("stdin is always a reader")
+        };
+        let rest = &bytes[*pos..];
+        let valid = match std::str::from_utf8(rest) {
+            Ok(s) => s,
+            Err(e) => std::str::from_utf8(&rest[..e.valid_up_to()]).unwrap(),
+        };
+        let Some(c) = valid.chars().next() else {
+            if rest.is_empty() {
+                return Ok(None);
+            }
+            *pos += 1;
+            return Ok(Some(char::REPLACEMENT_CHARACTER.into()));
+        };
+        *pos += c.len_utf8();
+        Ok(Some(match c {
+            '\n' | '\r' => "enter".into(),
+            '\t' => "tab".into(),
+            '\x1b' => "escape".into(),
+            '\x7f' => "backspace".into(),
+            '\x01'..='\x1a' => format!("ctrl-{}", (c as u8 - 1 + b'a') as char),
+            c => c.into(),
+        }))
+    }
+    fn var(&self, name: &str) -> Option<String> {
+        self.vars.lock().get(name).cloned()
+    }
+    fn set_var(&self, name: &str, value: &str) -> Result<(), String> {
+        self.vars.lock().insert(name.into(), value.into());
+        Ok(())
+    }
+    fn var_names(&self) -> Vec<String> {
+        self.vars.lock().keys().cloned().collect()
+    }
+    fn clipboard(&self) -> Result<String, String> {
+        Ok(self.clipboard.lock().clone())
+    }
+    fn set_clipboard(&self, contents: &str) -> Result<(), String> {
+        *self.clipboard.lock() = contents.into();
+        Ok(())
+    }
+    fn file_exists(&self, path: &str) -> bool {
+        self.files.lock().contains_key(&normalize(path)) || self.is_dir(path)
+    }
+    fn list_dir(&self, path: &str) -> Result<Vec<String>, String> {
+        if !self.is_dir(path) {
+            return Err(format!("No directory found at {path}"));
+        }
+        let dir = normalize(path);
+        let prefix = if dir.is_empty() {
+            String::new()
+        } else {
+            format!("{dir}/")
+        };
+        let mut paths: Vec<String> = Vec::new();
+        let files = self.files.lock();
+        let dirs = self.dirs.lock();
+        for entry in files.keys().chain(dirs.iter()) {
+            let Some(rest) = entry.strip_prefix(&prefix) else {
+                continue;
+            };
+            let name = rest.split('/').next().unwrap();
+            let entry = format!("{}/{name}", path.trim_end_matches('/'));
+            if !paths.contains(&entry) {
+                paths.push(entry);
+            }
+        }
+        Ok(paths)
+    }
+    fn is_file(&self, path: &str) -> Result<bool, String> {
+        if self.files.lock().contains_key(&normalize(path)) {
+            Ok(true)
+        } else if self.is_dir(path) {
+            Ok(false)
+        } else {
+            Err(format!("No file or directory found at {path}"))
+        }
+    }
+    fn metadata(&self, path: &str) -> Result<FileMetadata, String> {
+        if let Some(contents) = self.files.lock().get(&normalize(path)) {
+            return Ok(FileMetadata {
+                kind: FileKind::File,
+                size: contents.len() as u64,
+                modified: None,
+            });
+        }
+        if self.is_dir(path) {
+            Ok(FileMetadata {
+                kind: FileKind::Directory,
+                size: 0,
+                modified: None,
+            })
+        } else {
+            Err(format!("No file or directory found at {path}"))
+        }
+    }
+    fn create_dir(&self, path: &str) -> Result<(), String> {
+        if self.files.lock().contains_key(&normalize(path)) {
+            return Err(format!("A file already exists at {path}"));
+        }
+        self.dirs.lock().insert(normalize(path));
+        Ok(())
+    }
+    fn remove_dir(&self, path: &str) -> Result<(), String> {
+        let dir = normalize(path);
+        if dir.is_empty() || !self.is_dir(path) {
+            return Err(format!("No directory found at {path}"));
+        }
+        let prefix = format!("{dir}/");
+        let inside = |entry: &String| *entry == dir || entry.starts_with(&prefix);
+        self.files.lock().retain(|file, _| !inside(file));
+        self.dirs.lock().retain(|entry| !inside(entry));
+        Ok(())
+    }
+    fn read(&self, handle: Handle, count: usize) -> Result<Vec<u8>, String> {
+        if handle == Handle::STDIN {
+            return read_stream(&mut self.stdin.lock(), count);
+        }
+        let mut streams = self.streams.lock();
+        let stream = streams.get_mut(&handle).ok_or("Invalid file handle")?;
+        read_stream(stream, count)
+    }
+    fn write(&self, handle: Handle, contents: &[u8]) -> Result<(), String> {
+        match handle {
+            Handle::STDOUT => self.print_str_stdout(&String::from_utf8_lossy(contents)),
+            Handle::STDERR => self.print_str_stderr(&String::from_utf8_lossy(contents)),
+            _ => match self.streams.lock().get(&handle) {
+                Some(MemoryStream::Writer(path)) => {
+                    let mut files = self.files.lock();
+                    files.entry(path.clone()).or_default().extend(contents);
+                    Ok(())
+                }
+                Some(MemoryStream::Reader(..)) => {
+                    Err("Cannot write to a file opened for reading".into())
+                }
+                None => Err("Invalid file handle".into()),
+            },
+        }
+    }
+    fn create_file(&self, path: &str) -> Result<Handle, String> {
+        let path = normalize(path);
+        self.files.lock().insert(path.clone(), Vec::new());
+        let handle = Handle(self.next_handle.fetch_add(1, atomic::Ordering::Relaxed));
+        self.streams
+            .lock()
+            .insert(handle, MemoryStream::Writer(path));
+        Ok(handle)
+    }
+    fn open_file(&self, path: &str) -> Result<Handle, String> {
+        let bytes = self.file_read_all(path)?;
+        let handle = Handle(self.next_handle.fetch_add(1, atomic::Ordering::Relaxed));
+        self.streams
+            .lock()
+            .insert(handle, MemoryStream::Reader(bytes, 0));
+        Ok(handle)
+    }
+    fn file_read_all(&self, path: &str) -> Result<Vec<u8>, String> {
+        self.file(path)
+            .ok_or_else(|| format!("No file found at {path}"))
+    }
+    fn file_write_all(&self, path: &str, contents: &[u8]) -> Result<(), String> {
+        self.files.lock().insert(normalize(path), contents.to_vec());
+        Ok(())
+    }
+    fn sleep(&self, seconds: f64) -> Result<(), String> {
+        *self.clock.lock() += seconds;
+        Ok(())
+    }
+    fn monotonic_now(&self) -> f64 {
+        *self.clock.lock()
+    }
+    fn stream_capability(&self, handle: Handle) -> Option<Capability> {
+        self.streams
+            .lock()
+            .contains_key(&handle)
+            .then_some(Capability::ReadFiles)
+    }
+    fn close(&self, handle: Handle) -> Result<(), String> {
+        self.streams.lock().remove(&handle);
+        Ok(())
+    }
+    fn spawn(
+        &self,
+        mut env: Uiua,
+        f: Box<dyn FnOnce(&mut Uiua) -> UiuaResult + Send>,
+    ) -> Result<Handle, String> {
+        let handle = Handle(self.next_handle.fetch_add(1, atomic::Ordering::Relaxed));
+        let res = f(&mut env).map(|()| env.take_stack());
+        self.thread_results.lock().insert(handle, res);
+        Ok(handle)
+    }
+    fn wait(&self, handle: Handle) -> Result<Vec<Value>, Result<UiuaError, String>> {
+        match self.thread_results.lock().remove(&handle) {
+            Some(Ok(stack)) => Ok(stack),
+            Some(Err(err)) => Err(Ok(err)),
+            None => Err(Err("Invalid thread handle".into())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn memory_backend() {
+        let backend = MemorySys::new()
+            .with_file("data/nums.txt", "1\n2\n3")
+            .with_stdin("hello\nworld");
+        let mut env = Uiua::with_backend(backend);
+        let stack = env
+            .load_str(
+                "&fwa \"out/copy.txt\" &fras \"./data/nums.txt\"\n\
+                 &fe \"out\"\n\
+                 &fld \"data\"\n\
+                 &sc\n\
+                 &p \"done\"",
+            )
+            .unwrap()
+            .take_stack();
+        assert_eq!(stack[0].show(), "1");
+        assert_eq!(stack[2], Value::from("hello"));
+        let backend = env.downcast_backend::<MemorySys>().unwrap();
+        assert_eq!(backend.file("out/copy.txt").unwrap(), b"1\n2\n3");
+        assert_eq!(backend.file_paths(), ["data/nums.txt", "out/copy.txt"]);
+        assert_eq!(backend.stdout(), "done\n");
+        assert!(Uiua::with_backend(MemorySys::new())
+            .load_str("&fras \"missing.txt\"")
+            .is_err());
+    }
+
+    #[test]
+    fn tilde_between_names_is_trace() {
+        let mut env = Uiua::with_backend(MemorySys::new());
+        env.load_str("X ← 1\nY ← 2\nF ← +1\nX~Y\nF~Y").unwrap();
+        let stack: Vec<String> = env.take_stack().iter().map(|v| v.show()).collect();
+        assert_eq!(stack, ["2", "1", "3"]);
+        let backend = env.downcast_backend::<MemorySys>().unwrap();
+        assert_eq!(
+            backend.stderr().matches("\n2\n").count(),
+            2,
+            "{}",
+            backend.stderr()
+        );
+    }
+
+    #[test]
+    fn stdin_reading() {
+        let backend = MemorySys::new().with_stdin("first\n\nrest\nof it");
+        let mut env = Uiua::with_backend(backend);
+        let stack = env
+            .load_str("&tty\n&sc\n&sc\n&eof\n&sca\n&eof\n&sc\n&scb")
+            .unwrap()
+            .take_stack();
+        assert_eq!(stack[0].show(), "0");
+        assert_eq!(stack[1], Value::from("first"));
+        assert_eq!(stack[2], Value::from(""));
+        assert_eq!(stack[3].show(), "0");
+        assert_eq!(stack[4], Value::from("rest\nof it"));
+        assert_eq!(stack[5].show(), "1");
+        assert_eq!(stack[6], Value::from(""));
+        assert_eq!(stack[7].row_count(), 0);
+    }
+
+    #[test]
+    fn key_input() {
+        let backend = MemorySys::new().with_stdin("aé\x1b\x03\n");
+        let mut env = Uiua::with_backend(backend);
+        let stack = env
+            .load_str("&raw 1\n&key 0\n&key 0\n&key ∞\n&key 0\n&key 1\n&key 0\n&raw 0")
+            .unwrap()
+            .take_stack();
+        let keys: Vec<_> = ["a", "é", "escape", "ctrl-c", "enter", ""]
+            .into_iter()
+            .map(Value::from)
+            .collect();
+        assert_eq!(stack, keys);
+        assert!(Uiua::with_backend(MemorySys::new())
+            .load_str("&key ¯1")
+            .is_err());
+    }
+
+    #[test]
+    fn glob_and_directories() {
+        let backend = MemorySys::new()
+            .with_file("src/main.ua", "")
+            .with_file("src/lib/a.ua", "")
+            .with_file("src/lib/b.txt", "")
+            .with_file("src/.hidden.ua", "")
+            .with_file("notes1.md", "")
+            .with_file("notes2.md", "");
+        let mut env = Uiua::with_backend(backend);
+        let glob = |env: &mut Uiua, pattern: &str| -> Vec<String> {
+            let stack = env
+                .load_str(&format!("&fglob {pattern:?}"))
+                .unwrap()
+                .take_stack();
+            let Value::Func(paths) = &stack[0] else {
+                return Vec::new();
+            };
+            (paths.data.iter())
+                .map(|f| f.as_constant().unwrap().as_string(env, "").unwrap())
+                .collect()
+        };
+        assert_eq!(
+            glob(&mut env, "src/**/*.ua"),
+            ["src/lib/a.ua", "src/main.ua"]
+        );
+        assert_eq!(glob(&mut env, "notes[0-1].md"), ["notes1.md"]);
+        assert_eq!(
+            glob(&mut env, "*/lib/?.*"),
+            ["src/lib/a.ua", "src/lib/b.txt"]
+        );
+        assert_eq!(glob(&mut env, "src/.*"), ["src/.hidden.ua"]);
+        assert!(glob(&mut env, "missing/*").is_empty());
+        env.load_str("&fmd \"out/empty\"\n&frd \"src/lib\"")
+            .unwrap();
+        assert_eq!(glob(&mut env, "*/*"), ["out/empty", "src/main.ua"]);
+        assert!(env.load_str("&frd \"nothing\"").is_err());
+    }
+
+    #[test]
+    fn clipboard() {
+        let backend = MemorySys::new().with_clipboard("copied");
+        let mut env = Uiua::with_backend(backend);
+        let stack = env
+            .load_str("&setclip \"pasted\" &clip")
+            .unwrap()
+            .take_stack();
+        assert_eq!(stack, [Value::from("copied")]);
+        let backend = env.downcast_backend::<MemorySys>().unwrap();
+        assert_eq!(backend.clipboard(), "pasted");
+        let mut env = Uiua::with_backend(MemorySys::new()).with_sandbox(true);
+        assert!(env.load_str("&clip").is_err());
+    }
+}
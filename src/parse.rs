@@ -1,11 +1,11 @@
-use std::{error::Error, fmt, path::Path};
+use std::{collections::HashMap, error::Error, fmt, path::Path};
 
 use crate::{
     ast::*,
     function::{FunctionId, Signature},
     lex::{AsciiToken::*, Token::*, *},
     primitive::Primitive,
-    Ident,
+    ErrorCode, Ident,
 };
 
 #[derive(Debug, Clone)]
@@ -42,6 +42,22 @@ impl fmt::Display for Expectation {
     }
 }
 
+impl ParseError {
+    /// Get the code of the error's category
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            ParseError::Lex(_) => ErrorCode::Lex,
+            ParseError::Expected(..) => ErrorCode::Expected,
+            ParseError::InvalidNumber(_) => ErrorCode::InvalidNumber,
+            ParseError::Unexpected(_) => ErrorCode::Unexpected,
+            ParseError::InvalidArgCount(_) | ParseError::InvalidOutCount(_) => {
+                ErrorCode::InvalidSignature
+            }
+            ParseError::AmpersandBindingName => ErrorCode::AmpersandBindingName,
+        }
+    }
+}
+
 impl fmt::Display for ParseError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
@@ -96,23 +112,57 @@ pub fn parse(input: &str, path: Option<&Path>) -> (Vec<Item>, Vec<Sp<ParseError>
         tokens,
         index: 0,
         errors,
+        macros: HashMap::new(),
     };
-    let items = parser.items(true);
-    if parser.errors.is_empty() && parser.index < parser.tokens.len() {
-        parser.errors.push(
-            parser
-                .tokens
-                .remove(parser.index)
-                .map(ParseError::Unexpected),
-        );
+    let mut items = parser.items(true);
+    // Recover from a token that cannot start an item by skipping the rest of its line,
+    // so that editors still get the items that follow it
+    while let Some(token) = parser.tokens.get(parser.index) {
+        parser
+            .errors
+            .push(token.clone().map(ParseError::Unexpected));
+        while (parser.tokens.get(parser.index)).is_some_and(|token| token.value != Newline) {
+            parser.index += 1;
+        }
+        items.extend(parser.items(true));
     }
     (items, parser.errors)
 }
 
+#[test]
+fn parse_recovers_after_errors() {
+    let (items, errors) = parse("1 2\n) 3\n+ 4 5\n", None);
+    assert_eq!(errors.len(), 1);
+    assert!(matches!(errors[0].value, ParseError::Unexpected(_)));
+    let lines = items
+        .iter()
+        .filter(|item| matches!(item, Item::Words(_)))
+        .count();
+    assert_eq!(lines, 2);
+}
+
+#[test]
+fn parse_radix_numbers() {
+    let (items, errors) = parse("0xff ¯0b101 0x1F 1e¯2", None);
+    assert!(errors.is_empty(), "{errors:?}");
+    let [Item::Words(words)] = items.as_slice() else {
+        panic!("expected one line");
+    };
+    let numbers: Vec<f64> = (words.iter())
+        .filter_map(|word| match &word.value {
+            Word::Number(_, n) => Some(*n),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(numbers, [255.0, -5.0, 31.0, 0.01]);
+}
+
 struct Parser {
     tokens: Vec<Sp<crate::lex::Token>>,
     index: usize,
     errors: Vec<Sp<ParseError>>,
+    /// The number of operands of each macro defined so far
+    macros: HashMap<Ident, usize>,
 }
 
 impl Parser {
@@ -228,6 +278,9 @@ impl Parser {
             self.try_spaces();
             let sig = self.try_signature();
             let words = self.try_words().unwrap_or_default();
+            if ident.value.ends_with('!') {
+                (self.macros).insert(ident.value.clone(), macro_operand_count(&words));
+            }
             Binding {
                 name: ident,
                 words,
@@ -237,6 +290,28 @@ impl Parser {
             return None;
         })
     }
+    /// Parse a binding in a function, like `name ← words`
+    ///
+    /// Unlike top-level bindings, these cannot use `=`, because it is also `equals`.
+    fn try_local_binding(&mut self) -> Option<Sp<Word>> {
+        let start = self.index;
+        let name = self.try_ident()?;
+        self.try_spaces();
+        if self.try_exact(LeftArrow).is_none() {
+            self.index = start;
+            return None;
+        }
+        self.try_spaces();
+        let words = self.try_words().unwrap_or_default();
+        let span = (words.last()).map_or(name.span.clone(), |last| {
+            name.span.clone().merge(last.span.clone())
+        });
+        Some(span.sp(Word::LocalBinding(Box::new(Binding {
+            name,
+            signature: None,
+            words,
+        }))))
+    }
     fn try_ident(&mut self) -> Option<Sp<Ident>> {
         let span = self.try_exact(Token::Ident)?;
         let s = span.as_str().into();
@@ -295,10 +370,21 @@ impl Parser {
             Some(words)
         }
     }
-    fn multiline_words(&mut self) -> Vec<Vec<Sp<Word>>> {
+    /// Parse lines of words
+    ///
+    /// If `locals` is true, lines may be local bindings.
+    fn multiline_words(&mut self, locals: bool) -> Vec<Vec<Sp<Word>>> {
         let mut lines = Vec::new();
         while self.try_exact(Newline).is_some() || self.try_spaces().is_some() {}
-        while let Some(words) = self.try_words() {
+        loop {
+            let local = if locals {
+                self.try_local_binding()
+            } else {
+                None
+            };
+            let Some(words) = local.map(|local| vec![local]).or_else(|| self.try_words()) else {
+                break;
+            };
             lines.push(words);
             let mut newlines = 0;
             while self.try_exact(Newline).is_some() {
@@ -374,6 +460,9 @@ impl Parser {
         Some(span.sp(Word::Strand(items)))
     }
     fn try_modified(&mut self) -> Option<Sp<Word>> {
+        if let Some(call) = self.try_macro_call() {
+            return Some(call);
+        }
         let Some((modifier, margs)) = Primitive::all()
             .filter_map(|prim| prim.modifier_args().map(|margs| (prim, margs)))
             .find_map(|(prim, margs)| {
@@ -384,10 +473,27 @@ impl Parser {
         else {
             return self.try_term();
         };
+        let (args, terminated) = self.operands(margs as usize);
+        Some(if args.is_empty() {
+            modifier.map(Word::Primitive)
+        } else {
+            let span = modifier
+                .span
+                .clone()
+                .merge(args.last().unwrap().span.clone());
+            span.sp(Word::Modified(Box::new(Modified {
+                modifier,
+                operands: args,
+                terminated,
+            })))
+        })
+    }
+    /// Parse the operands of a modifier or macro
+    fn operands(&mut self, count: usize) -> (Vec<Sp<Word>>, bool) {
         let mut args = Vec::new();
         self.try_spaces();
         let mut terminated = false;
-        for _ in 0..margs {
+        for _ in 0..count {
             if self.try_exact(Caret).is_some() {
                 terminated = true;
                 break;
@@ -399,26 +505,44 @@ impl Parser {
                 break;
             }
         }
-        Some(if args.is_empty() {
-            modifier.map(Word::Primitive)
-        } else {
-            for arg in &mut args {
-                if let Word::Func(func) = &arg.value {
-                    if func.lines.is_empty() {
-                        arg.value = Word::Primitive(Primitive::Noop);
-                    }
+        for arg in &mut args {
+            if let Word::Func(func) = &arg.value {
+                if func.lines.is_empty() {
+                    arg.value = Word::Primitive(Primitive::Noop);
                 }
             }
-            let span = modifier
-                .span
-                .clone()
-                .merge(args.last().unwrap().span.clone());
-            span.sp(Word::Modified(Box::new(Modified {
-                modifier,
-                operands: args,
-                terminated,
-            })))
-        })
+        }
+        (args, terminated)
+    }
+    fn try_macro_call(&mut self) -> Option<Sp<Word>> {
+        let start = self.index;
+        let name = self.try_ident()?;
+        if !name.value.ends_with('!') {
+            self.index = start;
+            return None;
+        }
+        // Unknown macros are reported when compiling
+        let count = self.macros.get(&name.value).copied().unwrap_or(0);
+        let (operands, terminated) = self.operands(count);
+        let span = (operands.last()).map_or(name.span.clone(), |last| {
+            name.span.clone().merge(last.span.clone())
+        });
+        Some(span.sp(Word::MacroCall(Box::new(MacroCall {
+            name,
+            operands,
+            terminated,
+        }))))
+    }
+    /// Parse the consecutive lines of a multiline string
+    fn try_lines<T>(&mut self, f: impl Fn(&Token) -> Option<T>) -> Option<Sp<Vec<Sp<T>>>> {
+        let line = self.next_token_map(&f)?;
+        let mut span = line.span.clone();
+        let mut lines = vec![line];
+        while let Some(line) = self.next_token_map(&f) {
+            span = span.merge(line.span.clone());
+            lines.push(line);
+        }
+        Some(span.sp(lines))
     }
     fn try_term(&mut self) -> Option<Sp<Word>> {
         Some(if let Some(prim) = self.try_prim() {
@@ -427,26 +551,24 @@ impl Parser {
             ident.map(Word::Ident)
         } else if let Some(sn) = self.try_num() {
             sn.map(|(s, n)| Word::Number(s, n))
+        } else if let Some(n) = self.next_token_map(Token::as_placeholder) {
+            n.map(Word::Placeholder)
         } else if let Some(c) = self.next_token_map(Token::as_char) {
             c.map(Into::into).map(Word::Char)
         } else if let Some(s) = self.next_token_map(Token::as_string) {
             s.map(Into::into).map(Word::String)
         } else if let Some(frags) = self.next_token_map(Token::as_format_string) {
             frags.map(Word::FormatString)
-        } else if let Some(line) = self.next_token_map(Token::as_multiline_string) {
-            let start = line.span.clone();
-            let mut end = start.clone();
-            let mut lines = vec![line];
-            while let Some(line) = self.next_token_map(Token::as_multiline_string) {
-                end = line.span.clone();
-                lines.push(line);
-            }
-            let span = start.merge(end);
-            span.sp(Word::MultilineString(lines))
+        } else if let Some(lines) = self.try_lines(Token::as_multiline_string) {
+            lines.map(Word::MultilineString)
+        } else if let Some(lines) = self.try_lines(Token::as_raw_string) {
+            lines.map(Word::RawString)
+        } else if let Some(lines) = self.try_lines(Token::as_grid_string) {
+            lines.map(Word::GridString)
         } else if let Some(expr) = self.try_func() {
             expr
         } else if let Some(start) = self.try_exact(OpenBracket) {
-            let items = self.multiline_words();
+            let items = self.multiline_words(false);
             let end = self.expect_close(CloseBracket);
             let span = start.merge(end);
             span.sp(Word::Array(Arr {
@@ -454,7 +576,7 @@ impl Parser {
                 constant: false,
             }))
         } else if let Some(start) = self.try_exact(OpenCurly) {
-            let items = self.multiline_words();
+            let items = self.multiline_words(false);
             let end = self.expect_close(CloseCurly);
             let span = start.merge(end);
             span.sp(Word::Array(Arr {
@@ -470,8 +592,21 @@ impl Parser {
     fn try_num(&mut self) -> Option<Sp<(String, f64)>> {
         let span = self.try_exact(Token::Number)?;
         let s = span.as_str().to_string();
-        let parseable = s.replace(['`', '¯'], "-");
-        let n: f64 = match parseable.parse() {
+        let (negative, unsigned) = match s.strip_prefix(['`', '¯']) {
+            Some(unsigned) => (true, unsigned),
+            None => (false, s.as_str()),
+        };
+        let radix_digits = [("0x", 16), ("0b", 2)]
+            .into_iter()
+            .find_map(|(prefix, radix)| Some((unsigned.strip_prefix(prefix)?, radix)));
+        let parsed = if let Some((digits, radix)) = radix_digits {
+            u128::from_str_radix(digits, radix)
+                .map(|n| n as f64)
+                .map_err(drop)
+        } else {
+            (unsigned.replace(['`', '¯'], "-").parse::<f64>()).map_err(drop)
+        };
+        let n: f64 = match parsed.map(|n| if negative { -n } else { n }) {
             Ok(n) => n,
             Err(_) => {
                 self.errors
@@ -496,7 +631,7 @@ impl Parser {
         Some(if let Some(start) = self.try_exact(OpenParen) {
             while self.try_exact(Newline).is_some() || self.try_spaces().is_some() {}
             let signature = self.try_signature();
-            let body = self.multiline_words();
+            let body = self.multiline_words(true);
             let end = self.expect_close(CloseParen);
             let span = start.merge(end);
             span.clone().sp(Word::Func(Func {
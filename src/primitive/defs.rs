@@ -397,6 +397,8 @@ primitive!(
     /// [multiply] can be used as a logical AND.
     /// ex: ×,,≥5∶≤8. [6 2 5 9 6 5 0 4]
     (2, Mul, DyadicPervasive, ("multiply", AsciiToken::Star, '×')),
+    /// The sum of the products of two arrays
+    (2, Dot, DyadicArray),
     /// Divide values
     ///
     /// Formats from `%`.
@@ -778,6 +780,18 @@ primitive!(
     ///
     /// [keep]'s glyph is `▽` because its main use is to filter, and `▽` kind of looks like a coffee filter.
     (2, Keep, DyadicArray, ("keep", '▽')),
+    /// Keep the rows where two arrays are equal
+    (3, KeepEq, Misc),
+    /// Keep the rows where two arrays are not equal
+    (3, KeepNe, Misc),
+    /// Keep the rows where one array is less than another
+    (3, KeepLt, Misc),
+    /// Keep the rows where one array is less than or equal to another
+    (3, KeepLe, Misc),
+    /// Keep the rows where one array is greater than another
+    (3, KeepGt, Misc),
+    /// Keep the rows where one array is greater than or equal to another
+    (3, KeepGe, Misc),
     /// Find the occurences of one array in another
     ///
     /// ex: ⌕ 5 [1 8 5 2 3 5 4 5 6 7]
@@ -1284,6 +1298,31 @@ primitive!(
     /// ex: parse "3.1415926535897932"
     /// ex! parse "dog"
     (1, Parse, Misc, "parse"),
+    /// Format a number array as strings according to a format specifier
+    ///
+    /// The specifier is a string of the form `[[fill]align][width][.precision][base]`.
+    /// All parts are optional.
+    ///
+    /// The `width` is the minimum number of characters in each formatted number.
+    /// ex: format "8" π
+    /// ex: format "8.3" π
+    ///
+    /// The `fill` character and `align`ment (`<` left, `>` right, `^` center) control padding.
+    /// ex: format "*^9.2" π
+    /// ex: format "<6" [1 22 333]
+    /// A leading `0` pads with zeros after the sign.
+    /// ex: format "05" [¯7 42]
+    ///
+    /// The `base` can be `b` for binary, `o` for octal, `x` or `X` for hexadecimal, or `d` for decimal.
+    /// ex: format "x" [10 255 4096]
+    /// ex: format "08b" 5
+    /// Non-integers can only be formatted in a non-decimal base if a precision is given.
+    /// ex! format "b" 0.5
+    /// ex: format ".4b" 0.5
+    ///
+    /// Every row of the result has the same length, so formatting an array produces a character array with one more axis.
+    /// ex: format ".1" [1_2 3_4]
+    (2, Format, Misc, "format"),
     /// Generate a random number between 0 and 1
     ///
     /// If you need a seeded random number, use [gen].
@@ -1371,4 +1410,10 @@ primitive!(
     (1, Trace, Stack, ("trace", '~')),
     /// The inverse of trace
     (1, InvTrace, Stack),
+    /// Pause the program in the debugger
+    ///
+    /// When a program is run with `uiua run --debug`, reaching a [breakpoint] opens a prompt where you can inspect and change the stack before continuing.
+    /// Otherwise, [breakpoint] does nothing.
+    /// ex: +1 breakpoint 2
+    (0(0), Breakpoint, Misc, "breakpoint"),
 );
@@ -7,7 +7,6 @@ pub use defs::*;
 
 use std::{
     borrow::Cow,
-    cell::RefCell,
     f64::{
         consts::{PI, TAU},
         INFINITY,
@@ -25,7 +24,7 @@ use once_cell::sync::Lazy;
 use rand::prelude::*;
 
 use crate::{
-    algorithm::{fork, loops},
+    algorithm::{fork, fuse, loops, pervade},
     function::{Function, Signature},
     lex::AsciiToken,
     run::FunctionArg,
@@ -129,6 +128,13 @@ impl fmt::Display for Primitive {
                 Asin => write!(f, "{Invert}{Sin}"),
                 Acos => write!(f, "{Invert}{Cos}"),
                 Last => write!(f, "{First}{Reverse}"),
+                Dot => write!(f, "{Reduce}{Add}{Mul}"),
+                KeepEq => write!(f, "{Keep}{Eq}"),
+                KeepNe => write!(f, "{Keep}{Ne}"),
+                KeepLt => write!(f, "{Keep}{Lt}"),
+                KeepLe => write!(f, "{Keep}{Le}"),
+                KeepGt => write!(f, "{Keep}{Gt}"),
+                KeepGe => write!(f, "{Keep}{Ge}"),
                 _ => write!(f, "{self:?}"),
             }
         }
@@ -161,6 +167,31 @@ impl Primitive {
     pub fn is_modifier(&self) -> bool {
         self.modifier_args().is_some()
     }
+    /// Get the number of arguments and outputs, if they are fixed
+    ///
+    /// For modifiers, this does not include the function arguments.
+    pub fn signature(&self) -> Option<Signature> {
+        Some(Signature::new(
+            self.args()? as usize,
+            self.outputs()? as usize,
+        ))
+    }
+    /// Whether the primitive has no side effects and does not depend on the calling context
+    pub fn is_pure(&self) -> bool {
+        !matches!(
+            self,
+            Primitive::Sys(_)
+                | Primitive::Rand
+                | Primitive::Tag
+                | Primitive::Trace
+                | Primitive::InvTrace
+                | Primitive::Breakpoint
+                | Primitive::Break
+                | Primitive::Recur
+                | Primitive::Spawn
+                | Primitive::Wait
+        )
+    }
     pub fn inverse(&self) -> Option<Self> {
         use Primitive::*;
         Some(match self {
@@ -233,6 +264,24 @@ impl Primitive {
             break None;
         }
     }
+    /// Get the code that replaces this primitive if it is deprecated
+    ///
+    /// Deprecated primitives still work, but the compiler warns when they are
+    /// used, and the formatter replaces them with this code if
+    /// [`FormatConfig::migrate_deprecated`](crate::format::FormatConfig::migrate_deprecated)
+    /// is set.
+    pub fn deprecation(&self) -> Option<&'static str> {
+        #[allow(clippy::match_single_binding)]
+        match self {
+            // Deprecated primitives and their replacements go here, like
+            // `Primitive::Old => "new",`
+            _ => None,
+        }
+    }
+    /// Check if the primitive is deprecated
+    pub fn is_deprecated(&self) -> bool {
+        self.deprecation().is_some()
+    }
     pub fn as_constant(&self) -> Option<f64> {
         Some(match self {
             Primitive::Pi => PI,
@@ -243,13 +292,42 @@ impl Primitive {
         })
     }
     pub(crate) fn run(&self, env: &mut Uiua) -> UiuaResult {
+        #[cfg(feature = "gpu")]
+        if crate::algorithm::gpu::run(*self, env)? {
+            return Ok(());
+        }
+        // Calling and recurring are run apart from the other primitives,
+        // which share a large stack frame in debug builds that recursion
+        // would otherwise use for every call
+        match self {
+            Primitive::Call => {
+                let f = env.pop(1)?;
+                let sig = f.signature();
+                if !(sig.outputs == 1 || sig == (0, 0)) {
+                    return Err(env.error(format!(
+                        "Only functions with 1 output may be explicitly called, \
+                        but the function {f} has {} outputs",
+                        sig.outputs
+                    )));
+                }
+                env.call(f)?
+            }
+            Primitive::Recur => {
+                let n = env.pop(1)?.as_nat(env, "Recur expects a natural number")?;
+                env.recur(n)?;
+            }
+            _ => self.run_other(env)?,
+        }
+        Ok(())
+    }
+    fn run_other(&self, env: &mut Uiua) -> UiuaResult {
         match self {
             Primitive::Eta => env.push(PI / 2.0),
             Primitive::Pi => env.push(PI),
             Primitive::Tau => env.push(TAU),
             Primitive::Infinity => env.push(INFINITY),
             Primitive::Noop => {}
-            Primitive::Not => env.monadic_env(Value::not)?,
+            Primitive::Not => env.monadic_env(Value::not_mask)?,
             Primitive::Neg => env.monadic_env(Value::neg)?,
             Primitive::Abs => env.monadic_env(Value::abs)?,
             Primitive::Sign => env.monadic_env(Value::sign)?,
@@ -267,21 +345,28 @@ impl Primitive {
             Primitive::Le => env.dyadic_rr_env(Value::is_le)?,
             Primitive::Gt => env.dyadic_rr_env(Value::is_gt)?,
             Primitive::Ge => env.dyadic_rr_env(Value::is_ge)?,
-            Primitive::Add => env.dyadic_rr_env(Value::add)?,
-            Primitive::Sub => env.dyadic_rr_env(Value::sub)?,
-            Primitive::Mul => env.dyadic_rr_env(Value::mul)?,
-            Primitive::Div => env.dyadic_rr_env(Value::div)?,
-            Primitive::Mod => env.dyadic_rr_env(Value::modulus)?,
-            Primitive::Pow => env.dyadic_rr_env(Value::pow)?,
-            Primitive::Log => env.dyadic_rr_env(Value::log)?,
-            Primitive::Min => env.dyadic_rr_env(Value::min)?,
-            Primitive::Max => env.dyadic_rr_env(Value::max)?,
-            Primitive::Atan => env.dyadic_rr_env(Value::atan2)?,
+            Primitive::Add => env.dyadic_oo_env(Value::add_in_place)?,
+            Primitive::Sub => env.dyadic_oo_env(Value::sub_in_place)?,
+            Primitive::Mul => env.dyadic_oo_env(Value::mul_in_place)?,
+            Primitive::Dot => fuse::dot(env)?,
+            Primitive::Div => env.dyadic_oo_env(Value::div_in_place)?,
+            Primitive::Mod => env.dyadic_oo_env(Value::modulus_in_place)?,
+            Primitive::Pow => env.dyadic_oo_env(Value::pow_in_place)?,
+            Primitive::Log => env.dyadic_oo_env(Value::log_in_place)?,
+            Primitive::Min => env.dyadic_oo_env(Value::min_in_place)?,
+            Primitive::Max => env.dyadic_oo_env(Value::max_in_place)?,
+            Primitive::Atan => env.dyadic_oo_env(Value::atan2_in_place)?,
             Primitive::Match => env.dyadic_rr(|a, b| a == b)?,
             Primitive::Join => env.dyadic_oo_env(Value::join)?,
             Primitive::Transpose => env.monadic_mut(Value::transpose)?,
             Primitive::InvTranspose => env.monadic_mut(Value::inv_transpose)?,
             Primitive::Keep => env.dyadic_ro_env(Value::keep)?,
+            Primitive::KeepEq => fuse::keep_cmp(env, Value::is_eq, pervade::is_eq::num_num)?,
+            Primitive::KeepNe => fuse::keep_cmp(env, Value::is_ne, pervade::is_ne::num_num)?,
+            Primitive::KeepLt => fuse::keep_cmp(env, Value::is_lt, pervade::is_lt::num_num)?,
+            Primitive::KeepLe => fuse::keep_cmp(env, Value::is_le, pervade::is_le::num_num)?,
+            Primitive::KeepGt => fuse::keep_cmp(env, Value::is_gt, pervade::is_gt::num_num)?,
+            Primitive::KeepGe => fuse::keep_cmp(env, Value::is_ge, pervade::is_ge::num_num)?,
             Primitive::Take => env.dyadic_oo_env(Value::take)?,
             Primitive::Constant => {
                 let val = env.pop(1)?;
@@ -330,19 +415,8 @@ impl Primitive {
             Primitive::Member => env.dyadic_rr_env(Value::member)?,
             Primitive::Find => env.dyadic_rr_env(Value::find)?,
             Primitive::IndexOf => env.dyadic_rr_env(Value::index_of)?,
-            Primitive::Call => {
-                let f = env.pop(1)?;
-                let sig = f.signature();
-                if !(sig.outputs == 1 || sig == (0, 0)) {
-                    return Err(env.error(format!(
-                        "Only functions with 1 output may be explicitly called, \
-                        but the function {f} has {} outputs",
-                        sig.outputs
-                    )));
-                }
-                env.call(f)?
-            }
             Primitive::Parse => env.monadic_env(|v, env| v.parse_num(env))?,
+            Primitive::Format => env.dyadic_rr_env(Value::format_num)?,
             Primitive::Range => env.monadic_ref_env(Value::range)?,
             Primitive::Reverse => env.monadic_mut(Value::reverse)?,
             Primitive::Deshape => env.monadic_mut(Value::deshape)?,
@@ -379,10 +453,6 @@ impl Primitive {
                     return Err(UiuaError::Break(n - 1, env.span().clone()));
                 }
             }
-            Primitive::Recur => {
-                let n = env.pop(1)?.as_nat(env, "Recur expects a natural number")?;
-                env.recur(n)?;
-            }
             Primitive::Dup => {
                 let x = env.pop(1)?;
                 env.push(x.clone());
@@ -484,10 +554,8 @@ impl Primitive {
                 }
             }
             Primitive::Rand => {
-                thread_local! {
-                    static RNG: RefCell<SmallRng> = RefCell::new(SmallRng::seed_from_u64(instant::now().to_bits()));
-                }
-                env.push(RNG.with(|rng| rng.borrow_mut().gen::<f64>()));
+                let val = env.random();
+                env.push(val);
             }
             Primitive::Gen => {
                 let seed = env.pop(1)?;
@@ -538,7 +606,9 @@ impl Primitive {
             }
             Primitive::Trace => trace(env, false)?,
             Primitive::InvTrace => trace(env, true)?,
+            Primitive::Breakpoint => {}
             Primitive::Sys(io) => io.run(env)?,
+            Primitive::Call | Primitive::Recur => unreachable!("{self:?} is run by Primitive::run"),
         }
         Ok(())
     }
@@ -551,7 +621,7 @@ fn trace(env: &mut Uiua, inverse: bool) -> UiuaResult {
     } else {
         env.span().to_string()
     };
-    let formatted = val.show();
+    let formatted = val.show_with_budget(env.display_budget());
     const MD_ARRAY_INIT: &str = "╭─";
     let message = if let Some(first_line) = formatted
         .lines()
@@ -592,31 +662,48 @@ impl PrimDoc {
                 PrimDocFragment::Primitive { .. } => {}
             }
         }
-        let mut s = String::new();
-        for frag in &self.short {
-            match frag {
-                PrimDocFragment::Text(t) => s.push_str(t),
-                PrimDocFragment::Code(c) => s.push_str(c),
-                PrimDocFragment::Emphasis(e) => s.push_str(e),
-                PrimDocFragment::Primitive { prim, named } => {
-                    let mut name = String::new();
-                    if *named {
-                        s.push_str(prim.name().unwrap_or_else(|| {
-                            name = format!("{prim:?}");
-                            &name
-                        }));
-                    } else if let Some(c) = prim.unicode() {
-                        s.push(c);
-                    } else {
-                        s.push_str(prim.name().unwrap_or_else(|| {
-                            name = format!("{prim:?}");
-                            &name
-                        }));
+        Cow::Owned(fragments_text(&self.short))
+    }
+    /// Get the examples in the documentation
+    pub fn examples(&self) -> impl Iterator<Item = &PrimExample> {
+        self.lines.iter().filter_map(|line| match line {
+            PrimDocLine::Example(ex) => Some(ex),
+            PrimDocLine::Text(_) => None,
+        })
+    }
+    /// Render the full documentation as plain text
+    ///
+    /// Examples are indented, with each output line prefixed by `→`.
+    /// Rendering runs the examples.
+    pub fn text(&self) -> String {
+        let mut s = fragments_text(&self.short);
+        for line in &self.lines {
+            s.push('\n');
+            match line {
+                PrimDocLine::Text(frags) => s.push_str(&fragments_text(frags)),
+                PrimDocLine::Example(ex) => {
+                    for line in ex.input.lines() {
+                        s.push_str("\n  ");
+                        s.push_str(line);
                     }
+                    match ex.output() {
+                        Ok(values) => {
+                            for line in values.iter().flat_map(|val| val.lines()) {
+                                s.push_str("\n  → ");
+                                s.push_str(line);
+                            }
+                        }
+                        Err(e) => {
+                            s.push_str("\n  → Error: ");
+                            s.push_str(e);
+                        }
+                    }
+                    s.push('\n');
                 }
             }
         }
-        Cow::Owned(s)
+        s.truncate(s.trim_end().len());
+        s
     }
     pub fn from_lines(s: &str) -> Self {
         let mut short = Vec::new();
@@ -727,6 +814,34 @@ pub enum PrimDocFragment {
     Primitive { prim: Primitive, named: bool },
 }
 
+fn fragments_text(frags: &[PrimDocFragment]) -> String {
+    let mut s = String::new();
+    for frag in frags {
+        match frag {
+            PrimDocFragment::Text(t) => s.push_str(t),
+            PrimDocFragment::Code(c) => s.push_str(c),
+            PrimDocFragment::Emphasis(e) => s.push_str(e),
+            PrimDocFragment::Primitive { prim, named } => {
+                let mut name = String::new();
+                if *named {
+                    s.push_str(prim.name().unwrap_or_else(|| {
+                        name = format!("{prim:?}");
+                        &name
+                    }));
+                } else if let Some(c) = prim.unicode() {
+                    s.push(c);
+                } else {
+                    s.push_str(prim.name().unwrap_or_else(|| {
+                        name = format!("{prim:?}");
+                        &name
+                    }));
+                }
+            }
+        }
+    }
+    s
+}
+
 fn parse_doc_line_fragments(line: &str) -> Vec<PrimDocFragment> {
     let mut frags = Vec::new();
     #[derive(PartialEq, Eq)]
@@ -848,6 +963,18 @@ mod tests {
         }
     }
 
+    #[test]
+    fn prim_doc_text() {
+        let prim = Primitive::Reverse;
+        let doc = prim.doc().unwrap();
+        let text = doc.text();
+        assert!(text.starts_with(&*doc.short_text()));
+        let ex = doc.examples().next().unwrap();
+        assert!(text.contains(&format!("\n  {}\n  → ", ex.input())));
+        assert_eq!(prim.signature(), Some(Signature::new(1, 1)));
+        assert_eq!(Primitive::Each.signature(), None);
+    }
+
     #[test]
     fn primitive_from_name() {
         assert_eq!(Primitive::from_format_name("rev"), Some(Primitive::Reverse));
@@ -858,6 +985,29 @@ mod tests {
         );
     }
 
+    #[test]
+    fn deprecation_replacements() {
+        use crate::{ast::Item, check::instrs_signature, parse::parse};
+        for prim in Primitive::all() {
+            let Some(replacement) = prim.deprecation() else {
+                continue;
+            };
+            let (items, errors) = parse(replacement, None);
+            assert!(errors.is_empty(), "{prim:?} replacement failed to parse");
+            let Ok([Item::Words(words)]) = <[Item; 1]>::try_from(items) else {
+                panic!("{prim:?} replacement is not a single line of code");
+            };
+            let instrs = (Uiua::with_native_sys().compile_words(words, true))
+                .unwrap_or_else(|e| panic!("{prim:?} replacement failed to compile: {e}"));
+            if let (Some(sig), Ok(replaced)) = (prim.signature(), instrs_signature(&instrs)) {
+                assert_eq!(
+                    sig, replaced,
+                    "{prim:?} replacement has a different signature"
+                );
+            }
+        }
+    }
+
     #[test]
     fn from_multiname() {
         assert!(matches!(
@@ -35,6 +35,8 @@ pub(crate) mod enabled {
 
     const BENCHMARKS: &[(&str, &str)] = &[
         ("PRIMES", "▽¬∊∶♭⊞×...+2⇡1000"),
+        ("GRADE_INTS", "⊏⌂. ◿1000 ×7919 ⇡100000"),
+        ("GRADE_FLOATS", "⊏⌂. ◿1 ×0.618 ⇡100000"),
         (
             "STRIPES",
             "\
@@ -1,33 +1,59 @@
 use std::{
-    collections::{hash_map::DefaultHasher, HashMap, HashSet},
-    fs,
+    collections::{hash_map::DefaultHasher, HashMap, HashSet, VecDeque},
+    fmt, fs,
     hash::{Hash, Hasher},
-    mem::take,
+    mem::{replace, take},
     panic::{catch_unwind, AssertUnwindSafe},
     path::{Path, PathBuf},
     str::FromStr,
-    sync::Arc,
+    sync::{
+        atomic::{self, AtomicBool, AtomicU64},
+        Arc,
+    },
+    thread::{self, ThreadId},
 };
 
 use instant::Duration;
 use parking_lot::Mutex;
+use rand::prelude::*;
 
 use crate::{
+    algorithm::LazyRange,
+    array::{FormatShape, Shape},
     ast::*,
+    bytecode::{Decoder, Encoder},
     check::instrs_signature,
+    compile::{eliminate_pop, fold_constant, verify_signature, FOLD_FUEL},
     function::*,
+    grid_fmt::DEFAULT_DISPLAY_BUDGET,
     lex::{CodeSpan, Sp, Span},
     parse::parse,
-    primitive::{Primitive, CONSTANTS},
+    primitive::{PrimClass, Primitive, CONSTANTS},
     value::Value,
-    Handle, Ident, NativeSys, SysBackend, SysOp, TraceFrame, UiuaError, UiuaResult,
+    Capability, Diagnostic, DiagnosticKind, ErrorCode, Handle, Ident, NativeSys, SysBackend, SysOp,
+    TraceFrame, UiuaError, UiuaResult,
 };
 
+const DEFAULT_MAX_CALL_DEPTH: usize = 1000;
+
+/// How much of the host's stack must be left to run primitives and report errors
+///
+/// Running some primitives uses about 100 KB of stack in debug builds. This is
+/// in addition to room for the next call, which is estimated from how much
+/// stack the calls so far have used.
+const STACK_RED_ZONE: usize = 256 * 1024;
+
 /// The Uiua runtime
 #[derive(Clone)]
 pub struct Uiua {
     /// Functions which are under construction
     new_functions: Vec<Vec<Instr>>,
+    /// The operands of the macros currently being expanded
+    macro_operands: Vec<Vec<MacroOperand>>,
+    /// The names of the local bindings of each function being compiled
+    local_names: Vec<Vec<Ident>>,
+    /// The values of local bindings
+    locals: Vec<Value>,
     /// Global values
     globals: Arc<Mutex<Vec<Value>>>,
     /// Indexable spans
@@ -44,12 +70,444 @@ pub struct Uiua {
     execution_limit: Option<f64>,
     /// The time at which execution started
     execution_start: f64,
+    /// The number of instructions that may still be executed, shared between threads
+    fuel: Option<Arc<AtomicU64>>,
+    /// The amount of fuel to start each run with
+    instruction_limit: Option<u64>,
+    /// A limit on the size in bytes of arrays that may be allocated
+    memory_limit: Option<usize>,
+    /// The maximum number of nested function calls
+    max_call_depth: usize,
+    /// The position in the host's stack of the outermost call
+    call_stack_base: usize,
+    /// Set from another thread to stop execution
+    interrupt: InterruptHandle,
+    /// When the current slice of a resumable [`Execution`] should end
+    slice_end: Option<f64>,
+    /// The call stack height below which a resumable line's frames are
+    resumable_height: Option<usize>,
+    /// Whether a resumable line was suspended before it finished
+    suspended: bool,
+    /// The kinds of system access that are permitted
+    capabilities: HashSet<Capability>,
+    /// Whether looping modifiers may run iterations in parallel
+    parallel: bool,
+    /// Whether common instruction sequences are fused when compiling
+    fuse: bool,
+    /// Whether pure primitives applied to constants are evaluated when compiling
+    fold: bool,
+    /// The size in bytes of the constants that may still be folded in the current input
+    fold_fuel: usize,
+    /// Whether large array operations may be offloaded to the GPU
+    gpu: bool,
+    /// Whether operations offloaded to the GPU may be computed in single precision
+    gpu_single_precision: bool,
+    /// The maximum number of rows shown along each axis when showing arrays
+    display_budget: usize,
     /// The paths of files currently being imported (used to detect import cycles)
     current_imports: Arc<Mutex<HashSet<PathBuf>>>,
     /// The stacks of imported files
     imports: Arc<Mutex<HashMap<PathBuf, Vec<Value>>>>,
+    /// The sources of files that are imported without reading them
+    import_sources: Arc<HashMap<PathBuf, String>>,
+    /// The spans where bindings are defined and used, by global index
+    binding_spans: Arc<Mutex<HashMap<usize, BindingSpans>>>,
+    /// The spans and shapes of the literal operands of dyadic pervasive primitives,
+    /// by the primitive's span index
+    operand_spans: Arc<Mutex<HashMap<usize, [LiteralOperand; 2]>>>,
+    /// The operand spans of the primitive word being compiled
+    prim_operands: Option<[CodeSpan; 2]>,
+    /// The results of the test lines run so far
+    test_results: Vec<TestResult>,
+    /// Non-fatal problems found while compiling
+    diagnostics: Arc<Mutex<Vec<Diagnostic>>>,
+    /// Functions bound in previous runs, used by [`Uiua::reload_str`]
+    function_cache: Option<Arc<Mutex<FunctionCache>>>,
+    /// The program being compiled by [`Uiua::compile_program`]
+    program: Option<ProgramBuilder>,
     /// The system backend
     pub(crate) backend: Arc<dyn SysBackend>,
+    /// A function called before each instruction, used for debugging
+    step_handler: Option<StepHandler>,
+    /// The profile being recorded, if profiling is enabled
+    profile: Option<Arc<Mutex<Profile>>>,
+    /// The state of the random number generator, shared between threads
+    rng: Arc<AtomicU64>,
+    /// The virtual clock and thread results, if execution is deterministic
+    deterministic: Option<Arc<Mutex<Deterministic>>>,
+}
+
+/// A program that is run a slice at a time
+///
+/// Start one with [`Uiua::start_str`] or [`Uiua::start_file`], and run it
+/// with [`Uiua::resume`]. This lets a program run without blocking a thread
+/// that must stay responsive, like a browser's.
+///
+/// Execution is suspended at safe points: between lines, and between the
+/// instructions of a line that are not inside a function call. A single
+/// long-running function call or loop runs until it finishes. While a
+/// program is suspended, the runtime should not run other code.
+pub struct Execution {
+    items: VecDeque<Item>,
+    path: Option<PathBuf>,
+    /// The comment lines above the next item
+    doc: Option<String>,
+    first_global: usize,
+    /// The call stack height of the line that was suspended, if one was
+    suspended: Option<usize>,
+    finished: bool,
+}
+
+impl Execution {
+    /// Check whether the program has finished running
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+}
+
+/// A handle for interrupting a runtime from another thread
+///
+/// Get one with [`Uiua::interrupt_handle`].
+#[derive(Debug, Clone, Default)]
+pub struct InterruptHandle(Arc<AtomicBool>);
+
+impl InterruptHandle {
+    /// Interrupt the runtime
+    ///
+    /// The runtime stops with [`UiuaError::Interrupted`] before its next
+    /// function call or loop iteration. The values on the stack are kept,
+    /// and the runtime can load more code afterwards.
+    pub fn interrupt(&self) {
+        self.0.store(true, atomic::Ordering::Relaxed);
+    }
+    /// Check whether the runtime has been interrupted but has not yet loaded more code
+    pub fn is_interrupted(&self) -> bool {
+        self.0.load(atomic::Ordering::Relaxed)
+    }
+}
+
+/// The state of a deterministic runtime, shared between threads
+#[derive(Debug, Default)]
+struct Deterministic {
+    /// The virtual time in seconds
+    clock: f64,
+    /// The results of spawned threads that have not been waited on
+    threads: HashMap<Handle, UiuaResult<Vec<Value>>>,
+    /// The handle of the next spawned thread
+    next_handle: u64,
+}
+
+/// A function called before each instruction is executed
+type StepHandler = Arc<Mutex<dyn FnMut(&mut Uiua) -> UiuaResult + Send>>;
+
+/// The spans where a binding is defined and used
+#[derive(Debug, Clone)]
+pub struct BindingSpans {
+    /// The span of the binding's name where it is bound
+    pub definition: CodeSpan,
+    /// The spans of the names that refer to the binding
+    pub uses: Vec<CodeSpan>,
+    /// The comment lines directly above the binding
+    pub doc: Option<String>,
+}
+
+/// The result of running a line in a test scope
+#[derive(Debug, Clone)]
+pub struct TestResult {
+    /// The span of the line
+    pub span: CodeSpan,
+    /// The error the line failed with, if any
+    pub error: Option<UiuaError>,
+}
+
+impl TestResult {
+    /// Check if the line ran without an error
+    pub fn passed(&self) -> bool {
+        self.error.is_none()
+    }
+}
+
+/// Cumulative time and call counts recorded while profiling
+///
+/// Time spent in a primitive or binding includes the time spent in the
+/// functions it calls. Recursive calls are counted, but their time is only
+/// counted once.
+#[derive(Debug, Clone, Default)]
+pub struct Profile {
+    entries: HashMap<ProfileKey, ProfileEntry>,
+    /// The number of calls in progress for each key
+    active: HashMap<ProfileKey, usize>,
+    /// Every finished call, if a timeline is being recorded
+    events: Option<Vec<ProfileEvent>>,
+    /// The calls in progress on each thread, innermost last
+    open: HashMap<usize, Vec<OpenCall>>,
+    /// The threads that have made calls, in the order they made their first one
+    threads: Vec<ThreadId>,
+    /// The time at which the timeline started
+    origin: f64,
+}
+
+/// Something whose execution is recorded while profiling
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum ProfileKey {
+    Primitive(Primitive),
+    Binding(Ident),
+}
+
+/// The time and call count recorded for a [`ProfileKey`]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ProfileEntry {
+    /// The number of times it was called
+    pub calls: u64,
+    /// The cumulative time spent in it
+    pub time: Duration,
+}
+
+/// A single call recorded in a profile's timeline
+#[derive(Debug, Clone)]
+pub struct ProfileEvent {
+    /// What was called
+    pub key: ProfileKey,
+    /// The calls it was made in, outermost first
+    pub stack: Vec<ProfileKey>,
+    /// The index of the thread it was made on
+    pub thread: usize,
+    /// When it started, relative to the start of the timeline
+    pub start: Duration,
+    /// How long it took
+    pub duration: Duration,
+    /// How long it took, not including the calls it made
+    pub self_time: Duration,
+}
+
+#[derive(Debug, Clone)]
+struct OpenCall {
+    key: ProfileKey,
+    start: f64,
+    children_ms: f64,
+}
+
+impl fmt::Display for ProfileKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProfileKey::Primitive(prim) => match (prim.unicode(), prim.name()) {
+                (Some(c), Some(name)) => write!(f, "{c} {name}"),
+                _ => write!(f, "{prim}"),
+            },
+            ProfileKey::Binding(name) => write!(f, "{name}"),
+        }
+    }
+}
+
+fn ms_duration(ms: f64) -> Duration {
+    Duration::from_secs_f64(ms.max(0.0) / 1000.0)
+}
+
+impl Profile {
+    /// Create a profile that also records a timeline of every call
+    fn with_timeline() -> Self {
+        Profile {
+            events: Some(Vec::new()),
+            origin: instant::now(),
+            ..Default::default()
+        }
+    }
+    fn thread_index(&mut self) -> usize {
+        let id = thread::current().id();
+        match self.threads.iter().position(|&thread| thread == id) {
+            Some(i) => i,
+            None => {
+                self.threads.push(id);
+                self.threads.len() - 1
+            }
+        }
+    }
+    fn start(&mut self, key: &ProfileKey, now: f64) {
+        *self.active.entry(key.clone()).or_default() += 1;
+        if self.events.is_some() {
+            let thread = self.thread_index();
+            self.open.entry(thread).or_default().push(OpenCall {
+                key: key.clone(),
+                start: now,
+                children_ms: 0.0,
+            });
+        }
+    }
+    fn end(&mut self, key: ProfileKey, start: f64, now: f64) {
+        let elapsed_ms = now - start;
+        let active = self.active.entry(key.clone()).or_default();
+        *active = active.saturating_sub(1);
+        let outermost = *active == 0;
+        let entry = self.entries.entry(key.clone()).or_default();
+        entry.calls += 1;
+        if outermost {
+            entry.time += ms_duration(elapsed_ms);
+        }
+        if self.events.is_some() {
+            let thread = self.thread_index();
+            let open = self.open.entry(thread).or_default();
+            let Some(call) = open.pop() else {
+                return;
+            };
+            if let Some(parent) = open.last_mut() {
+                parent.children_ms += elapsed_ms;
+            }
+            let event = ProfileEvent {
+                key,
+                stack: open.iter().map(|call| call.key.clone()).collect(),
+                thread,
+                start: ms_duration(call.start - self.origin),
+                duration: ms_duration(elapsed_ms),
+                self_time: ms_duration(elapsed_ms - call.children_ms),
+            };
+            self.events.as_mut().unwrap().push(event);
+        }
+    }
+    /// Get the recorded entries, sorted by descending time
+    pub fn entries(&self) -> Vec<(&ProfileKey, ProfileEntry)> {
+        let mut entries: Vec<_> = (self.entries.iter())
+            .map(|(key, entry)| (key, *entry))
+            .collect();
+        entries.sort_by(|(_, a), (_, b)| b.time.cmp(&a.time).then(b.calls.cmp(&a.calls)));
+        entries
+    }
+    /// Get the timeline of every call, in the order the calls finished
+    ///
+    /// This is empty unless the timeline was enabled with [`Uiua::with_timeline`].
+    pub fn events(&self) -> &[ProfileEvent] {
+        self.events.as_deref().unwrap_or_default()
+    }
+    /// Format the entries as a table
+    pub fn report(&self) -> String {
+        let mut report = format!("{:>12} {:>10}  Name\n", "Time", "Calls");
+        for (key, entry) in self.entries() {
+            let ms = entry.time.as_secs_f64() * 1000.0;
+            report.push_str(&format!("{:>10.3}ms {:>10}  {key}\n", ms, entry.calls));
+        }
+        report
+    }
+    /// Format the timeline in the Chrome tracing JSON format
+    ///
+    /// The result can be opened in `chrome://tracing`, Perfetto, or Speedscope.
+    pub fn chrome_trace(&self) -> String {
+        let mut events = self.events().to_vec();
+        events.sort_by_key(|event| event.start);
+        let mut json = String::from("{\"traceEvents\":[");
+        for (i, event) in events.iter().enumerate() {
+            if i > 0 {
+                json.push(',');
+            }
+            let name = event
+                .key
+                .to_string()
+                .replace('\\', "\\\\")
+                .replace('"', "\\\"");
+            json.push_str(&format!(
+                "\n{{\"name\":\"{name}\",\"ph\":\"X\",\"ts\":{:.3},\"dur\":{:.3},\"pid\":1,\"tid\":{}}}",
+                event.start.as_secs_f64() * 1e6,
+                event.duration.as_secs_f64() * 1e6,
+                event.thread,
+            ));
+        }
+        json.push_str("\n],\"displayTimeUnit\":\"ms\"}\n");
+        json
+    }
+    /// Format the timeline as folded stacks
+    ///
+    /// Each line is a stack of calls separated by `;`, followed by the time
+    /// spent in the innermost call in microseconds. The result can be turned
+    /// into a flamegraph with tools like `inferno` or `flamegraph.pl`.
+    pub fn folded_stacks(&self) -> String {
+        let mut stacks: HashMap<String, f64> = HashMap::new();
+        for event in self.events() {
+            let mut stack: Vec<String> = event.stack.iter().map(ToString::to_string).collect();
+            stack.push(event.key.to_string());
+            *stacks.entry(stack.join(";")).or_default() += event.self_time.as_secs_f64() * 1e6;
+        }
+        let mut stacks: Vec<_> = stacks.into_iter().collect();
+        stacks.sort_by(|(a, _), (b, _)| a.cmp(b));
+        (stacks.into_iter())
+            .map(|(stack, us)| format!("{stack} {}\n", us.round() as u64))
+            .collect()
+    }
+}
+
+/// A key for a function bound in a previous run
+#[derive(PartialEq, Eq, Hash)]
+struct BindingKey {
+    /// The span of the definition, which includes its path and position
+    span: CodeSpan,
+    text: String,
+    referenced: Vec<Option<Value>>,
+    /// The keys of the definitions of the macros that are expanded
+    macros: Vec<Option<BindingKey>>,
+}
+
+/// Functions bound in previous runs, for reuse by [`Uiua::reload_str`]
+///
+/// Only the functions bound in the latest run are kept,
+/// so the cache does not grow as the code is edited.
+#[derive(Default)]
+struct FunctionCache {
+    /// Functions bound in the current run
+    current: HashMap<BindingKey, Value>,
+    /// Functions bound in the previous run
+    previous: HashMap<BindingKey, Value>,
+}
+
+impl FunctionCache {
+    fn get(&mut self, key: &BindingKey) -> Option<Value> {
+        if let Some(val) = self.current.get(key) {
+            return Some(val.clone());
+        }
+        let (key, val) = self.previous.remove_entry(key)?;
+        self.current.insert(key, val.clone());
+        Some(val)
+    }
+    /// Start a new run, forgetting the functions not bound in the latest one
+    fn start_run(&mut self) {
+        self.previous = take(&mut self.current);
+    }
+}
+
+/// The span and shape of a literal operand
+type LiteralOperand = (CodeSpan, Vec<usize>);
+
+/// The top-level lines of a program being compiled without running them
+#[derive(Clone, Default)]
+struct ProgramBuilder {
+    lines: Vec<Function>,
+    /// The height of the stack after the lines so far, if it is known
+    height: Option<usize>,
+    /// Whether the program was found to need running to be compiled
+    unsupported: bool,
+}
+
+/// Collect the identifiers and the names of the macros used in some words
+fn referenced_idents(words: &[Sp<Word>], idents: &mut Vec<Sp<Ident>>, macros: &mut Vec<Ident>) {
+    for word in words {
+        match &word.value {
+            Word::Ident(ident) => idents.push(word.span.clone().sp(ident.clone())),
+            Word::Strand(items) => referenced_idents(items, idents, macros),
+            Word::Array(arr) => {
+                for line in &arr.lines {
+                    referenced_idents(line, idents, macros);
+                }
+            }
+            Word::Func(func) => {
+                for line in &func.lines {
+                    referenced_idents(line, idents, macros);
+                }
+            }
+            Word::Modified(m) => referenced_idents(&m.operands, idents, macros),
+            Word::MacroCall(call) => {
+                macros.push(call.name.value.clone());
+                referenced_idents(&call.operands, idents, macros);
+            }
+            Word::LocalBinding(binding) => referenced_idents(&binding.words, idents, macros),
+            _ => {}
+        }
+    }
 }
 
 #[derive(Clone)]
@@ -60,6 +518,8 @@ pub struct Scope {
     call: Vec<StackFrame>,
     /// Map local names to global indices
     names: HashMap<Ident, usize>,
+    /// Map local names to macros
+    macros: HashMap<Ident, Arc<Macro>>,
     /// Whether this scope is local
     local: bool,
     /// The current fill values
@@ -80,14 +540,40 @@ impl Default for Scope {
                 call_span: 0,
                 pc: 0,
                 spans: Vec::new(),
+                tail_calls: 0,
+                repeats: 0,
             }],
             names: HashMap::new(),
+            macros: HashMap::new(),
             local: false,
             fills: Fills::default(),
         }
     }
 }
 
+/// A macro, which is expanded where it is used
+struct Macro {
+    /// The span of the macro's definition
+    span: CodeSpan,
+    words: Vec<Sp<Word>>,
+    operands: usize,
+    /// The names in scope where the macro was defined
+    ///
+    /// Names in the macro's words refer to these, so that they
+    /// cannot be captured by bindings where the macro is used.
+    names: HashMap<Ident, usize>,
+    macros: HashMap<Ident, Arc<Macro>>,
+}
+
+/// A macro operand, compiled where the macro is used
+#[derive(Clone)]
+struct MacroOperand {
+    /// The instructions for when the operand is called
+    called: Vec<Instr>,
+    /// The instructions for when the operand is passed as a function
+    value: Vec<Instr>,
+}
+
 #[derive(Default, Clone)]
 struct Fills {
     nums: Vec<f64>,
@@ -105,6 +591,59 @@ struct StackFrame {
     pc: usize,
     /// Additional spans for error reporting
     spans: Vec<(usize, Option<Primitive>)>,
+    /// The number of times the function has tail-recurred into this frame
+    tail_calls: usize,
+    /// The number of times the function will be rerun in this frame after it finishes
+    repeats: usize,
+}
+
+/// Get the address of the current position in the host's stack
+#[inline(never)]
+fn stack_position() -> usize {
+    let marker = 0u8;
+    std::hint::black_box(&marker) as *const u8 as usize
+}
+
+/// Get the lowest address the current thread's stack can grow to
+fn stack_limit() -> Option<usize> {
+    thread_local! {
+        static LIMIT: Option<usize> = find_stack_limit();
+    }
+    LIMIT.with(|limit| *limit)
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn find_stack_limit() -> Option<usize> {
+    // SAFETY: the attributes are initialized by `pthread_getattr_np` before use
+    // and destroyed afterwards
+    unsafe {
+        let mut attr: libc::pthread_attr_t = std::mem::zeroed();
+        if libc::pthread_getattr_np(libc::pthread_self(), &mut attr) != 0 {
+            return None;
+        }
+        let mut addr = std::ptr::null_mut();
+        let mut size = 0;
+        let res = libc::pthread_attr_getstack(&attr, &mut addr, &mut size);
+        libc::pthread_attr_destroy(&mut attr);
+        (res == 0).then_some(addr as usize)
+    }
+}
+
+#[cfg(target_os = "macos")]
+fn find_stack_limit() -> Option<usize> {
+    // SAFETY: these only query the current thread
+    unsafe {
+        let thread = libc::pthread_self();
+        let top = libc::pthread_get_stackaddr_np(thread) as usize;
+        Some(top.saturating_sub(libc::pthread_get_stacksize_np(thread)))
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android", target_os = "macos")))]
+fn find_stack_limit() -> Option<usize> {
+    // Assume the smallest common stack size, counting from the first call
+    const ASSUMED_STACK_SIZE: usize = 1024 * 1024;
+    Some(stack_position().saturating_sub(ASSUMED_STACK_SIZE))
 }
 
 impl Default for Uiua {
@@ -155,17 +694,50 @@ impl Uiua {
             higher_scopes: Vec::new(),
             globals: Arc::new(Mutex::new(globals)),
             new_functions: Vec::new(),
+            macro_operands: Vec::new(),
+            local_names: Vec::new(),
+            locals: Vec::new(),
             current_imports: Arc::new(Mutex::new(HashSet::new())),
             imports: Arc::new(Mutex::new(HashMap::new())),
+            import_sources: Arc::new(HashMap::new()),
+            binding_spans: Arc::new(Mutex::new(HashMap::new())),
+            operand_spans: Arc::new(Mutex::new(HashMap::new())),
+            prim_operands: None,
+            test_results: Vec::new(),
+            diagnostics: Arc::new(Mutex::new(Vec::new())),
+            function_cache: None,
+            program: None,
             mode: RunMode::Normal,
             backend: Arc::new(NativeSys),
+            step_handler: None,
+            profile: None,
+            rng: Arc::new(AtomicU64::new(NativeSys.random_seed())),
+            deterministic: None,
             execution_limit: None,
             execution_start: 0.0,
+            fuel: None,
+            instruction_limit: None,
+            memory_limit: None,
+            max_call_depth: DEFAULT_MAX_CALL_DEPTH,
+            call_stack_base: 0,
+            interrupt: InterruptHandle::default(),
+            slice_end: None,
+            resumable_height: None,
+            suspended: false,
+            capabilities: enum_iterator::all().collect(),
+            parallel: true,
+            fuse: true,
+            fold: true,
+            fold_fuel: FOLD_FUEL,
+            gpu: false,
+            gpu_single_precision: false,
+            display_budget: DEFAULT_DISPLAY_BUDGET,
         }
     }
     /// Create a new Uiua runtime with a custom IO backend
     pub fn with_backend(backend: impl SysBackend) -> Self {
         Uiua {
+            rng: Arc::new(AtomicU64::new(backend.random_seed())),
             backend: Arc::new(backend),
             ..Default::default()
         }
@@ -176,11 +748,328 @@ impl Uiua {
     pub fn downcast_backend<T: SysBackend>(&self) -> Option<&T> {
         self.backend.any().downcast_ref()
     }
+    /// Call a function before each instruction is executed
+    ///
+    /// This lets a debugger single-step a program. The handler can inspect
+    /// the runtime with methods like [`Uiua::stack`], [`Uiua::next_span`],
+    /// and [`Uiua::call_frames`]. Returning an error aborts the program.
+    ///
+    /// The handler is not called while it is running, so it may run code
+    /// itself. It is also not called in spawned threads.
+    pub fn with_step_handler(
+        mut self,
+        handler: impl FnMut(&mut Uiua) -> UiuaResult + Send + 'static,
+    ) -> Self {
+        self.step_handler = Some(Arc::new(Mutex::new(handler)));
+        self
+    }
+    /// Record the time spent in each primitive and binding
+    ///
+    /// The recorded times can be retrieved with [`Uiua::profile`].
+    pub fn with_profiling(mut self, profiling: bool) -> Self {
+        self.profile = profiling.then(Default::default);
+        self
+    }
+    /// Record the time spent in each primitive and binding, along with a
+    /// timeline of every call
+    ///
+    /// The timeline can be exported with [`Profile::chrome_trace`] or
+    /// [`Profile::folded_stacks`]. It grows with every call, so it uses much
+    /// more memory than [`Uiua::with_profiling`].
+    pub fn with_timeline(mut self, timeline: bool) -> Self {
+        self.profile = timeline.then(|| Arc::new(Mutex::new(Profile::with_timeline())));
+        self
+    }
+    /// Get the profile recorded so far, if profiling is enabled
+    pub fn profile(&self) -> Option<Profile> {
+        self.profile.as_ref().map(|profile| profile.lock().clone())
+    }
     /// Limit the execution duration
     pub fn with_execution_limit(mut self, limit: Duration) -> Self {
         self.execution_limit = Some(limit.as_millis() as f64);
         self
     }
+    /// Limit the number of instructions that may be executed in each run
+    ///
+    /// Unlike an execution limit, this stops a program at the same point
+    /// every time it is run. The runtime's fuel is refilled to the limit
+    /// whenever code is loaded.
+    pub fn with_instruction_limit(mut self, limit: u64) -> Self {
+        self.instruction_limit = Some(limit);
+        self.fuel = Some(Arc::new(AtomicU64::new(limit)));
+        self
+    }
+    /// Give the runtime an amount of fuel
+    ///
+    /// Each instruction uses one unit of fuel. Running out results in a
+    /// [`UiuaError::OutOfFuel`], which can be caught, but any instruction
+    /// run after that fails the same way until more fuel is added with
+    /// [`Uiua::add_fuel`]. Unlike [`Uiua::with_instruction_limit`], fuel is
+    /// not refilled when code is loaded.
+    pub fn with_fuel(mut self, fuel: u64) -> Self {
+        self.instruction_limit = None;
+        self.fuel = Some(Arc::new(AtomicU64::new(fuel)));
+        self
+    }
+    /// Get the remaining fuel, if fuel is limited
+    pub fn fuel(&self) -> Option<u64> {
+        (self.fuel.as_ref()).map(|fuel| fuel.load(atomic::Ordering::Relaxed))
+    }
+    /// Add fuel, if fuel is limited
+    pub fn add_fuel(&self, fuel: u64) {
+        if let Some(remaining) = &self.fuel {
+            _ = remaining.fetch_update(atomic::Ordering::Relaxed, atomic::Ordering::Relaxed, |f| {
+                Some(f.saturating_add(fuel))
+            });
+        }
+    }
+    /// Get a handle that can interrupt the runtime from another thread
+    pub fn interrupt_handle(&self) -> InterruptHandle {
+        self.interrupt.clone()
+    }
+    /// Limit the number of nested function calls
+    ///
+    /// Exceeding the limit results in an error that shows the chain of calls.
+    /// The default is 1000.
+    ///
+    /// Each nested call also uses some of the host's stack. Running low on
+    /// stack is an error as well, regardless of this limit, so deep recursion
+    /// fails instead of crashing even on small stacks.
+    ///
+    /// Tail calls with [`Primitive::Recur`] do not count toward the limit.
+    pub fn with_max_call_depth(mut self, depth: usize) -> Self {
+        self.max_call_depth = depth;
+        self
+    }
+    /// Limit the total size in bytes of the arrays held by the program
+    ///
+    /// This counts the arrays on the stack and in bindings. Creating an array
+    /// that would bring the total over the limit results in a catchable error
+    /// rather than an attempt to allocate it.
+    pub fn with_memory_limit(mut self, bytes: usize) -> Self {
+        self.memory_limit = Some(bytes);
+        self
+    }
+    /// Check that an array of the given size in bytes fits in the memory limit
+    /// alongside the arrays the runtime already holds
+    pub(crate) fn check_memory(&self, bytes: usize) -> UiuaResult {
+        let Some(limit) = self.memory_limit else {
+            return Ok(());
+        };
+        if bytes > limit {
+            return Err(self.error(format!(
+                "Memory limit exceeded: an array of {bytes} bytes \
+                would exceed the limit of {limit} bytes"
+            )));
+        }
+        let used = self.memory_used();
+        if used.saturating_add(bytes) > limit {
+            return Err(self.error(format!(
+                "Memory limit exceeded: an array of {bytes} bytes \
+                would exceed the limit of {limit} bytes \
+                with {used} bytes already in use"
+            )));
+        }
+        Ok(())
+    }
+    /// Get the total size in bytes of the arrays on the stack and in bindings
+    fn memory_used(&self) -> usize {
+        let size = |val: &Value| val.flat_len().saturating_mul(val.element_size());
+        let globals = self.globals.lock();
+        (self.stack.iter().chain(&self.locals).chain(globals.iter()))
+            .map(size)
+            .fold(0, usize::saturating_add)
+    }
+    /// Set whether system access is forbidden
+    ///
+    /// In a sandbox, no [`Capability`] is granted, so system functions that
+    /// access the filesystem, the network, or the process environment fail
+    /// with an error that can be caught with ⍣ try. Printing, reading stdin,
+    /// and sleeping are still permitted.
+    ///
+    /// Turning the sandbox off grants every capability.
+    ///
+    /// Default is `false`
+    pub fn with_sandbox(mut self, sandboxed: bool) -> Self {
+        self.capabilities = if sandboxed {
+            HashSet::new()
+        } else {
+            enum_iterator::all().collect()
+        };
+        self
+    }
+    /// Check whether system access is forbidden
+    pub fn sandboxed(&self) -> bool {
+        self.capabilities.is_empty()
+    }
+    /// Grant exactly the given capabilities
+    ///
+    /// Default is every capability
+    pub fn with_capabilities(mut self, capabilities: impl IntoIterator<Item = Capability>) -> Self {
+        self.capabilities = capabilities.into_iter().collect();
+        self
+    }
+    /// Grant or withhold a single capability
+    pub fn with_capability(mut self, capability: Capability, granted: bool) -> Self {
+        if granted {
+            self.capabilities.insert(capability);
+        } else {
+            self.capabilities.remove(&capability);
+        }
+        self
+    }
+    /// Check whether a capability is granted
+    pub fn has_capability(&self, capability: Capability) -> bool {
+        self.capabilities.contains(&capability)
+    }
+    /// Set whether looping modifiers may run iterations in parallel
+    ///
+    /// This only has an effect if the `parallel` feature is enabled.
+    /// Disabling it can be useful for deterministic debugging.
+    ///
+    /// Default is `true`
+    pub fn with_parallel(mut self, parallel: bool) -> Self {
+        self.parallel = parallel;
+        self
+    }
+    /// Check whether looping modifiers may run iterations in parallel
+    pub fn parallel(&self) -> bool {
+        self.parallel && self.deterministic.is_none()
+    }
+    /// Make execution reproducible from a seed
+    ///
+    /// ⚂ random is seeded with `seed`, and &n now reads a virtual clock that
+    /// starts at 0 and only advances when &sl sleep is called. Sleeping does
+    /// not block. Spawned threads run to completion as soon as they are
+    /// spawned, and looping modifiers do not run iterations in parallel.
+    pub fn with_deterministic(mut self, seed: u64) -> Self {
+        self.rng = Arc::new(AtomicU64::new(seed));
+        self.deterministic = Some(Default::default());
+        self
+    }
+    /// Check whether execution is deterministic
+    pub fn is_deterministic(&self) -> bool {
+        self.deterministic.is_some()
+    }
+    /// Get the current time in seconds
+    pub(crate) fn now(&self) -> f64 {
+        match &self.deterministic {
+            Some(det) => det.lock().clock,
+            None => self.backend.now(),
+        }
+    }
+    /// Get the time in seconds from a monotonic clock
+    pub(crate) fn monotonic_now(&self) -> f64 {
+        match &self.deterministic {
+            Some(det) => det.lock().clock,
+            None => self.backend.monotonic_now(),
+        }
+    }
+    /// Sleep for some number of seconds
+    ///
+    /// Long sleeps are split up so that an interrupt can stop them.
+    pub(crate) fn sleep(&self, seconds: f64) -> UiuaResult {
+        const CHUNK: f64 = 0.05;
+        if seconds.is_nan() || seconds < 0.0 {
+            return Err(self.error(format!(
+                "Sleep time must be a non-negative number, but it is {seconds}"
+            )));
+        }
+        if let Some(det) = &self.deterministic {
+            det.lock().clock += seconds;
+            return Ok(());
+        }
+        let end = self.backend.monotonic_now() + seconds;
+        loop {
+            self.check_interrupt()?;
+            self.check_execution_limit()?;
+            let remaining = end - self.backend.monotonic_now();
+            if remaining <= 0.0 {
+                return Ok(());
+            }
+            (self.backend)
+                .sleep(remaining.min(CHUNK))
+                .map_err(|e| self.error(e))?;
+        }
+    }
+    /// Set whether common instruction sequences are fused into dedicated instructions
+    ///
+    /// Disabling fusion makes compiled functions match their source more closely,
+    /// which can be useful when debugging the interpreter.
+    ///
+    /// Default is `true`
+    pub fn with_fusion(mut self, fuse: bool) -> Self {
+        self.fuse = fuse;
+        self
+    }
+    /// Set whether pure primitives applied to constants are evaluated when compiling
+    ///
+    /// Disabling folding makes every operation in the source run at runtime,
+    /// which can be useful when debugging the interpreter.
+    ///
+    /// Default is `true`
+    pub fn with_folding(mut self, fold: bool) -> Self {
+        self.fold = fold;
+        self
+    }
+    /// Set the maximum number of rows shown along each axis when showing arrays
+    ///
+    /// Longer axes show their first and last rows with an ellipsis between them.
+    ///
+    /// Default is `100`
+    pub fn with_display_budget(mut self, budget: usize) -> Self {
+        self.display_budget = budget;
+        self
+    }
+    /// Get the maximum number of rows shown along each axis when showing arrays
+    pub fn display_budget(&self) -> usize {
+        self.display_budget
+    }
+    /// Set the source of a file so that importing it does not read it
+    ///
+    /// Executables made with `uiua build` carry the files they import this way.
+    pub fn with_import_source(
+        mut self,
+        path: impl Into<PathBuf>,
+        source: impl Into<String>,
+    ) -> Self {
+        Arc::make_mut(&mut self.import_sources).insert(path.into(), source.into());
+        self
+    }
+    /// Get the source of a file set with [`Uiua::with_import_source`]
+    pub fn import_source(&self, path: impl AsRef<Path>) -> Option<&str> {
+        self.import_sources.get(path.as_ref()).map(String::as_str)
+    }
+    /// Set whether large array operations may be offloaded to the GPU
+    ///
+    /// This only has an effect if the `gpu` feature is enabled.
+    /// Offloaded operations are computed in double precision.
+    /// GPUs that do not support it are not used unless
+    /// [`Uiua::with_gpu_single_precision`] is set.
+    ///
+    /// Default is `false`
+    pub fn with_gpu(mut self, gpu: bool) -> Self {
+        self.gpu = gpu;
+        self
+    }
+    /// Check whether large array operations may be offloaded to the GPU
+    pub fn gpu(&self) -> bool {
+        self.gpu
+    }
+    /// Set whether operations offloaded to the GPU may be computed in single precision
+    ///
+    /// This allows GPUs without double precision support to be used,
+    /// but results may differ from those computed on the CPU.
+    ///
+    /// Default is `false`
+    pub fn with_gpu_single_precision(mut self, single_precision: bool) -> Self {
+        self.gpu_single_precision = single_precision;
+        self
+    }
+    /// Check whether operations offloaded to the GPU may be computed in single precision
+    pub fn gpu_single_precision(&self) -> bool {
+        self.gpu_single_precision
+    }
     /// Set the [`RunMode`]
     ///
     /// Default is [`RunMode::Normal`]
@@ -202,10 +1091,239 @@ impl Uiua {
     pub fn load_str(&mut self, input: &str) -> UiuaResult<&mut Self> {
         self.load_impl(input, None)
     }
+    /// Run edited code in place of the code previously run by this runtime
+    ///
+    /// The stack and bindings are reset first. Function bindings whose text,
+    /// position, and referenced bindings have not changed since the last run
+    /// reuse their compiled function instead of being compiled again, which
+    /// keeps rerunning code after small edits fast.
+    pub fn reload_str(&mut self, input: &str) -> UiuaResult<&mut Self> {
+        (self.function_cache.get_or_insert_with(Default::default))
+            .lock()
+            .start_run();
+        self.stack.clear();
+        self.higher_scopes.clear();
+        self.scope = Scope::default();
+        for (i, def) in CONSTANTS.iter().enumerate() {
+            self.scope.names.insert(def.name.into(), i);
+        }
+        self.globals.lock().truncate(CONSTANTS.len());
+        self.binding_spans.lock().clear();
+        self.load_str(input)
+    }
     /// Load a Uiua file from a string with a path for error reporting
     pub fn load_str_path<P: AsRef<Path>>(&mut self, input: &str, path: P) -> UiuaResult<&mut Self> {
         self.load_impl(input, Some(path.as_ref()))
     }
+    /// Compile a program so that it can be run later with [`Uiua::run_compiled`]
+    ///
+    /// Top-level lines are compiled but not run. Bindings that are evaluated
+    /// when they are bound are evaluated now, so programs with such bindings
+    /// that have side effects or take values from the stack cannot be compiled
+    /// ahead of time, and `None` is returned for them. Scopes cannot be compiled
+    /// ahead of time either.
+    pub fn compile_program(
+        &mut self,
+        input: &str,
+        path: Option<&Path>,
+    ) -> UiuaResult<Option<Vec<u8>>> {
+        self.program = Some(ProgramBuilder {
+            height: Some(self.stack.len()),
+            ..Default::default()
+        });
+        let res = self.load_impl(input, path).map(drop);
+        let program = self.program.take().unwrap();
+        match res {
+            Err(_) if program.unsupported => return Ok(None),
+            Err(e) => return Err(e),
+            Ok(_) => {}
+        }
+        let spans = self.spans.lock();
+        let mut enc = Encoder::new(&spans);
+        enc.count(program.lines.len());
+        for line in &program.lines {
+            // Dynamic functions cannot be encoded
+            if enc.function(line).is_err() {
+                return Ok(None);
+            }
+        }
+        Ok(Some(enc.finish()))
+    }
+    /// Run a program compiled with [`Uiua::compile_program`]
+    pub fn run_compiled(&mut self, bytes: &[u8]) -> UiuaResult<&mut Self> {
+        self.start_run();
+        let decoded = (|| {
+            let mut dec = Decoder::new(bytes, |span| self.add_span(span))?;
+            let count = dec.count()?;
+            let mut lines = Vec::with_capacity(count);
+            for _ in 0..count {
+                lines.push(dec.function()?);
+            }
+            if !dec.is_done() {
+                return Err("Compiled Uiua has trailing data".to_string());
+            }
+            Ok(lines)
+        })();
+        for line in decoded.map_err(|e| self.error(e))? {
+            self.exec_global_instrs(line.instrs)?;
+        }
+        Ok(self)
+    }
+    /// Stop compiling a program ahead of time because it must be run to be compiled
+    fn unsupported_ahead_of_time(&mut self, reason: impl Into<String>) -> UiuaError {
+        self.program.as_mut().unwrap().unsupported = true;
+        self.error(reason.into())
+    }
+    /// Encode a compiled function so that it can be loaded later with [`Uiua::load_function`]
+    ///
+    /// Dynamic functions cannot be encoded.
+    pub fn store_function(&self, f: &Function) -> UiuaResult<Vec<u8>> {
+        let encoded = {
+            let spans = self.spans.lock();
+            let mut enc = Encoder::new(&spans);
+            enc.function(f).map(|_| enc.finish())
+        };
+        encoded.map_err(|e| self.error(e))
+    }
+    /// Load a function encoded with [`Uiua::store_function`]
+    pub fn load_function(&self, bytes: &[u8]) -> UiuaResult<Function> {
+        let decoded = (|| {
+            let mut dec = Decoder::new(bytes, |span| self.add_span(span))?;
+            let f = dec.function()?;
+            if !dec.is_done() {
+                return Err("Compiled Uiua has trailing data".to_string());
+            }
+            Ok(f)
+        })();
+        decoded.map_err(|e| self.error(e))
+    }
+    /// Encode the values of all bindings in the current scope
+    ///
+    /// The bindings can be loaded into another runtime with [`Uiua::load_bindings`],
+    /// which is much faster than compiling the code that created them.
+    pub fn store_bindings(&self) -> UiuaResult<Vec<u8>> {
+        let mut names: Vec<_> = self
+            .scope
+            .names
+            .iter()
+            .filter(|(_, idx)| **idx >= CONSTANTS.len())
+            .collect();
+        names.sort_by_key(|(_, idx)| **idx);
+        let encoded = (|| -> Result<Vec<u8>, String> {
+            let spans = self.spans.lock();
+            let globals = self.globals.lock();
+            let mut enc = Encoder::new(&spans);
+            enc.count(names.len());
+            for (name, &idx) in names {
+                enc.name(name);
+                enc.value(&globals[idx])?;
+            }
+            Ok(enc.finish())
+        })();
+        encoded.map_err(|e| self.error(e))
+    }
+    /// Load bindings encoded with [`Uiua::store_bindings`] into the current scope
+    pub fn load_bindings(&mut self, bytes: &[u8]) -> UiuaResult {
+        let decoded = (|| {
+            let mut dec = Decoder::new(bytes, |span| self.add_span(span))?;
+            let count = dec.count()?;
+            let mut bindings = Vec::with_capacity(count);
+            for _ in 0..count {
+                bindings.push((dec.name()?, dec.value()?));
+            }
+            if !dec.is_done() {
+                return Err("Compiled Uiua has trailing data".to_string());
+            }
+            Ok(bindings)
+        })();
+        let bindings = decoded.map_err(|e| self.error(e))?;
+        let mut globals = self.globals.lock();
+        for (name, val) in bindings {
+            let idx = globals.len();
+            globals.push(val);
+            self.scope.names.insert(name, idx);
+        }
+        Ok(())
+    }
+    /// Encode the runtime state so that it can be restored with [`Uiua::load_state`]
+    ///
+    /// The state is the stack, the bindings in the current scope, and the
+    /// state of the random number generator.
+    pub fn store_state(&self) -> UiuaResult<Vec<u8>> {
+        let mut names: Vec<_> = self
+            .scope
+            .names
+            .iter()
+            .filter(|(_, idx)| **idx >= CONSTANTS.len())
+            .collect();
+        names.sort_by_key(|(_, idx)| **idx);
+        let encoded = (|| -> Result<Vec<u8>, String> {
+            let spans = self.spans.lock();
+            let globals = self.globals.lock();
+            let mut enc = Encoder::new(&spans);
+            enc.count(names.len());
+            for (name, &idx) in names {
+                enc.name(name);
+                enc.value(&globals[idx])?;
+            }
+            enc.count(self.stack.len());
+            for val in &self.stack {
+                enc.value(val)?;
+            }
+            enc.u64(self.rng.load(atomic::Ordering::Relaxed));
+            Ok(enc.finish())
+        })();
+        encoded.map_err(|e| self.error(e))
+    }
+    /// Restore runtime state encoded with [`Uiua::store_state`]
+    ///
+    /// The stack is replaced, and the bindings are added to the current scope.
+    pub fn load_state(&mut self, bytes: &[u8]) -> UiuaResult {
+        let decoded = (|| {
+            let mut dec = Decoder::new(bytes, |span| self.add_span(span))?;
+            let count = dec.count()?;
+            let mut bindings = Vec::with_capacity(count);
+            for _ in 0..count {
+                bindings.push((dec.name()?, dec.value()?));
+            }
+            let count = dec.count()?;
+            let mut stack = Vec::with_capacity(count);
+            for _ in 0..count {
+                stack.push(dec.value()?);
+            }
+            let rng = dec.u64()?;
+            if !dec.is_done() {
+                return Err("There is more data after the random number state".to_string());
+            }
+            Ok((bindings, stack, rng))
+        })();
+        let (bindings, stack, rng) =
+            decoded.map_err(|e| self.error(format!("Runtime state is not valid: {e}")))?;
+        let mut globals = self.globals.lock();
+        for (name, val) in bindings {
+            let idx = globals.len();
+            globals.push(val);
+            self.scope.names.insert(name, idx);
+        }
+        drop(globals);
+        self.stack = stack;
+        self.rng.store(rng, atomic::Ordering::Relaxed);
+        Ok(())
+    }
+    /// Generate a random number in the range `[0, 1)`
+    pub(crate) fn random(&self) -> f64 {
+        let mut val = 0.0;
+        _ = self.rng.fetch_update(
+            atomic::Ordering::Relaxed,
+            atomic::Ordering::Relaxed,
+            |state| {
+                let mut rng = SmallRng::seed_from_u64(state);
+                val = rng.gen();
+                Some(rng.gen())
+            },
+        );
+        val
+    }
     /// Run in a scoped context. Names defined in this context will be removed when the scope ends.
     ///
     /// While names defined in this context will be removed when the scope ends, values *bound* to
@@ -220,13 +1338,26 @@ impl Uiua {
         self.higher_scopes.push(take(&mut self.scope));
         self.scope.local = local;
         let start_height = self.stack.len();
-        f(self)?;
+        let res = f(self);
         let end_height = self.stack.len();
         self.scope = self.higher_scopes.pop().unwrap();
+        res?;
         Ok(self.stack.split_off(start_height.min(end_height)))
     }
-    fn load_impl(&mut self, input: &str, path: Option<&Path>) -> UiuaResult<&mut Self> {
+    /// Start a run, unless code is being imported
+    fn start_run(&mut self) {
         self.execution_start = instant::now();
+        if !self.higher_scopes.is_empty() {
+            return;
+        }
+        if let (Some(limit), Some(fuel)) = (self.instruction_limit, &self.fuel) {
+            fuel.store(limit, atomic::Ordering::Relaxed);
+        }
+        self.fold_fuel = FOLD_FUEL;
+        self.interrupt.0.store(false, atomic::Ordering::Relaxed);
+    }
+    fn load_impl(&mut self, input: &str, path: Option<&Path>) -> UiuaResult<&mut Self> {
+        self.start_run();
         let (items, errors) = parse(input, path);
         if !errors.is_empty() {
             return Err(errors.into());
@@ -234,6 +1365,7 @@ impl Uiua {
         if let Some(path) = path {
             self.current_imports.lock().insert(path.into());
         }
+        let first_global = self.globals.lock().len();
         let res = match catch_unwind(AssertUnwindSafe(|| self.items(items, false))) {
             Ok(res) => res,
             Err(_) => Err(self.error(format!(
@@ -252,8 +1384,41 @@ code:
         if let Some(path) = path {
             self.current_imports.lock().remove(path);
         }
+        self.diagnose_unused(first_global, path);
         res.map(|_| self)
     }
+    /// Report bindings defined in a file since some global index that are never used
+    fn diagnose_unused(&self, first_global: usize, path: Option<&Path>) {
+        let mut unused: Vec<_> = (self.binding_spans.lock().iter())
+            .filter(|(&idx, spans)| {
+                idx >= first_global
+                    && spans.uses.is_empty()
+                    && spans.definition.path.as_deref() == path
+            })
+            .map(|(_, spans)| spans.definition.clone())
+            .collect();
+        unused.sort_by_key(|span| span.start);
+        for span in unused {
+            self.diagnose(
+                DiagnosticKind::UnusedBinding,
+                format!("Binding `{}` is never used", span.as_str()),
+                span,
+            );
+        }
+    }
+    fn diagnose(&self, kind: DiagnosticKind, message: impl Into<String>, span: CodeSpan) {
+        (self.diagnostics.lock()).push(Diagnostic::new(kind, message, span));
+    }
+    /// Get the non-fatal problems found while compiling so far
+    ///
+    /// Unlike errors, diagnostics do not stop code from running.
+    pub fn diagnostics(&self) -> Vec<Diagnostic> {
+        self.diagnostics.lock().clone()
+    }
+    /// Take the non-fatal problems found while compiling so far
+    pub fn take_diagnostics(&mut self) -> Vec<Diagnostic> {
+        take(&mut *self.diagnostics.lock())
+    }
     fn trace_error(&self, mut error: UiuaError, frame: StackFrame) -> UiuaError {
         let mut frames = Vec::new();
         for (span, prim) in &frame.spans {
@@ -292,9 +1457,128 @@ code:
         self.stack.extend(self.imports.lock()[path].iter().cloned());
         Ok(())
     }
+    /// Start running a string of code a slice at a time
+    pub fn start_str(&mut self, input: &str) -> UiuaResult<Execution> {
+        self.start_impl(input, None)
+    }
+    /// Start running a file a slice at a time
+    pub fn start_file<P: AsRef<Path>>(&mut self, path: P) -> UiuaResult<Execution> {
+        let path = path.as_ref();
+        let input = fs::read_to_string(path).map_err(|e| UiuaError::Load(path.into(), e.into()))?;
+        self.start_impl(&input, Some(path))
+    }
+    fn start_impl(&mut self, input: &str, path: Option<&Path>) -> UiuaResult<Execution> {
+        self.start_run();
+        let (items, errors) = parse(input, path);
+        if !errors.is_empty() {
+            return Err(errors.into());
+        }
+        Ok(Execution {
+            items: items.into(),
+            path: path.map(Into::into),
+            doc: None,
+            first_global: self.globals.lock().len(),
+            suspended: None,
+            finished: false,
+        })
+    }
+    /// Run a program started with [`Uiua::start_str`] or [`Uiua::start_file`]
+    /// for about a slice of time
+    ///
+    /// Returns whether the program finished. If it did not, call this again
+    /// to keep running it. An error also finishes the program.
+    pub fn resume(&mut self, execution: &mut Execution, slice: Duration) -> UiuaResult<bool> {
+        if execution.finished {
+            return Ok(true);
+        }
+        let path = execution.path.clone();
+        if let Some(path) = &path {
+            self.current_imports.lock().insert(path.clone());
+        }
+        self.slice_end = Some(instant::now() + slice.as_secs_f64() * 1000.0);
+        let res = self.resume_impl(execution);
+        self.slice_end = None;
+        self.resumable_height = None;
+        self.suspended = false;
+        if let Some(path) = &path {
+            self.current_imports.lock().remove(path);
+        }
+        if !matches!(res, Ok(false)) {
+            execution.finished = true;
+            execution.suspended = None;
+            self.diagnose_unused(execution.first_global, path.as_deref());
+        }
+        res
+    }
+    fn resume_impl(&mut self, execution: &mut Execution) -> UiuaResult<bool> {
+        // Each slice runs at least one item, so that the program makes progress
+        let mut ran_item = false;
+        loop {
+            if let Some(height) = execution.suspended.take() {
+                self.resumable_height = Some(height);
+                self.run_frames(height)?;
+                if self.suspended {
+                    execution.suspended = Some(height);
+                    return Ok(false);
+                }
+                ran_item = true;
+            }
+            if execution.items.is_empty() {
+                return Ok(true);
+            }
+            if ran_item && self.slice_end.is_some_and(|end| instant::now() >= end) {
+                return Ok(false);
+            }
+            ran_item = true;
+            let item = execution.items.pop_front().unwrap();
+            // Only lines of words may be suspended partway through
+            self.resumable_height = matches!(item, Item::Words(_)).then(|| self.scope.call.len());
+            self.run_item(item, false, &mut execution.doc)?;
+            if self.suspended {
+                execution.suspended = self.resumable_height;
+                return Ok(false);
+            }
+        }
+    }
     fn items(&mut self, items: Vec<Item>, in_test: bool) -> UiuaResult {
+        let mut doc: Option<String> = None;
         for item in items {
+            self.run_item(item, in_test, &mut doc)?;
+        }
+        Ok(())
+    }
+    /// Run an item, tracking the comment lines that document bindings
+    fn run_item(&mut self, item: Item, in_test: bool, doc: &mut Option<String>) -> UiuaResult {
+        {
+            let name = match &item {
+                Item::Words(words) => {
+                    match line_comment(words) {
+                        Some(comment) => {
+                            push_doc_line(doc.get_or_insert_with(String::new), comment)
+                        }
+                        None => *doc = None,
+                    }
+                    None
+                }
+                Item::Binding(binding) => Some(binding.name.value.clone()),
+                Item::Scoped { .. } => {
+                    *doc = None;
+                    None
+                }
+                Item::ExtraNewlines(_) => None,
+            };
+            let globals_len = self.globals.lock().len();
             self.item(item, in_test)?;
+            // Comments directly above a binding document it
+            if let Some(name) = name {
+                let doc = doc.take();
+                let idx = self.scope.names.get(&name).copied();
+                if let Some(idx) = idx.filter(|&idx| idx >= globals_len) {
+                    if let Some(spans) = self.binding_spans.lock().get_mut(&idx) {
+                        spans.doc = doc;
+                    }
+                }
+            }
         }
         Ok(())
     }
@@ -305,6 +1589,11 @@ code:
                 .any(|w| matches!(w.value, Word::Primitive(Primitive::Sys(SysOp::Import))))
         }
         match item {
+            Item::Scoped { .. } if self.program.is_some() => {
+                return Err(
+                    self.unsupported_ahead_of_time("Scopes cannot be compiled ahead of time")
+                );
+            }
             Item::Scoped { items, test } => {
                 let scope_stack = self.in_scope(true, |env| env.items(items, test))?;
                 self.stack.extend(scope_stack);
@@ -315,9 +1604,45 @@ code:
                     RunMode::Test => in_test,
                     RunMode::All => true,
                 };
-                if can_run || words_have_import(&words) {
+                let code_span = (words.iter())
+                    .filter(|word| !matches!(word.value, Word::Spaces | Word::Comment(_)))
+                    .map(|word| word.span.clone())
+                    .reduce(CodeSpan::merge);
+                if let Some(span) = code_span.filter(|_| in_test && self.mode == RunMode::Test) {
+                    // Failures in tests are recorded rather than stopping execution
+                    let heights = (
+                        self.new_functions.len(),
+                        self.local_names.len(),
+                        self.scope.array.len(),
+                    );
+                    let res = (self.compile_words(words, true))
+                        .and_then(|instrs| self.exec_global_instrs(instrs));
+                    if res.is_err() {
+                        self.new_functions.truncate(heights.0);
+                        self.local_names.truncate(heights.1);
+                        self.scope.array.truncate(heights.2);
+                    }
+                    self.test_results.push(TestResult {
+                        span,
+                        error: res.err(),
+                    });
+                } else if can_run || words_have_import(&words) {
                     let instrs = self.compile_words(words, true)?;
-                    self.exec_global_instrs(instrs)?;
+                    if let Some(program) = &mut self.program {
+                        // Lines that would take more values than there are make the height unknown
+                        let sig = instrs_signature(&instrs).ok();
+                        program.height = (program.height.zip(sig))
+                            .and_then(|(height, sig)| height.checked_sub(sig.args))
+                            .map(|height| height + sig.unwrap().outputs);
+                        program.lines.push(Function::new(
+                            FunctionId::Main,
+                            instrs,
+                            FunctionKind::Normal,
+                            Signature::new(0, 0),
+                        ));
+                    } else {
+                        self.exec_global_instrs(instrs)?;
+                    }
                 }
             }
             Item::Binding(binding) => {
@@ -333,13 +1658,42 @@ code:
         }
         Ok(())
     }
-    fn add_span(&mut self, span: impl Into<Span>) -> usize {
+    fn add_span(&self, span: impl Into<Span>) -> usize {
         let mut spans = self.spans.lock();
         let idx = spans.len();
         spans.push(span.into());
         idx
     }
     fn binding(&mut self, binding: Binding) -> UiuaResult {
+        if binding.name.value.ends_with('!') {
+            return self.macro_binding(binding);
+        }
+        let cache_key = self
+            .function_cache
+            .is_some()
+            .then(|| self.binding_key(&binding));
+        if let Some(key) = &cache_key {
+            let cached = self.function_cache.as_ref().unwrap().lock().get(key);
+            // The binding would be run rather than bound if its arguments are on the stack
+            if let Some(val) = cached.filter(|val| {
+                (val.as_function()).is_some_and(|f| f.signature().args > self.stack.len())
+            }) {
+                let mut idents = Vec::new();
+                referenced_idents(&binding.words, &mut idents, &mut Vec::new());
+                for ident in idents {
+                    let name = (ident.value.split_once('~')).map_or(&*ident.value, |(m, _)| m);
+                    if let Some(idx) = self.find_name(name) {
+                        self.record_use(idx, &ident.span);
+                    }
+                }
+                let mut globals = self.globals.lock();
+                let idx = globals.len();
+                globals.push(val);
+                drop(globals);
+                self.bind_name(binding.name, idx);
+                return Ok(());
+            }
+        }
         let instrs = self.compile_words(binding.words, true)?;
         let make_fn = |instrs: Vec<Instr>, sig: Signature| {
             let func = Function::new(
@@ -350,67 +1704,289 @@ code:
             );
             Value::from(func)
         };
-        let mut val = match instrs_signature(&instrs) {
-            Ok(mut sig) => {
-                if let Some(declared_sig) = &binding.signature {
-                    if declared_sig.value.is_superset_of(sig) {
-                        sig = declared_sig.value;
-                    } else {
-                        return Err(UiuaError::Run(Span::Code(declared_sig.span.clone()).sp(
-                            format!(
-                                "Function signature mismatch: \
-                                 declared {} but inferred {}",
-                                declared_sig.value, sig
-                            ),
-                        )));
-                    }
-                }
-
-                if sig.args <= self.stack.len() {
-                    self.exec_global_instrs(instrs)?;
-                    self.stack
-                        .pop()
-                        .ok_or_else(|| self.error("Nothing on the stack to bind"))?
-                } else {
-                    make_fn(instrs, sig)
-                }
+        let (sig, inferred) =
+            verify_signature(&instrs, binding.signature.as_ref(), &binding.name.span)?;
+        if let Some(program) = &self.program {
+            // When the program runs, the binding may take values pushed by earlier lines
+            if inferred && sig.args > 0 && program.height.map_or(true, |height| sig.args <= height)
+            {
+                return Err(self.unsupported_ahead_of_time(format!(
+                    "`{}` may take values from the stack, so it cannot be compiled ahead of time",
+                    binding.name.value
+                )));
             }
-            Err(e) => {
-                if let Some(sig) = binding.signature {
-                    make_fn(instrs, sig.value)
-                } else {
-                    return Err(UiuaError::Run(
-                        Span::Code(binding.name.span.clone())
-                            .sp(format!("Cannot infer function signature: {e}")),
-                    ));
-                }
+            let pure = || {
+                Function::new(FunctionId::Main, instrs.clone(), FunctionKind::Normal, sig).is_pure()
+            };
+            if inferred && sig.args == 0 && !pure() {
+                return Err(self.unsupported_ahead_of_time(format!(
+                    "`{}` has side effects when it is bound, \
+                    so it cannot be compiled ahead of time",
+                    binding.name.value
+                )));
             }
+        }
+        // Functions whose signatures cannot be inferred are never run immediately
+        let mut val = if inferred && sig.args <= self.stack.len() {
+            self.exec_global_instrs(instrs)?;
+            self.stack
+                .pop()
+                .ok_or_else(|| self.error("Nothing on the stack to bind"))?
+        } else {
+            self.cache_function(cache_key, make_fn(instrs, sig))
         };
         val.compress();
         let mut globals = self.globals.lock();
         let idx = globals.len();
         globals.push(val);
-        self.scope.names.insert(binding.name.value, idx);
+        drop(globals);
+        self.bind_name(binding.name, idx);
         Ok(())
     }
-    fn compile_words(&mut self, words: Vec<Sp<Word>>, call: bool) -> UiuaResult<Vec<Instr>> {
+    fn bind_name(&mut self, name: Sp<Ident>, idx: usize) {
+        if let Some(prev) = self.scope.names.get(&name.value) {
+            let prev_span =
+                (self.binding_spans.lock().get(prev)).map(|spans| spans.definition.clone());
+            if let Some(prev_span) = prev_span {
+                let diagnostic = Diagnostic::new(
+                    DiagnosticKind::ShadowedName,
+                    format!("Binding `{}` shadows an earlier binding", name.value),
+                    name.span.clone(),
+                )
+                .with_label(prev_span, "previously bound here");
+                self.diagnostics.lock().push(diagnostic);
+            }
+        }
+        self.binding_spans.lock().insert(
+            idx,
+            BindingSpans {
+                definition: name.span,
+                uses: Vec::new(),
+                doc: None,
+            },
+        );
+        self.scope.names.insert(name.value, idx);
+    }
+    fn record_use(&self, idx: usize, span: &CodeSpan) {
+        if let Some(spans) = self.binding_spans.lock().get_mut(&idx) {
+            spans.uses.push(span.clone());
+        }
+    }
+    /// Get the spans where all bindings are defined and used
+    ///
+    /// This only includes bindings defined in code, not constants.
+    pub fn binding_spans(&self) -> Vec<BindingSpans> {
+        let mut spans: Vec<_> = self.binding_spans.lock().values().cloned().collect();
+        spans.sort_by_key(|spans| (spans.definition.path.clone(), spans.definition.start));
+        spans
+    }
+    /// Get the value of a binding in scope
+    ///
+    /// For function bindings, this is the function.
+    pub fn bound_value(&self, name: &str) -> Option<Value> {
+        let idx = self.find_name(name)?;
+        self.globals.lock().get(idx).cloned()
+    }
+    /// Get the names and signatures of the bindings in scope
+    ///
+    /// This does not include constants. Bindings are in the order they were bound.
+    pub fn bindings(&self) -> Vec<(Ident, Signature)> {
+        let globals = self.globals.lock();
+        let mut bindings: Vec<_> = (self.scope.names.iter())
+            .filter(|(_, idx)| **idx >= CONSTANTS.len())
+            .filter_map(|(name, idx)| Some((*idx, name.clone(), globals.get(*idx)?.signature())))
+            .collect();
+        bindings.sort_by_key(|(idx, ..)| *idx);
+        (bindings.into_iter())
+            .map(|(_, name, sig)| (name, sig))
+            .collect()
+    }
+    /// Get the documentation of a binding in scope
+    ///
+    /// A binding's documentation is the comment lines directly above it.
+    pub fn binding_doc(&self, name: &str) -> Option<String> {
+        let idx = self.find_name(name)?;
+        self.binding_spans.lock().get(&idx)?.doc.clone()
+    }
+    /// Get the span where the binding used at some span is defined
+    ///
+    /// The span may be the span of a use or any span within it.
+    /// The definition of a binding is its own definition.
+    pub fn definition(&self, span: &CodeSpan) -> Option<CodeSpan> {
+        let contains = |outer: &CodeSpan| {
+            outer.path == span.path && outer.start <= span.start && span.end <= outer.end
+        };
+        self.binding_spans
+            .lock()
+            .values()
+            .find(|spans| contains(&spans.definition) || spans.uses.iter().any(contains))
+            .map(|spans| spans.definition.clone())
+    }
+    /// Identify a binding for reuse by [`Uiua::reload_str`]
+    ///
+    /// Names are resolved when compiling, so the key includes the current
+    /// values of the names the binding refers to, and the definitions of the
+    /// macros it expands. It also includes the binding's span so that spans
+    /// in the reused function stay correct.
+    fn binding_key(&self, binding: &Binding) -> BindingKey {
+        let mut span = binding.name.span.clone();
+        if let Some(word) = binding.words.last() {
+            span = span.merge(word.span.clone());
+        }
+        self.words_key(
+            span,
+            &binding.words,
+            &|name| self.find_name(name),
+            &|name| self.find_macro(name),
+        )
+    }
+    fn words_key(
+        &self,
+        span: CodeSpan,
+        words: &[Sp<Word>],
+        find_name: &dyn Fn(&str) -> Option<usize>,
+        find_macro: &dyn Fn(&str) -> Option<Arc<Macro>>,
+    ) -> BindingKey {
+        let mut idents = Vec::new();
+        let mut macro_names = Vec::new();
+        referenced_idents(words, &mut idents, &mut macro_names);
+        let referenced = {
+            let globals = self.globals.lock();
+            (idents.iter())
+                .map(|ident| {
+                    let name = (ident.value.split_once('~')).map_or(&*ident.value, |(m, _)| m);
+                    find_name(name).map(|idx| globals[idx].clone())
+                })
+                .collect()
+        };
+        // Macro words are resolved in the scope where the macro was defined
+        let macros = (macro_names.iter())
+            .map(|name| {
+                let mac = find_macro(name)?;
+                Some(self.words_key(
+                    mac.span.clone(),
+                    &mac.words,
+                    &|name| mac.names.get(name).copied(),
+                    &|name| mac.macros.get(name).cloned(),
+                ))
+            })
+            .collect();
+        BindingKey {
+            text: span.as_str().into(),
+            span,
+            referenced,
+            macros,
+        }
+    }
+    fn cache_function(&self, key: Option<BindingKey>, val: Value) -> Value {
+        if let (Some(cache), Some(key)) = (&self.function_cache, key) {
+            cache.lock().current.insert(key, val.clone());
+        }
+        val
+    }
+    pub(crate) fn compile_words(
+        &mut self,
+        words: Vec<Sp<Word>>,
+        call: bool,
+    ) -> UiuaResult<Vec<Instr>> {
         self.new_functions.push(Vec::new());
         self.words(words, call)?;
         let instrs = self.new_functions.pop().unwrap();
         Ok(instrs)
     }
     fn words(&mut self, words: Vec<Sp<Word>>, call: bool) -> UiuaResult {
-        for word in words.into_iter().rev() {
+        self.diagnose_ambiguous_glyphs(&words);
+        let mut operands = if call {
+            literal_operands(&words)
+        } else {
+            Vec::new()
+        };
+        for (i, word) in words.into_iter().enumerate().rev() {
+            self.prim_operands = operands.get_mut(i).and_then(Option::take);
             self.word(word, call)?;
         }
         Ok(())
     }
+    /// Report names that were split into several primitives
+    fn diagnose_ambiguous_glyphs(&self, words: &[Sp<Word>]) {
+        let is_name = |word: &Sp<Word>| {
+            matches!(word.value, Word::Primitive(_))
+                && word.span.as_str().chars().all(|c| c.is_ascii_alphabetic())
+        };
+        let mut i = 0;
+        while i < words.len() {
+            let mut span = words[i].span.clone();
+            let mut end = i + 1;
+            if is_name(&words[i]) {
+                while end < words.len()
+                    && is_name(&words[end])
+                    && words[end].span.start.byte_pos == span.end.byte_pos
+                {
+                    span = span.merge(words[end].span.clone());
+                    end += 1;
+                }
+            }
+            if end - i > 1 {
+                let glyphs: String = (words[i..end].iter())
+                    .filter_map(|word| match &word.value {
+                        Word::Primitive(prim) => Some(prim.to_string()),
+                        _ => None,
+                    })
+                    .collect();
+                self.diagnose(
+                    DiagnosticKind::AmbiguousGlyph,
+                    format!("`{}` is parsed as the primitives {glyphs}", span.as_str()),
+                    span,
+                );
+            }
+            i = end;
+        }
+    }
+    fn diagnose_deprecated(&self, prim: Primitive, span: &CodeSpan) {
+        if let Some(replacement) = prim.deprecation() {
+            self.diagnose(
+                DiagnosticKind::Deprecated,
+                format!(
+                    "{} is deprecated and will be removed in a future version. \
+                    Use `{replacement}` instead.",
+                    prim.name().unwrap_or_default()
+                ),
+                span.clone(),
+            );
+        }
+    }
     /// Push an instruction to the current function being compiled
     ///
     /// Also performs some optimizations if the instruction and the previous
     /// instruction form some known pattern
     fn push_instr(&mut self, instr: Instr) {
         use Primitive::*;
+        // Dead code elimination
+        // This comes before folding, which would fold a pushed value and its pop away unnoticed
+        if let Instr::Prim(Pop, span) = instr {
+            let instrs = self.new_functions.last_mut().unwrap();
+            if eliminate_pop(instrs, span) {
+                if let Span::Code(span) = self.spans.lock()[span].clone() {
+                    self.diagnose(
+                        DiagnosticKind::AlwaysPopped,
+                        "This always pops the value pushed before it",
+                        span,
+                    );
+                }
+                return;
+            }
+        }
+        // Constant folding
+        if let (&Instr::Prim(prim, _), true) = (&instr, self.fold) {
+            let mut instrs = take(self.new_functions.last_mut().unwrap());
+            let mut fuel = self.fold_fuel;
+            let folded = fold_constant(&mut instrs, prim, self, &mut fuel);
+            self.fold_fuel = fuel;
+            *self.new_functions.last_mut().unwrap() = instrs;
+            if folded {
+                return;
+            }
+        }
         let instrs = self.new_functions.last_mut().unwrap();
         // Optimizations
         match (instrs.as_mut_slice(), instr) {
@@ -430,6 +2006,27 @@ code:
             ([.., Instr::Prim(top @ Shape, _)], Instr::Prim(Len, _)) => *top = Rank,
             // First reverse = last
             ([.., Instr::Prim(top @ Reverse, _)], Instr::Prim(First, _)) => *top = Last,
+            // Reduce add multiply = dot product
+            ([.., Instr::Prim(Mul, _), Instr::Push(f)], Instr::Prim(Reduce, span))
+                if self.fuse && f.as_flipped_primitive() == Some((Add, false)) =>
+            {
+                instrs.pop();
+                instrs.pop();
+                instrs.push(Instr::Prim(Dot, span));
+            }
+            // Keep comparison = filter
+            ([.., Instr::Prim(top @ (Eq | Ne | Lt | Le | Gt | Ge), _)], Instr::Prim(Keep, _))
+                if self.fuse =>
+            {
+                *top = match *top {
+                    Eq => KeepEq,
+                    Ne => KeepNe,
+                    Lt => KeepLt,
+                    Le => KeepLe,
+                    Gt => KeepGt,
+                    _ => KeepGe,
+                };
+            }
             (_, instr) => instrs.push(instr),
         }
     }
@@ -511,6 +2108,23 @@ code:
                     self.push_instr(Instr::Call(span));
                 }
             }
+            Word::RawString(lines) => {
+                let lines: Vec<String> = lines.into_iter().map(|line| line.value).collect();
+                self.push_instr(Instr::push(lines.join("\n")));
+            }
+            Word::GridString(lines) => {
+                let width = (lines.iter())
+                    .map(|line| line.value.chars().count())
+                    .max()
+                    .unwrap_or(0);
+                let mut data = Vec::with_capacity(lines.len() * width);
+                for line in &lines {
+                    data.extend(line.value.chars());
+                    data.extend((line.value.chars().count()..width).map(|_| ' '));
+                }
+                let shape = Shape::from_iter([lines.len(), width]);
+                self.push_instr(Instr::push(Value::from((shape, data))));
+            }
             Word::Ident(ident) => self.ident(ident, word.span, call)?,
             Word::Strand(items) => {
                 self.push_instr(Instr::BeginArray);
@@ -583,22 +2197,178 @@ code:
                 }
             }
             Word::Func(func) => self.func(func, word.span)?,
-            Word::Primitive(p) => self.primitive(p, word.span, call)?,
+            Word::Primitive(p) => {
+                self.diagnose_deprecated(p, &word.span);
+                self.primitive(p, word.span, call)?
+            }
             Word::Modified(m) => self.modified(*m, call)?,
+            Word::Placeholder(n) => {
+                let Some(operand) = self.macro_operands.last().and_then(|ops| ops.get(n)) else {
+                    return Err(UiuaError::compile(
+                        word.span,
+                        "Placeholder outside of a macro",
+                    ));
+                };
+                let instrs = if call {
+                    operand.called.clone()
+                } else {
+                    operand.value.clone()
+                };
+                for instr in instrs {
+                    self.push_instr(instr);
+                }
+            }
+            Word::MacroCall(m) => self.macro_call(*m, call)?,
+            Word::LocalBinding(binding) => {
+                if self.local_names.is_empty() {
+                    return Err(UiuaError::compile(
+                        word.span,
+                        "Local bindings are only allowed in functions",
+                    ));
+                }
+                self.words(binding.words, true)?;
+                self.push_instr(Instr::BindLocal);
+                (self.local_names.last_mut().unwrap()).push(binding.name.value);
+            }
             Word::Spaces | Word::Comment(_) => {}
         }
-        Ok(())
+        Ok(())
+    }
+    /// Find the global index of a name in scope
+    fn find_name(&self, name: &str) -> Option<usize> {
+        self.scope
+            .names
+            .get(name)
+            .or_else(|| {
+                self.higher_scopes
+                    .last()
+                    .filter(|_| self.scope.local)?
+                    .names
+                    .get(name)
+            })
+            .copied()
+    }
+    fn find_macro(&self, name: &str) -> Option<Arc<Macro>> {
+        self.scope
+            .macros
+            .get(name)
+            .or_else(|| {
+                self.higher_scopes
+                    .last()
+                    .filter(|_| self.scope.local)?
+                    .macros
+                    .get(name)
+            })
+            .cloned()
+    }
+    fn macro_binding(&mut self, binding: Binding) -> UiuaResult {
+        if let Some(sig) = binding.signature {
+            return Err(UiuaError::compile(
+                sig.span,
+                "Macros cannot have signatures",
+            ));
+        }
+        let mut names = HashMap::new();
+        let mut macros = HashMap::new();
+        if self.scope.local {
+            if let Some(higher) = self.higher_scopes.last() {
+                names = higher.names.clone();
+                macros = higher.macros.clone();
+            }
+        }
+        names.extend(self.scope.names.clone());
+        macros.extend(self.scope.macros.clone());
+        let mut span = binding.name.span.clone();
+        if let Some(word) = binding.words.last() {
+            span = span.merge(word.span.clone());
+        }
+        let mac = Macro {
+            span,
+            operands: macro_operand_count(&binding.words),
+            words: binding.words,
+            names,
+            macros,
+        };
+        (self.scope.macros).insert(binding.name.value, Arc::new(mac));
+        Ok(())
+    }
+    /// Expand a macro
+    ///
+    /// The operands are compiled in the current scope, and the macro's words
+    /// are compiled in the scope where it was defined, with placeholders
+    /// replaced by the operands.
+    fn macro_call(&mut self, macro_call: MacroCall, call: bool) -> UiuaResult {
+        let name = macro_call.name;
+        let Some(mac) = self.find_macro(&name.value) else {
+            return Err(UiuaError::compile(
+                name.span,
+                format!("Unknown macro `{}`", name.value),
+            ));
+        };
+        let mut operands = Vec::new();
+        for word in macro_call.operands {
+            if let Word::Spaces = word.value {
+                continue;
+            }
+            let called = match &word.value {
+                // Inline functions are inlined when called
+                Word::Func(func) => self.func_lines(func.lines.clone())?,
+                _ => self.compile_words(vec![word.clone()], true)?,
+            };
+            operands.push(MacroOperand {
+                called,
+                value: self.compile_words(vec![word], false)?,
+            });
+        }
+        if operands.len() < mac.operands {
+            return Err(UiuaError::compile(
+                name.span,
+                format!(
+                    "Macro `{}` takes {} operand{}, but it was given {}",
+                    name.value,
+                    mac.operands,
+                    if mac.operands == 1 { "" } else { "s" },
+                    operands.len()
+                ),
+            ));
+        }
+        let names = replace(&mut self.scope.names, mac.names.clone());
+        let macros = replace(&mut self.scope.macros, mac.macros.clone());
+        let local = replace(&mut self.scope.local, false);
+        let local_names = take(&mut self.local_names);
+        self.macro_operands.push(operands);
+        let res = self.words(mac.words.clone(), call);
+        self.macro_operands.pop();
+        self.scope.names = names;
+        self.scope.macros = macros;
+        self.scope.local = local;
+        self.local_names = local_names;
+        res
     }
     fn ident(&mut self, ident: Ident, span: CodeSpan, call: bool) -> UiuaResult {
-        if let Some(idx) = self.scope.names.get(&ident).or_else(|| {
-            self.higher_scopes
-                .last()
-                .filter(|_| self.scope.local)?
-                .names
-                .get(&ident)
-        }) {
+        if let Some((module, name)) = ident.split_once('~') {
+            return self.qualified_ident(module, name, span, call);
+        }
+        if let Some(locals) = self.local_names.last() {
+            if let Some(i) = locals.iter().rposition(|name| *name == ident) {
+                // Name is a local binding
+                self.push_instr(Instr::Local(locals.len() - 1 - i));
+                return Ok(());
+            }
+        }
+        if (self.local_names.iter().rev().skip(1)).any(|locals| locals.contains(&ident)) {
+            return Err(UiuaError::compile(
+                span,
+                format!(
+                    "`{ident}` is bound in an outer function, \
+                    so it cannot be used in this one"
+                ),
+            ));
+        }
+        if let Some(idx) = self.find_name(&ident) {
             // Name exists in scope
-            let value = self.globals.lock()[*idx].clone();
+            self.record_use(idx, &span);
+            let value = self.globals.lock()[idx].clone();
             let should_call = matches!(&value, Value::Func(f) if f.shape.is_empty());
             self.push_instr(Instr::push(value));
             if should_call && call {
@@ -612,49 +2382,86 @@ code:
             let span = self.add_span(span);
             self.push_instr(Instr::Prim(prim, span));
         } else {
-            return Err(span.sp(format!("Unknown identifier `{}`", ident)).into());
+            return Err(UiuaError::compile(
+                span,
+                format!("Unknown identifier `{}`", ident),
+            ));
         }
         Ok(())
     }
-    fn func(&mut self, func: Func, span: CodeSpan) -> UiuaResult {
-        let mut instrs = Vec::new();
-        for line in func.lines {
-            instrs.extend(self.compile_words(line, true)?);
+    /// Compile a reference to a function in a module, like `Module~Name`
+    ///
+    /// The module must be a bound array of named functions, like the ones
+    /// left on the stack by [`SysOp::Import`]. The function is resolved when
+    /// compiling, so a missing function is reported before anything runs.
+    ///
+    /// If `Module` is not a module, the `~` is compiled as [`Primitive::Trace`]
+    /// between the two names.
+    fn qualified_ident(
+        &mut self,
+        module: &str,
+        name: &str,
+        span: CodeSpan,
+        call: bool,
+    ) -> UiuaResult {
+        let is_local = self.local_names.iter().flatten().any(|l| &**l == module);
+        let found = (!is_local).then(|| self.find_name(module)).flatten();
+        let Some((idx, functions)) = found.and_then(|idx| {
+            let value = self.globals.lock()[idx].clone();
+            let functions = value.into_func_array().ok()?;
+            (functions.rank() == 1).then_some((idx, functions))
+        }) else {
+            // Not a module, so the `~` is a trace between two names
+            let (module_span, rest) = span.split_at(module.chars().count());
+            let (tilde_span, name_span) = rest.split_at(1);
+            self.ident(name.into(), name_span, call)?;
+            self.primitive(Primitive::Trace, tilde_span, call)?;
+            return self.ident(module.into(), module_span, call);
+        };
+        self.record_use(idx, &span);
+        let Some(f) = functions.data.iter().find(|f| f.id == name) else {
+            return Err(UiuaError::compile(
+                span,
+                format!("Module `{module}` has no function `{name}`"),
+            ));
+        };
+        self.push_instr(Instr::push(f.clone()));
+        if call {
+            let span = self.add_span(span);
+            self.push_instr(Instr::Call(span));
         }
-
-        // Validate signature
-        let sig = match instrs_signature(&instrs) {
-            Ok(mut sig) => {
-                if let Some(declared_sig) = &func.signature {
-                    if declared_sig.value.is_superset_of(sig) {
-                        sig = declared_sig.value;
-                    } else {
-                        return Err(UiuaError::Run(Span::Code(declared_sig.span.clone()).sp(
-                            format!(
-                                "Function signature mismatch: declared {} but inferred {}",
-                                declared_sig.value, sig
-                            ),
-                        )));
-                    }
-                }
-                sig
-            }
-            Err(e) => {
-                if let Some(declared_sig) = &func.signature {
-                    declared_sig.value
-                } else {
-                    return Err(UiuaError::Run(
-                        Span::Code(span.clone())
-                            .sp(format!("Cannot infer function signature: {e}")),
-                    ));
-                }
+        Ok(())
+    }
+    /// Compile the lines of a function
+    ///
+    /// Local bindings in the lines are unbound at the end.
+    fn func_lines(&mut self, lines: Vec<Vec<Sp<Word>>>) -> UiuaResult<Vec<Instr>> {
+        self.local_names.push(Vec::new());
+        let lines: UiuaResult<Vec<_>> = (lines.into_iter())
+            .map(|line| self.compile_words(line, true))
+            .collect();
+        let locals = self.local_names.pop().unwrap();
+        let mut instrs: Vec<Instr> = lines?.into_iter().flatten().collect();
+        if !locals.is_empty() {
+            let drop = Instr::DropLocals(locals.len());
+            // Unbinding before a final recur keeps it a tail call
+            if let Some(Instr::Prim(Primitive::Recur, _)) = instrs.last() {
+                instrs.insert(instrs.len() - 1, drop);
+            } else {
+                instrs.push(drop);
             }
-        };
+        }
+        Ok(instrs)
+    }
+    fn func(&mut self, func: Func, span: CodeSpan) -> UiuaResult {
+        let instrs = self.func_lines(func.lines)?;
+        let (sig, _) = verify_signature(&instrs, func.signature.as_ref(), &span)?;
         let function = Function::new(func.id, instrs, FunctionKind::Normal, sig);
         self.push_instr(Instr::push(function));
         Ok(())
     }
     fn modified(&mut self, modified: Modified, call: bool) -> UiuaResult {
+        self.diagnose_deprecated(modified.modifier.value, &modified.modifier.span);
         if call {
             self.words(modified.operands, false)?;
             let span = self.add_span(modified.modifier.span);
@@ -679,7 +2486,8 @@ code:
                     self.push_instr(Instr::push(func));
                 }
                 Err(e) => {
-                    return Err(UiuaError::Run(
+                    return Err(UiuaError::Compile(
+                        ErrorCode::SignatureMismatch,
                         Span::Code(modified.modifier.span.clone())
                             .sp(format!("Cannot infer function signature: {e}")),
                     ));
@@ -689,7 +2497,16 @@ code:
         Ok(())
     }
     fn primitive(&mut self, prim: Primitive, span: CodeSpan, call: bool) -> UiuaResult {
+        let operands = self.prim_operands.take();
+        let code_span = span.clone();
         let span = self.add_span(span);
+        // The operands are compiled before the primitive, so their values are the last instructions
+        if let (Some([a_span, b_span]), [.., Instr::Push(b), Instr::Push(a)]) =
+            (operands, self.new_functions.last().unwrap().as_slice())
+        {
+            let operands = [(a_span, a.shape().to_vec()), (b_span, b.shape().to_vec())];
+            self.operand_spans.lock().insert(span, operands);
+        }
         if call || prim.as_constant().is_some() {
             self.push_instr(Instr::Prim(prim, span));
         } else {
@@ -699,10 +2516,13 @@ code:
             match func {
                 Ok(func) => self.push_instr(Instr::push(func)),
                 Err(e) => {
-                    return Err(self.error(format!(
-                        "{prim} cannot be used here because it does \
-                        not have a well-defined signature: {e}"
-                    )))
+                    return Err(UiuaError::compile(
+                        code_span,
+                        format!(
+                            "{prim} cannot be used here because it does \
+                            not have a well-defined signature: {e}"
+                        ),
+                    ))
                 }
             }
         }
@@ -720,17 +2540,51 @@ code:
             call_span: 0,
             spans: Vec::new(),
             pc: 0,
+            tail_calls: 0,
+            repeats: 0,
         })
     }
     fn exec(&mut self, frame: StackFrame) -> UiuaResult {
+        // Interrupts are only honored between calls and loop iterations,
+        // so a line's intermediate values are never left on the stack
+        self.check_interrupt()?;
         let ret_height = self.scope.call.len();
         self.scope.call.push(frame);
+        self.run_frames(ret_height)
+    }
+    /// Run the frames on the call stack above some height until they finish
+    ///
+    /// If the frames are a resumable line, they may be left on the call stack
+    /// when the current slice ends.
+    fn run_frames(&mut self, ret_height: usize) -> UiuaResult {
+        self.suspended = false;
         while self.scope.call.len() > ret_height {
+            if self.step_handler.is_some() {
+                self.step()?;
+            }
+            #[cfg(feature = "jit")]
+            if self.step_handler.is_none() && self.jit_chain()? {
+                continue;
+            }
             let frame = self.scope.call.last().unwrap();
             let Some(instr) = frame.function.instrs.get(frame.pc) else {
-                self.scope.call.pop();
+                let frame = self.scope.call.last_mut().unwrap();
+                if frame.repeats > 0 {
+                    // Rerun the function without pushing a new frame
+                    if frame.repeats != usize::MAX {
+                        frame.repeats -= 1;
+                    }
+                    frame.pc = 0;
+                    frame.tail_calls = 0;
+                    self.check_interrupt()?;
+                    self.check_execution_limit()?;
+                } else {
+                    self.scope.call.pop();
+                }
                 continue;
             };
+            let is_tail = frame.pc + 1 == frame.function.instrs.len();
+            let mut tail_recur = false;
             // println!("{:?}", self.stack);
             // println!("  {:?}", instr);
             let res = match instr {
@@ -745,6 +2599,11 @@ code:
                 &Instr::EndArray { span, constant } => (|| {
                     let start = self.scope.array.pop().unwrap();
                     self.push_span(span, None);
+                    let bytes = self.stack[start..]
+                        .iter()
+                        .map(|val| val.flat_len().saturating_mul(val.element_size()))
+                        .fold(0, usize::saturating_add);
+                    self.check_memory(bytes)?;
                     let values = self.stack.drain(start..).rev();
                     let values: Vec<Value> = if constant {
                         values
@@ -760,15 +2619,63 @@ code:
                     self.push(val);
                     Ok(())
                 })(),
-                &Instr::Prim(prim, span) => (|| {
-                    self.push_span(span, Some(prim));
-                    prim.run(self)?;
+                &Instr::Prim(Primitive::Recur, span) if is_tail => (|| {
+                    self.push_span(span, Some(Primitive::Recur));
+                    let n = self
+                        .pop(1)?
+                        .as_nat(self, "Recur expects a natural number")?;
+                    if n == 1 {
+                        // Recurring as the last instruction reuses the current frame
+                        tail_recur = true;
+                    } else {
+                        self.recur(n)?;
+                    }
                     self.pop_span();
                     Ok(())
                 })(),
+                &Instr::Prim(prim, span) => {
+                    let start = self.profile_start(&ProfileKey::Primitive(prim));
+                    let res: UiuaResult = (|| {
+                        self.push_span(span, Some(prim));
+                        if prim == Primitive::Range {
+                            self.lazy_range()?;
+                        } else {
+                            prim.run(self)?;
+                        }
+                        self.pop_span();
+                        Ok(())
+                    })();
+                    self.profile_end(ProfileKey::Primitive(prim), start);
+                    match res {
+                        Err(e) if e.is_fill() => {
+                            let operands = self.operand_spans.lock().get(&span).cloned();
+                            Err(
+                                (operands.into_iter().flatten()).fold(e, |e, (span, shape)| {
+                                    e.with_label(
+                                        span,
+                                        format!("shape {} from here", FormatShape(&shape)),
+                                    )
+                                }),
+                            )
+                        }
+                        res => res,
+                    }
+                }
                 &Instr::Call(span) => self
                     .pop("called function")
                     .and_then(|f| self.call_with_span(f, span)),
+                Instr::BindLocal => self.pop("local value").map(|val| self.locals.push(val)),
+                &Instr::Local(n) => match self.locals.len().checked_sub(n + 1) {
+                    Some(i) => {
+                        self.stack.push(self.locals[i].clone());
+                        Ok(())
+                    }
+                    None => Err(self.error("Local binding is not bound")),
+                },
+                &Instr::DropLocals(n) => {
+                    self.locals.truncate(self.locals.len().saturating_sub(n));
+                    Ok(())
+                }
             };
             if let Err(mut err) = res {
                 // Trace errors
@@ -781,17 +2688,195 @@ code:
                 }
                 return Err(err);
             } else {
-                // Go to next instruction
-                self.scope.call.last_mut().unwrap().pc += 1;
-                if let Some(limit) = self.execution_limit {
-                    if instant::now() - self.execution_start > limit {
-                        return Err(UiuaError::Timeout(self.span()));
-                    }
+                let frame = self.scope.call.last_mut().unwrap();
+                if tail_recur {
+                    // Restart the function
+                    frame.pc = 0;
+                    frame.tail_calls = frame.tail_calls.saturating_add(1);
+                    self.check_interrupt()?;
+                } else {
+                    // Go to next instruction
+                    frame.pc += 1;
+                }
+                self.use_fuel()?;
+                self.check_execution_limit()?;
+                if self.resumable_height == Some(ret_height)
+                    && self.higher_scopes.is_empty()
+                    && self.scope.call.len() > ret_height
+                    && self.slice_end.is_some_and(|end| instant::now() >= end)
+                {
+                    self.suspended = true;
+                    return Ok(());
                 }
             }
         }
         Ok(())
     }
+    /// Start timing something if profiling is enabled
+    fn profile_start(&self, key: &ProfileKey) -> Option<f64> {
+        let profile = self.profile.as_ref()?;
+        let now = instant::now();
+        profile.lock().start(key, now);
+        Some(now)
+    }
+    /// Finish timing something started with [`Uiua::profile_start`]
+    fn profile_end(&self, key: ProfileKey, start: Option<f64>) {
+        if let (Some(profile), Some(start)) = (&self.profile, start) {
+            profile.lock().end(key, start, instant::now());
+        }
+    }
+    /// Call the step handler if the current frame has an instruction left to run
+    fn step(&mut self) -> UiuaResult {
+        let frame = self.scope.call.last().unwrap();
+        if frame.pc >= frame.function.instrs.len() {
+            return Ok(());
+        }
+        let Some(handler) = self.step_handler.take() else {
+            return Ok(());
+        };
+        let res = (*handler.lock())(self);
+        self.step_handler = Some(handler);
+        res
+    }
+    /// Use one unit of fuel, failing if there is none left
+    fn use_fuel(&self) -> UiuaResult {
+        self.use_fuel_n(1)
+    }
+    /// Use some units of fuel, failing if there is not enough left
+    ///
+    /// Running out uses up all remaining fuel.
+    fn use_fuel_n(&self, n: usize) -> UiuaResult {
+        if let Some(fuel) = self.fuel.as_ref().filter(|_| n > 0) {
+            let n = n as u64;
+            let prev =
+                fuel.fetch_update(atomic::Ordering::Relaxed, atomic::Ordering::Relaxed, |f| {
+                    (f > 0).then(|| f.saturating_sub(n))
+                });
+            if !prev.is_ok_and(|prev| prev >= n) {
+                return Err(UiuaError::OutOfFuel(self.span()));
+            }
+        }
+        Ok(())
+    }
+    fn check_interrupt(&self) -> UiuaResult {
+        if self.interrupt.is_interrupted() {
+            return Err(UiuaError::Interrupted(self.span()));
+        }
+        Ok(())
+    }
+    fn check_execution_limit(&self) -> UiuaResult {
+        if let Some(limit) = self.execution_limit {
+            if instant::now() - self.execution_start > limit {
+                return Err(UiuaError::Timeout(self.span()));
+            }
+        }
+        Ok(())
+    }
+    /// Run a chain of arithmetic on a large array with compiled native code
+    ///
+    /// Returns whether a chain was run.
+    #[cfg(feature = "jit")]
+    fn jit_chain(&mut self) -> UiuaResult<bool> {
+        use crate::algorithm::jit::{self, Chain};
+        let Some(Value::Num(xs)) = self.stack.last() else {
+            return Ok(false);
+        };
+        if xs.flat_len() < jit::MIN_LEN {
+            return Ok(false);
+        }
+        let frame = self.scope.call.last().unwrap();
+        let instrs = &frame.function.instrs[frame.pc.min(frame.function.instrs.len())..];
+        if !matches!(instrs.first(), Some(Instr::Push(_))) {
+            return Ok(false);
+        }
+        let Some(chain) = Chain::parse(instrs, xs.rank() == 1) else {
+            return Ok(false);
+        };
+        let Some(result) = chain.run(xs, self) else {
+            return Ok(false);
+        };
+        // The chain's instructions use fuel and are profiled as if they were run
+        for instr in &instrs[..chain.len] {
+            if let Instr::Prim(prim, _) = instr {
+                self.profile_consumed(*prim);
+            }
+        }
+        self.use_fuel_n(chain.len)?;
+        self.check_execution_limit()?;
+        *self.stack.last_mut().unwrap() = result;
+        self.scope.call.last_mut().unwrap().pc += chain.len;
+        Ok(true)
+    }
+    /// Profile a primitive whose work was done by the instruction before it
+    fn profile_consumed(&self, prim: Primitive) {
+        let key = ProfileKey::Primitive(prim);
+        self.profile_end(key.clone(), self.profile_start(&key));
+    }
+    /// Run `range`, letting the instructions that follow it consume the range
+    /// symbolically when they can so that its buffer is never allocated
+    ///
+    /// Consumed instructions use fuel and are profiled as if they were run,
+    /// and materializing the range uses fuel for each element.
+    fn lazy_range(&mut self) -> UiuaResult {
+        let Some(mut range) = self.stack.last().and_then(LazyRange::from_max) else {
+            return Primitive::Range.run(self);
+        };
+        self.stack.pop();
+        let frame = self.scope.call.last().unwrap();
+        let function = frame.function.clone();
+        let start_pc = frame.pc;
+        let mut pc = start_pc;
+        let materialize = |env: &Uiua, range: LazyRange| {
+            env.use_fuel_n(range.len())?;
+            range.materialize(env)
+        };
+        let result = loop {
+            match &function.instrs[pc + 1..] {
+                [Instr::Prim(Primitive::Len, _), ..] => {
+                    self.profile_consumed(Primitive::Len);
+                    pc += 1;
+                    break range.len().into();
+                }
+                [Instr::Push(n), Instr::Prim(prim @ (Primitive::Take | Primitive::Drop), _), ..] => {
+                    let n = match &**n {
+                        Value::Num(n) if n.rank() == 0 && n.data[0].fract() == 0.0 => {
+                            n.data[0] as isize
+                        }
+                        Value::Byte(n) if n.rank() == 0 => n.data[0] as isize,
+                        _ => break materialize(self, range)?,
+                    };
+                    if *prim == Primitive::Drop {
+                        range = range.drop(n);
+                    } else if let Some(taken) = range.take(n) {
+                        range = taken;
+                    } else {
+                        break materialize(self, range)?;
+                    }
+                    self.profile_consumed(*prim);
+                    pc += 2;
+                }
+                [Instr::Push(f), Instr::Prim(Primitive::Reduce, _), ..] => {
+                    match f
+                        .as_flipped_primitive()
+                        .and_then(|(prim, _)| range.reduce(prim))
+                    {
+                        Some(reduced) => {
+                            self.profile_consumed(Primitive::Reduce);
+                            pc += 2;
+                            break reduced.into();
+                        }
+                        None => break materialize(self, range)?,
+                    }
+                }
+                _ => break materialize(self, range)?,
+            }
+        };
+        // The main loop uses fuel for the range itself
+        self.use_fuel_n(pc - start_pc)?;
+        self.scope.call.last_mut().unwrap().pc = pc;
+        self.push(result);
+        Ok(())
+    }
     fn push_span(&mut self, span: usize, prim: Option<Primitive>) {
         self.scope.call.last_mut().unwrap().spans.push((span, prim));
     }
@@ -804,6 +2889,28 @@ code:
             match f {
                 Value::Func(f) if f.shape.is_empty() => {
                     // Call function
+                    let position = stack_position();
+                    let depth = self.scope.call.len();
+                    if depth <= 1 {
+                        self.call_stack_base = position;
+                    }
+                    // Leave room for twice the stack that an average call has used so far
+                    let per_call = self.call_stack_base.saturating_sub(position) / depth.max(1);
+                    let needed = per_call.saturating_mul(2).saturating_add(STACK_RED_ZONE);
+                    if stack_limit().is_some_and(|limit| position < limit.saturating_add(needed)) {
+                        return Err(self.error(format!(
+                            "The call stack overflowed after {} nested calls. \
+                            A function may be recursing without stopping.",
+                            self.scope.call.len()
+                        )));
+                    }
+                    if self.scope.call.len() > self.max_call_depth {
+                        return Err(self.error(format!(
+                            "Maximum call depth of {} exceeded. \
+                            A function may be recursing without stopping.",
+                            self.max_call_depth
+                        )));
+                    }
                     let f = f.into_scalar().unwrap();
                     match &f.kind {
                         FunctionKind::Normal => {}
@@ -813,18 +2920,38 @@ code:
                                 call_span,
                                 spans: Vec::new(),
                                 pc: 0,
+                                tail_calls: 0,
+                                repeats: 0,
                             });
                             (dfk.f)(self)?;
                             self.scope.call.pop();
                             break Ok(());
                         }
                     }
-                    break self.exec(StackFrame {
+                    let locals_height = self.locals.len();
+                    let key = match &f.id {
+                        FunctionId::Named(name) if self.profile.is_some() => {
+                            Some(ProfileKey::Binding(name.clone()))
+                        }
+                        _ => None,
+                    };
+                    let start = key.as_ref().and_then(|key| self.profile_start(key));
+                    let res = self.exec(StackFrame {
                         function: f,
                         call_span,
                         spans: Vec::new(),
                         pc: 0,
+                        tail_calls: 0,
+                        repeats: 0,
                     });
+                    if let Some(key) = key {
+                        self.profile_end(key, start);
+                    }
+                    // A function that fails does not get to unbind its locals
+                    if res.is_err() {
+                        self.locals.truncate(locals_height);
+                    }
+                    break res;
                 }
                 Value::Func(_) if first_pass => {
                     // Call non-scalar function array
@@ -850,16 +2977,73 @@ code:
         if n == 0 {
             return Ok(());
         }
-        if n > self.scope.call.len() {
+        // A frame that has tail-recurred stands in for one level per recursion
+        let mut levels = n;
+        let mut depth: usize = 0;
+        let mut target = None;
+        for frame in self.scope.call.iter().rev() {
+            let frame_levels = frame.tail_calls.saturating_add(1);
+            depth = depth.saturating_add(frame_levels);
+            if target.is_none() {
+                if levels <= frame_levels {
+                    target = Some(frame.function.clone());
+                } else {
+                    levels -= frame_levels;
+                }
+            }
+        }
+        let Some(f) = target else {
             return Err(self.error(format!(
-                "Cannot recur {} levels up, only {} levels down",
-                n,
-                self.scope.call.len()
+                "Cannot recur {n} levels up, only {depth} levels down"
             )));
-        }
-        let f = self.scope.call[self.scope.call.len() - n].function.clone();
+        };
         self.call(f)
     }
+    /// Call a function a number of times, reusing a single stack frame for every call
+    ///
+    /// `None` repeats the function until it breaks.
+    /// Returns whether the loop was broken out of.
+    pub(crate) fn call_repeated(&mut self, f: Value, times: Option<usize>) -> UiuaResult<bool> {
+        let repeats = match times {
+            Some(0) => return Ok(false),
+            Some(n) => n - 1,
+            None => usize::MAX,
+        };
+        let function = match f {
+            Value::Func(fs)
+                if fs.rank() == 0 && matches!(fs.data[0].kind, FunctionKind::Normal) =>
+            {
+                fs.into_scalar().unwrap()
+            }
+            f => {
+                let mut remaining = times;
+                while remaining != Some(0) {
+                    if self.call_catch_break(f.clone())? {
+                        return Ok(true);
+                    }
+                    remaining = remaining.map(|n| n - 1);
+                }
+                return Ok(false);
+            }
+        };
+        let call_span = self.span_index();
+        let res = self.exec(StackFrame {
+            function,
+            call_span,
+            spans: Vec::new(),
+            pc: 0,
+            tail_calls: 0,
+            repeats,
+        });
+        match res {
+            Ok(_) => Ok(false),
+            Err(e) => match e.break_data() {
+                Ok((0, _)) => Ok(true),
+                Ok((n, span)) => Err(UiuaError::Break(n - 1, span)),
+                Err(e) => Err(e),
+            },
+        }
+    }
     pub fn call_catch_break(&mut self, f: Value) -> UiuaResult<bool> {
         match self.call(f) {
             Ok(_) => Ok(false),
@@ -896,6 +3080,37 @@ code:
                 .unwrap_or(frame.call_span)
         })
     }
+    /// Get the span of the instruction that will be executed next
+    ///
+    /// Instructions like pushing a value have no span.
+    pub fn next_span(&self) -> Option<Span> {
+        let frame = self.scope.call.last()?;
+        let span = match frame.function.instrs.get(frame.pc)? {
+            Instr::EndArray { span, .. } | Instr::Prim(_, span) | Instr::Call(span) => *span,
+            _ => return None,
+        };
+        Some(self.spans.lock()[span].clone())
+    }
+    /// Get the primitive that will be executed next, if the next instruction is one
+    pub fn next_primitive(&self) -> Option<Primitive> {
+        let frame = self.scope.call.last()?;
+        match frame.function.instrs.get(frame.pc)? {
+            Instr::Prim(prim, _) => Some(*prim),
+            _ => None,
+        }
+    }
+    /// Get the functions currently being called, outermost first
+    ///
+    /// Each frame's span is where its function was called.
+    pub fn call_frames(&self) -> Vec<TraceFrame> {
+        let spans = self.spans.lock();
+        (self.scope.call.iter())
+            .map(|frame| TraceFrame {
+                id: frame.function.id.clone(),
+                span: spans[frame.call_span].clone(),
+            })
+            .collect()
+    }
     /// Get the span of the current function call
     pub fn span(&self) -> Span {
         self.spans.lock()[self.span_index()].clone()
@@ -921,6 +3136,21 @@ code:
     pub fn push(&mut self, val: impl Into<Value>) {
         self.stack.push(val.into());
     }
+    /// Take the results of the test lines run so far
+    ///
+    /// Lines in test scopes are only run as tests in [`RunMode::Test`].
+    /// A line that fails is recorded here instead of stopping the program.
+    pub fn take_test_results(&mut self) -> Vec<TestResult> {
+        take(&mut self.test_results)
+    }
+    /// Get the entire stack
+    pub fn stack(&self) -> &[Value] {
+        &self.stack
+    }
+    /// Remove all values from the stack
+    pub fn clear_stack(&mut self) {
+        self.stack.clear();
+    }
     /// Take the entire stack
     pub fn take_stack(&mut self) -> Vec<Value> {
         take(&mut self.stack)
@@ -1073,27 +3303,83 @@ code:
                 self.stack.len()
             )))?;
         }
-        let env = Uiua {
+        let stack = self
+            .stack
+            .drain(self.stack.len() - capture_count..)
+            .collect();
+        let mut env = self.thread_env(stack);
+        if let Some(det) = &self.deterministic {
+            let res = f(&mut env).map(|()| env.take_stack());
+            let mut det = det.lock();
+            let handle = Handle(det.next_handle);
+            det.next_handle += 1;
+            det.threads.insert(handle, res);
+            return Ok(handle.into());
+        }
+        self.backend
+            .spawn(env, Box::new(f))
+            .map(Value::from)
+            .map_err(|e| self.error(e))
+    }
+    /// Create an environment for running code on another thread
+    pub(crate) fn thread_env(&self, stack: Vec<Value>) -> Self {
+        Uiua {
             new_functions: Vec::new(),
+            macro_operands: Vec::new(),
+            local_names: Vec::new(),
+            locals: Vec::new(),
             globals: self.globals.clone(),
             spans: self.spans.clone(),
-            stack: self
-                .stack
-                .drain(self.stack.len() - capture_count..)
-                .collect(),
+            stack,
             scope: self.scope.clone(),
             higher_scopes: self.higher_scopes.last().cloned().into_iter().collect(),
             mode: self.mode,
             current_imports: self.current_imports.clone(),
             imports: self.imports.clone(),
+            import_sources: self.import_sources.clone(),
+            binding_spans: self.binding_spans.clone(),
+            operand_spans: self.operand_spans.clone(),
+            prim_operands: None,
+            test_results: Vec::new(),
+            diagnostics: self.diagnostics.clone(),
+            function_cache: self.function_cache.clone(),
+            program: None,
             backend: self.backend.clone(),
+            step_handler: None,
+            profile: self.profile.clone(),
+            rng: self.rng.clone(),
+            deterministic: self.deterministic.clone(),
             execution_limit: self.execution_limit,
             execution_start: self.execution_start,
-        };
-        self.backend
-            .spawn(env, Box::new(f))
-            .map(Value::from)
-            .map_err(|e| self.error(e))
+            fuel: self.fuel.clone(),
+            instruction_limit: self.instruction_limit,
+            memory_limit: self.memory_limit,
+            max_call_depth: self.max_call_depth,
+            call_stack_base: 0,
+            interrupt: self.interrupt.clone(),
+            slice_end: None,
+            resumable_height: None,
+            suspended: false,
+            capabilities: self.capabilities.clone(),
+            parallel: self.parallel,
+            fuse: self.fuse,
+            fold: self.fold,
+            fold_fuel: self.fold_fuel,
+            gpu: self.gpu,
+            gpu_single_precision: self.gpu_single_precision,
+            display_budget: self.display_budget,
+        }
+    }
+    /// Create a runtime for evaluating a primitive at compile time
+    ///
+    /// It holds nothing but the primitive's arguments, so the memory limit
+    /// applies to the primitive alone, and it uses none of this runtime's fuel.
+    pub(crate) fn fold_env(&self, args: Vec<Value>, memory_limit: usize) -> Self {
+        Uiua {
+            globals: Arc::default(),
+            fuel: None,
+            ..self.thread_env(args).with_memory_limit(memory_limit)
+        }
     }
     /// Wait for a thread to finish
     pub(crate) fn wait(&mut self, handle: Value) -> UiuaResult {
@@ -1106,18 +3392,12 @@ code:
         )?;
         if handles.shape.is_empty() {
             let handle = handles.data.into_iter().next().unwrap();
-            let thread_stack = self
-                .backend
-                .wait(handle)
-                .map_err(|e| e.unwrap_or_else(|e| self.error(e)))?;
+            let thread_stack = self.wait_thread(handle)?;
             self.stack.extend(thread_stack);
         } else {
             let mut rows = Vec::new();
             for handle in handles.data {
-                let thread_stack = self
-                    .backend
-                    .wait(handle)
-                    .map_err(|e| e.unwrap_or_else(|e| self.error(e)))?;
+                let thread_stack = self.wait_thread(handle)?;
                 let row = if thread_stack.len() == 1 {
                     thread_stack.into_iter().next().unwrap()
                 } else {
@@ -1129,6 +3409,17 @@ code:
         }
         Ok(())
     }
+    fn wait_thread(&self, handle: Handle) -> UiuaResult<Vec<Value>> {
+        // Commands started with &runs are waited on like threads but run in the backend
+        if let Some(det) = &self.deterministic {
+            if let Some(res) = det.lock().threads.remove(&handle) {
+                return res;
+            }
+        }
+        self.backend
+            .wait(handle)
+            .map_err(|e| e.unwrap_or_else(|e| self.error(e)))
+    }
 }
 
 /// A trait for types that can be used as argument specifiers for [`Uiua::pop`] and [`Uiua::antipop`]
@@ -1192,3 +3483,499 @@ where
         format!("array {}", self.0.arg_name())
     }
 }
+
+/// Find the spans of the operands of dyadic pervasive primitives whose
+/// operands are both literals written right after them
+fn literal_operands(words: &[Sp<Word>]) -> Vec<Option<[CodeSpan; 2]>> {
+    fn is_literal(word: &Word) -> bool {
+        match word {
+            Word::Number(..)
+            | Word::Char(_)
+            | Word::String(_)
+            | Word::RawString(_)
+            | Word::GridString(_) => true,
+            Word::Strand(items) => items.iter().all(|item| is_literal(&item.value)),
+            Word::Array(arr) => (arr.lines.iter().flatten()).all(|word| {
+                matches!(word.value, Word::Spaces | Word::Comment(_)) || is_literal(&word.value)
+            }),
+            _ => false,
+        }
+    }
+    (0..words.len())
+        .map(|i| {
+            if !matches!(&words[i].value, Word::Primitive(prim)
+                if prim.class() == PrimClass::DyadicPervasive)
+            {
+                return None;
+            }
+            let mut following = (words[i + 1..].iter())
+                .filter(|word| !matches!(word.value, Word::Spaces | Word::Comment(_)));
+            let a = following.next().filter(|word| is_literal(&word.value))?;
+            let b = following.next().filter(|word| is_literal(&word.value))?;
+            Some([a.span.clone(), b.span.clone()])
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn memory_limit() {
+        let mut env = Uiua::with_native_sys().with_memory_limit(1 << 20);
+        for code in ["⇡1e9", "↯1e9 1", "⊞+.⇡1000", "⊂⇡1e5 ⇡1e5"] {
+            assert!(
+                env.load_str(code).is_err(),
+                "{code} should exceed the limit"
+            );
+        }
+        env.load_str("⊞+.⇡100 ↯100 1 ⧻⇡1e9").unwrap();
+        // Arrays that each fit still count toward the total
+        let mut env = Uiua::with_native_sys().with_memory_limit(1 << 20);
+        env.load_str("A ← ↯1e5 0.5").unwrap();
+        assert!(env.load_str("B ← ↯1e5 0.5").is_err());
+        assert!(env.load_str("↯1e5 0.5 ↯1e5 0.5").is_err());
+    }
+
+    #[test]
+    fn lazy_range() {
+        // The range maximums are pushed at runtime so the ranges are not folded away
+        let run = |env: &mut Uiua, max: f64, code: &str| {
+            env.push(max);
+            env.load_str(code)?;
+            Ok::<_, UiuaError>(env.take_stack().pop().unwrap().show())
+        };
+        let mut env = Uiua::with_native_sys().with_memory_limit(1 << 20);
+        assert_eq!(run(&mut env, 1e9, "⧻⇡").unwrap(), "1000000000");
+        assert!(run(&mut env, 1e9, "⇡").is_err());
+        // Materialized elements use fuel
+        let mut env = Uiua::with_native_sys().with_fuel(1000);
+        assert_eq!(run(&mut env, 1e6, "⧻⇡").unwrap(), "1000000");
+        let err = run(&mut env, 1e6, "⇡").unwrap_err();
+        assert_eq!(err.code(), ErrorCode::OutOfFuel);
+        // Consumed primitives are profiled
+        let mut env = Uiua::with_native_sys().with_profiling(true);
+        run(&mut env, 10.0, "/+⇡").unwrap();
+        let profile = env.profile().unwrap();
+        let calls = |prim: Primitive| {
+            (profile.entries().into_iter())
+                .find(|(k, _)| **k == ProfileKey::Primitive(prim))
+                .map_or(0, |(_, entry)| entry.calls)
+        };
+        assert_eq!(calls(Primitive::Range), 1);
+        assert_eq!(calls(Primitive::Reduce), 1);
+    }
+
+    #[test]
+    fn instruction_limit() {
+        let mut env = Uiua::with_native_sys().with_instruction_limit(1000);
+        let err = env.load_str("⍥(+1)∞ 0").err().unwrap();
+        assert!(err.to_string().contains("Instruction limit"), "{err}");
+        env.load_str("⍥(+1)10 0").unwrap();
+    }
+
+    #[test]
+    fn max_call_depth() {
+        // Debug builds use a lot of stack per call, so the limit is kept well
+        // below what fits in a test thread's stack
+        let mut env = Uiua::with_native_sys().with_max_call_depth(50);
+        let err = env.load_str("!(|1 +1 ↬>0.-1) 500").err().unwrap();
+        assert!(
+            err.to_string().contains("Maximum call depth of 50"),
+            "{err}"
+        );
+        env.load_str("!(|1 +1 ↬>0.-1) 25").unwrap();
+        // Running out of stack is an error instead of a crash
+        let mut env = Uiua::with_native_sys().with_max_call_depth(usize::MAX);
+        let err = env.load_str("!(|1 +1 ↬>0.-1) 100000").err().unwrap();
+        assert!(err.to_string().contains("call stack overflowed"), "{err}");
+    }
+
+    #[test]
+    fn interrupt() {
+        // The time limit keeps the test from hanging if interrupting does not work
+        let mut env =
+            Uiua::with_native_sys().with_execution_limit(std::time::Duration::from_secs(10));
+        env.load_str("1 2").unwrap();
+        let handle = env.interrupt_handle();
+        let interrupter = std::thread::spawn(move || {
+            std::thread::sleep(std::time::Duration::from_millis(50));
+            handle.interrupt();
+        });
+        let err = env.load_str("⍥(+1)∞ 0").err().unwrap();
+        interrupter.join().unwrap();
+        assert_eq!(err.code(), ErrorCode::Interrupted);
+        // The loop is interrupted between iterations, so only the counter is left
+        assert_eq!(env.stack().len(), 3);
+        env.load_str("+").unwrap();
+        assert!(!env.interrupt_handle().is_interrupted());
+    }
+
+    #[test]
+    fn fuel() {
+        let mut env = Uiua::with_native_sys().with_fuel(1000);
+        let err = env.load_str("⍥(+1)∞ 0").err().unwrap();
+        assert_eq!(err.code(), ErrorCode::OutOfFuel);
+        assert_eq!(env.fuel(), Some(0));
+        // Fuel is not refilled by loading
+        assert!(env.load_str("⍥(+1)10 0").is_err());
+        // Catching the error does not let the program keep running
+        let err = env.load_str("⍣(⍥(+1)∞ 0)(5)").err().unwrap();
+        assert_eq!(err.code(), ErrorCode::OutOfFuel);
+        env.add_fuel(1000);
+        env.load_str("⍥(+1)10 0").unwrap();
+        assert!(env.fuel().unwrap() < 1000);
+        assert_eq!(Uiua::with_native_sys().fuel(), None);
+    }
+
+    #[test]
+    fn reload_reuses_unchanged_functions() {
+        let mut env = Uiua::with_native_sys();
+        let first = env.reload_str("F ← +1\nF_F").unwrap().take_stack();
+        let unchanged = env.reload_str("F ← +1\nF_F 5").unwrap().take_stack();
+        assert_eq!(first[0], unchanged[1]);
+        let changed = env.reload_str("F ← +2\nF_F").unwrap().take_stack();
+        assert_ne!(first, changed);
+        let result = env.reload_str("F ← +2\nF 5").unwrap().take_stack();
+        assert_eq!(result, [Value::from(7.0)]);
+
+        // Changing a macro or a name the macro uses recompiles the bindings that expand it
+        let mut env = Uiua::with_native_sys();
+        let code = "N ← 1\nM! ← +N^0\nF ← M!(×2)\nF 5";
+        let result = env.reload_str(code).unwrap().take_stack();
+        assert_eq!(result, [Value::from(11.0)]);
+        let code = "N ← 1\nM! ← -N^0\nF ← M!(×2)\nF 5";
+        let result = env.reload_str(code).unwrap().take_stack();
+        assert_eq!(result, [Value::from(9.0)]);
+        let code = "N ← 2\nM! ← -N^0\nF ← M!(×2)\nF 5";
+        let result = env.reload_str(code).unwrap().take_stack();
+        assert_eq!(result, [Value::from(8.0)]);
+
+        // Uses in reused functions are still recorded
+        let mut env = Uiua::with_native_sys();
+        for _ in 0..2 {
+            env.reload_str("F ← +1\nG ← F F\nG 1").unwrap();
+            let spans = env.binding_spans();
+            assert_eq!(spans[0].uses.len(), 2, "{spans:?}");
+        }
+    }
+
+    #[test]
+    fn fusion_matches_unfused() {
+        for code in [
+            "/+×1_2_3 4_5_6",
+            "/+×[1_2 3_4] 5",
+            // Long lists are summed with several accumulators
+            "/+×÷3⇡10000 ÷7⇡10000",
+            "/+×÷3⇡100000 ÷7⇡100000",
+            "▽≥3.[1 5 2 4]",
+            "▽<@m.\"hello\"",
+        ] {
+            let fused = Uiua::with_native_sys()
+                .with_folding(false)
+                .load_str(code)
+                .unwrap()
+                .take_stack();
+            let unfused = Uiua::with_native_sys()
+                .with_folding(false)
+                .with_fusion(false)
+                .load_str(code)
+                .unwrap()
+                .take_stack();
+            assert_eq!(fused, unfused, "{code}");
+        }
+    }
+
+    #[test]
+    fn folding_is_limited() {
+        // Evaluating these when compiling would take a long time or a lot of memory
+        Uiua::with_native_sys()
+            .load_str("F ← (▽1e12 1)\nG ← (⇡1e12)\nH ← (⊞+.⇡1e6)")
+            .unwrap();
+    }
+
+    #[test]
+    fn compiled_program_round_trip() {
+        let code = "Sq ← ×.\nX ← ⇡5\nSq X\n/+X";
+        let bytes = (Uiua::with_native_sys().compile_program(code, None))
+            .unwrap()
+            .unwrap();
+        let stack = (Uiua::with_native_sys().run_compiled(&bytes))
+            .unwrap()
+            .take_stack();
+        let expected = Uiua::with_native_sys().load_str(code).unwrap().take_stack();
+        assert_eq!(stack, expected);
+        // Bindings that must be run when they are bound cannot be compiled ahead of time
+        for code in ["X ← &sc\nX", "1 2\nY ← +\nY", "---\n1\n---"] {
+            let compiled = Uiua::with_native_sys().compile_program(code, None).unwrap();
+            assert!(compiled.is_none(), "{code}");
+        }
+    }
+
+    #[test]
+    fn bindings_round_trip() {
+        let mut env = Uiua::with_native_sys();
+        env.load_str("F ← +1×2\nG ← \"hi\"").unwrap();
+        let bytes = env.store_bindings().unwrap();
+        let mut loaded = Uiua::with_native_sys();
+        loaded.load_bindings(&bytes).unwrap();
+        let stack = loaded.load_str("F 5 G").unwrap().take_stack();
+        let expected = Uiua::with_native_sys()
+            .load_str("F ← +1×2\nF 5 \"hi\"")
+            .unwrap()
+            .take_stack();
+        assert_eq!(stack, expected);
+        assert!(loaded.load_bindings(&bytes[..bytes.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn binding_definitions_are_tracked() {
+        let code = "F ← +1\nG ← F F\nG 5";
+        let mut env = Uiua::with_native_sys();
+        env.load_str(code).unwrap();
+        let spans = env.binding_spans();
+        assert_eq!(spans.len(), 2);
+        assert_eq!(spans[0].uses.len(), 2);
+        assert_eq!(spans[1].uses.len(), 1);
+        let def = env.definition(&spans[1].uses[0]).unwrap();
+        assert_eq!(&code[def.start.byte_pos..def.end.byte_pos], "G");
+        assert!(env.definition(&spans[0].definition).is_some());
+    }
+
+    #[test]
+    fn locals_are_scoped_to_their_function() {
+        let nested = "!(x ← 1\n∵(+x) 1_2)";
+        assert!(Uiua::with_native_sys().load_str(nested).is_err());
+    }
+
+    #[test]
+    fn diagnostics_are_collected() {
+        let mut env = Uiua::with_native_sys();
+        env.load_str("X ← 5\nX ← 6\nY ← X\n;1\n;.1\nrevrev 1_2")
+            .unwrap();
+        let diagnostics = env.take_diagnostics();
+        let kinds: Vec<_> = diagnostics.iter().map(|d| d.kind).collect();
+        use DiagnosticKind::*;
+        assert_eq!(
+            kinds,
+            [
+                ShadowedName,
+                AlwaysPopped,
+                AlwaysPopped,
+                AmbiguousGlyph,
+                UnusedBinding,
+                UnusedBinding
+            ],
+            "{diagnostics:?}"
+        );
+        assert_eq!(diagnostics[4].span.start.line, 1);
+        assert_eq!(diagnostics[5].span.as_str(), "Y");
+        assert!(env.take_diagnostics().is_empty());
+    }
+
+    #[test]
+    fn shebang_line_is_skipped() {
+        let code = "#!/usr/bin/env uiua\n+1 2";
+        let mut env = Uiua::with_native_sys();
+        env.load_str(code).unwrap();
+        assert_eq!(env.take_stack(), [Value::from(3.0)]);
+        let formatted = crate::format::format_str(code, &Default::default()).unwrap();
+        assert_eq!(formatted, "#!/usr/bin/env uiua\n+1 2\n");
+    }
+
+    #[test]
+    fn test_failures_are_collected() {
+        let code =
+            "~~~\n⍤\"fails\" 0\n[⍤\"fails in array\" 0]\n⍤.≅ [1] [1]\n~~~\n⍤\"not a test\" 0";
+        let mut env = Uiua::with_native_sys().with_mode(RunMode::Test);
+        env.load_str(code).unwrap();
+        let results = env.take_test_results();
+        let passed: Vec<bool> = results.iter().map(|result| result.passed()).collect();
+        assert_eq!(passed, [false, false, true]);
+        assert_eq!(results[1].span.start.line, 3);
+        assert!(Uiua::with_native_sys().load_str(code).is_err());
+    }
+
+    #[test]
+    fn binding_docs_are_captured() {
+        let mut env = Uiua::with_native_sys();
+        env.load_str(
+            "# Add one\n# to a number\n#\n# Used below\nInc ← +1\n# Not a doc\n5\nX ← Inc 1",
+        )
+        .unwrap();
+        assert_eq!(
+            env.binding_doc("Inc").as_deref(),
+            Some("Add one to a number\n\nUsed below")
+        );
+        assert_eq!(env.binding_doc("X"), None);
+    }
+
+    #[test]
+    fn inspect_bindings() {
+        let mut env = Uiua::with_native_sys();
+        env.load_str("F ← +1\nX ← 5\n1 2").unwrap();
+        let bindings: Vec<String> = (env.bindings().into_iter())
+            .map(|(name, sig)| format!("{name} {sig}"))
+            .collect();
+        assert_eq!(bindings, ["F |1.1", "X |0.1"]);
+        assert_eq!(env.stack().len(), 2);
+        env.clear_stack();
+        assert!(env.stack().is_empty());
+    }
+
+    #[test]
+    fn step_handler() {
+        let steps = Arc::new(Mutex::new(Vec::new()));
+        let recorded = steps.clone();
+        let mut env = Uiua::with_native_sys().with_step_handler(move |env| {
+            let span = env.next_span().map(|span| span.to_string());
+            recorded.lock().push((env.stack().len(), span));
+            Ok(())
+        });
+        env.load_str("+⚂ 1").unwrap();
+        let steps = steps.lock();
+        assert_eq!(steps.len(), 3);
+        assert_eq!(steps.last().unwrap().0, 2);
+        assert!(steps.last().unwrap().1.is_some());
+
+        let mut env = Uiua::with_native_sys().with_step_handler(|env| {
+            if env.stack().len() >= 3 {
+                Err(env.error("Aborted"))
+            } else {
+                Ok(())
+            }
+        });
+        assert!(env.load_str("⍥(.)∞ 1").is_err());
+    }
+
+    #[test]
+    fn breakpoints() {
+        let hits = Arc::new(Mutex::new(Vec::new()));
+        let recorded = hits.clone();
+        let mut env = Uiua::with_native_sys().with_step_handler(move |env| {
+            if env.next_primitive() == Some(Primitive::Breakpoint) {
+                recorded.lock().push(env.stack().len());
+                env.push(10);
+            }
+            Ok(())
+        });
+        env.load_str("F ← (breakpoint +)\nF 1 2\n+").unwrap();
+        assert_eq!(*hits.lock(), [1]);
+        let stack: Vec<String> = env.take_stack().iter().map(|v| v.show()).collect();
+        assert_eq!(stack, ["13"]);
+    }
+
+    #[test]
+    fn profiling() {
+        let mut env = Uiua::with_native_sys().with_profiling(true);
+        env.load_str("F ← ⌂\nG ← |1 ≡(F)\nG ⊞+.⇡5").unwrap();
+        let profile = env.profile().unwrap();
+        let calls = |key: ProfileKey| {
+            (profile.entries().into_iter())
+                .find(|(k, _)| **k == key)
+                .map_or(0, |(_, entry)| entry.calls)
+        };
+        assert_eq!(calls(ProfileKey::Binding("G".into())), 1);
+        assert_eq!(calls(ProfileKey::Binding("F".into())), 5);
+        assert_eq!(calls(ProfileKey::Primitive(Primitive::Rows)), 1);
+        assert!(profile.report().contains("≡ rows"));
+        assert!(Uiua::with_native_sys().profile().is_none());
+    }
+
+    #[test]
+    fn profile_timeline() {
+        let mut env = Uiua::with_native_sys().with_timeline(true);
+        env.load_str("F ← ≡⌂\nF ⊞+.⇡3").unwrap();
+        let profile = env.profile().unwrap();
+        let folded = profile.folded_stacks();
+        assert!(
+            folded
+                .lines()
+                .any(|line| line.starts_with("F;≡ rows;⌂ grade ")),
+            "{folded}"
+        );
+        let trace = profile.chrome_trace();
+        assert!(trace.starts_with("{\"traceEvents\":["), "{trace}");
+        assert!(trace.contains("\"name\":\"F\",\"ph\":\"X\""), "{trace}");
+        let event = (profile.events().iter())
+            .find(|event| event.key.to_string() == "⌂ grade")
+            .unwrap();
+        assert!(event.self_time <= event.duration);
+        assert!(Uiua::with_native_sys()
+            .with_profiling(true)
+            .profile()
+            .unwrap()
+            .events()
+            .is_empty());
+    }
+
+    #[test]
+    fn resumable_execution() {
+        let mut env = Uiua::with_native_sys();
+        let mut execution = env
+            .start_str("X ← 5\n1 2\n⍥(+1)1000 0\n+X ⚂ ⚂ ⚂\n3")
+            .unwrap();
+        let mut slices = 0;
+        while !env.resume(&mut execution, Duration::ZERO).unwrap() {
+            slices += 1;
+        }
+        assert!(execution.is_finished());
+        // Lines and the instructions of a line are separate slices
+        assert!(slices > 5, "{slices}");
+        let stack = env.take_stack();
+        assert_eq!(stack.len(), 7);
+        assert_eq!(stack[2].show(), "1000");
+        assert_eq!(stack[6].show(), "3");
+
+        let mut env = Uiua::with_native_sys();
+        let mut execution = env.start_str("1\n+ [1 2] [1 2 3]\n3").unwrap();
+        let mut res = Ok(false);
+        while res == Ok(false) {
+            res = env.resume(&mut execution, Duration::ZERO).map_err(drop);
+        }
+        assert!(res.is_err());
+        assert!(env.resume(&mut execution, Duration::ZERO).unwrap());
+    }
+
+    #[test]
+    fn state_round_trip() {
+        let mut env = Uiua::with_native_sys();
+        env.load_str("F ← +1×2\n1 \"hi\" [2 3]").unwrap();
+        let bytes = env.store_state().unwrap();
+        let expected = env.load_str("F 5 ⚂ ⚂").unwrap().take_stack();
+        let mut loaded = Uiua::with_native_sys();
+        loaded.load_state(&bytes).unwrap();
+        let stack = loaded.load_str("F 5 ⚂ ⚂").unwrap().take_stack();
+        assert_eq!(stack, expected);
+        assert_eq!(stack.len(), 6);
+        let err = loaded.load_state(&bytes[..bytes.len() - 1]).unwrap_err();
+        assert!(
+            err.to_string().contains("Runtime state is not valid"),
+            "{err}"
+        );
+        let err = loaded
+            .load_state(&[bytes.as_slice(), &[0]].concat())
+            .unwrap_err();
+        assert!(err.to_string().contains("more data after"), "{err}");
+    }
+
+    #[test]
+    fn deterministic_execution() {
+        let code = "⚂\n&n\n&sl 1.5\n&n\n[⍥⚂3]\n↲↰(×2⚂)";
+        let run = |seed| {
+            Uiua::with_native_sys()
+                .with_deterministic(seed)
+                .load_str(code)
+                .unwrap()
+                .take_stack()
+        };
+        let stack = run(42);
+        assert_eq!(stack, run(42));
+        assert_ne!(stack, run(43));
+        assert_eq!(stack.len(), 5);
+        assert_eq!(stack[1].show(), "0");
+        assert_eq!(stack[2].show(), "1.5");
+        assert!(!Uiua::with_native_sys().is_deterministic());
+    }
+}
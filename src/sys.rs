@@ -1,9 +1,11 @@
 use std::{
     any::Any,
-    env,
+    env, fmt,
     fs::{self, File},
-    io::{stderr, stdin, stdout, BufRead, Cursor, Read, Write},
+    io::{self, stderr, stdin, stdout, BufRead, BufReader, Cursor, IsTerminal, Read, Write},
     net::*,
+    process::{Child, Command, Stdio},
+    str::FromStr,
     sync::{
         atomic::{self, AtomicU64},
         Arc, OnceLock,
@@ -22,7 +24,7 @@ use parking_lot::Mutex;
 use tinyvec::tiny_vec;
 
 use crate::{
-    array::Array, cowslice::CowSlice, function::Function, grid_fmt::GridFmt, primitive::PrimDoc,
+    array::Array, cowslice::CowSlice, function::Function, lex::Span, primitive::PrimDoc,
     value::Value, Uiua, UiuaError, UiuaResult,
 };
 
@@ -103,16 +105,75 @@ sys_op! {
     /// Print a value to stdout followed by a newline
     (1(0), Print, "&p", "print with newline"),
     /// Read a line from stdin
+    ///
+    /// The trailing newline is not included.
+    /// At the end of input, the result is an empty string.
+    /// Use [&eof] to tell an empty line apart from the end of input.
     (0, ScanLine, "&sc", "scan line"),
+    /// Read the rest of stdin as a string
+    ///
+    /// If stdin is a terminal, this waits until the user ends the input.
+    (0, ScanAll, "&sca", "scan all"),
+    /// Read the rest of stdin as bytes
+    ///
+    /// If stdin is a terminal, this waits until the user ends the input.
+    (0, ScanBytes, "&scb", "scan bytes"),
+    /// Check if stdin has no more input
+    ///
+    /// If stdin is a terminal, this waits until the user types something or ends the input.
+    (0, Eof, "&eof", "end of input"),
+    /// Check if stdin is a terminal
+    ///
+    /// The result is 0 if stdin is a pipe or a file.
+    (0, IsTty, "&tty", "stdin is terminal"),
+    /// Set the terminal to raw mode
+    ///
+    /// Expects a boolean.
+    /// In raw mode, input is not echoed and is available to [&key] as soon as a key is pressed.
+    /// Raw mode is turned off when the program ends.
+    (1(0), RawMode, "&raw", "set raw mode"),
+    /// Read a key press from the terminal
+    ///
+    /// Expects a timeout in seconds. Use [infinity] to wait forever.
+    /// The result is the typed character, or the name of a special key like `"up"`, `"enter"`, `"escape"`, or `"f1"`.
+    /// Modifiers are prefixed, like `"ctrl-c"` or `"alt-x"`.
+    /// If no key is pressed before the timeout, the result is an empty string.
+    ///
+    /// Use [&raw] first to read keys without waiting for a newline.
+    (1, Key, "&key", "read key"),
     /// Get the size of the terminal
     ///
     /// The result is a 2-element array of the height and width of the terminal.
     /// Height comes first so that the array can be used as a shape in [reshape].
     (0, TermSize, "&ts", "terminal size"),
+    /// Style text with colors and attributes for the terminal
+    ///
+    /// Expects a style and a string.
+    /// The style is a string of space-separated names, or a list of them.
+    /// Available names are `bold`, `dim`, `italic`, `underline`, `blink`, `reverse`, and `strikethrough`,
+    /// and the colors `black`, `red`, `green`, `yellow`, `blue`, `magenta`, `cyan`, and `white`.
+    /// Colors can be prefixed with `bright-` and with `on-` to set the background, like `on-bright-blue`.
+    ///
+    /// If stdout is not a terminal, or if the `NO_COLOR` environment variable is set, the string is returned unchanged.
+    /// Unknown names are an error either way.
+    (2, Style, "&sty", "style text"),
     /// Get the command line arguments
     (0, Args, "&args", "arguments"),
     /// Get the value of an environment variable
+    ///
+    /// If the variable is not set, the result is an empty string.
     (1, Var, "&var", "environment variable"),
+    /// Set the value of an environment variable
+    ///
+    /// Expects the name and the value.
+    /// Commands run with [&runc] and [&runs] see the new value.
+    (2(0), SetVar, "&setvar", "set environment variable"),
+    /// Get the names of all environment variables, in order
+    (0, Vars, "&vars", "environment variables"),
+    /// Get the text on the clipboard
+    (0, Clip, "&clip", "get clipboard"),
+    /// Set the text on the clipboard
+    (1(0), SetClip, "&setclip", "set clipboard"),
     /// Open a file and return a handle to it
     (1, FOpen, "&fo", "file - open"),
     /// Create a file and return a handle to it
@@ -123,12 +184,54 @@ sys_op! {
     (1, FListDir, "&fld", "file - list directory"),
     /// Check if a path is a file
     (1, FIsFile, "&fif", "file - is file"),
+    /// Get the size of a file in bytes
+    (1, FSize, "&fsize", "file - size"),
+    /// Get the last modification time of a file
+    ///
+    /// The result is in seconds since the Unix epoch, like [&now].
+    (1, FModified, "&fmod", "file - modified time"),
+    /// Get the kind of a path
+    ///
+    /// The result is one of `"file"`, `"directory"`, or `"symlink"`.
+    /// Symlinks are not followed.
+    (1, FKind, "&fkind", "file - kind"),
+    /// Find the paths that match a glob pattern
+    ///
+    /// `*` matches any part of a name, `?` matches a single character, and `[abc]` matches one of the characters in brackets.
+    /// `**` matches any number of directories. It does not follow symlinks to directories.
+    /// Wildcards do not match names that start with a `.` unless the pattern does too.
+    /// The result is a sorted list of paths.
+    ///
+    /// For example, `&fglob "src/**/*.ua"` finds all the Uiua files in `src`.
+    (1, FGlob, "&fglob", "file - glob"),
+    /// Create a directory, along with any missing parent directories
+    (1(0), FMakeDir, "&fmd", "file - make directory"),
+    /// Remove a directory and everything in it
+    (1(0), FRemoveDir, "&frd", "file - remove directory"),
+    /// Wait for changes to a file or directory
+    ///
+    /// Expects a path and a timeout in seconds. Use [infinity] to wait forever.
+    /// Directories are watched recursively.
+    /// The result is a list of the paths that were created, modified, or removed.
+    /// If nothing changes before the timeout, the list is empty.
+    ///
+    /// For example, `&fwatch "src" ∞` waits until something in `src` changes.
+    (2, FWatch, "&fwatch", "file - watch"),
     /// Read all the contents of a file into a string
     (1, FReadAllStr, "&fras", "file - read all to string"),
     /// Read all the contents of a file into a byte array
     (1, FReadAllBytes, "&frab", "file - read all to bytes"),
     /// Write the entire contents of an array to a file
     (2(0), FWriteAll, "&fwa", "file - write all"),
+    /// Fold over the lines of a file without reading the whole file into memory
+    ///
+    /// Expects a function, an initial accumulator, and a path.
+    /// For each line, the function is called with the line and the accumulator, and its result becomes the new accumulator.
+    /// Lines are passed without their trailing newline.
+    /// [break] stops reading the file early.
+    ///
+    /// For example, `&ffl (+⧻) 0 "log.txt"` counts the characters in a file, excluding newlines.
+    (3, FFoldLines, "&ffl", "file - fold lines"),
     /// Read at most n bytes from a stream
     (2, ReadStr, "&rs", "read to string"),
     /// Read at most n bytes from a stream
@@ -150,6 +253,13 @@ sys_op! {
     ///
     /// ex: &n
     (0, Now, "&n", "now"),
+    /// Get the time in seconds from a clock that never goes backwards
+    ///
+    /// Unlike [&n], this is not affected by changes to the system clock, so it is better for measuring how long something takes.
+    /// The starting point is unspecified, so only differences between times are meaningful.
+    ///
+    /// ex: &mono
+    (0, Monotonic, "&mono", "monotonic time"),
     /// Decode an image from a byte array
     ///
     /// Supported formats are `jpg`, `png`, `bmp`, `gif`, and `ico`.
@@ -174,7 +284,10 @@ sys_op! {
     ///
     /// How the image is shown depends on the system backend.
     ///
-    /// In the default backend, the image is shown in the terminal.
+    /// In the default backend, the image is shown in the terminal if it supports the kitty or iTerm graphics protocols.
+    /// Sixel is supported too if the interpreter is built with the `sixel` feature.
+    /// Otherwise, the image is drawn in the terminal with text characters.
+    /// If the interpreter is built with the `image_window` feature, the image is opened in the system's image viewer instead.
     /// On the web, the image is shown in the output area.
     ///
     /// The image must be a rank 2 or 3 numeric array.
@@ -214,7 +327,15 @@ sys_op! {
     ///
     /// The samples must be between -1 and 1.
     /// The sample rate is [&asr].
+    /// Use [&apr] to play audio at a different sample rate.
     (1(0), AudioPlay, "&ap", "audio - play"),
+    /// Play some audio at a sample rate
+    ///
+    /// Expects a sample rate and the audio, which is like for [&ap].
+    /// The audio is resampled to the output device's rate if necessary.
+    ///
+    /// For example, `&apr 8000 ○×τ×440 ÷8000 ⇡8000` plays one second of a 440 Hz tone.
+    (2(0), AudioPlayRate, "&apr", "audio - play at sample rate"),
     /// Get the sample rate of the audio output backend
     ///
     /// ex: &asr
@@ -228,16 +349,35 @@ sys_op! {
     /// Expects a function that takes a list of sample times and returns a list of samples.
     /// The function will be called repeatedly to generate the audio.
     (1(0), AudioStream, "&ast", "audio - stream"),
+    /// Record audio from the default input device
+    ///
+    /// Expects a number of seconds to record.
+    /// The result is a list of mono samples between -1 and 1, with the sample rate of the recording below it.
+    /// Recording a short amount of time in a loop is a way to process audio as it comes in.
+    ///
+    /// For example, `/↥⌵ &arec 1` gets the peak volume of one second of audio.
+    (1(2), AudioRecord, "&arec", "audio - record"),
     /// Sleep for n seconds
     ///
+    /// Fractions of a millisecond are respected where the system allows.
+    /// Interrupting the runtime stops the sleep early.
+    ///
     /// On the web, this example will hang for 1 second.
     /// ex: ⚂ &sl 1
     (1(0), Sleep, "&sl", "sleep"),
     /// Create a TCP listener and bind it to an address
+    ///
+    /// Binding to port 0, like `&tcpl "127.0.0.1:0"`, picks a free port.
+    /// Use [&tcpaddr] to find out which one.
     (1, TcpListen, "&tcpl", "tcp - listen"),
     /// Accept a connection with a TCP listener
+    ///
+    /// This waits for a connection and returns a handle to a socket for it.
     (1, TcpAccept, "&tcpa", "tcp - accept"),
     /// Create a TCP socket and connect it to an address
+    ///
+    /// The socket's handle can be used with [&rs], [&rb], [&ru], and [&w] to
+    /// read and write, and with [&cl] to close the connection.
     (1, TcpConnect, "&tcpc", "tcp - connect"),
     /// Set a TCP socket to non-blocking mode
     (1, TcpSetNonBlocking, "&tcpsnb", "tcp - set non-blocking"),
@@ -245,14 +385,255 @@ sys_op! {
     (2(0), TcpSetReadTimeout, "&tcpsrt", "tcp - set read timeout"),
     /// Set the write timeout of a TCP socket in seconds
     (2(0), TcpSetWriteTimeout, "&tcpswt", "tcp - set write timeout"),
-    /// Get the connection address of a TCP socket
+    /// Get the address of a TCP socket or listener
+    ///
+    /// For a socket, this is the address of the other end of the connection.
+    /// For a listener, this is the address it is bound to.
     (1, TcpAddr, "&tcpaddr", "tcp - address"),
+    /// Upgrade a TCP socket to use TLS
+    ///
+    /// Expects the verification options, the name of the server, and the socket's handle.
+    /// The socket keeps its handle, and reading and writing it are encrypted afterwards.
+    ///
+    /// With empty options like `[]`, the server's certificate is verified against
+    /// the standard root certificates.
+    /// If the options are a PEM string, only the certificates in it are trusted,
+    /// which is useful for servers with self-signed certificates.
+    /// If the options are `0`, the certificate is not verified at all.
+    /// This is insecure and should only be used for testing.
+    ///
+    /// For example, `&tlsu [] "example.com" &tcpc "example.com:443"` connects to an HTTPS server.
+    (3, TlsUpgrade, "&tlsu", "tls - upgrade"),
+    /// Connect to a WebSocket server
+    ///
+    /// Expects a URL starting with `ws://` or `wss://`, and returns a handle to the connection.
+    /// Close the connection with [&cl].
+    (1, WsConnect, "&wsc", "websocket - connect"),
+    /// Send a message over a WebSocket
+    ///
+    /// Expects the message and the connection's handle.
+    /// Strings are sent as text messages, and byte arrays as binary messages.
+    (2(0), WsSend, "&wss", "websocket - send"),
+    /// Receive a message over a WebSocket
+    ///
+    /// Expects the connection's handle.
+    /// Text messages become strings, and binary messages become byte arrays.
+    ///
+    /// In the default backend, this waits until a message arrives.
+    /// On the web, it is an error if no message has arrived yet.
+    (1, WsReceive, "&wsr", "websocket - receive"),
+    /// Run a command and wait for it to finish
+    ///
+    /// Expects the command and the text to write to its standard input.
+    /// The command is either a string naming a program, or a list of the program and its arguments,
+    /// like `{"git" "status" "--short"}`.
+    ///
+    /// Pushes the command's standard output, then its standard error, then its exit code.
+    /// A nonzero exit code is not an error in Uiua, so check the code.
+    ///
+    /// For example, `&runc {"sort"} "b\na\n"` sorts some lines.
+    (2(3), RunCapture, "&runc", "run command - capture"),
+    /// Start a command without waiting for it to finish
+    ///
+    /// Expects the same arguments as [&runc], and returns a handle.
+    /// Use [wait] on the handle to get the command's standard output,
+    /// standard error, and exit code.
+    (2, RunSpawn, "&runs", "run command - spawn"),
+    /// Create a UDP socket and bind it to an address
+    ///
+    /// Binding to port 0, like `&udpb "127.0.0.1:0"`, picks a free port.
+    /// Use [&udpaddr] to find out which one.
+    (1, UdpBind, "&udpb", "udp - bind"),
+    /// Send a datagram with a UDP socket
+    ///
+    /// Expects the data, the address to send it to, and the socket's handle.
+    (3(0), UdpSendTo, "&udpst", "udp - send to"),
+    /// Receive a datagram with a UDP socket
+    ///
+    /// Expects the maximum number of bytes to receive and the socket's handle.
+    /// Bytes past the maximum are discarded.
+    /// Pushes the received bytes, then the address they came from.
+    (2(2), UdpReceiveFrom, "&udprf", "udp - receive from"),
+    /// Get the address a UDP socket is bound to
+    (1, UdpAddr, "&udpaddr", "udp - address"),
+    /// Make an HTTP request
+    ///
+    /// Expects a method, a URL, a list of headers, and a body.
+    /// Each header is a string like `"Content-Type: application/json"`.
+    /// The headers and the body may be empty.
+    /// HTTPS URLs are supported.
+    ///
+    /// Pushes the body of the response as bytes, then its headers, then its status code.
+    /// A status that indicates an error, like 404, is not an error in Uiua, so check the status.
+    ///
+    /// For example, `&http "GET" "https://example.com" [] []` gets a web page, and
+    /// `&http "POST" "https://example.com/api" {"Content-Type: text/plain"} "hello"` sends some text.
+    (4(3), Http, "&http", "http - request"),
     /// Close a stream by its handle
     ///
-    /// This will close files, tcp listeners, and tcp sockets.
+    /// This will close files, tcp listeners, tcp and tls sockets, udp sockets, and websockets.
     (1(0), Close, "&cl", "close"),
 }
 
+/// An HTTP request made with &http
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HttpRequest {
+    /// The method, like `GET` or `POST`
+    pub method: String,
+    pub url: String,
+    /// The header names and values
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+/// The response to an [`HttpRequest`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HttpResponse {
+    pub status: u16,
+    /// The header names and values
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+/// The result of running a command
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CommandOutput {
+    /// The exit code, or -1 if the command was stopped by a signal
+    pub status: i32,
+    pub stdout: Vec<u8>,
+    pub stderr: Vec<u8>,
+}
+
+impl CommandOutput {
+    fn into_values(self) -> Vec<Value> {
+        vec![
+            String::from_utf8_lossy(&self.stdout).into_owned().into(),
+            String::from_utf8_lossy(&self.stderr).into_owned().into(),
+            (self.status as f64).into(),
+        ]
+    }
+}
+
+/// Information about a file or directory
+#[derive(Debug, Clone, PartialEq)]
+pub struct FileMetadata {
+    pub kind: FileKind,
+    /// The size in bytes
+    pub size: u64,
+    /// The last modification time in seconds since the Unix epoch, if known
+    pub modified: Option<f64>,
+}
+
+/// The kind of a path
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileKind {
+    File,
+    Directory,
+    Symlink,
+}
+
+impl FileKind {
+    fn name(&self) -> &'static str {
+        match self {
+            FileKind::File => "file",
+            FileKind::Directory => "directory",
+            FileKind::Symlink => "symlink",
+        }
+    }
+}
+
+/// A WebSocket message
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WsMessage {
+    Text(String),
+    Binary(Vec<u8>),
+}
+
+/// How the certificate of a TLS server is verified
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TlsVerification {
+    /// Verify against the standard root certificates
+    Default,
+    /// Trust only the certificates in some PEM data
+    Certificates(Vec<u8>),
+    /// Do not verify the certificate
+    None,
+}
+
+/// A kind of system access that a runtime can be granted
+///
+/// Each system function that needs one checks for it when it is called.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Sequence)]
+pub enum Capability {
+    /// Read files and list directories
+    ReadFiles,
+    /// Create and write files
+    WriteFiles,
+    /// Open network connections and listen for them
+    Network,
+    /// Run other programs
+    Subprocess,
+    /// Play and record audio
+    Audio,
+    /// Read command line arguments and environment variables
+    Environment,
+    /// Read and write the clipboard
+    Clipboard,
+    /// Show images in windows or the terminal
+    Display,
+}
+
+impl Capability {
+    /// Get the name of the capability, like `read-files`
+    pub fn name(&self) -> &'static str {
+        match self {
+            Capability::ReadFiles => "read-files",
+            Capability::WriteFiles => "write-files",
+            Capability::Network => "network",
+            Capability::Subprocess => "subprocess",
+            Capability::Audio => "audio",
+            Capability::Environment => "environment",
+            Capability::Clipboard => "clipboard",
+            Capability::Display => "display",
+        }
+    }
+}
+
+impl fmt::Display for Capability {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Capability {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        enum_iterator::all::<Capability>()
+            .find(|cap| cap.name() == s)
+            .ok_or_else(|| format!("unknown capability `{s}`"))
+    }
+}
+
+/// Read from a stream one byte at a time until a delimiter is reached
+fn read_until_bytewise<B: SysBackend + ?Sized>(
+    backend: &B,
+    handle: Handle,
+    delim: &[u8],
+) -> Result<Vec<u8>, String> {
+    let mut buffer = Vec::new();
+    loop {
+        let bytes = backend.read(handle, 1)?;
+        if bytes.is_empty() {
+            break;
+        }
+        buffer.extend_from_slice(&bytes);
+        if buffer.ends_with(delim) {
+            break;
+        }
+    }
+    Ok(buffer)
+}
+
 /// A handle to an IO stream
 ///
 /// 0 is stdin, 1 is stdout, 2 is stderr.
@@ -262,9 +643,9 @@ sys_op! {
 pub struct Handle(pub u64);
 
 impl Handle {
-    const STDIN: Self = Self(0);
-    const STDOUT: Self = Self(1);
-    const STDERR: Self = Self(2);
+    pub(crate) const STDIN: Self = Self(0);
+    pub(crate) const STDOUT: Self = Self(1);
+    pub(crate) const STDERR: Self = Self(2);
     pub const FIRST_UNRESERVED: Self = Self(3);
 }
 
@@ -282,6 +663,13 @@ impl From<Handle> for Value {
 
 type AudioStreamFn = Box<dyn FnMut(Vec<f64>) -> UiuaResult<Vec<[f64; 2]>> + Send>;
 
+/// The environment a runtime interacts with
+///
+/// Every system function goes through the runtime's backend, so tests,
+/// the browser, and embedders can substitute their own environment with
+/// [`Uiua::with_backend`]. [`NativeSys`] is the default.
+///
+/// Most methods default to an error saying the operation is not supported.
 #[allow(unused_variables)]
 pub trait SysBackend: Any + Send + Sync + 'static {
     fn any(&self) -> &dyn Any;
@@ -300,9 +688,53 @@ pub trait SysBackend: Any + Send + Sync + 'static {
     fn scan_line_stdin(&self) -> Result<String, String> {
         Err("Reading from stdin is not supported in this environment".into())
     }
+    /// Read a line from stdin, or `None` at the end of input
+    ///
+    /// By default, this reads a line with [`SysBackend::scan_line_stdin`]
+    /// and never reaches the end of input.
+    fn scan_line_stdin_or_eof(&self) -> Result<Option<String>, String> {
+        self.scan_line_stdin().map(Some)
+    }
+    /// Read the rest of stdin
+    fn scan_all_stdin(&self) -> Result<Vec<u8>, String> {
+        Err("Reading from stdin is not supported in this environment".into())
+    }
+    /// Check if stdin has no more input
+    fn stdin_eof(&self) -> Result<bool, String> {
+        Err("Reading from stdin is not supported in this environment".into())
+    }
+    fn stdin_is_terminal(&self) -> bool {
+        false
+    }
+    /// Check if text printed to stdout can be styled with ANSI escape codes
+    fn stdout_supports_color(&self) -> bool {
+        false
+    }
+    fn set_raw_mode(&self, raw: bool) -> Result<(), String> {
+        Err("Raw mode is not supported in this environment".into())
+    }
+    /// Read a key press, or `None` if the timeout passes first
+    ///
+    /// A timeout of `None` waits forever.
+    fn read_key(&self, timeout: Option<f64>) -> Result<Option<String>, String> {
+        Err("Reading keys is not supported in this environment".into())
+    }
     fn var(&self, name: &str) -> Option<String> {
         None
     }
+    fn set_var(&self, name: &str, value: &str) -> Result<(), String> {
+        Err("Setting environment variables is not supported in this environment".into())
+    }
+    /// Get the names of all environment variables
+    fn var_names(&self) -> Vec<String> {
+        Vec::new()
+    }
+    fn clipboard(&self) -> Result<String, String> {
+        Err("Getting the clipboard is not supported in this environment".into())
+    }
+    fn set_clipboard(&self, contents: &str) -> Result<(), String> {
+        Err("Setting the clipboard is not supported in this environment".into())
+    }
     fn term_size(&self) -> Result<(usize, usize), String> {
         Err("Getting the terminal size is not supported in this environment".into())
     }
@@ -318,22 +750,31 @@ pub trait SysBackend: Any + Send + Sync + 'static {
     fn is_file(&self, path: &str) -> Result<bool, String> {
         Err("This IO operation is not supported in this environment".into())
     }
+    /// Get information about a path
+    ///
+    /// Symlinks are not followed for the kind, but are for the size and modification time.
+    fn metadata(&self, path: &str) -> Result<FileMetadata, String> {
+        Err("This IO operation is not supported in this environment".into())
+    }
+    /// Create a directory and its missing parents
+    fn create_dir(&self, path: &str) -> Result<(), String> {
+        Err("This IO operation is not supported in this environment".into())
+    }
+    /// Remove a directory and everything in it
+    fn remove_dir(&self, path: &str) -> Result<(), String> {
+        Err("This IO operation is not supported in this environment".into())
+    }
+    /// Wait for changes to a path, returning the paths that changed
+    ///
+    /// A timeout of `None` waits forever.
+    fn watch(&self, path: &str, timeout: Option<f64>) -> Result<Vec<String>, String> {
+        Err("Watching files is not supported in this environment".into())
+    }
     fn read(&self, handle: Handle, count: usize) -> Result<Vec<u8>, String> {
         Err("This IO operation is not supported in this environment".into())
     }
     fn read_until(&self, handle: Handle, delim: &[u8]) -> Result<Vec<u8>, String> {
-        let mut buffer = Vec::new();
-        loop {
-            let bytes = self.read(handle, 1)?;
-            if bytes.is_empty() {
-                break;
-            }
-            buffer.extend_from_slice(&bytes);
-            if buffer.ends_with(delim) {
-                break;
-            }
-        }
-        Ok(buffer)
+        read_until_bytewise(self, handle, delim)
     }
     fn write(&self, handle: Handle, contents: &[u8]) -> Result<(), String> {
         Err("This IO operation is not supported in this environment".into())
@@ -359,6 +800,19 @@ pub trait SysBackend: Any + Send + Sync + 'static {
     fn sleep(&self, seconds: f64) -> Result<(), String> {
         Err("Sleeping is not supported in this environment".into())
     }
+    /// Get the current time in seconds
+    fn now(&self) -> f64 {
+        instant::now() / 1000.0
+    }
+    /// Get the time in seconds from a monotonic clock
+    fn monotonic_now(&self) -> f64 {
+        static START: Lazy<instant::Instant> = Lazy::new(instant::Instant::now);
+        START.elapsed().as_secs_f64()
+    }
+    /// Get a seed for the random number generator of a new runtime
+    fn random_seed(&self) -> u64 {
+        instant::now().to_bits()
+    }
     fn show_image(&self, image: DynamicImage) -> Result<(), String> {
         Err("Showing images not supported in this environment".into())
     }
@@ -371,6 +825,10 @@ pub trait SysBackend: Any + Send + Sync + 'static {
     fn stream_audio(&self, f: AudioStreamFn) -> Result<(), String> {
         Err("Streaming audio not supported in this environment".into())
     }
+    /// Record audio, returning the mono samples and the sample rate
+    fn record_audio(&self, seconds: f64) -> Result<(Vec<f64>, u32), String> {
+        Err("Recording audio not supported in this environment".into())
+    }
     fn tcp_listen(&self, addr: &str) -> Result<Handle, String> {
         Err("TCP listeners are not supported in this environment".into())
     }
@@ -383,6 +841,56 @@ pub trait SysBackend: Any + Send + Sync + 'static {
     fn tcp_addr(&self, handle: Handle) -> Result<String, String> {
         Err("TCP sockets are not supported in this environment".into())
     }
+    fn tls_upgrade(
+        &self,
+        handle: Handle,
+        server_name: &str,
+        verification: TlsVerification,
+    ) -> Result<(), String> {
+        Err("TLS is not supported in this environment".into())
+    }
+    /// Run a command and wait for it to finish
+    fn run_command(
+        &self,
+        command: &str,
+        args: &[String],
+        stdin: &[u8],
+    ) -> Result<CommandOutput, String> {
+        Err("Running commands is not supported in this environment".into())
+    }
+    /// Start a command and return a handle that can be waited on like a thread
+    fn spawn_command(
+        &self,
+        command: &str,
+        args: &[String],
+        stdin: &[u8],
+    ) -> Result<Handle, String> {
+        Err("Running commands is not supported in this environment".into())
+    }
+    fn ws_connect(&self, url: &str) -> Result<Handle, String> {
+        Err("WebSockets are not supported in this environment".into())
+    }
+    fn ws_send(&self, handle: Handle, message: WsMessage) -> Result<(), String> {
+        Err("WebSockets are not supported in this environment".into())
+    }
+    fn ws_receive(&self, handle: Handle) -> Result<WsMessage, String> {
+        Err("WebSockets are not supported in this environment".into())
+    }
+    fn udp_bind(&self, addr: &str) -> Result<Handle, String> {
+        Err("UDP sockets are not supported in this environment".into())
+    }
+    fn udp_send_to(&self, handle: Handle, addr: &str, data: &[u8]) -> Result<(), String> {
+        Err("UDP sockets are not supported in this environment".into())
+    }
+    fn udp_receive_from(&self, handle: Handle, max: usize) -> Result<(Vec<u8>, String), String> {
+        Err("UDP sockets are not supported in this environment".into())
+    }
+    fn udp_addr(&self, handle: Handle) -> Result<String, String> {
+        Err("UDP sockets are not supported in this environment".into())
+    }
+    fn http_request(&self, request: HttpRequest) -> Result<HttpResponse, String> {
+        Err("HTTP requests are not supported in this environment".into())
+    }
     fn tcp_set_non_blocking(&self, handle: Handle, non_blocking: bool) -> Result<(), String> {
         Err("TCP sockets are not supported in this environment".into())
     }
@@ -403,6 +911,14 @@ pub trait SysBackend: Any + Send + Sync + 'static {
     fn close(&self, handle: Handle) -> Result<(), String> {
         Ok(())
     }
+    /// Get the capability needed to use the stream with the given handle
+    ///
+    /// This is [`Capability::ReadFiles`] for files and [`Capability::Network`]
+    /// for sockets. Streams that return `None` can only be used by runtimes
+    /// that have every capability.
+    fn stream_capability(&self, handle: Handle) -> Option<Capability> {
+        None
+    }
     fn spawn(
         &self,
         env: Uiua,
@@ -427,20 +943,184 @@ struct GlobalNativeSys {
     files: DashMap<Handle, Buffered<File>>,
     tcp_listeners: DashMap<Handle, TcpListener>,
     tcp_sockets: DashMap<Handle, Buffered<TcpStream>>,
+    #[cfg(feature = "tls")]
+    tls_sockets: DashMap<Handle, Buffered<TlsStream>>,
+    udp_sockets: DashMap<Handle, UdpSocket>,
+    #[cfg(feature = "websocket")]
+    websockets: DashMap<Handle, Arc<Mutex<NativeWebSocket>>>,
     threads: DashMap<Handle, JoinHandle<UiuaResult<Vec<Value>>>>,
     #[cfg(feature = "audio")]
     audio_stream_time: Mutex<Option<f64>>,
     #[cfg(feature = "audio")]
     audio_time_socket: Mutex<Option<Arc<std::net::UdpSocket>>>,
     colored_errors: DashMap<String, String>,
+    /// Environment variables set by programs
+    ///
+    /// These are not set in the process's own environment, because doing so
+    /// is not thread-safe.
+    vars: DashMap<String, String>,
+    stdin: Mutex<NativeStdin>,
+    #[cfg(feature = "raw_mode")]
+    raw_mode: atomic::AtomicBool,
+    /// Kept open because on some platforms, the clipboard's contents are lost when it closes
+    #[cfg(feature = "clipboard")]
+    clipboard: Mutex<Option<arboard::Clipboard>>,
+}
+
+/// Where the native system reads standard input from
+pub enum NativeStdin {
+    /// The process's standard input
+    Inherit,
+    /// Some other reader, like a file
+    Reader(BufReader<Box<dyn Read + Send + Sync>>),
+    /// Reading standard input is an error
+    Disabled,
+}
+
+/// Set where the native system reads standard input from
+///
+/// This is useful when the process's standard input is used for something
+/// else, like the program itself.
+pub fn set_native_stdin(stdin: NativeStdin) {
+    *NATIVE_SYS.stdin.lock() = stdin;
+}
+
+/// Turn off raw mode if a program turned it on
+///
+/// This should be called before the process exits.
+pub fn restore_native_terminal() {
+    #[cfg(feature = "raw_mode")]
+    if NATIVE_SYS.raw_mode.swap(false, atomic::Ordering::Relaxed) {
+        _ = crossterm::terminal::disable_raw_mode();
+    }
+}
+
+/// Get the name of a key press, or `None` for keys that have no name
+#[cfg(feature = "raw_mode")]
+fn key_name(key: crossterm::event::KeyEvent) -> Option<String> {
+    use crossterm::event::{KeyCode, KeyModifiers};
+    let name = match key.code {
+        KeyCode::Char(c) => c.to_string(),
+        KeyCode::F(n) => format!("f{n}"),
+        KeyCode::Enter => "enter".into(),
+        KeyCode::Backspace => "backspace".into(),
+        KeyCode::Tab => "tab".into(),
+        KeyCode::BackTab => "backtab".into(),
+        KeyCode::Esc => "escape".into(),
+        KeyCode::Up => "up".into(),
+        KeyCode::Down => "down".into(),
+        KeyCode::Left => "left".into(),
+        KeyCode::Right => "right".into(),
+        KeyCode::Home => "home".into(),
+        KeyCode::End => "end".into(),
+        KeyCode::PageUp => "pageup".into(),
+        KeyCode::PageDown => "pagedown".into(),
+        KeyCode::Insert => "insert".into(),
+        KeyCode::Delete => "delete".into(),
+        _ => return None,
+    };
+    let mut prefix = String::new();
+    if key.modifiers.contains(KeyModifiers::CONTROL) {
+        prefix.push_str("ctrl-");
+    }
+    if key.modifiers.contains(KeyModifiers::ALT) {
+        prefix.push_str("alt-");
+    }
+    if key.modifiers.contains(KeyModifiers::SHIFT) && !matches!(key.code, KeyCode::Char(_)) {
+        prefix.push_str("shift-");
+    }
+    Some(prefix + &name)
+}
+
+/// Use the native system's clipboard, opening it if necessary
+#[cfg(feature = "clipboard")]
+fn with_native_clipboard<T>(
+    f: impl FnOnce(&mut arboard::Clipboard) -> Result<T, arboard::Error>,
+) -> Result<T, String> {
+    let mut clipboard = NATIVE_SYS.clipboard.lock();
+    if clipboard.is_none() {
+        *clipboard = Some(arboard::Clipboard::new().map_err(|e| e.to_string())?);
+    }
+    f(clipboard.as_mut().unwrap()).map_err(|e| e.to_string())
+}
+
+/// Check if the terminal can show images with a graphics protocol
+#[cfg(feature = "terminal_image")]
+fn terminal_supports_graphics() -> bool {
+    #[cfg(feature = "sixel")]
+    if viuer::is_sixel_supported() {
+        return true;
+    }
+    !matches!(viuer::get_kitty_support(), viuer::KittySupport::None) || viuer::is_iterm_supported()
+}
+
+/// Print an image to the terminal, scaled to fit
+#[cfg(feature = "terminal_image")]
+fn print_terminal_image(image: &DynamicImage) -> Result<(), String> {
+    let (width, height) = if let Some((w, h)) = term_size::dimensions() {
+        let (tw, th) = (w as u32, h.saturating_sub(1) as u32);
+        let (iw, ih) = (image.width(), image.height() / 2);
+        let scaled_to_height = (iw * th / ih, th);
+        let scaled_to_width = (tw, ih * tw / iw);
+        let (w, h) = if scaled_to_height.0 <= tw {
+            scaled_to_height
+        } else {
+            scaled_to_width
+        };
+        (Some(w), Some(h))
+    } else {
+        (None, None)
+    };
+    viuer::print(
+        image,
+        &viuer::Config {
+            width,
+            height,
+            absolute_offset: false,
+            transparent: true,
+            ..Default::default()
+        },
+    )
+    .map(drop)
+    .map_err(|e| format!("Failed to show image: {e}"))
+}
+
+/// Save an image to a temporary file and open it in the system's image viewer
+#[cfg(feature = "image_window")]
+fn open_image_window(image: &DynamicImage) -> Result<(), String> {
+    static NEXT_IMAGE: AtomicU64 = AtomicU64::new(0);
+    let number = NEXT_IMAGE.fetch_add(1, atomic::Ordering::Relaxed);
+    let path = env::temp_dir().join(format!("uiua-image-{}-{number}.png", std::process::id()));
+    image
+        .save_with_format(&path, image::ImageFormat::Png)
+        .map_err(|e| format!("Failed to save image: {e}"))?;
+    open::that(&path).map_err(|e| format!("Failed to open image: {e}"))
+}
+
+/// Read from the native system's standard input
+fn with_stdin<T>(f: impl FnOnce(&mut dyn BufRead) -> io::Result<T>) -> Result<T, String> {
+    match &mut *NATIVE_SYS.stdin.lock() {
+        NativeStdin::Inherit => f(&mut stdin().lock()),
+        NativeStdin::Reader(reader) => f(reader),
+        NativeStdin::Disabled => return Err("Reading from stdin is disabled".into()),
+    }
+    .map_err(|e| e.to_string())
 }
 
 enum SysStream<'a> {
     File(dashmap::mapref::one::RefMut<'a, Handle, Buffered<File>>),
     TcpListener(dashmap::mapref::one::RefMut<'a, Handle, TcpListener>),
     TcpSocket(dashmap::mapref::one::RefMut<'a, Handle, Buffered<TcpStream>>),
+    #[cfg(feature = "tls")]
+    TlsSocket(dashmap::mapref::one::RefMut<'a, Handle, Buffered<TlsStream>>),
 }
 
+#[cfg(feature = "websocket")]
+type NativeWebSocket = tungstenite::WebSocket<tungstenite::stream::MaybeTlsStream<TcpStream>>;
+
+#[cfg(feature = "tls")]
+type TlsStream = rustls::StreamOwned<rustls::ClientConnection, TcpStream>;
+
 impl Default for GlobalNativeSys {
     fn default() -> Self {
         Self {
@@ -448,12 +1128,23 @@ impl Default for GlobalNativeSys {
             files: DashMap::new(),
             tcp_listeners: DashMap::new(),
             tcp_sockets: DashMap::new(),
+            #[cfg(feature = "tls")]
+            tls_sockets: DashMap::new(),
+            udp_sockets: DashMap::new(),
+            #[cfg(feature = "websocket")]
+            websockets: DashMap::new(),
             threads: DashMap::new(),
             #[cfg(feature = "audio")]
             audio_stream_time: Mutex::new(None),
             #[cfg(feature = "audio")]
             audio_time_socket: Mutex::new(None),
             colored_errors: DashMap::new(),
+            vars: DashMap::new(),
+            stdin: Mutex::new(NativeStdin::Inherit),
+            #[cfg(feature = "raw_mode")]
+            raw_mode: atomic::AtomicBool::new(false),
+            #[cfg(feature = "clipboard")]
+            clipboard: Mutex::new(None),
         }
     }
 }
@@ -465,12 +1156,67 @@ impl GlobalNativeSys {
             if !self.files.contains_key(&handle)
                 && !self.tcp_listeners.contains_key(&handle)
                 && !self.tcp_sockets.contains_key(&handle)
+                && !self.udp_sockets.contains_key(&handle)
+                && !self.is_tls_socket(handle)
+                && !self.is_websocket(handle)
             {
                 return handle;
             }
         }
         panic!("Ran out of file handles");
     }
+    #[cfg(feature = "websocket")]
+    fn is_websocket(&self, handle: Handle) -> bool {
+        self.websockets.contains_key(&handle)
+    }
+    #[cfg(feature = "websocket")]
+    fn websocket(&self, handle: Handle) -> Result<Arc<Mutex<NativeWebSocket>>, String> {
+        (self.websockets.get(&handle))
+            .map(|socket| socket.clone())
+            .ok_or_else(|| "Invalid websocket handle".to_string())
+    }
+    #[cfg(not(feature = "websocket"))]
+    fn is_websocket(&self, _: Handle) -> bool {
+        false
+    }
+    #[cfg(feature = "websocket")]
+    fn remove_websocket(&self, handle: Handle) -> bool {
+        let Some((_, socket)) = self.websockets.remove(&handle) else {
+            return false;
+        };
+        let mut socket = socket.lock();
+        _ = socket.close(None);
+        _ = socket.flush();
+        true
+    }
+    #[cfg(not(feature = "websocket"))]
+    fn remove_websocket(&self, _: Handle) -> bool {
+        false
+    }
+    #[cfg(feature = "tls")]
+    fn remove_tls_socket(&self, handle: Handle) -> bool {
+        self.tls_sockets.remove(&handle).is_some()
+    }
+    #[cfg(not(feature = "tls"))]
+    fn remove_tls_socket(&self, _: Handle) -> bool {
+        false
+    }
+    #[cfg(feature = "tls")]
+    fn is_tls_socket(&self, handle: Handle) -> bool {
+        self.tls_sockets.contains_key(&handle)
+    }
+    #[cfg(not(feature = "tls"))]
+    fn is_tls_socket(&self, _: Handle) -> bool {
+        false
+    }
+    #[cfg(feature = "tls")]
+    fn tls_socket(&self, handle: Handle) -> Option<SysStream> {
+        self.tls_sockets.get_mut(&handle).map(SysStream::TlsSocket)
+    }
+    #[cfg(not(feature = "tls"))]
+    fn tls_socket(&self, _: Handle) -> Option<SysStream> {
+        None
+    }
     fn get_stream(&self, handle: Handle) -> Result<SysStream, String> {
         Ok(if let Some(file) = self.files.get_mut(&handle) {
             SysStream::File(file)
@@ -478,12 +1224,42 @@ impl GlobalNativeSys {
             SysStream::TcpListener(listener)
         } else if let Some(socket) = self.tcp_sockets.get_mut(&handle) {
             SysStream::TcpSocket(socket)
+        } else if let Some(socket) = self.tls_socket(handle) {
+            socket
         } else {
             return Err("Invalid file handle".to_string());
         })
     }
 }
 
+fn start_command(command: &str, args: &[String]) -> Result<Child, String> {
+    let vars = (NATIVE_SYS.vars.iter()).map(|entry| (entry.key().clone(), entry.value().clone()));
+    Command::new(command)
+        .args(args)
+        .envs(vars)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("Failed to run {command}: {e}"))
+}
+
+fn finish_command(mut child: Child, stdin: Vec<u8>) -> Result<CommandOutput, String> {
+    // Stdin is written on another thread so that a command
+    // that fills its output pipes before reading all of its input cannot deadlock
+    let mut child_stdin = child.stdin.take().unwrap();
+    let writer = spawn(move || {
+        _ = child_stdin.write_all(&stdin);
+    });
+    let output = child.wait_with_output().map_err(|e| e.to_string())?;
+    _ = writer.join();
+    Ok(CommandOutput {
+        status: output.status.code().unwrap_or(-1),
+        stdout: output.stdout,
+        stderr: output.stderr,
+    })
+}
+
 static NATIVE_SYS: Lazy<GlobalNativeSys> = Lazy::new(Default::default);
 
 #[cfg(feature = "audio")]
@@ -516,12 +1292,72 @@ impl SysBackend for NativeSys {
             .map_err(|e| e.to_string())
     }
     fn scan_line_stdin(&self) -> Result<String, String> {
-        stdin()
-            .lock()
-            .lines()
-            .next()
-            .unwrap()
-            .map_err(|e| e.to_string())
+        self.scan_line_stdin_or_eof().map(Option::unwrap_or_default)
+    }
+    fn scan_line_stdin_or_eof(&self) -> Result<Option<String>, String> {
+        with_stdin(|stdin| {
+            let mut line = String::new();
+            if stdin.read_line(&mut line)? == 0 {
+                return Ok(None);
+            }
+            if line.ends_with('\n') {
+                line.pop();
+                if line.ends_with('\r') {
+                    line.pop();
+                }
+            }
+            Ok(Some(line))
+        })
+    }
+    fn scan_all_stdin(&self) -> Result<Vec<u8>, String> {
+        with_stdin(|stdin| {
+            let mut bytes = Vec::new();
+            stdin.read_to_end(&mut bytes)?;
+            Ok(bytes)
+        })
+    }
+    fn stdin_eof(&self) -> Result<bool, String> {
+        with_stdin(|stdin| Ok(stdin.fill_buf()?.is_empty()))
+    }
+    fn stdin_is_terminal(&self) -> bool {
+        match &*NATIVE_SYS.stdin.lock() {
+            NativeStdin::Inherit => stdin().is_terminal(),
+            NativeStdin::Reader(_) | NativeStdin::Disabled => false,
+        }
+    }
+    fn stdout_supports_color(&self) -> bool {
+        stdout().is_terminal() && env::var_os("NO_COLOR").is_none()
+    }
+    #[cfg(feature = "raw_mode")]
+    fn set_raw_mode(&self, raw: bool) -> Result<(), String> {
+        if raw {
+            crossterm::terminal::enable_raw_mode()
+        } else {
+            crossterm::terminal::disable_raw_mode()
+        }
+        .map_err(|e| e.to_string())?;
+        NATIVE_SYS.raw_mode.store(raw, atomic::Ordering::Relaxed);
+        Ok(())
+    }
+    #[cfg(feature = "raw_mode")]
+    fn read_key(&self, timeout: Option<f64>) -> Result<Option<String>, String> {
+        use crossterm::event::{self, Event, KeyEventKind};
+        let start = std::time::Instant::now();
+        loop {
+            if let Some(timeout) = timeout {
+                let remaining = (timeout - start.elapsed().as_secs_f64()).max(0.0);
+                if !event::poll(Duration::from_secs_f64(remaining)).map_err(|e| e.to_string())? {
+                    return Ok(None);
+                }
+            }
+            if let Event::Key(key) = event::read().map_err(|e| e.to_string())? {
+                if key.kind != KeyEventKind::Release {
+                    if let Some(name) = key_name(key) {
+                        return Ok(Some(name));
+                    }
+                }
+            }
+        }
     }
     fn save_error_color(&self, error: &UiuaError) {
         NATIVE_SYS
@@ -533,7 +1369,34 @@ impl SysBackend for NativeSys {
         Ok((w, h.saturating_sub(1)))
     }
     fn var(&self, name: &str) -> Option<String> {
-        env::var(name).ok()
+        match NATIVE_SYS.vars.get(name) {
+            Some(value) => Some(value.clone()),
+            None => env::var(name).ok(),
+        }
+    }
+    fn set_var(&self, name: &str, value: &str) -> Result<(), String> {
+        if name.is_empty() || name.contains(['=', '\0']) || value.contains('\0') {
+            return Err(format!("Invalid environment variable {name:?}"));
+        }
+        NATIVE_SYS.vars.insert(name.into(), value.into());
+        Ok(())
+    }
+    fn var_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = env::vars_os()
+            .map(|(name, _)| name.to_string_lossy().into_owned())
+            .chain(NATIVE_SYS.vars.iter().map(|entry| entry.key().clone()))
+            .collect();
+        names.sort();
+        names.dedup();
+        names
+    }
+    #[cfg(feature = "clipboard")]
+    fn clipboard(&self) -> Result<String, String> {
+        with_native_clipboard(|clipboard| clipboard.get_text())
+    }
+    #[cfg(feature = "clipboard")]
+    fn set_clipboard(&self, contents: &str) -> Result<(), String> {
+        with_native_clipboard(|clipboard| clipboard.set_text(contents))
     }
     fn args(&self) -> Vec<String> {
         env::args().skip(1).collect()
@@ -541,6 +1404,69 @@ impl SysBackend for NativeSys {
     fn file_exists(&self, path: &str) -> bool {
         fs::metadata(path).is_ok()
     }
+    fn metadata(&self, path: &str) -> Result<FileMetadata, String> {
+        let link = fs::symlink_metadata(path).map_err(|e| e.to_string())?;
+        let kind = if link.is_symlink() {
+            FileKind::Symlink
+        } else if link.is_dir() {
+            FileKind::Directory
+        } else {
+            FileKind::File
+        };
+        let meta = fs::metadata(path).unwrap_or(link);
+        let modified = (meta.modified().ok())
+            .and_then(|time| time.duration_since(std::time::UNIX_EPOCH).ok())
+            .map(|since| since.as_secs_f64());
+        Ok(FileMetadata {
+            kind,
+            size: meta.len(),
+            modified,
+        })
+    }
+    fn create_dir(&self, path: &str) -> Result<(), String> {
+        fs::create_dir_all(path).map_err(|e| e.to_string())
+    }
+    fn remove_dir(&self, path: &str) -> Result<(), String> {
+        fs::remove_dir_all(path).map_err(|e| e.to_string())
+    }
+    #[cfg(feature = "file_watch")]
+    fn watch(&self, path: &str, timeout: Option<f64>) -> Result<Vec<String>, String> {
+        use notify::{EventKind, RecursiveMode, Watcher};
+        use std::sync::mpsc::{channel, RecvTimeoutError};
+        let (send, recv) = channel();
+        let mut watcher = notify::recommended_watcher(send).map_err(|e| e.to_string())?;
+        watcher
+            .watch(path.as_ref(), RecursiveMode::Recursive)
+            .map_err(|e| e.to_string())?;
+        let mut paths: Vec<String> = Vec::new();
+        // Wait for the first change, then collect the changes that come with it
+        let mut wait = timeout.map(Duration::from_secs_f64);
+        loop {
+            let event = match wait {
+                Some(wait) => match recv.recv_timeout(wait) {
+                    Ok(event) => event,
+                    Err(RecvTimeoutError::Timeout) => break,
+                    Err(RecvTimeoutError::Disconnected) => return Err("Watcher stopped".into()),
+                },
+                None => recv.recv().map_err(|_| "Watcher stopped")?,
+            };
+            let event = event.map_err(|e| e.to_string())?;
+            if !matches!(
+                event.kind,
+                EventKind::Create(_) | EventKind::Modify(_) | EventKind::Remove(_)
+            ) {
+                continue;
+            }
+            for path in event.paths {
+                let path = path.to_string_lossy().into_owned();
+                if !paths.contains(&path) {
+                    paths.push(path);
+                }
+            }
+            wait = Some(Duration::from_millis(50));
+        }
+        Ok(paths)
+    }
     fn is_file(&self, path: &str) -> Result<bool, String> {
         fs::metadata(path)
             .map(|m| m.is_file())
@@ -567,6 +1493,9 @@ impl SysBackend for NativeSys {
         Ok(handle)
     }
     fn read(&self, handle: Handle, len: usize) -> Result<Vec<u8>, String> {
+        if handle == Handle::STDIN {
+            return with_stdin(|stdin| stdin.bytes().take(len).collect());
+        }
         Ok(match NATIVE_SYS.get_stream(handle)? {
             SysStream::File(mut file) => {
                 let mut buf = Vec::new();
@@ -585,8 +1514,33 @@ impl SysBackend for NativeSys {
                     .map_err(|e| e.to_string())?;
                 buf
             }
+            #[cfg(feature = "tls")]
+            SysStream::TlsSocket(mut socket) => {
+                let mut buf = Vec::new();
+                Write::by_ref(&mut *socket)
+                    .take(len as u64)
+                    .read_to_end(&mut buf)
+                    .map_err(|e| e.to_string())?;
+                buf
+            }
         })
     }
+    fn read_until(&self, handle: Handle, delim: &[u8]) -> Result<Vec<u8>, String> {
+        // Files being read from have a read buffer,
+        // so single-byte delimiters can be found without reading bytewise
+        if let [delim_byte] = *delim {
+            if let SysStream::File(mut file) = NATIVE_SYS.get_stream(handle)? {
+                if let Some(reader) = file.get_bufreader_mut() {
+                    let mut buffer = Vec::new();
+                    reader
+                        .read_until(delim_byte, &mut buffer)
+                        .map_err(|e| e.to_string())?;
+                    return Ok(buffer);
+                }
+            }
+        }
+        read_until_bytewise(self, handle, delim)
+    }
     fn write(&self, handle: Handle, conts: &[u8]) -> Result<(), String> {
         let mut conts = conts;
         let colored;
@@ -603,40 +1557,39 @@ impl SysBackend for NativeSys {
             SysStream::File(mut file) => file.write_all(conts).map_err(|e| e.to_string()),
             SysStream::TcpListener(_) => Err("Cannot write to a tcp listener".to_string()),
             SysStream::TcpSocket(mut socket) => socket.write_all(conts).map_err(|e| e.to_string()),
+            #[cfg(feature = "tls")]
+            SysStream::TlsSocket(mut socket) => socket.write_all(conts).map_err(|e| e.to_string()),
         }
     }
     fn sleep(&self, seconds: f64) -> Result<(), String> {
-        sleep(Duration::from_secs_f64(seconds));
+        // Sleeping is only as precise as the OS scheduler,
+        // so the last bit of the wait is spent yielding instead
+        const SPIN: Duration = Duration::from_millis(1);
+        let duration = Duration::from_secs_f64(seconds);
+        let deadline = std::time::Instant::now() + duration;
+        if duration > SPIN {
+            sleep(duration - SPIN);
+        }
+        while std::time::Instant::now() < deadline {
+            std::thread::yield_now();
+        }
         Ok(())
     }
-    #[cfg(feature = "terminal_image")]
+    #[allow(unused_variables)]
     fn show_image(&self, image: DynamicImage) -> Result<(), String> {
-        let (width, height) = if let Some((w, h)) = term_size::dimensions() {
-            let (tw, th) = (w as u32, h.saturating_sub(1) as u32);
-            let (iw, ih) = (image.width(), image.height() / 2);
-            let scaled_to_height = (iw * th / ih, th);
-            let scaled_to_width = (tw, ih * tw / iw);
-            let (w, h) = if scaled_to_height.0 <= tw {
-                scaled_to_height
-            } else {
-                scaled_to_width
-            };
-            (Some(w), Some(h))
-        } else {
-            (None, None)
-        };
-        viuer::print(
-            &image,
-            &viuer::Config {
-                width,
-                height,
-                absolute_offset: false,
-                transparent: true,
-                ..Default::default()
-            },
-        )
-        .map(drop)
-        .map_err(|e| format!("Failed to show image: {e}"))
+        #[cfg(feature = "terminal_image")]
+        if stdout().is_terminal() && terminal_supports_graphics() {
+            return print_terminal_image(&image);
+        }
+        #[cfg(feature = "image_window")]
+        let res = open_image_window(&image);
+        #[cfg(not(feature = "image_window"))]
+        let res = Err("Showing images not supported in this environment".into());
+        #[cfg(feature = "terminal_image")]
+        if res.is_err() {
+            return print_terminal_image(&image);
+        }
+        res
     }
     #[cfg(feature = "audio")]
     fn play_audio(&self, wav_bytes: Vec<u8>) -> Result<(), String> {
@@ -715,6 +1668,70 @@ impl SysBackend for NativeSys {
             Err(e) => Err(format!("Failed to initialize audio output stream: {e}").to_string()),
         }
     }
+    #[cfg(feature = "audio")]
+    fn record_audio(&self, seconds: f64) -> Result<(Vec<f64>, u32), String> {
+        use hodaun::cpal::{
+            self,
+            traits::{DeviceTrait, HostTrait, StreamTrait},
+            FromSample, Sample, SampleFormat, SizedSample,
+        };
+        fn build<T: SizedSample>(
+            device: &cpal::Device,
+            config: &cpal::StreamConfig,
+            send: crossbeam_channel::Sender<Vec<f64>>,
+        ) -> Result<cpal::Stream, cpal::BuildStreamError>
+        where
+            f64: FromSample<T>,
+        {
+            let channels = config.channels as usize;
+            device.build_input_stream(
+                config,
+                move |data: &[T], _| {
+                    // Mix the channels down to mono
+                    let samples = (data.chunks(channels))
+                        .map(|frame| {
+                            frame.iter().map(|s| s.to_sample::<f64>()).sum::<f64>()
+                                / channels as f64
+                        })
+                        .collect();
+                    _ = send.send(samples);
+                },
+                |e| eprintln!("Audio input error: {e}"),
+                None,
+            )
+        }
+        let device = (cpal::default_host().default_input_device())
+            .ok_or("No audio input device is available")?;
+        let supported = device
+            .default_input_config()
+            .map_err(|e| format!("Failed to get audio input config: {e}"))?;
+        let format = supported.sample_format();
+        let config: cpal::StreamConfig = supported.into();
+        let sample_rate = config.sample_rate.0;
+        let (send, recv) = crossbeam_channel::unbounded();
+        let stream = match format {
+            SampleFormat::F32 => build::<f32>(&device, &config, send),
+            SampleFormat::F64 => build::<f64>(&device, &config, send),
+            SampleFormat::I16 => build::<i16>(&device, &config, send),
+            SampleFormat::I32 => build::<i32>(&device, &config, send),
+            SampleFormat::U16 => build::<u16>(&device, &config, send),
+            SampleFormat::U8 => build::<u8>(&device, &config, send),
+            format => return Err(format!("Unsupported audio input format {format}")),
+        }
+        .map_err(|e| format!("Failed to initialize audio input stream: {e}"))?;
+        stream
+            .play()
+            .map_err(|e| format!("Failed to start recording: {e}"))?;
+        let count = (seconds * sample_rate as f64).round() as usize;
+        let mut samples = Vec::new();
+        while samples.len() < count {
+            let chunk = (recv.recv_timeout(Duration::from_secs(1)))
+                .map_err(|_| "Audio input stopped unexpectedly")?;
+            samples.extend(chunk);
+        }
+        samples.truncate(count);
+        Ok((samples, sample_rate))
+    }
     fn tcp_listen(&self, addr: &str) -> Result<Handle, String> {
         let handle = NATIVE_SYS.new_handle();
         let listener = TcpListener::bind(addr).map_err(|e| e.to_string())?;
@@ -743,15 +1760,238 @@ impl SysBackend for NativeSys {
         Ok(handle)
     }
     fn tcp_addr(&self, handle: Handle) -> Result<String, String> {
-        let socket = NATIVE_SYS
+        let addr = if let Some(socket) = NATIVE_SYS.tcp_sockets.get(&handle) {
+            socket.get_ref().peer_addr()
+        } else if let Some(listener) = NATIVE_SYS.tcp_listeners.get(&handle) {
+            listener.local_addr()
+        } else {
+            return Err("Invalid tcp socket handle".to_string());
+        };
+        Ok(addr.map_err(|e| e.to_string())?.to_string())
+    }
+    #[cfg(feature = "tls")]
+    fn tls_upgrade(
+        &self,
+        handle: Handle,
+        server_name: &str,
+        verification: TlsVerification,
+    ) -> Result<(), String> {
+        use rustls::{
+            client::{ServerCertVerified, ServerCertVerifier},
+            Certificate, ClientConfig, ClientConnection, OwnedTrustAnchor, RootCertStore,
+            ServerName,
+        };
+
+        struct NoVerification;
+        impl ServerCertVerifier for NoVerification {
+            fn verify_server_cert(
+                &self,
+                _: &Certificate,
+                _: &[Certificate],
+                _: &ServerName,
+                _: &mut dyn Iterator<Item = &[u8]>,
+                _: &[u8],
+                _: std::time::SystemTime,
+            ) -> Result<ServerCertVerified, rustls::Error> {
+                Ok(ServerCertVerified::assertion())
+            }
+        }
+
+        let mut roots = RootCertStore::empty();
+        match &verification {
+            TlsVerification::Default | TlsVerification::None => {
+                roots.add_trust_anchors(webpki_roots::TLS_SERVER_ROOTS.iter().map(|ta| {
+                    OwnedTrustAnchor::from_subject_spki_name_constraints(
+                        ta.subject,
+                        ta.spki,
+                        ta.name_constraints,
+                    )
+                }))
+            }
+            TlsVerification::Certificates(pem) => {
+                let certs = rustls_pemfile::certs(&mut pem.as_slice())
+                    .map_err(|e| format!("Invalid certificates: {e}"))?;
+                if certs.is_empty() {
+                    return Err("No certificates found in the PEM data".into());
+                }
+                for cert in certs {
+                    roots
+                        .add(&Certificate(cert))
+                        .map_err(|e| format!("Invalid certificate: {e}"))?;
+                }
+            }
+        }
+        let mut config = ClientConfig::builder()
+            .with_safe_defaults()
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+        if verification == TlsVerification::None {
+            (config.dangerous()).set_certificate_verifier(Arc::new(NoVerification));
+        }
+        let name = ServerName::try_from(server_name)
+            .map_err(|_| format!("Invalid server name {server_name:?}"))?;
+        let mut conn = ClientConnection::new(Arc::new(config), name).map_err(|e| e.to_string())?;
+        let mut socket = NATIVE_SYS
             .tcp_sockets
-            .get(&handle)
+            .get_mut(&handle)
             .ok_or_else(|| "Invalid tcp socket handle".to_string())?;
-        Ok(socket
-            .get_ref()
-            .peer_addr()
-            .map_err(|e| e.to_string())?
-            .to_string())
+        // Bytes that were already read from the socket belong to the TLS stream
+        let buffered = match socket.get_bufreader_mut() {
+            Some(reader) => reader.buffer().to_vec(),
+            None => {
+                socket.flush().map_err(|e| e.to_string())?;
+                Vec::new()
+            }
+        };
+        let stream = socket.get_ref().try_clone().map_err(|e| e.to_string())?;
+        drop(socket);
+        let mut buffered = buffered.as_slice();
+        while !buffered.is_empty() {
+            conn.read_tls(&mut buffered).map_err(|e| e.to_string())?;
+            (conn.process_new_packets()).map_err(|e| format!("TLS handshake failed: {e}"))?;
+        }
+        let mut stream = rustls::StreamOwned::new(conn, stream);
+        // Handshake now so that certificate errors are reported here
+        while stream.conn.is_handshaking() {
+            stream
+                .conn
+                .complete_io(&mut stream.sock)
+                .map_err(|e| format!("TLS handshake failed: {e}"))?;
+        }
+        // The socket is only replaced once the handshake has succeeded
+        NATIVE_SYS.tcp_sockets.remove(&handle);
+        NATIVE_SYS
+            .tls_sockets
+            .insert(handle, Buffered::new_writer(stream));
+        Ok(())
+    }
+    fn run_command(
+        &self,
+        command: &str,
+        args: &[String],
+        stdin: &[u8],
+    ) -> Result<CommandOutput, String> {
+        let child = start_command(command, args)?;
+        finish_command(child, stdin.to_vec())
+    }
+    fn spawn_command(
+        &self,
+        command: &str,
+        args: &[String],
+        stdin: &[u8],
+    ) -> Result<Handle, String> {
+        let child = start_command(command, args)?;
+        let stdin = stdin.to_vec();
+        let thread = spawn(move || {
+            let output = finish_command(child, stdin).map_err(|e| Span::Builtin.error(e))?;
+            Ok(output.into_values())
+        });
+        let handle = NATIVE_SYS.new_handle();
+        NATIVE_SYS.threads.insert(handle, thread);
+        Ok(handle)
+    }
+    #[cfg(feature = "websocket")]
+    fn ws_connect(&self, url: &str) -> Result<Handle, String> {
+        let (socket, _) = tungstenite::connect(url).map_err(|e| e.to_string())?;
+        let handle = NATIVE_SYS.new_handle();
+        (NATIVE_SYS.websockets).insert(handle, Arc::new(Mutex::new(socket)));
+        Ok(handle)
+    }
+    #[cfg(feature = "websocket")]
+    fn ws_send(&self, handle: Handle, message: WsMessage) -> Result<(), String> {
+        let socket = NATIVE_SYS.websocket(handle)?;
+        let message = match message {
+            WsMessage::Text(text) => tungstenite::Message::Text(text),
+            WsMessage::Binary(bytes) => tungstenite::Message::Binary(bytes),
+        };
+        let mut socket = socket.lock();
+        socket.send(message).map_err(|e| e.to_string())
+    }
+    #[cfg(feature = "websocket")]
+    fn ws_receive(&self, handle: Handle) -> Result<WsMessage, String> {
+        // The map is not locked while waiting, so other sockets can still be used
+        let socket = NATIVE_SYS.websocket(handle)?;
+        let mut socket = socket.lock();
+        loop {
+            // Pings are answered automatically
+            match socket.read().map_err(|e| e.to_string())? {
+                tungstenite::Message::Text(text) => return Ok(WsMessage::Text(text)),
+                tungstenite::Message::Binary(bytes) => return Ok(WsMessage::Binary(bytes)),
+                tungstenite::Message::Close(_) => return Err("The websocket was closed".into()),
+                _ => {}
+            }
+        }
+    }
+    fn udp_bind(&self, addr: &str) -> Result<Handle, String> {
+        let handle = NATIVE_SYS.new_handle();
+        let socket = UdpSocket::bind(addr).map_err(|e| e.to_string())?;
+        NATIVE_SYS.udp_sockets.insert(handle, socket);
+        Ok(handle)
+    }
+    fn udp_send_to(&self, handle: Handle, addr: &str, data: &[u8]) -> Result<(), String> {
+        let socket = NATIVE_SYS
+            .udp_sockets
+            .get(&handle)
+            .ok_or_else(|| "Invalid udp socket handle".to_string())?;
+        socket.send_to(data, addr).map_err(|e| e.to_string())?;
+        Ok(())
+    }
+    fn udp_receive_from(&self, handle: Handle, max: usize) -> Result<(Vec<u8>, String), String> {
+        let socket = NATIVE_SYS
+            .udp_sockets
+            .get(&handle)
+            .ok_or_else(|| "Invalid udp socket handle".to_string())?
+            .try_clone()
+            .map_err(|e| e.to_string())?;
+        // No datagram is larger than this
+        let mut buf = vec![0; max.min(65536)];
+        let (len, addr) = socket.recv_from(&mut buf).map_err(|e| e.to_string())?;
+        buf.truncate(len);
+        Ok((buf, addr.to_string()))
+    }
+    fn udp_addr(&self, handle: Handle) -> Result<String, String> {
+        let socket = NATIVE_SYS
+            .udp_sockets
+            .get(&handle)
+            .ok_or_else(|| "Invalid udp socket handle".to_string())?;
+        Ok(socket.local_addr().map_err(|e| e.to_string())?.to_string())
+    }
+    #[cfg(feature = "http")]
+    fn http_request(&self, request: HttpRequest) -> Result<HttpResponse, String> {
+        // Don't let an unresponsive server hang the program forever
+        let agent = ureq::AgentBuilder::new()
+            .timeout_connect(Duration::from_secs(30))
+            .timeout_read(Duration::from_secs(60))
+            .build();
+        let mut req = agent.request(&request.method, &request.url);
+        for (name, value) in &request.headers {
+            req = req.set(name, value);
+        }
+        let res = if request.body.is_empty() {
+            req.call()
+        } else {
+            req.send_bytes(&request.body)
+        };
+        let res = match res {
+            Ok(res) | Err(ureq::Error::Status(_, res)) => res,
+            Err(e) => return Err(e.to_string()),
+        };
+        let status = res.status();
+        let headers = (res.headers_names().into_iter())
+            .filter_map(|name| {
+                let value = res.header(&name)?.to_string();
+                Some((name, value))
+            })
+            .collect();
+        let mut body = Vec::new();
+        res.into_reader()
+            .read_to_end(&mut body)
+            .map_err(|e| e.to_string())?;
+        Ok(HttpResponse {
+            status,
+            headers,
+            body,
+        })
     }
     fn tcp_set_non_blocking(&self, handle: Handle, non_blocking: bool) -> Result<(), String> {
         let socket = NATIVE_SYS
@@ -794,10 +2034,30 @@ impl SysBackend for NativeSys {
             .map_err(|e| e.to_string())?;
         Ok(())
     }
+    fn stream_capability(&self, handle: Handle) -> Option<Capability> {
+        if NATIVE_SYS.files.contains_key(&handle) {
+            return Some(Capability::ReadFiles);
+        }
+        #[cfg(feature = "tls")]
+        if NATIVE_SYS.tls_sockets.contains_key(&handle) {
+            return Some(Capability::Network);
+        }
+        #[cfg(feature = "websocket")]
+        if NATIVE_SYS.websockets.contains_key(&handle) {
+            return Some(Capability::Network);
+        }
+        (NATIVE_SYS.tcp_listeners.contains_key(&handle)
+            || NATIVE_SYS.tcp_sockets.contains_key(&handle)
+            || NATIVE_SYS.udp_sockets.contains_key(&handle))
+        .then_some(Capability::Network)
+    }
     fn close(&self, handle: Handle) -> Result<(), String> {
         if NATIVE_SYS.files.remove(&handle).is_some()
             || NATIVE_SYS.tcp_listeners.remove(&handle).is_some()
             || NATIVE_SYS.tcp_sockets.remove(&handle).is_some()
+            || NATIVE_SYS.udp_sockets.remove(&handle).is_some()
+            || NATIVE_SYS.remove_tls_socket(handle)
+            || NATIVE_SYS.remove_websocket(handle)
         {
             Ok(())
         } else {
@@ -831,10 +2091,63 @@ impl SysBackend for NativeSys {
 }
 
 impl SysOp {
+    /// The capability a runtime needs to run the op, if any
+    pub fn capability(&self) -> Option<Capability> {
+        use SysOp::*;
+        Some(match self {
+            Args | Var | SetVar | Vars => Capability::Environment,
+            Clip | SetClip => Capability::Clipboard,
+            FOpen | FExists | FListDir | FIsFile | FSize | FModified | FKind | FGlob | FWatch
+            | FReadAllStr | FReadAllBytes | FFoldLines | Import => Capability::ReadFiles,
+            FCreate | FWriteAll | FMakeDir | FRemoveDir => Capability::WriteFiles,
+            TcpListen | TcpAccept | TcpConnect | TcpSetNonBlocking | TcpSetReadTimeout
+            | TcpSetWriteTimeout | TcpAddr | TlsUpgrade | WsConnect | WsSend | WsReceive
+            | UdpBind | UdpSendTo | UdpReceiveFrom | UdpAddr | Http => Capability::Network,
+            AudioPlay | AudioPlayRate | AudioStream | AudioRecord => Capability::Audio,
+            RunCapture | RunSpawn => Capability::Subprocess,
+            ImShow => Capability::Display,
+            _ => return None,
+        })
+    }
+    /// Check that a runtime may use a stream with this op
+    ///
+    /// The standard streams may always be used. Other streams need the
+    /// capability for the kind of resource they are, so that a restricted
+    /// runtime cannot use streams opened by another one. Writing to a file
+    /// needs the capability to write files.
+    fn check_stream(&self, env: &Uiua, handle: Handle) -> UiuaResult {
+        use Capability::*;
+        if handle < Handle::FIRST_UNRESERVED {
+            return Ok(());
+        }
+        let needed = match env.backend.stream_capability(handle) {
+            Some(ReadFiles | WriteFiles) => match self {
+                SysOp::Write => Some(WriteFiles),
+                SysOp::Close if env.has_capability(WriteFiles) => None,
+                _ => Some(ReadFiles),
+            },
+            Some(cap) => Some(cap),
+            None => enum_iterator::all().find(|&cap| !env.has_capability(cap)),
+        };
+        match needed.filter(|&cap| !env.has_capability(cap)) {
+            Some(cap) => Err(env.error(format!(
+                "{} on handle {} is not permitted without the {cap} capability",
+                self.long_name(),
+                handle.0
+            ))),
+            None => Ok(()),
+        }
+    }
     pub(crate) fn run(&self, env: &mut Uiua) -> UiuaResult {
+        if let Some(cap) = self.capability().filter(|&cap| !env.has_capability(cap)) {
+            return Err(env.error(format!(
+                "{} is not permitted without the {cap} capability",
+                self.long_name()
+            )));
+        }
         match self {
             SysOp::Show => {
-                let s = env.pop(1)?.grid_string();
+                let s = env.pop(1)?.show_with_budget(env.display_budget());
                 env.backend.print_str_stdout(&s).map_err(|e| env.error(e))?;
                 env.backend
                     .print_str_stdout("\n")
@@ -856,13 +2169,59 @@ impl SysOp {
                     .map_err(|e| env.error(e))?;
             }
             SysOp::ScanLine => {
-                let line = env.backend.scan_line_stdin().map_err(|e| env.error(e))?;
-                env.push(line);
+                let line = (env.backend.scan_line_stdin_or_eof()).map_err(|e| env.error(e))?;
+                env.push(line.unwrap_or_default());
+            }
+            SysOp::ScanAll => {
+                let bytes = env.backend.scan_all_stdin().map_err(|e| env.error(e))?;
+                let s = String::from_utf8(bytes).map_err(|e| env.error(e))?;
+                env.push(s);
+            }
+            SysOp::ScanBytes => {
+                let bytes = env.backend.scan_all_stdin().map_err(|e| env.error(e))?;
+                env.push(bytes);
+            }
+            SysOp::Eof => {
+                let eof = env.backend.stdin_eof().map_err(|e| env.error(e))?;
+                env.push(eof);
+            }
+            SysOp::IsTty => {
+                let is_terminal = env.backend.stdin_is_terminal();
+                env.push(is_terminal);
+            }
+            SysOp::RawMode => {
+                let raw = env.pop(1)?.as_num(env, "Raw mode must be a boolean")? != 0.0;
+                env.backend.set_raw_mode(raw).map_err(|e| env.error(e))?;
+            }
+            SysOp::Key => {
+                let timeout = env.pop(1)?.as_num(env, "Timeout must be a number")?;
+                if timeout < 0.0 {
+                    return Err(env.error("Timeout must be non-negative"));
+                }
+                let key = env
+                    .backend
+                    .read_key(timeout.is_finite().then_some(timeout))
+                    .map_err(|e| env.error(e))?;
+                env.push(key.unwrap_or_default());
             }
             SysOp::TermSize => {
                 let (width, height) = env.backend.term_size().map_err(|e| env.error(e))?;
                 env.push(vec![height as f64, width as f64])
             }
+            SysOp::Style => {
+                let style = string_list(
+                    env.pop(1)?,
+                    env,
+                    "Style must be a string or list of strings",
+                )?;
+                let text = env.pop(2)?.as_string(env, "Text must be a string")?;
+                let codes = ansi_codes(&style).map_err(|e| env.error(e))?;
+                if codes.is_empty() || !env.backend.stdout_supports_color() {
+                    env.push(text);
+                } else {
+                    env.push(format!("\x1b[{codes}m{text}\x1b[0m"));
+                }
+            }
             SysOp::Args => {
                 let args = env.backend.args();
                 env.push(Array::<Arc<Function>>::from_iter(args));
@@ -874,6 +2233,33 @@ impl SysOp {
                 let var = env.backend.var(&key).unwrap_or_default();
                 env.push(var);
             }
+            SysOp::SetVar => {
+                let name = env
+                    .pop(1)?
+                    .as_string(env, "Variable name must be a string")?;
+                let value = env
+                    .pop(2)?
+                    .as_string(env, "Variable value must be a string")?;
+                env.backend
+                    .set_var(&name, &value)
+                    .map_err(|e| env.error(e))?;
+            }
+            SysOp::Vars => {
+                let names = env.backend.var_names();
+                env.push(Array::<Arc<Function>>::from_iter(names));
+            }
+            SysOp::Clip => {
+                let contents = env.backend.clipboard().map_err(|e| env.error(e))?;
+                env.push(contents);
+            }
+            SysOp::SetClip => {
+                let contents = env
+                    .pop(1)?
+                    .as_string(env, "Clipboard contents must be a string")?;
+                env.backend
+                    .set_clipboard(&contents)
+                    .map_err(|e| env.error(e))?;
+            }
             SysOp::FOpen => {
                 let path = env.pop(1)?.as_string(env, "Path must be a string")?;
                 let handle = env.backend.open_file(&path).map_err(|e| env.error(e))?;
@@ -884,21 +2270,50 @@ impl SysOp {
                 let handle = env.backend.create_file(&path).map_err(|e| env.error(e))?;
                 env.push(handle.0 as f64);
             }
+            SysOp::FFoldLines => {
+                let f = env.pop(1)?;
+                let acc = env.pop(2)?;
+                let path = env.pop(3)?.as_string(env, "Path must be a string")?;
+                let handle = env.backend.open_file(&path).map_err(|e| env.error(e))?;
+                let folded = (|| -> UiuaResult<Value> {
+                    let mut acc = acc;
+                    loop {
+                        let mut line = env
+                            .backend
+                            .read_until(handle, b"\n")
+                            .map_err(|e| env.error(e))?;
+                        if line.is_empty() {
+                            return Ok(acc);
+                        }
+                        if line.ends_with(b"\n") {
+                            line.pop();
+                            if line.ends_with(b"\r") {
+                                line.pop();
+                            }
+                        }
+                        let line = String::from_utf8(line).map_err(|e| env.error(e))?;
+                        env.push(line);
+                        env.push(acc);
+                        let should_break = env.call_catch_break(f.clone())?;
+                        acc = env.pop("folded function result")?;
+                        if should_break {
+                            return Ok(acc);
+                        }
+                    }
+                })();
+                env.backend.close(handle).map_err(|e| env.error(e))?;
+                env.push(folded?);
+            }
             SysOp::ReadStr => {
                 let count = env.pop(1)?.as_nat(env, "Count must be an integer")?;
                 let handle = env
                     .pop(2)?
                     .as_nat(env, "Handle must be an natural number")?
                     .into();
+                self.check_stream(env, handle)?;
                 let bytes = match handle {
                     Handle::STDOUT => return Err(env.error("Cannot read from stdout")),
                     Handle::STDERR => return Err(env.error("Cannot read from stderr")),
-                    Handle::STDIN => stdin()
-                        .lock()
-                        .bytes()
-                        .take(count)
-                        .collect::<Result<Vec<_>, _>>()
-                        .map_err(|e| env.error(e))?,
                     _ => env.backend.read(handle, count).map_err(|e| env.error(e))?,
                 };
                 let s = String::from_utf8(bytes).map_err(|e| env.error(e))?;
@@ -910,15 +2325,10 @@ impl SysOp {
                     .pop(2)?
                     .as_nat(env, "Handle must be an natural number")?
                     .into();
+                self.check_stream(env, handle)?;
                 let bytes = match handle {
                     Handle::STDOUT => return Err(env.error("Cannot read from stdout")),
                     Handle::STDERR => return Err(env.error("Cannot read from stderr")),
-                    Handle::STDIN => stdin()
-                        .lock()
-                        .bytes()
-                        .take(count)
-                        .collect::<Result<Vec<_>, _>>()
-                        .map_err(|e| env.error(e))?,
                     _ => env.backend.read(handle, count).map_err(|e| env.error(e))?,
                 };
                 env.push(bytes);
@@ -929,6 +2339,7 @@ impl SysOp {
                     .pop(2)?
                     .as_nat(env, "Handle must be an natural number")?
                     .into();
+                self.check_stream(env, handle)?;
                 if delim.rank() > 1 {
                     return Err(env.error("Delimiter must be a rank 0 or 1 string or byte array"));
                 }
@@ -946,15 +2357,17 @@ impl SysOp {
                             }
                             _ => return Err(env.error("Delimiter must be a string or byte array")),
                         };
-                        let mut buffer = Vec::new();
-                        let stdin = stdin().lock();
-                        for byte in stdin.bytes() {
-                            let byte = byte.map_err(|e| env.error(e))?;
-                            buffer.push(byte);
-                            if buffer.ends_with(&delim_bytes) {
-                                break;
+                        let buffer = with_stdin(|stdin| {
+                            let mut buffer = Vec::new();
+                            for byte in stdin.bytes() {
+                                buffer.push(byte?);
+                                if buffer.ends_with(&delim_bytes) {
+                                    break;
+                                }
                             }
-                        }
+                            Ok(buffer)
+                        })
+                        .map_err(|e| env.error(e))?;
                         if is_string {
                             let s = String::from_utf8_lossy(&buffer).into_owned();
                             env.push(s);
@@ -998,6 +2411,7 @@ impl SysOp {
                     .pop(2)?
                     .as_nat(env, "Handle must be an natural number")?
                     .into();
+                self.check_stream(env, handle)?;
                 let bytes: Vec<u8> = match data {
                     Value::Num(arr) => arr.data.iter().map(|&x| x as u8).collect(),
                     Value::Byte(arr) => arr.data.into(),
@@ -1089,24 +2503,72 @@ impl SysOp {
                 let is_file = env.backend.is_file(&path).map_err(|e| env.error(e))?;
                 env.push(is_file);
             }
+            SysOp::FSize => {
+                let path = env.pop(1)?.as_string(env, "Path must be a string")?;
+                let meta = env.backend.metadata(&path).map_err(|e| env.error(e))?;
+                env.push(meta.size as f64);
+            }
+            SysOp::FModified => {
+                let path = env.pop(1)?.as_string(env, "Path must be a string")?;
+                let meta = env.backend.metadata(&path).map_err(|e| env.error(e))?;
+                let modified = meta.modified.ok_or_else(|| {
+                    env.error(format!("The modification time of {path} is not available"))
+                })?;
+                env.push(modified);
+            }
+            SysOp::FKind => {
+                let path = env.pop(1)?.as_string(env, "Path must be a string")?;
+                let meta = env.backend.metadata(&path).map_err(|e| env.error(e))?;
+                env.push(meta.kind.name());
+            }
+            SysOp::FGlob => {
+                let pattern = env.pop(1)?.as_string(env, "Pattern must be a string")?;
+                let paths = glob(&*env.backend, &pattern);
+                env.push(Array::<Arc<Function>>::from_iter(paths));
+            }
+            SysOp::FMakeDir => {
+                let path = env.pop(1)?.as_string(env, "Path must be a string")?;
+                env.backend.create_dir(&path).map_err(|e| env.error(e))?;
+            }
+            SysOp::FRemoveDir => {
+                let path = env.pop(1)?.as_string(env, "Path must be a string")?;
+                env.backend.remove_dir(&path).map_err(|e| env.error(e))?;
+            }
+            SysOp::FWatch => {
+                let path = env.pop(1)?.as_string(env, "Path must be a string")?;
+                let timeout = env.pop(2)?.as_num(env, "Timeout must be a number")?;
+                if timeout < 0.0 {
+                    return Err(env.error("Timeout must be non-negative"));
+                }
+                let paths = env
+                    .backend
+                    .watch(&path, timeout.is_finite().then_some(timeout))
+                    .map_err(|e| env.error(e))?;
+                env.push(Array::<Arc<Function>>::from_iter(paths));
+            }
             SysOp::Import => {
                 let path = env.pop(1)?.as_string(env, "Import path must be a string")?;
-                let input = String::from_utf8(
-                    env.backend
-                        .file_read_all(&path)
-                        .or_else(|e| {
-                            if path == "example.ua" {
-                                Ok(example_ua(|ex| ex.as_bytes().to_vec()))
-                            } else {
-                                Err(e)
-                            }
-                        })
-                        .map_err(|e| env.error(e))?,
-                )
-                .map_err(|e| env.error(format!("Failed to read file: {e}")))?;
+                let input = if let Some(source) = env.import_source(&path) {
+                    source.into()
+                } else {
+                    String::from_utf8(
+                        env.backend
+                            .file_read_all(&path)
+                            .or_else(|e| {
+                                if path == "example.ua" {
+                                    Ok(example_ua(|ex| ex.as_bytes().to_vec()))
+                                } else {
+                                    Err(e)
+                                }
+                            })
+                            .map_err(|e| env.error(e))?,
+                    )
+                    .map_err(|e| env.error(format!("Failed to read file: {e}")))?
+                };
                 env.import(&input, path.as_ref())?;
             }
-            SysOp::Now => env.push(instant::now() / 1000.0),
+            SysOp::Now => env.push(env.now()),
+            SysOp::Monotonic => env.push(env.monotonic_now()),
             SysOp::ImDecode => {
                 let bytes = match env.pop(1)? {
                     Value::Byte(arr) => {
@@ -1208,6 +2670,18 @@ impl SysOp {
                     .map_err(|e| env.error(e))?;
                 env.backend.play_audio(bytes).map_err(|e| env.error(e))?;
             }
+            SysOp::AudioPlayRate => {
+                let sample_rate = env
+                    .pop(1)?
+                    .as_nat(env, "Sample rate must be a natural number")?;
+                if sample_rate == 0 || sample_rate > u32::MAX as usize {
+                    return Err(env.error(format!("Invalid sample rate {sample_rate}")));
+                }
+                let value = env.pop(2)?;
+                let bytes =
+                    value_to_wav_bytes(&value, sample_rate as u32).map_err(|e| env.error(e))?;
+                env.backend.play_audio(bytes).map_err(|e| env.error(e))?;
+            }
             SysOp::AudioSampleRate => {
                 let sample_rate = env.backend.audio_sample_rate();
                 env.push(f64::from(sample_rate));
@@ -1242,12 +2716,21 @@ impl SysOp {
                     return Err(env.error(e));
                 }
             }
+            SysOp::AudioRecord => {
+                let seconds = env.pop(1)?.as_num(env, "Recording time must be a number")?;
+                if !(seconds >= 0.0 && seconds.is_finite()) {
+                    return Err(env.error("Recording time must be a non-negative finite number"));
+                }
+                let (samples, sample_rate) = env
+                    .backend
+                    .record_audio(seconds)
+                    .map_err(|e| env.error(e))?;
+                env.push(f64::from(sample_rate));
+                env.push(samples);
+            }
             SysOp::Sleep => {
-                let seconds = env
-                    .pop(1)?
-                    .as_num(env, "Sleep time must be a number")?
-                    .max(0.0);
-                env.backend.sleep(seconds).map_err(|e| env.error(e))?;
+                let seconds = env.pop(1)?.as_num(env, "Sleep time must be a number")?;
+                env.sleep(seconds)?;
             }
             SysOp::TcpListen => {
                 let addr = env.pop(1)?.as_string(env, "Address must be a string")?;
@@ -1314,11 +2797,153 @@ impl SysOp {
                     .tcp_set_write_timeout(handle, timeout)
                     .map_err(|e| env.error(e))?;
             }
+            SysOp::TlsUpgrade => {
+                let verification = match env.pop(1)? {
+                    Value::Num(n) if n.rank() == 0 && n.data[0] == 0.0 => TlsVerification::None,
+                    Value::Byte(n) if n.rank() == 0 && n.data[0] == 0 => TlsVerification::None,
+                    options if options.flat_len() == 0 => TlsVerification::Default,
+                    options => TlsVerification::Certificates(options.into_bytes(
+                        env,
+                        "TLS options must be empty, 0, or a PEM string of certificates",
+                    )?),
+                };
+                let server_name = env.pop(2)?.as_string(env, "Server name must be a string")?;
+                let handle = env
+                    .pop(3)?
+                    .as_nat(env, "Handle must be an natural number")?
+                    .into();
+                env.backend
+                    .tls_upgrade(handle, &server_name, verification)
+                    .map_err(|e| env.error(e))?;
+                env.push(handle);
+            }
+            SysOp::RunCapture | SysOp::RunSpawn => {
+                let mut command = string_list(
+                    env.pop(1)?,
+                    env,
+                    "Command must be a string or list of strings",
+                )?;
+                if command.is_empty() {
+                    return Err(env.error("Command must not be empty"));
+                }
+                let program = command.remove(0);
+                let stdin = env
+                    .pop(2)?
+                    .into_bytes(env, "Input must be a string or bytes")?;
+                if *self == SysOp::RunCapture {
+                    let output = (env.backend)
+                        .run_command(&program, &command, &stdin)
+                        .map_err(|e| env.error(e))?;
+                    for value in output.into_values() {
+                        env.push(value);
+                    }
+                } else {
+                    let handle = (env.backend)
+                        .spawn_command(&program, &command, &stdin)
+                        .map_err(|e| env.error(e))?;
+                    env.push(handle);
+                }
+            }
+            SysOp::WsConnect => {
+                let url = env.pop(1)?.as_string(env, "URL must be a string")?;
+                let handle = env.backend.ws_connect(&url).map_err(|e| env.error(e))?;
+                env.push(handle);
+            }
+            SysOp::WsSend => {
+                let message = match env.pop(1)? {
+                    Value::Char(arr) if arr.rank() <= 1 => {
+                        WsMessage::Text(arr.data.iter().collect())
+                    }
+                    data => WsMessage::Binary(
+                        data.into_bytes(env, "Message must be a string or bytes")?,
+                    ),
+                };
+                let handle = env
+                    .pop(2)?
+                    .as_nat(env, "Handle must be an natural number")?
+                    .into();
+                env.backend
+                    .ws_send(handle, message)
+                    .map_err(|e| env.error(e))?;
+            }
+            SysOp::WsReceive => {
+                let handle = env
+                    .pop(1)?
+                    .as_nat(env, "Handle must be an natural number")?
+                    .into();
+                match env.backend.ws_receive(handle).map_err(|e| env.error(e))? {
+                    WsMessage::Text(text) => env.push(text),
+                    WsMessage::Binary(bytes) => env.push(bytes),
+                }
+            }
+            SysOp::UdpBind => {
+                let addr = env.pop(1)?.as_string(env, "Address must be a string")?;
+                let handle = env.backend.udp_bind(&addr).map_err(|e| env.error(e))?;
+                env.push(handle);
+            }
+            SysOp::UdpSendTo => {
+                let data = env
+                    .pop(1)?
+                    .into_bytes(env, "Data must be a string or bytes")?;
+                let addr = env.pop(2)?.as_string(env, "Address must be a string")?;
+                let handle = env
+                    .pop(3)?
+                    .as_nat(env, "Handle must be an natural number")?
+                    .into();
+                env.backend
+                    .udp_send_to(handle, &addr, &data)
+                    .map_err(|e| env.error(e))?;
+            }
+            SysOp::UdpReceiveFrom => {
+                let max = env.pop(1)?.as_nat(env, "Count must be an integer")?;
+                let handle = env
+                    .pop(2)?
+                    .as_nat(env, "Handle must be an natural number")?
+                    .into();
+                let (bytes, addr) = env
+                    .backend
+                    .udp_receive_from(handle, max)
+                    .map_err(|e| env.error(e))?;
+                env.push(bytes);
+                env.push(addr);
+            }
+            SysOp::UdpAddr => {
+                let handle = env
+                    .pop(1)?
+                    .as_nat(env, "Handle must be an natural number")?
+                    .into();
+                let addr = env.backend.udp_addr(handle).map_err(|e| env.error(e))?;
+                env.push(addr);
+            }
+            SysOp::Http => {
+                let method = env.pop(1)?.as_string(env, "Method must be a string")?;
+                let url = env.pop(2)?.as_string(env, "URL must be a string")?;
+                let headers = http_headers(env.pop(3)?, env)?;
+                let body = env
+                    .pop(4)?
+                    .into_bytes(env, "Body must be a string or bytes")?;
+                let request = HttpRequest {
+                    method: method.to_uppercase(),
+                    url,
+                    headers,
+                    body,
+                };
+                let response = env
+                    .backend
+                    .http_request(request)
+                    .map_err(|e| env.error(e))?;
+                env.push(Array::<u8>::from_iter(response.body));
+                let headers =
+                    (response.headers.into_iter()).map(|(name, value)| format!("{name}: {value}"));
+                env.push(Array::<Arc<Function>>::from_iter(headers));
+                env.push(response.status as f64);
+            }
             SysOp::Close => {
                 let handle = env
                     .pop(1)?
                     .as_nat(env, "Handle must be an natural number")?
                     .into();
+                self.check_stream(env, handle)?;
                 env.backend.close(handle).map_err(|e| env.error(e))?;
             }
         }
@@ -1539,3 +3164,676 @@ fn array_from_wav_bytes_impl<T: hound::Sample>(
         Array::from_row_arrays(channels.into_iter().map(|ch| ch.into()), env)
     }
 }
+
+/// Get the strings in a list of boxed strings, a single string, or an empty array
+fn string_list(value: Value, env: &Uiua, requirement: &'static str) -> UiuaResult<Vec<String>> {
+    Ok(match value {
+        Value::Func(fs) => (fs.data.iter())
+            .map(|f| {
+                f.as_constant()
+                    .ok_or_else(|| env.error(requirement))?
+                    .as_string(env, requirement)
+            })
+            .collect::<UiuaResult<_>>()?,
+        Value::Char(_) if value.rank() == 1 => vec![value.as_string(env, requirement)?],
+        value if value.flat_len() == 0 => Vec::new(),
+        _ => return Err(env.error(requirement)),
+    })
+}
+
+/// Find the paths that match a glob pattern, using the backend's directory listing
+fn glob(backend: &dyn SysBackend, pattern: &str) -> Vec<String> {
+    let join = |base: &Option<String>, name: &str| -> String {
+        match base {
+            Some(base) if base.ends_with(['/', '\\']) => format!("{base}{name}"),
+            Some(base) => format!("{base}/{name}"),
+            None => name.to_string(),
+        }
+    };
+    let is_dir = |path: &Option<String>| -> bool {
+        path.as_deref()
+            .map_or(true, |path| backend.is_file(path) == Ok(false))
+    };
+    // Get the names of the entries in a directory
+    let entries = |base: &Option<String>| -> Vec<String> {
+        let dir = base.as_deref().unwrap_or(".");
+        let Ok(paths) = backend.list_dir(dir) else {
+            return Vec::new();
+        };
+        (paths.iter())
+            .filter_map(|path| path.rsplit(['/', '\\']).next())
+            .map(Into::into)
+            .collect()
+    };
+    let (root, rest) = match pattern.strip_prefix(['/', '\\']) {
+        Some(rest) => (Some("/".to_string()), rest),
+        None => (None, pattern),
+    };
+    let mut bases = vec![root];
+    let components: Vec<&str> = rest.split(['/', '\\']).filter(|c| !c.is_empty()).collect();
+    for (i, component) in components.iter().enumerate() {
+        let last = i == components.len() - 1;
+        let mut next = Vec::new();
+        if *component == "**" {
+            // Every directory under each base, including the base itself.
+            // Links are not followed, because they can form cycles.
+            let mut stack = bases;
+            while let Some(base) = stack.pop() {
+                for name in entries(&base) {
+                    if name.starts_with('.') {
+                        continue;
+                    }
+                    let path = join(&base, &name);
+                    let is_link = (backend.metadata(&path))
+                        .is_ok_and(|meta| matches!(meta.kind, FileKind::Symlink));
+                    let path = Some(path);
+                    if !is_link && is_dir(&path) {
+                        stack.push(path);
+                    }
+                }
+                next.push(base);
+            }
+        } else if !component.contains(['*', '?', '[']) {
+            for base in &bases {
+                let path = join(base, component);
+                if backend.file_exists(&path) {
+                    next.push(Some(path));
+                }
+            }
+        } else {
+            let pattern: Vec<char> = component.chars().collect();
+            for base in &bases {
+                for name in entries(base) {
+                    let name_chars: Vec<char> = name.chars().collect();
+                    if (name.starts_with('.') && !component.starts_with('.'))
+                        || !glob_match(&pattern, &name_chars)
+                    {
+                        continue;
+                    }
+                    next.push(Some(join(base, &name)));
+                }
+            }
+        }
+        if !last {
+            next.retain(is_dir);
+        }
+        bases = next;
+    }
+    let mut paths: Vec<String> = bases.into_iter().flatten().collect();
+    paths.sort();
+    paths.dedup();
+    paths
+}
+
+/// Check if a name matches a single component of a glob pattern
+fn glob_match(pattern: &[char], name: &[char]) -> bool {
+    match pattern.first() {
+        None => name.is_empty(),
+        Some('*') => (0..=name.len()).any(|i| glob_match(&pattern[1..], &name[i..])),
+        Some('?') => !name.is_empty() && glob_match(&pattern[1..], &name[1..]),
+        Some('[') => {
+            let Some(end) = pattern.iter().skip(2).position(|&c| c == ']') else {
+                return name.first() == Some(&'[') && glob_match(&pattern[1..], &name[1..]);
+            };
+            let Some(&c) = name.first() else {
+                return false;
+            };
+            let mut class = &pattern[1..end + 2];
+            let negated = matches!(class.first(), Some('!' | '^'));
+            if negated {
+                class = &class[1..];
+            }
+            let mut matched = false;
+            let mut i = 0;
+            while i < class.len() {
+                if i + 2 < class.len() && class[i + 1] == '-' {
+                    matched |= (class[i]..=class[i + 2]).contains(&c);
+                    i += 3;
+                } else {
+                    matched |= class[i] == c;
+                    i += 1;
+                }
+            }
+            matched != negated && glob_match(&pattern[end + 3..], &name[1..])
+        }
+        Some(&p) => name.first() == Some(&p) && glob_match(&pattern[1..], &name[1..]),
+    }
+}
+
+/// Get the ANSI SGR parameters for a list of style names
+fn ansi_codes(style: &[String]) -> Result<String, String> {
+    let mut codes = String::new();
+    for name in style.iter().flat_map(|names| names.split_whitespace()) {
+        let code = match name {
+            "bold" => 1,
+            "dim" => 2,
+            "italic" => 3,
+            "underline" => 4,
+            "blink" => 5,
+            "reverse" => 7,
+            "strikethrough" => 9,
+            _ => {
+                let (color, background) = match name.strip_prefix("on-") {
+                    Some(color) => (color, true),
+                    None => (name, false),
+                };
+                let (color, bright) = match color.strip_prefix("bright-") {
+                    Some(color) => (color, true),
+                    None => (color, false),
+                };
+                let index = [
+                    "black", "red", "green", "yellow", "blue", "magenta", "cyan", "white",
+                ]
+                .iter()
+                .position(|&c| c == color)
+                .ok_or_else(|| format!("Unknown style {name:?}"))?;
+                let base = match (background, bright) {
+                    (false, false) => 30,
+                    (true, false) => 40,
+                    (false, true) => 90,
+                    (true, true) => 100,
+                };
+                base + index
+            }
+        };
+        if !codes.is_empty() {
+            codes.push(';');
+        }
+        codes.push_str(&code.to_string());
+    }
+    Ok(codes)
+}
+
+/// Get the names and values of a list of headers like `"Name: value"`
+fn http_headers(headers: Value, env: &Uiua) -> UiuaResult<Vec<(String, String)>> {
+    let lines = string_list(headers, env, "Headers must be a list of strings")?;
+    (lines.into_iter())
+        .map(|line| match line.split_once(':') {
+            Some((name, value)) => Ok((name.trim().into(), value.trim().into())),
+            None => Err(env.error(format!("Header {line:?} has no colon"))),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ErrorCode, MemorySys};
+
+    #[test]
+    fn sandbox() {
+        let mut env = Uiua::with_native_sys().with_sandbox(true);
+        let err = env.load_str("&fras \"Cargo.toml\"").err().unwrap();
+        assert!(err.to_string().contains("not permitted"), "{err}");
+        env.take_stack();
+        let stack = env.load_str("⍣(&var \"HOME\")(5;)").unwrap().take_stack();
+        assert_eq!(stack, [Value::from(5.0)]);
+        let stack = Uiua::with_native_sys()
+            .load_str("&fe \"Cargo.toml\"")
+            .unwrap()
+            .take_stack();
+        assert_eq!(stack[0].show(), "1");
+        // Streams opened by other runtimes cannot be used
+        let handle = Uiua::with_native_sys()
+            .load_str("&fo \"Cargo.toml\"")
+            .unwrap()
+            .take_stack()
+            .remove(0);
+        let mut env = Uiua::with_native_sys().with_sandbox(true);
+        for op in ["&rs 10", "&cl"] {
+            env.push(handle.clone());
+            let err = env.load_str(op).err().unwrap();
+            assert!(err.to_string().contains("read-files"), "{err}");
+            env.take_stack();
+        }
+        let mut env = Uiua::with_native_sys();
+        env.push(handle);
+        env.load_str("&cl").unwrap();
+        // Showing images may open windows
+        let err = (Uiua::with_native_sys().with_sandbox(true))
+            .load_str("&ims ↯2_3 0.2")
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("display"), "{err}");
+    }
+
+    #[test]
+    fn capabilities() {
+        let mut env = Uiua::with_native_sys().with_capabilities([Capability::ReadFiles]);
+        assert!(!env.sandboxed());
+        env.load_str("&fe \"Cargo.toml\"").unwrap();
+        let err = env.load_str("&fwa \"out.txt\" \"hi\"").err().unwrap();
+        assert!(err.to_string().contains("write-files"), "{err}");
+        let mut env = Uiua::with_native_sys().with_capability(Capability::Environment, false);
+        assert!(env.load_str("&var \"HOME\"").is_err());
+        assert!(env.load_str("&fe \"Cargo.toml\"").is_ok());
+        assert_eq!("network".parse(), Ok(Capability::Network));
+    }
+
+    #[test]
+    fn custom_backend() {
+        struct FixedBackend;
+        impl SysBackend for FixedBackend {
+            fn any(&self) -> &dyn std::any::Any {
+                self
+            }
+            fn now(&self) -> f64 {
+                12.5
+            }
+            fn random_seed(&self) -> u64 {
+                7
+            }
+        }
+        let run = || {
+            Uiua::with_backend(FixedBackend)
+                .load_str("&n ⚂")
+                .unwrap()
+                .take_stack()
+        };
+        let stack = run();
+        assert_eq!(stack, run());
+        assert_eq!(stack[1].show(), "12.5");
+        let err = Uiua::with_backend(FixedBackend)
+            .load_str("&fras \"Cargo.toml\"")
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("not supported"), "{err}");
+    }
+
+    #[test]
+    fn http_request() {
+        struct EchoBackend;
+        impl SysBackend for EchoBackend {
+            fn any(&self) -> &dyn std::any::Any {
+                self
+            }
+            fn http_request(&self, request: HttpRequest) -> Result<HttpResponse, String> {
+                let body = format!("{} {}", request.method, request.url);
+                Ok(HttpResponse {
+                    status: 201,
+                    headers: request.headers,
+                    body: body.into_bytes(),
+                })
+            }
+        }
+        let stack = Uiua::with_backend(EchoBackend)
+            .load_str("&http \"post\" \"https://example.com\" {\"A: 1\" \"B:2\"} \"hi\"")
+            .unwrap()
+            .take_stack();
+        assert_eq!(stack.len(), 3);
+        assert_eq!(stack[2].show(), "201");
+        let expected = Uiua::with_native_sys()
+            .load_str("{\"A: 1\" \"B: 2\"}")
+            .unwrap()
+            .take_stack();
+        assert_eq!(stack[1], expected[0]);
+        assert_eq!(
+            stack[0]
+                .clone()
+                .into_bytes(&Uiua::with_native_sys(), "")
+                .unwrap(),
+            b"POST https://example.com"
+        );
+        let mut env = Uiua::with_backend(EchoBackend);
+        assert!(env.load_str("&http \"GET\" \"x\" \"no colon\" []").is_err());
+        assert!(env.load_str("&http \"GET\" \"x\" [] []").is_ok());
+        assert!(Uiua::with_backend(EchoBackend)
+            .with_capability(Capability::Network, false)
+            .load_str("&http \"GET\" \"x\" [] []")
+            .is_err());
+    }
+
+    #[test]
+    fn tcp_loopback() {
+        let code = "\
+L ← &tcpl \"127.0.0.1:0\"
+&cl &w \"hello\\n\" . &tcpc &tcpaddr L
+&ru \"\\n\" &tcpa L
+&cl L";
+        let stack = Uiua::with_native_sys().load_str(code).unwrap().take_stack();
+        assert_eq!(stack.len(), 1);
+        let received = stack[0].as_string(&Uiua::with_native_sys(), "").unwrap();
+        assert!(received.starts_with("hello"), "{received:?}");
+    }
+
+    #[test]
+    fn udp_loopback() {
+        let code = "\
+A ← &udpb \"127.0.0.1:0\"
+B ← &udpb \"127.0.0.1:0\"
+&udpst \"ping\" &udpaddr B A
+&udprf 1e15 B
+&udpaddr A
+&cl A
+&cl B";
+        let env = Uiua::with_native_sys();
+        let stack = Uiua::with_native_sys().load_str(code).unwrap().take_stack();
+        assert_eq!(stack.len(), 3);
+        assert_eq!(stack[0].clone().into_bytes(&env, "").unwrap(), b"ping");
+        assert_eq!(stack[1], stack[2]);
+    }
+
+    #[cfg(feature = "tls")]
+    #[test]
+    fn tls_upgrade_errors() {
+        let mut env = Uiua::with_native_sys();
+        let err = env
+            .load_str("&tlsu [] \"example.com\" 12345")
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("handle"), "{err}");
+        let err = (Uiua::with_backend(MemorySys::new()))
+            .load_str("&tlsu 0 \"example.com\" 3")
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("not supported"), "{err}");
+        // A failed handshake leaves the TCP socket open
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = std::thread::spawn(move || {
+            use std::io::{Read, Write};
+            let (mut stream, _) = listener.accept().unwrap();
+            assert!(stream.read(&mut [0; 1024]).unwrap() > 0);
+            stream.write_all(b"not tls\r\n").unwrap();
+            while stream.read(&mut [0; 1024]).unwrap() > 0 {}
+        });
+        let mut env = Uiua::with_native_sys();
+        let code = format!("&tcpc \"127.0.0.1:{port}\"");
+        let handle = env.load_str(&code).unwrap().take_stack().remove(0).show();
+        let code = format!("&tlsu [] \"localhost\" {handle}");
+        let err = env.load_str(&code).err().unwrap();
+        assert!(err.to_string().contains("handshake"), "{err}");
+        env.take_stack();
+        env.load_str(&format!("&tcpaddr {handle}\n&cl {handle}"))
+            .unwrap();
+        server.join().unwrap();
+    }
+
+    #[cfg(feature = "websocket")]
+    #[test]
+    fn websocket_echo() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = std::thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut socket = tungstenite::accept(stream).unwrap();
+            for _ in 0..2 {
+                let message = socket.read().unwrap();
+                socket.send(message).unwrap();
+            }
+        });
+        let code =
+            format!("&wsr . &wss [1 2 3] . ∶ &wsr . &wss \"hi\" . &wsc \"ws://127.0.0.1:{port}\"");
+        let mut stack = Uiua::with_native_sys()
+            .load_str(&code)
+            .unwrap()
+            .take_stack();
+        server.join().unwrap();
+        assert_eq!(stack.len(), 3);
+        assert_eq!(stack[0], Value::from("hi"));
+        let bytes = stack
+            .pop()
+            .unwrap()
+            .into_bytes(&Uiua::with_native_sys(), "");
+        assert_eq!(bytes.unwrap(), [1, 2, 3]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn run_command() {
+        let mut env = Uiua::with_native_sys();
+        let stack = env
+            .load_str("&runc {\"sort\"} \"b\\na\\n\"")
+            .unwrap()
+            .take_stack();
+        assert_eq!(stack[0], Value::from("a\nb\n"));
+        assert_eq!(stack[1], Value::from(""));
+        assert_eq!(stack[2].show(), "0");
+        let stack = env
+            .load_str("↲&runs {\"sh\" \"-c\" \"echo oops >&2; exit 3\"} \"\"")
+            .unwrap()
+            .take_stack();
+        assert_eq!(stack[1], Value::from("oops\n"));
+        assert_eq!(stack[2].show(), "3");
+        assert!(env
+            .load_str("&runc \"surely-not-a-real-program\" \"\"")
+            .is_err());
+        assert!(Uiua::with_native_sys()
+            .with_capability(Capability::Subprocess, false)
+            .load_str("&runc \"true\" \"\"")
+            .is_err());
+    }
+
+    #[test]
+    fn environment_variables() {
+        let backend = MemorySys::new().with_var("HOME", "/home/uiua");
+        let mut env = Uiua::with_backend(backend);
+        let stack = env
+            .load_str(
+                "&setvar \"MODE\" \"fast\"\n&var \"HOME\"\n&var \"MODE\"\n&var \"NOPE\"\n&vars",
+            )
+            .unwrap()
+            .take_stack();
+        assert_eq!(stack[0], Value::from("/home/uiua"));
+        assert_eq!(stack[1], Value::from("fast"));
+        assert_eq!(stack[2], Value::from(""));
+        let names = Uiua::with_native_sys()
+            .load_str("{\"HOME\" \"MODE\"}")
+            .unwrap()
+            .take_stack();
+        assert_eq!(stack[3], names[0]);
+        let mut env = Uiua::with_native_sys();
+        let stack = env
+            .load_str("&setvar \"UIUA_TEST_VAR\" \"1\"\n&var \"UIUA_TEST_VAR\"")
+            .unwrap()
+            .take_stack();
+        assert_eq!(stack, [Value::from("1")]);
+        assert!(std::env::var("UIUA_TEST_VAR").is_err());
+        let stack = env
+            .load_str("&runc {\"sh\" \"-c\" \"echo $UIUA_TEST_VAR\"} \"\"")
+            .unwrap()
+            .take_stack();
+        assert_eq!(stack[0], Value::from("1\n"));
+        assert!(env.load_str("&setvar \"A=B\" \"1\"").is_err());
+    }
+
+    #[test]
+    fn text_style() {
+        struct ColorBackend;
+        impl SysBackend for ColorBackend {
+            fn any(&self) -> &dyn std::any::Any {
+                self
+            }
+            fn stdout_supports_color(&self) -> bool {
+                true
+            }
+        }
+        let code = "&sty \"bold red\" \"hi\"\n&sty {\"on-bright-blue\" \"underline\"} \"yo\"\n&sty \"\" \"plain\"";
+        let stack = Uiua::with_backend(ColorBackend)
+            .load_str(code)
+            .unwrap()
+            .take_stack();
+        assert_eq!(stack[0], Value::from("\x1b[1;31mhi\x1b[0m"));
+        assert_eq!(stack[1], Value::from("\x1b[104;4myo\x1b[0m"));
+        assert_eq!(stack[2], Value::from("plain"));
+        let stack = Uiua::with_backend(MemorySys::new())
+            .load_str(code)
+            .unwrap()
+            .take_stack();
+        assert_eq!(stack[0], Value::from("hi"));
+        assert!(Uiua::with_backend(ColorBackend)
+            .load_str("&sty \"sparkly\" \"hi\"")
+            .is_err());
+    }
+
+    #[cfg(feature = "file_watch")]
+    #[test]
+    fn file_watch() {
+        let dir = std::env::temp_dir().join(format!("uiua-watch-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.to_string_lossy().into_owned();
+        let file = dir.join("a.txt");
+        let writer = std::thread::spawn(move || {
+            std::thread::sleep(std::time::Duration::from_millis(200));
+            std::fs::write(file, "hi").unwrap();
+        });
+        let stack = Uiua::with_native_sys()
+            .load_str(&format!("&fwatch {path:?} 10"))
+            .unwrap()
+            .take_stack();
+        writer.join().unwrap();
+        assert!(stack[0].row_count() > 0);
+        let stack = Uiua::with_native_sys()
+            .load_str(&format!("&fwatch {path:?} 0.1"))
+            .unwrap()
+            .take_stack();
+        assert_eq!(stack[0].row_count(), 0);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn glob_does_not_follow_links() {
+        let dir = std::env::temp_dir().join(format!("uiua-glob-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("a")).unwrap();
+        std::fs::write(dir.join("a/main.ua"), "").unwrap();
+        std::os::unix::fs::symlink(&dir, dir.join("a/loop")).unwrap();
+        let pattern = format!("{}/**/*.ua", dir.display());
+        let mut env = Uiua::with_native_sys();
+        let res = env
+            .load_str(&format!("⧻&fglob {pattern:?}"))
+            .map(Uiua::take_stack);
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(res.unwrap(), [Value::from(1.0)]);
+    }
+
+    #[test]
+    fn file_metadata() {
+        let backend = MemorySys::new().with_file("dir/data.bin", vec![1u8, 2, 3]);
+        let stack = Uiua::with_backend(backend)
+            .load_str("&fsize \"dir/data.bin\"\n&fkind \"dir/data.bin\"\n&fkind \"dir\"")
+            .unwrap()
+            .take_stack();
+        assert_eq!(stack[0], Value::from(3.0));
+        assert_eq!(stack[1], Value::from("file"));
+        assert_eq!(stack[2], Value::from("directory"));
+        assert!(Uiua::with_backend(MemorySys::new())
+            .load_str("&fmod \"missing\"")
+            .is_err());
+        let stack = Uiua::with_native_sys()
+            .load_str("&fkind \"src\"\n&fmod \"Cargo.toml\"\n&n")
+            .unwrap()
+            .take_stack();
+        assert_eq!(stack[0], Value::from("directory"));
+        let modified = stack[1].as_num(&Uiua::with_native_sys(), "").unwrap();
+        let now = stack[2].as_num(&Uiua::with_native_sys(), "").unwrap();
+        assert!(modified > 0.0 && modified <= now + 1.0);
+    }
+
+    #[test]
+    fn sleep_and_monotonic_time() {
+        let stack = Uiua::with_backend(MemorySys::new())
+            .load_str("&mono &sl 2.5 &mono")
+            .unwrap()
+            .take_stack();
+        assert_eq!(stack, [Value::from(0.0), Value::from(2.5)]);
+        for time in ["÷0 0", "¯1"] {
+            let err = (Uiua::with_native_sys())
+                .load_str(&format!("&sl {time}"))
+                .err()
+                .unwrap();
+            assert!(err.to_string().contains("non-negative"), "{err}");
+        }
+        let mut env = Uiua::with_native_sys();
+        let stack = env
+            .load_str("-∶&mono &sl 0.0005 &mono")
+            .unwrap()
+            .take_stack();
+        let elapsed = stack[0].as_num(&env, "").unwrap();
+        assert!(elapsed >= 0.0005, "{elapsed}");
+        let handle = env.interrupt_handle();
+        let interrupter = std::thread::spawn(move || {
+            std::thread::sleep(std::time::Duration::from_millis(100));
+            handle.interrupt();
+        });
+        let start = std::time::Instant::now();
+        let err = env.load_str("&sl 60").err().unwrap();
+        interrupter.join().unwrap();
+        assert_eq!(err.code(), ErrorCode::Interrupted);
+        assert!(start.elapsed() < std::time::Duration::from_secs(5));
+    }
+
+    #[test]
+    fn audio_sample_rate() {
+        #[derive(Default)]
+        struct AudioBackend(std::sync::Mutex<Vec<Vec<u8>>>);
+        impl SysBackend for AudioBackend {
+            fn any(&self) -> &dyn std::any::Any {
+                self
+            }
+            fn play_audio(&self, wav_bytes: Vec<u8>) -> Result<(), String> {
+                self.0.lock().unwrap().push(wav_bytes);
+                Ok(())
+            }
+        }
+        let mut env = Uiua::with_backend(AudioBackend::default());
+        env.load_str("&apr 8000 ÷4○×τ×440 ÷8000 ⇡8000\n&ap [0 0.5 0]")
+            .unwrap();
+        let backend = env.downcast_backend::<AudioBackend>().unwrap();
+        let played = backend.0.lock().unwrap();
+        let rates: Vec<u32> = (played.iter())
+            .map(|bytes| {
+                let reader = hound::WavReader::new(std::io::Cursor::new(bytes)).unwrap();
+                reader.spec().sample_rate
+            })
+            .collect();
+        assert_eq!(rates, [8000, 44100]);
+        drop(played);
+        assert!(env.load_str("&apr 0 [0 0.5 0]").is_err());
+    }
+
+    #[test]
+    fn audio_recording() {
+        struct MicBackend;
+        impl SysBackend for MicBackend {
+            fn any(&self) -> &dyn std::any::Any {
+                self
+            }
+            fn record_audio(&self, seconds: f64) -> Result<(Vec<f64>, u32), String> {
+                let count = (seconds * 100.0) as usize;
+                Ok(((0..count).map(|i| i as f64 / count as f64).collect(), 100))
+            }
+        }
+        let stack = Uiua::with_backend(MicBackend)
+            .load_str("&arec 0.5")
+            .unwrap()
+            .take_stack();
+        assert_eq!(stack[0], Value::from(100.0));
+        assert_eq!(stack[1].row_count(), 50);
+        assert!(Uiua::with_backend(MicBackend).load_str("&arec ∞").is_err());
+        assert!(Uiua::with_backend(MicBackend)
+            .with_sandbox(true)
+            .load_str("&arec 1")
+            .is_err());
+    }
+
+    #[test]
+    fn image_show() {
+        #[derive(Default)]
+        struct ImageBackend(std::sync::Mutex<Vec<(u32, u32)>>);
+        impl SysBackend for ImageBackend {
+            fn any(&self) -> &dyn std::any::Any {
+                self
+            }
+            fn show_image(&self, image: image::DynamicImage) -> Result<(), String> {
+                (self.0.lock().unwrap()).push((image.width(), image.height()));
+                Ok(())
+            }
+        }
+        let mut env = Uiua::with_backend(ImageBackend::default());
+        env.load_str("&ims ↯2_3 0.2\n&ims ↯2_4_3 0.5").unwrap();
+        let backend = env.downcast_backend::<ImageBackend>().unwrap();
+        assert_eq!(*backend.0.lock().unwrap(), [(3, 2), (4, 2)]);
+        assert!(env.load_str("&ims ↯2_2_2_2 0").is_err());
+    }
+}
@@ -3,7 +3,7 @@ use std::{
     cmp::Ordering,
     fmt,
     hash::{Hash, Hasher},
-    mem::take,
+    mem::{size_of, take},
     sync::Arc,
 };
 
@@ -11,7 +11,7 @@ use crate::{
     algorithm::pervade::*,
     array::*,
     function::{Function, Signature},
-    grid_fmt::GridFmt,
+    grid_fmt::{grid_to_string, DEFAULT_DISPLAY_BUDGET},
     primitive::Primitive,
     Uiua, UiuaResult,
 };
@@ -150,6 +150,15 @@ impl Value {
             Array::flat_len,
         )
     }
+    /// Get the size in bytes of each of the value's elements
+    pub(crate) fn element_size(&self) -> usize {
+        match self {
+            Self::Num(_) => size_of::<f64>(),
+            Self::Byte(_) => size_of::<u8>(),
+            Self::Char(_) => size_of::<char>(),
+            Self::Func(_) => size_of::<Arc<Function>>(),
+        }
+    }
     pub(crate) fn first_dim_zero(&self) -> Self {
         match self {
             Self::Num(array) => array.first_dim_zero().into(),
@@ -262,12 +271,19 @@ impl Value {
     }
     /// Get the pretty-printed string representation of the value
     pub fn show(&self) -> String {
-        match self {
-            Self::Num(array) => array.grid_string(),
-            Self::Byte(array) => array.grid_string(),
-            Self::Char(array) => array.grid_string(),
-            Self::Func(array) => array.grid_string(),
-        }
+        self.show_with_budget(DEFAULT_DISPLAY_BUDGET)
+    }
+    /// Get the pretty-printed string representation of the value,
+    /// showing at most `budget` rows along each axis
+    ///
+    /// Longer axes show their first and last rows with an ellipsis between them.
+    pub fn show_with_budget(&self, budget: usize) -> String {
+        grid_to_string(match self {
+            Self::Num(array) => array.fmt_grid_budget(false, budget),
+            Self::Byte(array) => array.fmt_grid_budget(false, budget),
+            Self::Char(array) => array.fmt_grid_budget(false, budget),
+            Self::Func(array) => array.fmt_grid_budget(false, budget),
+        })
     }
     pub fn as_primitive(&self) -> Option<(Primitive, usize)> {
         if let Value::Func(fs) = self {
@@ -662,6 +678,21 @@ macro_rules! value_un_impl_all {
 
 value_un_impl_all!(neg, not, abs, sign, sqrt, sin, cos, tan, asin, acos, floor, ceil, round);
 
+impl Value {
+    /// Like [`Value::not`], but boolean byte arrays stay byte arrays
+    pub fn not_mask(self, env: &Uiua) -> UiuaResult<Self> {
+        match self {
+            Value::Byte(mut bytes) if bytes.data.iter().all(|&b| b <= 1) => {
+                for b in bytes.data.iter_mut() {
+                    *b = not::bool(*b);
+                }
+                Ok(bytes.into())
+            }
+            val => val.not(env),
+        }
+    }
+}
+
 macro_rules! val_retry {
     (Byte, $env:expr) => {
         $env.num_fill().is_some()
@@ -800,6 +831,45 @@ value_bin_impl!(
     (Num, Byte, num_byte, num_num),
 );
 
+macro_rules! value_bin_in_place_impl {
+    ($name:ident, $in_place:ident, $(($variant:ident, $f:ident)),* $(,)?) => {
+        impl Value {
+            /// Like the by-reference version, but reuses an operand's buffer if it is uniquely owned
+            pub fn $in_place(self, other: Self, env: &Uiua) -> UiuaResult<Self> {
+                Ok(match (self, other) {
+                    $((Value::$variant(a), Value::$variant(b)) => {
+                        bin_pervade_mut(a, b, env, $name::$f)?.into()
+                    })*
+                    (a, b) => Value::$name(&a, &b, env)?,
+                })
+            }
+        }
+    };
+}
+
+value_bin_in_place_impl!(add, add_in_place, (Num, num_num));
+value_bin_in_place_impl!(sub, sub_in_place, (Num, num_num));
+value_bin_in_place_impl!(mul, mul_in_place, (Num, num_num));
+value_bin_in_place_impl!(div, div_in_place, (Num, num_num));
+value_bin_in_place_impl!(modulus, modulus_in_place, (Num, num_num));
+value_bin_in_place_impl!(pow, pow_in_place, (Num, num_num));
+value_bin_in_place_impl!(log, log_in_place, (Num, num_num));
+value_bin_in_place_impl!(atan2, atan2_in_place, (Num, num_num));
+value_bin_in_place_impl!(
+    min,
+    min_in_place,
+    (Num, num_num),
+    (Byte, byte_byte),
+    (Char, char_char)
+);
+value_bin_in_place_impl!(
+    max,
+    max_in_place,
+    (Num, num_num),
+    (Byte, byte_byte),
+    (Char, char_char)
+);
+
 macro_rules! cmp_impls {
     ($($name:ident),*) => {
         $(
@@ -829,10 +899,10 @@ cmp_impls!(is_eq, is_ne, is_lt, is_le, is_gt, is_ge);
 impl PartialEq for Value {
     fn eq(&self, other: &Self) -> bool {
         match (self, other) {
-            (Value::Num(a), Value::Num(b)) => a == b,
-            (Value::Byte(a), Value::Byte(b)) => a == b,
-            (Value::Char(a), Value::Char(b)) => a == b,
-            (Value::Func(a), Value::Func(b)) => a == b,
+            (Value::Num(a), Value::Num(b)) => a.ptr_eq(b) || a == b,
+            (Value::Byte(a), Value::Byte(b)) => a.ptr_eq(b) || a == b,
+            (Value::Char(a), Value::Char(b)) => a.ptr_eq(b) || a == b,
+            (Value::Func(a), Value::Func(b)) => a.ptr_eq(b) || a == b,
             (Value::Num(a), Value::Byte(b)) => a == b,
             (Value::Byte(a), Value::Num(b)) => a == b,
             _ => false,